@@ -0,0 +1,88 @@
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use viewstamped_replication::buffer::{BufferedMailbox, ProtocolPayload};
+use viewstamped_replication::{Client, Configuration, Protocol, Replica, Service};
+
+/// A service that tracks the total number of bytes it has seen so requests have a realistic size.
+#[derive(Default)]
+pub struct Blob(usize);
+
+impl Protocol for Blob {
+    type Request = Vec<u8>;
+    type Prediction = ();
+    type Reply = usize;
+    type Checkpoint = usize;
+}
+
+impl From<<Self as Protocol>::Checkpoint> for Blob {
+    fn from(value: <Self as Protocol>::Checkpoint) -> Self {
+        Blob(value)
+    }
+}
+
+impl Service for Blob {
+    fn predict(&self, _: &<Self as Protocol>::Request) -> <Self as Protocol>::Prediction {}
+
+    fn checkpoint(&self) -> <Self as Protocol>::Checkpoint {
+        self.0
+    }
+
+    fn invoke(
+        &mut self,
+        request: &<Self as Protocol>::Request,
+        _: &<Self as Protocol>::Prediction,
+    ) -> <Self as Protocol>::Reply {
+        self.0 += request.len();
+        self.0
+    }
+}
+
+/// Builds a primary with the given number of uncommitted operations in its log
+/// along with a start view change message that causes it to send its log in a do view change message.
+fn setup(entries: usize) -> (Replica<Blob>, ProtocolPayload<Blob>, BufferedMailbox<Blob>) {
+    let configuration = Configuration::from(3);
+    let mut primary = Replica::new(configuration, 0, Blob::default());
+    let mut backup = Replica::new(configuration, 1, Blob::default());
+    let mut client = Client::new(configuration);
+    let mut mailbox = BufferedMailbox::default();
+
+    for _ in 0..entries {
+        let request = client.new_request(vec![0; 1024]);
+
+        primary.handle_request(request, &mut mailbox);
+        mailbox.drain_broadcast().count();
+    }
+
+    backup.idle(&mut mailbox);
+
+    let message = mailbox
+        .drain_broadcast()
+        .next()
+        .expect("backup did not start a view change");
+
+    (primary, message, mailbox)
+}
+
+fn do_view_change(c: &mut Criterion) {
+    let mut group = c.benchmark_group("do_view_change");
+
+    for entries in [100, 1_000, 10_000] {
+        group.bench_with_input(BenchmarkId::from_parameter(entries), &entries, |b, &n| {
+            b.iter_batched(
+                || setup(n),
+                |(mut primary, message, mut mailbox)| {
+                    if let ProtocolPayload::StartViewChange(message) = message {
+                        primary.handle_start_view_change(message, &mut mailbox);
+                    }
+
+                    mailbox
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, do_view_change);
+criterion_main!(benches);
@@ -0,0 +1,288 @@
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use std::fs::File;
+use std::io::Write;
+use std::time::{Duration, Instant};
+use viewstamped_replication::buffer::{BufferedMailbox, ClientPayload};
+use viewstamped_replication::driver::LocalDriver;
+use viewstamped_replication::services::{Ledger, LedgerRequest};
+use viewstamped_replication::{
+    seal, Client, Configuration, FsyncPolicy, Protocol, Replica, Service,
+};
+
+const SUFFIX: usize = 1_000;
+
+#[derive(Default)]
+pub struct Adder(i32);
+
+impl Protocol for Adder {
+    type Request = i32;
+    type Prediction = ();
+    type Reply = i32;
+    type Checkpoint = i32;
+}
+
+impl From<<Self as Protocol>::Checkpoint> for Adder {
+    fn from(value: <Self as Protocol>::Checkpoint) -> Self {
+        Adder(value)
+    }
+}
+
+impl Service for Adder {
+    fn predict(&self, _: &<Self as Protocol>::Request) -> <Self as Protocol>::Prediction {}
+
+    fn checkpoint(&self) -> <Self as Protocol>::Checkpoint {
+        self.0
+    }
+
+    fn invoke(
+        &mut self,
+        request: &<Self as Protocol>::Request,
+        _: &<Self as Protocol>::Prediction,
+    ) -> <Self as Protocol>::Reply {
+        self.0 = self.0.wrapping_add(*request);
+        self.0
+    }
+}
+
+/// Sends a single request from every client and waits for all the replies.
+fn round_trip(driver: &mut LocalDriver<Adder>, clients: &mut [Client]) {
+    for client in clients.iter_mut() {
+        let request = client.new_request(1);
+        driver.request(client.primary(), request);
+    }
+
+    driver.drive();
+
+    for client in clients.iter_mut() {
+        for payload in driver.take_replies(client.identifier()) {
+            if let ClientPayload::Reply(reply) = payload {
+                client.update_view(&reply);
+            }
+        }
+    }
+
+    for index in 0..driver.configuration().replicas() {
+        driver.checkpoint_with_suffix(index, SUFFIX);
+    }
+}
+
+fn requests(c: &mut Criterion) {
+    let mut group = c.benchmark_group("requests");
+
+    for count in [1, 10, 100] {
+        let configuration = Configuration::from(3);
+        let mut driver = LocalDriver::new(configuration, |_| Adder::default());
+        let mut clients = Vec::from_iter((0..count).map(|_| Client::new(configuration)));
+
+        group.throughput(Throughput::Elements(count as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(count), &count, |b, _| {
+            b.iter(|| round_trip(&mut driver, &mut clients))
+        });
+    }
+
+    group.finish();
+}
+
+fn commit_latency(c: &mut Criterion) {
+    let mut group = c.benchmark_group("commit_latency");
+
+    for replicas in [3, 5, 7] {
+        let configuration = Configuration::from(replicas);
+        let mut driver = LocalDriver::new(configuration, |_| Adder::default());
+        let mut clients = [Client::new(configuration)];
+
+        group.bench_with_input(BenchmarkId::from_parameter(replicas), &replicas, |b, _| {
+            b.iter(|| round_trip(&mut driver, &mut clients))
+        });
+    }
+
+    group.finish();
+}
+
+/// Alternates between crediting each account and transferring twice the credit out of it,
+/// so every other transfer is rejected for insufficient funds and replicas cache replies for failed operations too.
+fn ledger_transfers(c: &mut Criterion) {
+    let mut group = c.benchmark_group("ledger_transfers");
+
+    for count in [1, 10, 100] {
+        let configuration = Configuration::from(3);
+        let mut driver = LocalDriver::new(configuration, |_| Ledger::default());
+        let mut clients = Vec::from_iter((0..count).map(|_| Client::new(configuration)));
+        let mut funded = false;
+
+        group.throughput(Throughput::Elements(count as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(count), &count, |b, _| {
+            b.iter(|| {
+                for (index, client) in clients.iter_mut().enumerate() {
+                    let account = format!("account-{index}");
+                    let request = if funded {
+                        LedgerRequest::Transfer {
+                            from: account,
+                            to: "sink".to_string(),
+                            amount: 2,
+                        }
+                    } else {
+                        LedgerRequest::Credit { account, amount: 1 }
+                    };
+
+                    driver.request(client.primary(), client.new_request(request));
+                }
+
+                funded = !funded;
+                driver.drive();
+
+                for client in clients.iter_mut() {
+                    for payload in driver.take_replies(client.identifier()) {
+                        if let ClientPayload::Reply(reply) = payload {
+                            client.update_view(&reply);
+                        }
+                    }
+                }
+
+                for index in 0..driver.configuration().replicas() {
+                    driver.checkpoint_with_suffix(index, SUFFIX);
+                }
+            })
+        });
+    }
+
+    group.finish();
+}
+
+/// Dispatches the messages of a request directly to the replicas through an in-process mailbox,
+/// measuring the replica hot path without the serialization and routing of the local driver.
+fn dispatch(c: &mut Criterion) {
+    let mut group = c.benchmark_group("dispatch");
+
+    for replicas in [3, 5, 7] {
+        let configuration = Configuration::from(replicas);
+        let mut group_replicas = Vec::from_iter(
+            (0..replicas).map(|index| Replica::new(configuration, index, Adder::default())),
+        );
+        let mut mailbox = BufferedMailbox::default();
+        let mut client = Client::new(configuration);
+
+        group.bench_with_input(BenchmarkId::from_parameter(replicas), &replicas, |b, _| {
+            b.iter(|| {
+                group_replicas[0].handle_request(client.new_request(1), &mut mailbox);
+
+                let prepare = mailbox.drain_broadcast().next().unwrap();
+
+                for replica in group_replicas.iter_mut().skip(1) {
+                    replica.handle_payload(prepare.clone(), &mut mailbox);
+                }
+
+                for envelope in Vec::from_iter(mailbox.drain_send()) {
+                    group_replicas[envelope.destination]
+                        .handle_payload(envelope.payload, &mut mailbox);
+                }
+
+                for envelope in mailbox.drain_replies() {
+                    if let ClientPayload::Reply(reply) = envelope.payload {
+                        client.update_view(&reply);
+                    }
+                }
+
+                for replica in group_replicas.iter_mut() {
+                    replica.checkpoint_with_suffix(SUFFIX);
+                }
+            })
+        });
+    }
+
+    group.finish();
+}
+
+/// Appends a sealed checkpoint of the primary to a file after every commit,
+/// flushing it to storage according to each policy.
+fn fsync(c: &mut Criterion) {
+    let mut group = c.benchmark_group("fsync");
+    let second = Duration::from_secs(1);
+
+    for policy in [
+        FsyncPolicy::Always,
+        FsyncPolicy::EveryNOps(10),
+        FsyncPolicy::EveryDuration(second),
+        FsyncPolicy::Never,
+    ] {
+        let configuration = Configuration::from(3);
+        let mut driver = LocalDriver::new(configuration, |_| Adder::default());
+        let mut clients = [Client::new(configuration)];
+        let path = std::env::temp_dir().join(format!("vr-fsync-{}", std::process::id()));
+        let mut file = File::create(&path).unwrap();
+        let mut operations = 0;
+        let mut flushed = Instant::now();
+
+        group.bench_function(format!("{policy:?}"), |b| {
+            b.iter(|| {
+                round_trip(&mut driver, &mut clients);
+
+                let checkpoint = driver.replica_mut(0).checkpoint();
+
+                file.write_all(&seal(&checkpoint).unwrap()).unwrap();
+                operations += 1;
+
+                if policy.is_due(operations, flushed.elapsed()) {
+                    file.sync_data().unwrap();
+                    operations = 0;
+                    flushed = Instant::now();
+                }
+            })
+        });
+
+        drop(file);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    group.finish();
+}
+
+fn view_change(c: &mut Criterion) {
+    let mut group = c.benchmark_group("view_change");
+
+    for operations in [10, 100, 1_000] {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(operations),
+            &operations,
+            |b, &operations| {
+                b.iter_batched(
+                    || {
+                        let configuration = Configuration::from(3);
+                        let mut driver = LocalDriver::new(configuration, |_| Adder::default());
+                        let mut clients = [Client::new(configuration)];
+
+                        for _ in 0..operations {
+                            round_trip(&mut driver, &mut clients);
+                        }
+
+                        driver
+                    },
+                    |mut driver| {
+                        driver.crash(0);
+
+                        for index in 1..driver.configuration().replicas() {
+                            driver.idle(index);
+                        }
+
+                        driver.drive();
+                        driver
+                    },
+                    BatchSize::SmallInput,
+                )
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    requests,
+    commit_latency,
+    ledger_transfers,
+    dispatch,
+    fsync,
+    view_change
+);
+criterion_main!(benches);
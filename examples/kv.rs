@@ -0,0 +1,73 @@
+use std::time::Duration;
+use viewstamped_replication::driver::{ClusterController, ClusterRunner};
+use viewstamped_replication::services::{KvReply, KvRequest, KvStore};
+use viewstamped_replication::Configuration;
+
+fn main() {
+    let configuration = Configuration::from(3)
+        .with_commit_interval(Duration::from_millis(10))
+        .with_view_timeout(Duration::from_millis(100), Duration::from_millis(50));
+    let runner = ClusterRunner::start(configuration, |_| KvStore::default());
+    let mut client = runner.client();
+    let retry = Duration::from_millis(200);
+    let deadline = Duration::from_secs(5);
+    let mut invoke = |request: KvRequest| {
+        let reply = client
+            .invoke(request.clone(), retry, deadline)
+            .expect("the group did not reply in time");
+
+        println!("{request:?} => {reply:?}");
+
+        reply
+    };
+
+    invoke(KvRequest::Put {
+        key: "greeting".to_string(),
+        value: "hello".to_string(),
+    });
+    invoke(KvRequest::Put {
+        key: "subject".to_string(),
+        value: "world".to_string(),
+    });
+
+    let reply = invoke(KvRequest::CompareAndSwap {
+        key: "greeting".to_string(),
+        expected: Some("hello".to_string()),
+        value: Some("goodbye".to_string()),
+    });
+
+    assert!(matches!(reply, KvReply::Swapped { swapped: true, .. }));
+
+    // Every replica restarts from a checkpoint and recovers while the others keep serving requests.
+    ClusterController::new(&runner)
+        .rolling_restart()
+        .expect("the rolling restart failed");
+
+    let reply = invoke(KvRequest::Get {
+        key: "greeting".to_string(),
+    });
+
+    assert_eq!(reply, KvReply::Value(Some("goodbye".to_string())));
+
+    invoke(KvRequest::Delete {
+        key: "subject".to_string(),
+    });
+
+    let mut replicas = runner.shutdown();
+    let latest = replicas
+        .iter_mut()
+        .max_by_key(|replica| replica.committed())
+        .expect("the group should have replicas");
+    let checkpoint = latest.checkpoint();
+
+    println!(
+        "Checkpoint at op-number {:?}: {:?}",
+        checkpoint.committed, checkpoint.state
+    );
+
+    assert_eq!(checkpoint.state.len(), 1);
+    assert_eq!(
+        KvStore::from(checkpoint.state).get("greeting"),
+        Some("goodbye")
+    );
+}
@@ -0,0 +1,29 @@
+use std::time::Duration;
+use viewstamped_replication::bootstrap::{self, ClusterConfig};
+use viewstamped_replication::services::{KvRequest, KvStore};
+
+/// Launches a group in this process from the JSON configuration file given as the first argument,
+/// or from a default configuration of 3 replicas when no file is given.
+fn main() {
+    let config = match std::env::args().nth(1) {
+        Some(path) => ClusterConfig::load(path),
+        None => ClusterConfig::from_json(
+            r#"{ "replicas": 3, "commit_interval_ms": 10, "view_timeout_ms": 100, "view_jitter_ms": 50 }"#,
+        ),
+    }
+    .expect("invalid configuration");
+    let runner = bootstrap::run(&config, |_| KvStore::default()).expect("invalid configuration");
+    let mut client = runner.client();
+    let reply = client.invoke(
+        KvRequest::Put {
+            key: "greeting".to_string(),
+            value: "hello".to_string(),
+        },
+        Duration::from_millis(200),
+        Duration::from_secs(5),
+    );
+
+    println!("{reply:?}");
+
+    runner.shutdown();
+}
@@ -0,0 +1,23 @@
+use viewstamped_replication::bootstrap::{self, ClusterConfig};
+use viewstamped_replication::services::KvStore;
+
+/// Runs the replica with the index given as the second argument as one process of the group described by the
+/// JSON configuration file given as the first argument, which must list the members of the group.
+#[tokio::main]
+async fn main() {
+    let mut args = std::env::args().skip(1);
+    let config = ClusterConfig::load(args.next().expect("missing configuration file"))
+        .expect("invalid configuration");
+    let index = args
+        .next()
+        .expect("missing replica index")
+        .parse()
+        .expect("invalid replica index");
+    let server = bootstrap::serve(&config, index, KvStore::default())
+        .await
+        .expect("unable to start the replica");
+
+    println!("replica {index} listening on {}", server.local_addr());
+
+    std::future::pending::<()>().await;
+}
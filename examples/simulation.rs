@@ -8,9 +8,9 @@ use tokio::sync::mpsc::{
     channel, unbounded_channel, Receiver, Sender, UnboundedReceiver, UnboundedSender,
 };
 use tokio::task::JoinSet;
-use viewstamped_replication::buffer::{BufferedMailbox, ProtocolPayload};
+use viewstamped_replication::buffer::{BufferedMailbox, ClientPayload, ProtocolPayload};
 use viewstamped_replication::{
-    Client, ClientIdentifier, Configuration, Protocol, Replica, Reply, Request, Service,
+    Client, ClientIdentifier, Configuration, Protocol, Replica, Request, Service,
 };
 
 #[derive(Copy, Clone, Debug, Parser)]
@@ -28,6 +28,9 @@ pub struct Options {
     /// Timeout in milliseconds for backups considering themselves idle.
     #[arg(long, default_value_t = 500)]
     view_timeout: u64,
+    /// Maximum random delay in milliseconds added to the view timeout of backups.
+    #[arg(long, default_value_t = 250)]
+    view_jitter: u64,
     /// Timeout in milliseconds for clients to broadcast their request.
     #[arg(long, default_value_t = 1000)]
     reply_timeout: u64,
@@ -43,6 +46,9 @@ pub struct Options {
     /// Total number of requests each client will make.
     #[arg(short, long, default_value_t = 0.00)]
     network_drop_rate: f64,
+    /// Maximum number of uncommitted operations on the primary before clients are told to back off.
+    #[arg(long)]
+    in_flight_limit: Option<usize>,
 }
 
 #[derive(Default)]
@@ -62,9 +68,7 @@ impl From<<Self as Protocol>::Checkpoint> for Adder {
 }
 
 impl Service for Adder {
-    fn predict(&self, _: &<Self as Protocol>::Request) -> <Self as Protocol>::Prediction {
-        ()
-    }
+    fn predict(&self, _: &<Self as Protocol>::Request) -> <Self as Protocol>::Prediction {}
 
     fn checkpoint(&self) -> <Self as Protocol>::Checkpoint {
         self.0
@@ -90,11 +94,13 @@ where
     Recover,
 }
 
-impl<P, Req, Pre> Debug for Command<P>
+impl<P, Req, Pre, Rep, Chk> Debug for Command<P>
 where
-    P: Protocol<Request = Req, Prediction = Pre>,
+    P: Protocol<Request = Req, Prediction = Pre, Reply = Rep, Checkpoint = Chk>,
     Req: Debug,
     Pre: Debug,
+    Rep: Debug,
+    Chk: Debug,
 {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -106,14 +112,72 @@ where
     }
 }
 
+/// Separate channels for each replica so that view change, recovery and state transfer messages
+/// are not stuck behind a backlog of client requests.
+pub struct Lanes<P>
+where
+    P: Protocol,
+{
+    priority: UnboundedSender<Command<P>>,
+    normal: UnboundedSender<Command<P>>,
+}
+
+impl<P> Clone for Lanes<P>
+where
+    P: Protocol,
+{
+    fn clone(&self) -> Self {
+        Self {
+            priority: self.priority.clone(),
+            normal: self.normal.clone(),
+        }
+    }
+}
+
+impl<P> Lanes<P>
+where
+    P: Protocol,
+{
+    fn send(&self, command: Command<P>) -> Result<(), ()> {
+        let lane = match &command {
+            Command::Protocol(message) if message.is_priority() => &self.priority,
+            Command::Crash | Command::Recover => &self.priority,
+            _ => &self.normal,
+        };
+
+        lane.send(command).map_err(|_| ())
+    }
+}
+
+pub struct LaneReceivers<P>
+where
+    P: Protocol,
+{
+    priority: UnboundedReceiver<Command<P>>,
+    normal: UnboundedReceiver<Command<P>>,
+}
+
+impl<P> LaneReceivers<P>
+where
+    P: Protocol,
+{
+    pub async fn recv(&mut self) -> Option<Command<P>> {
+        tokio::select! {
+            biased;
+            Some(command) = self.priority.recv() => Some(command),
+            command = self.normal.recv() => command,
+        }
+    }
+}
+
 pub struct Network<P>
 where
     P: Protocol,
 {
     configuration: Configuration,
     options: Options,
-    senders: Vec<UnboundedSender<Command<P>>>,
-    clients: HashMap<ClientIdentifier, Sender<Reply<P::Reply>>>,
+    senders: Vec<Lanes<P>>,
+    clients: HashMap<ClientIdentifier, Sender<ClientPayload<P>>>,
 }
 
 impl<P> Clone for Network<P>
@@ -130,12 +194,13 @@ where
     }
 }
 
-impl<P, Req, Pre, Rep> Network<P>
+impl<P, Req, Pre, Rep, Chk> Network<P>
 where
-    P: Protocol<Request = Req, Prediction = Pre, Reply = Rep>,
+    P: Protocol<Request = Req, Prediction = Pre, Reply = Rep, Checkpoint = Chk>,
     Req: Clone + Debug,
     Pre: Debug,
     Rep: Debug,
+    Chk: Debug,
 {
     pub fn new(configuration: Configuration, options: Options) -> Self {
         let senders = Vec::with_capacity(configuration.replicas());
@@ -148,15 +213,19 @@ where
         }
     }
 
-    pub fn bind(&mut self) -> UnboundedReceiver<Command<P>> {
-        let (sender, receiver) = unbounded_channel();
+    pub fn bind(&mut self) -> LaneReceivers<P> {
+        let (priority, priority_receiver) = unbounded_channel();
+        let (normal, normal_receiver) = unbounded_channel();
 
-        self.senders.push(sender);
+        self.senders.push(Lanes { priority, normal });
 
-        receiver
+        LaneReceivers {
+            priority: priority_receiver,
+            normal: normal_receiver,
+        }
     }
 
-    pub fn bind_client(&mut self, identifier: ClientIdentifier) -> Receiver<Reply<P::Reply>> {
+    pub fn bind_client(&mut self, identifier: ClientIdentifier) -> Receiver<ClientPayload<P>> {
         let (sender, receiver) = channel(1);
 
         self.clients.insert(identifier, sender);
@@ -170,7 +239,7 @@ where
         }
 
         if let Some(sender) = self.senders.get(index) {
-            if let Err(_) = sender.send(Command::Request(request.clone())) {
+            if sender.send(Command::Request(request.clone())).is_err() {
                 warn!("unable to send message to {index}")
             }
         }
@@ -182,7 +251,7 @@ where
         }
 
         for (index, sender) in self.senders.iter().enumerate() {
-            if let Err(_) = sender.send(Command::Request(request.clone())) {
+            if sender.send(Command::Request(request.clone())).is_err() {
                 warn!("unable to send message to {index}")
             }
         }
@@ -190,7 +259,7 @@ where
 
     pub async fn crash(&mut self, index: usize) {
         if let Some(sender) = self.senders.get(index) {
-            if let Err(_) = sender.send(Command::Crash) {
+            if sender.send(Command::Crash).is_err() {
                 warn!("unable to send message to {index}")
             }
         }
@@ -198,7 +267,7 @@ where
 
     pub async fn recover(&mut self, index: usize) {
         if let Some(sender) = self.senders.get(index) {
-            if let Err(_) = sender.send(Command::Recover) {
+            if sender.send(Command::Recover).is_err() {
                 warn!("unable to send message to {index}")
             }
         }
@@ -209,7 +278,7 @@ where
             for message in inbox.drain_inbound() {
                 trace!("Re-queuing {message:?} on replica {index}...");
 
-                if let Err(_) = sender.send(Command::Protocol(message)) {
+                if sender.send(Command::Protocol(message)).is_err() {
                     warn!("unable to send message to {index}")
                 }
             }
@@ -229,7 +298,7 @@ where
                     &message.destination
                 );
 
-                if let Err(_) = sender.send(message.payload).await {
+                if sender.send(message.payload).await.is_err() {
                     warn!("unable to send message to client {:?}", message.destination)
                 }
             }
@@ -247,7 +316,7 @@ where
                     &message.destination
                 );
 
-                if let Err(_) = sender.send(Command::Protocol(message.payload)) {
+                if sender.send(Command::Protocol(message.payload)).is_err() {
                     warn!("unable to send message to {:?}", message.destination)
                 }
             }
@@ -261,10 +330,8 @@ where
                     continue;
                 }
 
-                if source != index {
-                    if let Err(_) = sender.send(Command::Protocol(message.clone())) {
-                        warn!("unable to send message to {index}")
-                    }
+                if source != index && sender.send(Command::Protocol(message.clone())).is_err() {
+                    warn!("unable to send message to {index}")
                 }
             }
         }
@@ -281,7 +348,16 @@ async fn main() {
 
     let options = Options::parse();
     let start = Instant::now();
-    let configuration = Configuration::from(options.f * 2 + 1);
+    let mut configuration = Configuration::from(options.f * 2 + 1)
+        .with_commit_interval(Duration::from_millis(options.commit_timeout))
+        .with_view_timeout(
+            Duration::from_millis(options.view_timeout),
+            Duration::from_millis(options.view_jitter),
+        );
+
+    if let Some(limit) = options.in_flight_limit {
+        configuration = configuration.with_in_flight_limit(limit);
+    }
 
     let mut network = Network::<Adder>::new(configuration, options);
     let mut receivers = VecDeque::with_capacity(configuration.replicas());
@@ -296,7 +372,7 @@ async fn main() {
         options.clients
     );
 
-    let mut clients: Vec<(Client, Receiver<Reply<<Adder as Protocol>::Reply>>)> =
+    let mut clients: Vec<(Client, Receiver<ClientPayload<Adder>>)> =
         Vec::with_capacity(options.clients);
     for _ in 0..options.clients {
         let client = Client::new(configuration);
@@ -313,12 +389,14 @@ async fn main() {
             .pop_front()
             .expect("no receiver found for replica");
 
-        replica_tasks.spawn(run_replica(
-            options,
-            Replica::new(configuration, index, Default::default()),
-            receiver,
-            network.clone(),
-        ));
+        let replica = Replica::builder()
+            .configuration(configuration)
+            .index(index)
+            .service(Default::default())
+            .build()
+            .expect("invalid replica configuration");
+
+        replica_tasks.spawn(run_replica(options, replica, receiver, network.clone()));
     }
 
     for (client, receiver) in clients {
@@ -358,18 +436,12 @@ async fn main() {
 async fn run_replica(
     options: Options,
     mut replica: Replica<Adder>,
-    mut receiver: UnboundedReceiver<Command<Adder>>,
+    mut receiver: LaneReceivers<Adder>,
     mut network: Network<Adder>,
 ) {
     let mut mailbox = BufferedMailbox::default();
     let mut checkpoint = replica.checkpoint();
     let mut crashed = false;
-    let mut view = replica.view();
-    let mut timeout = if replica.is_primary() {
-        Duration::from_millis(options.commit_timeout)
-    } else {
-        Duration::from_millis(options.view_timeout)
-    };
 
     loop {
         if let Some(new_checkpoint) = replica.checkpoint_with_suffix(options.suffix) {
@@ -381,7 +453,7 @@ async fn run_replica(
             );
         }
 
-        match tokio::time::timeout(timeout, receiver.recv()).await {
+        match tokio::time::timeout(replica.idle_timeout(), receiver.recv()).await {
             Ok(None) => {
                 panic!("replica channel unexpected closed.")
             }
@@ -411,38 +483,7 @@ async fn run_replica(
 
                 trace!("Processing {message:?} on replica {}...", replica.index());
 
-                match message {
-                    ProtocolPayload::Prepare(message) => {
-                        replica.handle_prepare(message, &mut mailbox);
-                    }
-                    ProtocolPayload::PrepareOk(message) => {
-                        replica.handle_prepare_ok(message, &mut mailbox);
-                    }
-                    ProtocolPayload::Commit(message) => {
-                        replica.handle_commit(message, &mut mailbox);
-                    }
-                    ProtocolPayload::GetState(message) => {
-                        replica.handle_get_state(message, &mut mailbox);
-                    }
-                    ProtocolPayload::NewState(message) => {
-                        replica.handle_new_state(message, &mut mailbox);
-                    }
-                    ProtocolPayload::StartViewChange(message) => {
-                        replica.handle_start_view_change(message, &mut mailbox);
-                    }
-                    ProtocolPayload::DoViewChange(message) => {
-                        replica.handle_do_view_change(message, &mut mailbox);
-                    }
-                    ProtocolPayload::StartView(message) => {
-                        replica.handle_start_view(message, &mut mailbox);
-                    }
-                    ProtocolPayload::Recovery(message) => {
-                        replica.handle_recovery(message, &mut mailbox);
-                    }
-                    ProtocolPayload::RecoveryResponse(message) => {
-                        replica.handle_recovery_response(message, &mut mailbox);
-                    }
-                }
+                replica.handle_payload(message, &mut mailbox);
             }
             Err(_) => {
                 if !crashed {
@@ -459,23 +500,13 @@ async fn run_replica(
         network
             .process_outbound(replica.index(), &mut mailbox)
             .await;
-
-        let current_view = replica.view();
-        if view != current_view {
-            view = current_view;
-            timeout = if replica.is_primary() {
-                Duration::from_millis(options.commit_timeout)
-            } else {
-                Duration::from_millis(options.view_timeout)
-            };
-        }
     }
 }
 
 async fn run_client(
     options: Options,
     mut client: Client,
-    mut receiver: Receiver<Reply<<Adder as Protocol>::Reply>>,
+    mut receiver: Receiver<ClientPayload<Adder>>,
     mut network: Network<Adder>,
 ) -> usize {
     if options.requests_per_client == 0 {
@@ -496,7 +527,7 @@ async fn run_client(
 
     loop {
         match tokio::time::timeout(timeout, receiver.recv()).await {
-            Ok(Some(reply)) => {
+            Ok(Some(ClientPayload::Reply(reply))) => {
                 info!(
                             "Client {:?} received reply #{} for request {:?} with view {:?} and payload {} after {} microseconds.",
                             client.identifier(), replies, reply.id, reply.view, reply.payload, start.elapsed().as_micros()
@@ -513,6 +544,51 @@ async fn run_client(
 
                 network.send(primary, request.clone()).await;
             }
+            Ok(Some(ClientPayload::Busy(busy))) => {
+                let backoff = client.backoff(&busy);
+
+                trace!(
+                    "Client {:?} backing off for {backoff:?} for request {:?} in view {:?}.",
+                    client.identifier(),
+                    busy.id,
+                    busy.view
+                );
+
+                tokio::time::sleep(backoff).await;
+                primary = client.primary();
+                network.send(primary, request.clone()).await;
+            }
+            Ok(Some(ClientPayload::Expired(expired))) => {
+                panic!("request {:?} without a deadline expired", expired.id);
+            }
+            Ok(Some(ClientPayload::OutdatedView(outdated))) => {
+                if client.redirect(&outdated) {
+                    primary = client.primary();
+                    network.send(primary, request.clone()).await;
+                }
+            }
+            Ok(Some(ClientPayload::SessionAccepted(_))) => {
+                panic!("client {:?} did not open a session", client.identifier());
+            }
+            Ok(Some(ClientPayload::RequestStatus(_))) => {
+                panic!("client {:?} did not query a request", client.identifier());
+            }
+            Ok(Some(ClientPayload::Notification(_))) => {
+                panic!("client {:?} did not watch the service", client.identifier());
+            }
+            Ok(Some(ClientPayload::OutdatedEpoch(outdated))) => {
+                panic!(
+                    "client {:?} is behind epoch {}",
+                    client.identifier(),
+                    outdated.epoch
+                );
+            }
+            Ok(Some(ClientPayload::Discovered(_))) => {
+                panic!(
+                    "client {:?} did not discover the group",
+                    client.identifier()
+                );
+            }
             Ok(None) => {
                 panic!("client channel unexpected closed");
             }
@@ -17,9 +17,7 @@ impl From<<Self as Protocol>::Checkpoint> for Adder {
 }
 
 impl Service for Adder {
-    fn predict(&self, _: &<Self as Protocol>::Request) -> <Self as Protocol>::Prediction {
-        ()
-    }
+    fn predict(&self, _: &<Self as Protocol>::Request) -> <Self as Protocol>::Prediction {}
 
     fn checkpoint(&self) -> <Self as Protocol>::Checkpoint {
         self.0
@@ -69,11 +67,12 @@ fn main() {
     primary.handle_prepare_ok(prepare_ok2, &mut mailbox);
 
     let mut replies = Vec::from_iter(mailbox.drain_replies());
-    let reply = replies.pop().unwrap();
+    let envelope = replies.pop().unwrap();
+    let reply = envelope.payload.unwrap_reply();
 
     assert!(mailbox.is_empty());
-    assert_eq!(reply.destination, request.client);
-    assert_eq!(reply.payload.payload, delta);
-    assert_eq!(reply.payload.view, primary.view());
-    assert_eq!(reply.payload.id, request.id);
+    assert_eq!(envelope.destination, request.client);
+    assert_eq!(reply.payload, delta);
+    assert_eq!(reply.view, primary.view());
+    assert_eq!(reply.id, request.id);
 }
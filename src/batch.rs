@@ -0,0 +1,234 @@
+use crate::client::Client;
+use crate::request::{ClientIdentifier, Request};
+use crate::service::{Conflicts, Context, Protocol, Role, Rollback, Service};
+use crate::viewstamp::View;
+use alloc::vec;
+use alloc::vec::Vec;
+use serde::{Deserialize, Serialize};
+
+/// Runs a service on batches of operations, where each request is a sequence of operations
+/// applied in order and each reply holds the result of every operation in the batch.
+/// Lets clients amortize the cost of the protocol over many operations without changing the service.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Batched<S>(S);
+
+impl<S> Batched<S> {
+    pub fn new(service: S) -> Self {
+        Self(service)
+    }
+
+    pub fn service(&self) -> &S {
+        &self.0
+    }
+
+    pub fn into_inner(self) -> S {
+        self.0
+    }
+}
+
+/// The checkpoint of a [`Batched`] service, serialized the same way as the checkpoint of the wrapped service.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct BatchedCheckpoint<C>(pub C);
+
+impl<S> Protocol for Batched<S>
+where
+    S: Protocol,
+{
+    type Request = Vec<S::Request>;
+    type Prediction = Vec<S::Prediction>;
+    type Reply = Vec<S::Reply>;
+    type Checkpoint = BatchedCheckpoint<S::Checkpoint>;
+}
+
+impl<S> From<BatchedCheckpoint<S::Checkpoint>> for Batched<S>
+where
+    S: Service,
+{
+    fn from(checkpoint: BatchedCheckpoint<S::Checkpoint>) -> Self {
+        Self(S::from(checkpoint.0))
+    }
+}
+
+impl<S> Service for Batched<S>
+where
+    S: Service,
+{
+    fn predict(&self, request: &Vec<S::Request>) -> Vec<S::Prediction> {
+        request
+            .iter()
+            .map(|operation| self.0.predict(operation))
+            .collect()
+    }
+
+    fn checkpoint(&self) -> BatchedCheckpoint<S::Checkpoint> {
+        BatchedCheckpoint(self.0.checkpoint())
+    }
+
+    fn invoke(
+        &mut self,
+        request: &Vec<S::Request>,
+        prediction: &Vec<S::Prediction>,
+    ) -> Vec<S::Reply> {
+        request
+            .iter()
+            .zip(prediction)
+            .map(|(operation, prediction)| self.0.invoke(operation, prediction))
+            .collect()
+    }
+
+    fn invoke_with_context(
+        &mut self,
+        request: &Vec<S::Request>,
+        prediction: &Vec<S::Prediction>,
+        context: &Context,
+    ) -> Vec<S::Reply> {
+        request
+            .iter()
+            .zip(prediction)
+            .map(|(operation, prediction)| {
+                self.0.invoke_with_context(operation, prediction, context)
+            })
+            .collect()
+    }
+
+    fn on_role_change(&mut self, role: Role, view: View) {
+        self.0.on_role_change(role, view);
+    }
+
+    fn take_notifications(&mut self) -> Vec<(ClientIdentifier, Vec<S::Reply>)> {
+        self.0
+            .take_notifications()
+            .into_iter()
+            .map(|(client, payload)| (client, vec![payload]))
+            .collect()
+    }
+}
+
+impl<S> Conflicts for Batched<S>
+where
+    S: Conflicts,
+{
+    fn conflicts(&self, a: &Vec<S::Request>, b: &Vec<S::Request>) -> bool {
+        a.iter().any(|a| b.iter().any(|b| self.0.conflicts(a, b)))
+    }
+
+    fn invoke_concurrently(
+        &self,
+        request: &Vec<S::Request>,
+        prediction: &Vec<S::Prediction>,
+    ) -> Vec<S::Reply> {
+        request
+            .iter()
+            .zip(prediction)
+            .map(|(operation, prediction)| self.0.invoke_concurrently(operation, prediction))
+            .collect()
+    }
+}
+
+impl<S> Rollback for Batched<S>
+where
+    S: Rollback,
+{
+    type Undo = Vec<S::Undo>;
+
+    fn invoke_speculatively(
+        &mut self,
+        request: &Vec<S::Request>,
+        prediction: &Vec<S::Prediction>,
+    ) -> (Vec<S::Reply>, Vec<S::Undo>) {
+        request
+            .iter()
+            .zip(prediction)
+            .map(|(operation, prediction)| self.0.invoke_speculatively(operation, prediction))
+            .unzip()
+    }
+
+    fn rollback(&mut self, undo: Vec<S::Undo>) {
+        for undo in undo.into_iter().rev() {
+            self.0.rollback(undo);
+        }
+    }
+}
+
+/// Groups operations into a single request for a [`Batched`] service.
+pub struct BatchBuilder<'a, P> {
+    client: &'a mut Client,
+    operations: Vec<P>,
+}
+
+impl<'a, P> BatchBuilder<'a, P> {
+    pub(crate) fn new(client: &'a mut Client) -> Self {
+        Self {
+            client,
+            operations: Vec::new(),
+        }
+    }
+
+    /// Adds an operation to the end of the batch.
+    pub fn push(mut self, operation: P) -> Self {
+        self.operations.push(operation);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.operations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+
+    /// Creates a single request for all the operations in the batch.
+    pub fn build(self) -> Request<Vec<P>> {
+        self.client.new_request(self.operations)
+    }
+}
+
+#[cfg(all(test, feature = "os"))]
+mod tests {
+    use super::*;
+    use crate::buffer::BufferedMailbox;
+    use crate::protocol::PrepareOk;
+    use crate::{Configuration, Replica};
+
+    #[test]
+    fn batch() {
+        let configuration = Configuration::from(3);
+        let mut replica = Replica::new(configuration, 0, Batched::new(0));
+        let mut mailbox = BufferedMailbox::default();
+        let mut client = Client::new(configuration);
+        let batch = client.new_batch().push(1).push(2).push(-4);
+
+        assert_eq!(batch.len(), 3);
+
+        let request = batch.build();
+
+        replica.handle_request(request.clone(), &mut mailbox);
+
+        let prepare = mailbox.drain_broadcast().next().unwrap().unwrap_prepare();
+
+        replica.handle_prepare_ok(
+            PrepareOk {
+                view: replica.view(),
+                epoch: 0,
+                op_number: prepare.op_number,
+                index: 1,
+                committed: Default::default(),
+                promised: replica.view(),
+            },
+            &mut mailbox,
+        );
+
+        let reply = mailbox
+            .drain_replies()
+            .next()
+            .unwrap()
+            .payload
+            .unwrap_reply();
+
+        assert_eq!(reply.id, request.id);
+        assert_eq!(reply.payload, vec![1, 3, -1]);
+        assert_eq!(replica.checkpoint().state, BatchedCheckpoint(-1));
+    }
+}
@@ -0,0 +1,616 @@
+//! Launches replicas from a configuration file, replacing the glue code that creates the configuration,
+//! transport and storage of every replica.
+//!
+//! [`serve`] runs one replica of a group deployed over a network as its own process,
+//! while [`run`] starts every replica of a group in the current process for local testing.
+
+use crate::builder::{validate, BuildError};
+use crate::configuration::{Configuration, Group};
+use crate::driver::ClusterRunner;
+use crate::members::{Members, MembersError, ReplicaEndpoint};
+use crate::service::Service;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+#[cfg(feature = "http")]
+use {
+    crate::buffer::BufferedMailbox,
+    crate::durable::{unseal, FileState, Remedy, State},
+    crate::gateway::http::{Gateway, HttpTransport, JsonPayload},
+    crate::replica::Replica,
+    std::future::IntoFuture,
+    std::net::SocketAddr,
+    tokio::task::AbortHandle,
+};
+
+/// The members and tunables of a group as read from a JSON configuration file.
+/// Missing tunables keep the defaults of [`Configuration`].
+///
+/// ```json
+/// { "replicas": 3, "commit_interval_ms": 10, "view_timeout_ms": 100, "view_jitter_ms": 50 }
+/// ```
+///
+/// Groups deployed over a network list the endpoint of each replica, where the listen address
+/// may differ from the address others connect to (e.g. behind NAT or in a container):
+///
+/// ```json
+/// { "replicas": 1, "members": [{ "id": 0, "listen": "0.0.0.0:3000", "advertise": "203.0.113.7:3000", "tls": "vr-0.example.com" }] }
+/// ```
+///
+/// Replicas started with [`serve`] keep their checkpoints in the `storage` directory, if one is given.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ClusterConfig {
+    /// The number of replicas in the group.
+    pub replicas: usize,
+    /// The number of observers following the replicas without voting.
+    #[serde(default)]
+    pub observers: Option<usize>,
+    /// The identifier of the group. When missing, it is derived from the members if they are listed,
+    /// and generated at random otherwise.
+    #[serde(default)]
+    pub group: Option<u128>,
+    /// The network endpoint of every replica in the group.
+    #[serde(default)]
+    pub members: Option<Vec<ReplicaEndpoint>>,
+    /// The number of times operators replaced the members of the group, handed out to clients discovering the group.
+    #[serde(default)]
+    pub epoch: Option<u64>,
+    /// The directory where each replica keeps its latest checkpoint, in a file named after its index
+    /// (e.g. `replica-0.checkpoint`). Without it, a restarted replica recovers from an empty checkpoint.
+    #[serde(default)]
+    pub storage: Option<PathBuf>,
+    /// How long the gateway of a replica waits for the reply to a client's request.
+    #[serde(default)]
+    pub reply_timeout_ms: Option<u64>,
+    #[serde(default)]
+    pub commit_interval_ms: Option<u64>,
+    /// The view timeout of backups. Requires `view_jitter_ms` to be set as well.
+    #[serde(default)]
+    pub view_timeout_ms: Option<u64>,
+    #[serde(default)]
+    pub view_jitter_ms: Option<u64>,
+    #[serde(default)]
+    pub in_flight_limit: Option<usize>,
+    #[serde(default)]
+    pub queue_limit: Option<usize>,
+    #[serde(default)]
+    pub state_transfer_limit: Option<usize>,
+    #[serde(default)]
+    pub lease_ms: Option<u64>,
+    #[serde(default)]
+    pub view_change_backoff_ms: Option<u64>,
+    #[serde(default)]
+    pub view_change_timeout_ms: Option<u64>,
+    #[serde(default)]
+    pub recovery_backoff_ms: Option<u64>,
+    #[serde(default)]
+    pub recovery_attempts: Option<u32>,
+}
+
+impl ClusterConfig {
+    pub fn from_json(json: &str) -> Result<Self, BootstrapError> {
+        serde_json::from_str(json).map_err(|error| BootstrapError::Parse(error.to_string()))
+    }
+
+    /// Reads the configuration from a JSON file.
+    pub fn load<P>(path: P) -> Result<Self, BootstrapError>
+    where
+        P: AsRef<Path>,
+    {
+        let json = std::fs::read_to_string(path)
+            .map_err(|error| BootstrapError::Read(error.to_string()))?;
+
+        Self::from_json(&json)
+    }
+
+    /// The validated endpoints of the replicas, if the configuration lists them.
+    pub fn members(&self) -> Result<Option<Members>, BootstrapError> {
+        let Some(endpoints) = self.members.clone() else {
+            return Ok(None);
+        };
+
+        if endpoints.len() != self.replicas {
+            return Err(BootstrapError::MemberCount {
+                replicas: self.replicas,
+                members: endpoints.len(),
+            });
+        }
+
+        Members::try_from(endpoints)
+            .map(Some)
+            .map_err(BootstrapError::Members)
+    }
+
+    /// The file where the replica with the given index keeps its checkpoints, if the configuration has a storage directory.
+    pub fn checkpoint_path(&self, index: usize) -> Option<PathBuf> {
+        self.storage
+            .as_ref()
+            .map(|directory| directory.join(format!("replica-{index}.checkpoint")))
+    }
+
+    /// The validated configuration of the group.
+    pub fn configuration(&self) -> Result<Configuration, BootstrapError> {
+        let members = self.members()?;
+        let group = match (self.group, members) {
+            (Some(group), _) => Group::from(group),
+            (None, Some(members)) => members.group(),
+            (None, None) => Group::default(),
+        };
+        let mut configuration = Configuration::new(self.replicas, group);
+
+        if let Some(epoch) = self.epoch {
+            configuration = configuration.with_epoch(epoch);
+        }
+
+        if let Some(observers) = self.observers {
+            configuration = configuration.with_observers(observers);
+        }
+
+        if let Some(interval) = self.commit_interval_ms {
+            configuration = configuration.with_commit_interval(Duration::from_millis(interval));
+        }
+
+        match (self.view_timeout_ms, self.view_jitter_ms) {
+            (Some(timeout), Some(jitter)) => {
+                configuration = configuration.with_view_timeout(
+                    Duration::from_millis(timeout),
+                    Duration::from_millis(jitter),
+                );
+            }
+            (None, None) => {}
+            _ => return Err(BootstrapError::IncompleteViewTimeout),
+        }
+
+        if let Some(limit) = self.in_flight_limit {
+            configuration = configuration.with_in_flight_limit(limit);
+        }
+
+        if let Some(limit) = self.queue_limit {
+            configuration = configuration.with_queue_limit(limit);
+        }
+
+        if let Some(limit) = self.state_transfer_limit {
+            configuration = configuration.with_state_transfer_limit(limit);
+        }
+
+        if let Some(lease) = self.lease_ms {
+            configuration = configuration.with_lease(Duration::from_millis(lease));
+        }
+
+        if let Some(limit) = self.view_change_backoff_ms {
+            configuration = configuration.with_view_change_backoff(Duration::from_millis(limit));
+        }
+
+        if let Some(timeout) = self.view_change_timeout_ms {
+            configuration = configuration.with_view_change_timeout(Duration::from_millis(timeout));
+        }
+
+        if let Some(limit) = self.recovery_backoff_ms {
+            configuration = configuration.with_recovery_backoff(Duration::from_millis(limit));
+        }
+
+        if let Some(attempts) = self.recovery_attempts {
+            configuration = configuration.with_recovery_attempts(attempts);
+        }
+
+        validate(configuration, 0).map_err(BootstrapError::Invalid)?;
+
+        Ok(configuration)
+    }
+}
+
+/// Starts every replica of the group described by the configuration on its own thread of the current process,
+/// where each replica's service is created by the given factory.
+/// The replicas exchange messages over in-memory channels and ignore the listed members and storage,
+/// which makes this suited to local testing; use [`serve`] to deploy a group over a network.
+pub fn run<S, F>(config: &ClusterConfig, factory: F) -> Result<ClusterRunner<S>, BootstrapError>
+where
+    S: Service + Send + 'static,
+    S::Request: Send + Sync + 'static,
+    S::Prediction: Send + Sync + 'static,
+    S::Reply: Send + Sync + 'static,
+    S::Checkpoint: Send + Sync + 'static,
+    F: FnMut(usize) -> S,
+{
+    Ok(ClusterRunner::start(config.configuration()?, factory))
+}
+
+/// How long a gateway waits for a reply when the configuration does not say.
+#[cfg(feature = "http")]
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Runs the replica with the given index as one process of a group deployed over a network.
+///
+/// The replica's HTTP gateway binds to its listen address, while protocol messages go to the advertised addresses
+/// of the other members, which clients discovering the group through the gateway learn as well. When the configuration has a storage directory, the replica saves its checkpoints there
+/// and, once restarted, recovers from the latest one with the recovery protocol. Connections do not use TLS,
+/// so endpoints that require it must be fronted by a proxy terminating TLS at the advertised address.
+///
+/// Must be called from within a tokio runtime, which runs the replica until the returned server shuts down.
+#[cfg(feature = "http")]
+pub async fn serve<S>(
+    config: &ClusterConfig,
+    index: usize,
+    service: S,
+) -> Result<Server<S>, BootstrapError>
+where
+    S: Service + Send + 'static,
+    S::Request: JsonPayload,
+    S::Prediction: JsonPayload,
+    S::Reply: JsonPayload,
+    S::Checkpoint: JsonPayload,
+{
+    let configuration = config.configuration()?;
+    let members = config.members()?.ok_or(BootstrapError::NoMembers)?;
+    let endpoint = members
+        .endpoints()
+        .get(index)
+        .ok_or(BootstrapError::UnknownReplica(index))?;
+    let storage = config.checkpoint_path(index).map(FileState::new);
+    let saved = match &storage {
+        Some(storage) => storage
+            .load()
+            .map_err(|error| BootstrapError::Storage(error.to_string()))?,
+        None => None,
+    };
+
+    let mut mailbox = BufferedMailbox::default();
+    let mut replica = match saved {
+        None => Replica::new(configuration, index, service),
+        Some(bytes) => {
+            let checkpoint = match unseal(&bytes) {
+                Ok(checkpoint) => checkpoint,
+                Err(error) if error.remedy() == Remedy::RecoverAsNew => {
+                    Replica::new(configuration, index, service).checkpoint()
+                }
+                Err(error) => return Err(BootstrapError::Storage(error.to_string())),
+            };
+
+            Replica::recovering(configuration, index, checkpoint, &mut mailbox)
+        }
+    };
+
+    replica.advertise_members(&members);
+
+    let listener = tokio::net::TcpListener::bind(endpoint.listen)
+        .await
+        .map_err(|error| BootstrapError::Bind(error.to_string()))?;
+    let address = listener
+        .local_addr()
+        .map_err(|error| BootstrapError::Bind(error.to_string()))?;
+    let idle_timeout = replica.idle_timeout();
+    let reply_timeout = config
+        .reply_timeout_ms
+        .map_or(REPLY_TIMEOUT, Duration::from_millis);
+    let (gateway, mut event_loop) = Gateway::with_mailbox(replica, mailbox, reply_timeout);
+
+    if let Some(storage) = storage {
+        event_loop = event_loop.with_storage(storage);
+    }
+
+    let transport = HttpTransport::new(&members, index);
+    let serving = tokio::spawn(axum::serve(listener, gateway.router()).into_future());
+    let running = tokio::spawn(event_loop.run(transport, idle_timeout));
+
+    Ok(Server {
+        address,
+        gateway,
+        tasks: vec![serving.abort_handle(), running.abort_handle()],
+    })
+}
+
+/// A replica started by [`serve`], which runs until it is shut down or the runtime stops.
+#[cfg(feature = "http")]
+pub struct Server<S>
+where
+    S: Service,
+{
+    address: SocketAddr,
+    gateway: Gateway<S>,
+    tasks: Vec<AbortHandle>,
+}
+
+#[cfg(feature = "http")]
+impl<S> Server<S>
+where
+    S: Service,
+{
+    /// The address the gateway is bound to, which tells the port picked when listening on port 0.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.address
+    }
+
+    pub fn gateway(&self) -> &Gateway<S> {
+        &self.gateway
+    }
+
+    /// Stops serving requests and processing messages, as if the process crashed.
+    pub fn shutdown(self) {
+        for task in self.tasks {
+            task.abort();
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum BootstrapError {
+    /// The configuration file could not be read.
+    Read(String),
+    /// The configuration is not valid JSON or has unknown fields.
+    Parse(String),
+    /// Only one of the view timeout and its jitter was configured.
+    IncompleteViewTimeout,
+    /// The configuration would create an invalid group.
+    Invalid(BuildError),
+    /// The number of listed members differs from the number of replicas.
+    MemberCount { replicas: usize, members: usize },
+    /// The listed members do not describe every replica exactly once.
+    Members(MembersError),
+    /// The configuration lists no members to serve a replica for.
+    NoMembers,
+    /// The configuration lists no member with the given index.
+    UnknownReplica(usize),
+    /// The checkpoint of the replica could not be loaded.
+    Storage(String),
+    /// The gateway could not bind to the listen address of the replica.
+    Bind(String),
+}
+
+impl Display for BootstrapError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BootstrapError::Read(error) => write!(f, "unable to read the configuration: {error}"),
+            BootstrapError::Parse(error) => write!(f, "unable to parse the configuration: {error}"),
+            BootstrapError::IncompleteViewTimeout => write!(
+                f,
+                "the view timeout and its jitter must be configured together"
+            ),
+            BootstrapError::Invalid(error) => write!(f, "invalid configuration: {error}"),
+            BootstrapError::MemberCount { replicas, members } => write!(
+                f,
+                "the configuration lists {members} members for {replicas} replicas"
+            ),
+            BootstrapError::Members(error) => write!(f, "invalid members: {error}"),
+            BootstrapError::NoMembers => write!(f, "the configuration lists no members"),
+            BootstrapError::UnknownReplica(index) => {
+                write!(f, "the configuration lists no member {index}")
+            }
+            BootstrapError::Storage(error) => write!(f, "unable to load the checkpoint: {error}"),
+            BootstrapError::Bind(error) => write!(f, "unable to bind the gateway: {error}"),
+        }
+    }
+}
+
+impl Error for BootstrapError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn configuration() {
+        let config = ClusterConfig::from_json(
+            r#"{ "replicas": 3, "observers": 1, "group": 7, "epoch": 2, "commit_interval_ms": 10, "view_timeout_ms": 100, "view_jitter_ms": 0, "in_flight_limit": 5 }"#,
+        )
+        .unwrap();
+        let configuration = config.configuration().unwrap();
+
+        assert_eq!(configuration.replicas(), 3);
+        assert_eq!(configuration.members(), 4);
+        assert_eq!(configuration.group(), Group::from(7));
+        assert_eq!(configuration.epoch(), 2);
+        assert_eq!(configuration.in_flight_limit(), Some(5));
+        assert_eq!(
+            configuration.idle_timeout(false),
+            Duration::from_millis(100)
+        );
+    }
+
+    #[test]
+    fn members() {
+        let config = ClusterConfig::from_json(
+            r#"{ "replicas": 3, "members": [
+                { "id": 2, "listen": "0.0.0.0:3002", "advertise": "203.0.113.7:3002" },
+                { "id": 0, "listen": "0.0.0.0:3000", "advertise": "203.0.113.7:3000", "tls": "vr-0.example.com" },
+                { "id": 1, "listen": "0.0.0.0:3001", "advertise": "203.0.113.7:3001" }
+            ] }"#,
+        )
+        .unwrap();
+        let members = config.members().unwrap().unwrap();
+        let replica = members.configuration().replica(0).unwrap();
+
+        assert_eq!(config.configuration().unwrap().group(), members.group());
+        assert_eq!(
+            members.resolve(replica),
+            Some("203.0.113.7:3000".parse().unwrap())
+        );
+        assert_eq!(
+            members.listen(replica),
+            Some("0.0.0.0:3000".parse().unwrap())
+        );
+        assert_eq!(
+            ClusterConfig::from_json(
+                r#"{ "replicas": 3, "members": [{ "id": 0, "listen": "0.0.0.0:3000", "advertise": "203.0.113.7:3000" }] }"#
+            )
+            .unwrap()
+            .configuration(),
+            Err(BootstrapError::MemberCount {
+                replicas: 3,
+                members: 1
+            })
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(matches!(
+            ClusterConfig::from_json(r#"{ "replicas": 3, "ports": [1] }"#),
+            Err(BootstrapError::Parse(_))
+        ));
+        assert_eq!(
+            ClusterConfig::from_json(r#"{ "replicas": 4 }"#)
+                .unwrap()
+                .configuration(),
+            Err(BootstrapError::Invalid(BuildError::EvenReplicas(4)))
+        );
+        assert_eq!(
+            ClusterConfig::from_json(r#"{ "replicas": 3, "view_timeout_ms": 100 }"#)
+                .unwrap()
+                .configuration(),
+            Err(BootstrapError::IncompleteViewTimeout)
+        );
+    }
+
+    #[test]
+    fn run() {
+        let config = ClusterConfig::from_json(
+            r#"{ "replicas": 3, "commit_interval_ms": 10, "view_timeout_ms": 100, "view_jitter_ms": 50 }"#,
+        )
+        .unwrap();
+        let runner = super::run(&config, |_| 0).unwrap();
+        let mut client = runner.client();
+
+        assert_eq!(
+            client.invoke(5, Duration::from_millis(200), Duration::from_secs(5)),
+            Some(5)
+        );
+
+        runner.shutdown();
+    }
+
+    /// Serves a group of 3 replicas on free local ports, where each replica listens on every interface
+    /// and advertises the loopback address.
+    #[cfg(feature = "http")]
+    async fn serve_group(storage: Option<&Path>) -> (ClusterConfig, Vec<Server<i32>>) {
+        let listeners: Vec<_> = (0..3)
+            .map(|_| std::net::TcpListener::bind("127.0.0.1:0").unwrap())
+            .collect();
+        let members: Vec<_> = listeners
+            .iter()
+            .enumerate()
+            .map(|(id, listener)| {
+                let advertise = listener.local_addr().unwrap();
+
+                ReplicaEndpoint::new(id, advertise)
+                    .with_listen(([0, 0, 0, 0], advertise.port()).into())
+            })
+            .collect();
+        drop(listeners);
+
+        let config: ClusterConfig = serde_json::from_value(serde_json::json!({
+            "replicas": 3,
+            "commit_interval_ms": 10,
+            "view_timeout_ms": 1000,
+            "view_jitter_ms": 100,
+            "members": members,
+            "storage": storage,
+        }))
+        .unwrap();
+        let mut servers = Vec::new();
+
+        for index in 0..3 {
+            servers.push(super::serve(&config, index, 0).await.unwrap());
+        }
+
+        (config, servers)
+    }
+
+    #[cfg(feature = "http")]
+    #[tokio::test]
+    async fn serve() {
+        use crate::buffer::ClientPayload;
+        use crate::gateway::http::post_json;
+        use crate::protocol::Checkpoint;
+        use crate::Client;
+
+        let storage = std::env::temp_dir().join(format!("vr-serve-{}", std::process::id()));
+        std::fs::create_dir_all(&storage).unwrap();
+
+        let (config, servers) = serve_group(Some(&storage)).await;
+        let members = config.members().unwrap().unwrap();
+        let mut client = Client::new(config.configuration().unwrap());
+        let request = client.new_request(5);
+        let (status, body) = post_json(
+            members.endpoints()[0].advertise,
+            "/request",
+            &serde_json::to_vec(&request).unwrap(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(servers[0].local_addr(), members.endpoints()[0].listen);
+        assert_eq!(status, axum::http::StatusCode::OK);
+
+        let reply: ClientPayload<i32> = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(reply.unwrap_reply().payload, 5);
+
+        let path = config.checkpoint_path(0).unwrap();
+        let mut saved = None;
+
+        for _ in 0..100 {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+
+            if let Ok(bytes) = std::fs::read(&path) {
+                saved = unseal::<Checkpoint<i32, i32>>(&bytes).ok();
+                break;
+            }
+        }
+
+        servers.into_iter().for_each(Server::shutdown);
+        std::fs::remove_dir_all(storage).unwrap();
+
+        assert_eq!(saved.map(|checkpoint| checkpoint.state), Some(5));
+    }
+
+    #[cfg(feature = "http")]
+    #[tokio::test]
+    async fn discover() {
+        use crate::buffer::ClientPayload;
+        use crate::gateway::http::post_json;
+        use crate::request::{ClientIdentifier, Discover};
+        use crate::Client;
+
+        let (config, servers) = serve_group(None).await;
+        let members = config.members().unwrap().unwrap();
+        let client = ClientIdentifier::default();
+
+        // The client only knows the address of a single replica ahead of time.
+        let seed = members.endpoints()[2].advertise;
+        let (_, body) = post_json(
+            seed,
+            "/discover",
+            &serde_json::to_vec(&Discover { client }).unwrap(),
+        )
+        .await
+        .unwrap();
+        let discovered = serde_json::from_slice::<ClientPayload<i32>>(&body)
+            .unwrap()
+            .unwrap_discovered();
+        let mut client = Client::new(discovered.configuration()).with_identifier(client);
+
+        client.rediscover(&discovered);
+
+        let primary = client.address(client.primary()).unwrap();
+        let request = client.new_request(7);
+        let (status, body) = post_json(primary, "/request", &serde_json::to_vec(&request).unwrap())
+            .await
+            .unwrap();
+
+        servers.into_iter().for_each(Server::shutdown);
+
+        assert_eq!(primary, members.endpoints()[0].advertise);
+        assert_ne!(primary, members.endpoints()[0].listen);
+        assert_eq!(status, axum::http::StatusCode::OK);
+        assert_eq!(
+            serde_json::from_slice::<ClientPayload<i32>>(&body)
+                .unwrap()
+                .unwrap_reply()
+                .payload,
+            7
+        );
+    }
+}
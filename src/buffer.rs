@@ -1,20 +1,36 @@
+use crate::chunk::{ChunkError, Reassembler};
+use crate::configuration::Configuration;
+use crate::log::{Log, LogDigest};
 use crate::mail::{Inbox, Mailbox, Outbox};
 use crate::protocol::{
-    Commit, DoViewChange, GetState, NewState, Prepare, PrepareOk, Recovery, RecoveryResponse,
-    StartView, StartViewChange,
+    Commit, Digest, DoViewChange, GetLog, GetState, InstallSnapshot, NewLog, NewState, Prepare,
+    PrepareOk, Recovery, RecoveryResponse, StartView, StartViewChange,
 };
-use crate::request::{ClientIdentifier, Reply};
-use crate::service::Protocol;
-use std::collections::VecDeque;
-use std::fmt::{Debug, Formatter};
-use std::iter::FusedIterator;
+use crate::request::{
+    Busy, ClientIdentifier, Discovered, Expired, Notification, OutdatedEpoch, OutdatedView, Reply,
+    RequestStatus, SessionAccepted,
+};
+use crate::service::{Protocol, Serializable};
+use crate::viewstamp::{OpNumber, View};
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::fmt::{Debug, Formatter};
+use core::iter::FusedIterator;
+use core::mem::{self, Discriminant};
+use hashbrown::HashMap;
+use serde::{Deserialize, Serialize};
 
 pub struct Envelope<D, P> {
     pub destination: D,
     pub payload: P,
 }
 
-#[derive(Eq, PartialEq)]
+#[derive(Eq, PartialEq, Serialize, Deserialize)]
+#[serde(bound(
+    serialize = "P::Request: Serialize, P::Prediction: Serialize, P::Reply: Serialize, P::Checkpoint: Serialize",
+    deserialize = "P::Request: Deserialize<'de>, P::Prediction: Deserialize<'de>, P::Reply: Deserialize<'de>, P::Checkpoint: Deserialize<'de>"
+))]
 pub enum ProtocolPayload<P>
 where
     P: Protocol,
@@ -24,11 +40,15 @@ where
     Commit(Commit),
     GetState(GetState),
     NewState(NewState<P::Request, P::Prediction>),
+    InstallSnapshot(InstallSnapshot<P::Checkpoint, P::Reply, P::Request, P::Prediction>),
     StartViewChange(StartViewChange),
     DoViewChange(DoViewChange<P::Request, P::Prediction>),
+    GetLog(GetLog),
+    NewLog(NewLog<P::Request, P::Prediction>),
     StartView(StartView<P::Request, P::Prediction>),
     Recovery(Recovery),
     RecoveryResponse(RecoveryResponse<P::Request, P::Prediction>),
+    Digest(Digest),
 }
 
 impl<P> Clone for ProtocolPayload<P>
@@ -42,33 +62,43 @@ where
             ProtocolPayload::Commit(message) => Self::Commit(message.clone()),
             ProtocolPayload::GetState(message) => Self::GetState(message.clone()),
             ProtocolPayload::NewState(message) => Self::NewState(message.clone()),
+            ProtocolPayload::InstallSnapshot(message) => Self::InstallSnapshot(message.clone()),
             ProtocolPayload::StartViewChange(message) => Self::StartViewChange(message.clone()),
             ProtocolPayload::DoViewChange(message) => Self::DoViewChange(message.clone()),
+            ProtocolPayload::GetLog(message) => Self::GetLog(message.clone()),
+            ProtocolPayload::NewLog(message) => Self::NewLog(message.clone()),
             ProtocolPayload::StartView(message) => Self::StartView(message.clone()),
             ProtocolPayload::Recovery(message) => Self::Recovery(message.clone()),
             ProtocolPayload::RecoveryResponse(message) => Self::RecoveryResponse(message.clone()),
+            ProtocolPayload::Digest(message) => Self::Digest(message.clone()),
         }
     }
 }
 
-impl<P, Req, Pre> Debug for ProtocolPayload<P>
+impl<P, Req, Pre, Rep, Chk> Debug for ProtocolPayload<P>
 where
-    P: Protocol<Request = Req, Prediction = Pre>,
+    P: Protocol<Request = Req, Prediction = Pre, Reply = Rep, Checkpoint = Chk>,
     Req: Debug,
     Pre: Debug,
+    Rep: Debug,
+    Chk: Debug,
 {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         match self {
             ProtocolPayload::Prepare(message) => write!(f, "{message:?}"),
             ProtocolPayload::PrepareOk(message) => write!(f, "{message:?}"),
             ProtocolPayload::Commit(message) => write!(f, "{message:?}"),
             ProtocolPayload::GetState(message) => write!(f, "{message:?}"),
             ProtocolPayload::NewState(message) => write!(f, "{message:?}"),
+            ProtocolPayload::InstallSnapshot(message) => write!(f, "{message:?}"),
             ProtocolPayload::StartViewChange(message) => write!(f, "{message:?}"),
             ProtocolPayload::DoViewChange(message) => write!(f, "{message:?}"),
+            ProtocolPayload::GetLog(message) => write!(f, "{message:?}"),
+            ProtocolPayload::NewLog(message) => write!(f, "{message:?}"),
             ProtocolPayload::StartView(message) => write!(f, "{message:?}"),
             ProtocolPayload::Recovery(message) => write!(f, "{message:?}"),
             ProtocolPayload::RecoveryResponse(message) => write!(f, "{message:?}"),
+            ProtocolPayload::Digest(message) => write!(f, "{message:?}"),
         }
     }
 }
@@ -77,6 +107,180 @@ impl<P> ProtocolPayload<P>
 where
     P: Protocol,
 {
+    /// Whether the message belongs to a protocol (view change, recovery or state transfer)
+    /// that must make progress before the replica can process normal operations again.
+    pub fn is_priority(&self) -> bool {
+        !matches!(
+            self,
+            ProtocolPayload::Prepare(_)
+                | ProtocolPayload::PrepareOk(_)
+                | ProtocolPayload::Commit(_)
+                | ProtocolPayload::Digest(_)
+        )
+    }
+
+    /// The view and index of the replica that sent the message, when the message identifies its sender.
+    /// Messages only the primary sends (e.g. prepares) are attributed to the primary of their view.
+    pub fn origin(&self, configuration: Configuration) -> Option<(View, usize)> {
+        match self {
+            ProtocolPayload::Prepare(message) => Some((message.view, configuration % message.view)),
+            ProtocolPayload::PrepareOk(message) => Some((message.view, message.index)),
+            ProtocolPayload::Commit(message) => Some((message.view, configuration % message.view)),
+            ProtocolPayload::GetState(message) => Some((message.view, message.index)),
+            ProtocolPayload::StartViewChange(message) => Some((message.view, message.index)),
+            ProtocolPayload::DoViewChange(message) => Some((message.view, message.index)),
+            ProtocolPayload::GetLog(message) => Some((message.view, message.index)),
+            ProtocolPayload::NewLog(message) => Some((message.view, message.index)),
+            ProtocolPayload::StartView(message) => {
+                Some((message.view, configuration % message.view))
+            }
+            ProtocolPayload::RecoveryResponse(message) => Some((message.view, message.index)),
+            ProtocolPayload::Digest(message) => Some((message.view, message.index)),
+            ProtocolPayload::NewState(_)
+            | ProtocolPayload::InstallSnapshot(_)
+            | ProtocolPayload::Recovery(_) => None,
+        }
+    }
+
+    /// The epoch of the configuration of the replica that sent the message.
+    pub fn epoch(&self) -> u64 {
+        match self {
+            ProtocolPayload::Prepare(message) => message.epoch,
+            ProtocolPayload::PrepareOk(message) => message.epoch,
+            ProtocolPayload::Commit(message) => message.epoch,
+            ProtocolPayload::GetState(message) => message.epoch,
+            ProtocolPayload::NewState(message) => message.epoch,
+            ProtocolPayload::InstallSnapshot(message) => message.epoch,
+            ProtocolPayload::StartViewChange(message) => message.epoch,
+            ProtocolPayload::DoViewChange(message) => message.epoch,
+            ProtocolPayload::GetLog(message) => message.epoch,
+            ProtocolPayload::NewLog(message) => message.epoch,
+            ProtocolPayload::StartView(message) => message.epoch,
+            ProtocolPayload::Recovery(message) => message.epoch,
+            ProtocolPayload::RecoveryResponse(message) => message.epoch,
+            ProtocolPayload::Digest(message) => message.epoch,
+        }
+    }
+
+    /// The highest view and op-number the message refers to, including the last op-number of any log it carries.
+    pub(crate) fn reach(&self) -> (View, OpNumber) {
+        match self {
+            ProtocolPayload::Prepare(message) => (
+                message.view.max(message.previous.unwrap_or_default()),
+                message.op_number.max(message.committed),
+            ),
+            ProtocolPayload::PrepareOk(message) => (
+                message.view.max(message.promised),
+                message.op_number.max(message.committed),
+            ),
+            ProtocolPayload::Commit(message) => (message.view, message.committed),
+            ProtocolPayload::GetState(message) => (message.view, message.op_number),
+            ProtocolPayload::NewState(message) => (
+                message.view,
+                message
+                    .op_number
+                    .max(message.committed)
+                    .max(message.log.last_op_number()),
+            ),
+            ProtocolPayload::InstallSnapshot(message) => (
+                message.view,
+                message
+                    .op_number
+                    .max(message.checkpoint.committed)
+                    .max(message.log.last_op_number()),
+            ),
+            ProtocolPayload::StartViewChange(message) => (message.view, OpNumber::default()),
+            ProtocolPayload::DoViewChange(message) => (
+                message.view,
+                message.committed.max(message.log.last_op_number()),
+            ),
+            ProtocolPayload::GetLog(message) => (message.view, message.op_number),
+            ProtocolPayload::NewLog(message) => (message.view, message.log.last_op_number()),
+            ProtocolPayload::StartView(message) => (
+                message.view,
+                message.committed.max(message.log.last_op_number()),
+            ),
+            ProtocolPayload::Recovery(message) => (
+                View::default(),
+                message.committed.max(
+                    message
+                        .digest
+                        .as_ref()
+                        .map(LogDigest::last_op_number)
+                        .unwrap_or_default(),
+                ),
+            ),
+            ProtocolPayload::RecoveryResponse(message) => (
+                message.view,
+                message.committed.unwrap_or_default().max(
+                    message
+                        .log
+                        .as_ref()
+                        .map(Log::last_op_number)
+                        .unwrap_or_default(),
+                ),
+            ),
+            ProtocolPayload::Digest(message) => (
+                message.view,
+                message
+                    .ranges
+                    .iter()
+                    .map(|&(start, end)| start.max(end))
+                    .max()
+                    .unwrap_or_default(),
+            ),
+        }
+    }
+
+    /// The kind of message and the sender it is coalesced by, along with the view that orders messages of that kind
+    /// from that sender, for the messages a later one from the same sender supersedes.
+    /// Recoveries carry no view, so the latest recovery of a replica supersedes its earlier ones.
+    fn supersession(&self) -> Option<(Discriminant<Self>, usize, View)> {
+        let (index, view) = match self {
+            ProtocolPayload::StartViewChange(message) => (message.index, message.view),
+            ProtocolPayload::DoViewChange(message) => (message.index, message.view),
+            ProtocolPayload::Recovery(message) => (message.index, View::default()),
+            _ => return None,
+        };
+
+        Some((mem::discriminant(self), index, view))
+    }
+
+    /// Whether sending the message right after the given one would be redundant.
+    /// Prepares for the same viewstamp hold the same operation; messages that carry logs or service state
+    /// are never considered duplicates, since comparing them would be as expensive as sending them.
+    fn is_duplicate_of(&self, previous: &Self) -> bool {
+        match (self, previous) {
+            (ProtocolPayload::Prepare(message), ProtocolPayload::Prepare(previous)) => {
+                message.view == previous.view && message.op_number == previous.op_number
+            }
+            (ProtocolPayload::PrepareOk(message), ProtocolPayload::PrepareOk(previous)) => {
+                message == previous
+            }
+            // A later commit for the same commit number only differs in when the primary sent it.
+            (ProtocolPayload::Commit(message), ProtocolPayload::Commit(previous)) => {
+                message.view == previous.view && message.committed == previous.committed
+            }
+            (ProtocolPayload::GetState(message), ProtocolPayload::GetState(previous)) => {
+                message == previous
+            }
+            (
+                ProtocolPayload::StartViewChange(message),
+                ProtocolPayload::StartViewChange(previous),
+            ) => message == previous,
+            (ProtocolPayload::GetLog(message), ProtocolPayload::GetLog(previous)) => {
+                message == previous
+            }
+            (ProtocolPayload::Recovery(message), ProtocolPayload::Recovery(previous)) => {
+                message == previous
+            }
+            (ProtocolPayload::Digest(message), ProtocolPayload::Digest(previous)) => {
+                message == previous
+            }
+            _ => false,
+        }
+    }
+
     pub fn unwrap_prepare(self) -> Prepare<P::Request, P::Prediction> {
         let Self::Prepare(message) = self else {
             panic!("called `ProtocolPayload::unwrap_prepare` on a unsupported variant",)
@@ -104,16 +308,374 @@ where
         };
         message
     }
+
+    pub fn unwrap_new_state(self) -> NewState<P::Request, P::Prediction> {
+        let Self::NewState(message) = self else {
+            panic!("called `ProtocolPayload::unwrap_new_state` on a unsupported variant",)
+        };
+        message
+    }
+
+    pub fn unwrap_get_log(self) -> GetLog {
+        let Self::GetLog(message) = self else {
+            panic!("called `ProtocolPayload::unwrap_get_log` on a unsupported variant",)
+        };
+        message
+    }
+
+    pub fn unwrap_new_log(self) -> NewLog<P::Request, P::Prediction> {
+        let Self::NewLog(message) = self else {
+            panic!("called `ProtocolPayload::unwrap_new_log` on a unsupported variant",)
+        };
+        message
+    }
+
+    pub fn unwrap_install_snapshot(
+        self,
+    ) -> InstallSnapshot<P::Checkpoint, P::Reply, P::Request, P::Prediction> {
+        let Self::InstallSnapshot(message) = self else {
+            panic!("called `ProtocolPayload::unwrap_install_snapshot` on a unsupported variant",)
+        };
+        message
+    }
+}
+
+/// Converts each protocol payload into the message it holds, handing back payloads of other message types unchanged.
+/// Lets callers select messages by type (e.g. `mailbox.take_inbound::<StartView<_, _>>()`) instead of
+/// matching on every variant.
+macro_rules! payload_conversions {
+    ($($variant:ident($message:ty)),* $(,)?) => {
+        $(
+            impl<P> TryFrom<ProtocolPayload<P>> for $message
+            where
+                P: Protocol,
+            {
+                type Error = ProtocolPayload<P>;
+
+                fn try_from(payload: ProtocolPayload<P>) -> Result<Self, Self::Error> {
+                    match payload {
+                        ProtocolPayload::$variant(message) => Ok(message),
+                        payload => Err(payload),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+payload_conversions! {
+    Prepare(Prepare<P::Request, P::Prediction>),
+    PrepareOk(PrepareOk),
+    Commit(Commit),
+    GetState(GetState),
+    NewState(NewState<P::Request, P::Prediction>),
+    InstallSnapshot(InstallSnapshot<P::Checkpoint, P::Reply, P::Request, P::Prediction>),
+    StartViewChange(StartViewChange),
+    DoViewChange(DoViewChange<P::Request, P::Prediction>),
+    GetLog(GetLog),
+    NewLog(NewLog<P::Request, P::Prediction>),
+    StartView(StartView<P::Request, P::Prediction>),
+    Recovery(Recovery),
+    RecoveryResponse(RecoveryResponse<P::Request, P::Prediction>),
+    Digest(Digest),
+}
+
+#[derive(Eq, PartialEq, Serialize, Deserialize)]
+#[serde(bound(
+    serialize = "P::Reply: Serialize",
+    deserialize = "P::Reply: Deserialize<'de>"
+))]
+pub enum ClientPayload<P>
+where
+    P: Protocol,
+{
+    Reply(Reply<P::Reply>),
+    Busy(Busy),
+    Expired(Expired),
+    SessionAccepted(SessionAccepted),
+    RequestStatus(RequestStatus),
+    OutdatedView(OutdatedView),
+    OutdatedEpoch(OutdatedEpoch),
+    Notification(Notification<P::Reply>),
+    Discovered(Discovered),
+}
+
+impl<P> Clone for ClientPayload<P>
+where
+    P: Protocol,
+{
+    fn clone(&self) -> Self {
+        match self {
+            ClientPayload::Reply(message) => Self::Reply(message.clone()),
+            ClientPayload::Busy(message) => Self::Busy(message.clone()),
+            ClientPayload::Expired(message) => Self::Expired(message.clone()),
+            ClientPayload::SessionAccepted(message) => Self::SessionAccepted(*message),
+            ClientPayload::RequestStatus(message) => Self::RequestStatus(*message),
+            ClientPayload::OutdatedView(message) => Self::OutdatedView(*message),
+            ClientPayload::OutdatedEpoch(message) => Self::OutdatedEpoch(*message),
+            ClientPayload::Notification(message) => Self::Notification(message.clone()),
+            ClientPayload::Discovered(message) => Self::Discovered(message.clone()),
+        }
+    }
+}
+
+impl<P, Rep> Debug for ClientPayload<P>
+where
+    P: Protocol<Reply = Rep>,
+    Rep: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ClientPayload::Reply(message) => write!(f, "{message:?}"),
+            ClientPayload::Busy(message) => write!(f, "{message:?}"),
+            ClientPayload::Expired(message) => write!(f, "{message:?}"),
+            ClientPayload::SessionAccepted(message) => write!(f, "{message:?}"),
+            ClientPayload::RequestStatus(message) => write!(f, "{message:?}"),
+            ClientPayload::OutdatedView(message) => write!(f, "{message:?}"),
+            ClientPayload::OutdatedEpoch(message) => write!(f, "{message:?}"),
+            ClientPayload::Notification(message) => write!(f, "{message:?}"),
+            ClientPayload::Discovered(message) => write!(f, "{message:?}"),
+        }
+    }
+}
+
+impl<P> ClientPayload<P>
+where
+    P: Protocol,
+{
+    /// Whether sending the message right after the given one would be redundant.
+    /// Replies to the same request in the same view hold the cached result of the request.
+    fn is_duplicate_of(&self, previous: &Self) -> bool {
+        match (self, previous) {
+            (ClientPayload::Reply(message), ClientPayload::Reply(previous)) => {
+                message.view == previous.view && message.id == previous.id
+            }
+            (ClientPayload::Busy(message), ClientPayload::Busy(previous)) => message == previous,
+            (ClientPayload::Expired(message), ClientPayload::Expired(previous)) => {
+                message == previous
+            }
+            (ClientPayload::SessionAccepted(message), ClientPayload::SessionAccepted(previous)) => {
+                message == previous
+            }
+            (ClientPayload::RequestStatus(message), ClientPayload::RequestStatus(previous)) => {
+                message == previous
+            }
+            (ClientPayload::OutdatedView(message), ClientPayload::OutdatedView(previous)) => {
+                message == previous
+            }
+            (ClientPayload::OutdatedEpoch(message), ClientPayload::OutdatedEpoch(previous)) => {
+                message == previous
+            }
+            (ClientPayload::Notification(message), ClientPayload::Notification(previous)) => {
+                message.view == previous.view && message.op_number == previous.op_number
+            }
+            (ClientPayload::Discovered(message), ClientPayload::Discovered(previous)) => {
+                message == previous
+            }
+            _ => false,
+        }
+    }
+
+    pub fn unwrap_reply(self) -> Reply<P::Reply> {
+        let Self::Reply(message) = self else {
+            panic!("called `ClientPayload::unwrap_reply` on a unsupported variant",)
+        };
+        message
+    }
+
+    pub fn unwrap_busy(self) -> Busy {
+        let Self::Busy(message) = self else {
+            panic!("called `ClientPayload::unwrap_busy` on a unsupported variant",)
+        };
+        message
+    }
+
+    pub fn unwrap_expired(self) -> Expired {
+        let Self::Expired(message) = self else {
+            panic!("called `ClientPayload::unwrap_expired` on a unsupported variant",)
+        };
+        message
+    }
+
+    pub fn unwrap_session_accepted(self) -> SessionAccepted {
+        let Self::SessionAccepted(message) = self else {
+            panic!("called `ClientPayload::unwrap_session_accepted` on a unsupported variant",)
+        };
+        message
+    }
+
+    pub fn unwrap_request_status(self) -> RequestStatus {
+        let Self::RequestStatus(message) = self else {
+            panic!("called `ClientPayload::unwrap_request_status` on a unsupported variant",)
+        };
+        message
+    }
+
+    pub fn unwrap_outdated_view(self) -> OutdatedView {
+        let Self::OutdatedView(message) = self else {
+            panic!("called `ClientPayload::unwrap_outdated_view` on a unsupported variant",)
+        };
+        message
+    }
+
+    pub fn unwrap_outdated_epoch(self) -> OutdatedEpoch {
+        let Self::OutdatedEpoch(message) = self else {
+            panic!("called `ClientPayload::unwrap_outdated_epoch` on a unsupported variant",)
+        };
+        message
+    }
+
+    pub fn unwrap_notification(self) -> Notification<P::Reply> {
+        let Self::Notification(message) = self else {
+            panic!("called `ClientPayload::unwrap_notification` on a unsupported variant",)
+        };
+        message
+    }
+
+    pub fn unwrap_discovered(self) -> Discovered {
+        let Self::Discovered(message) = self else {
+            panic!("called `ClientPayload::unwrap_discovered` on a unsupported variant",)
+        };
+        message
+    }
+}
+
+/// Determines which message is discarded when a queue in a [`BufferedMailbox`] is at capacity.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum OverflowPolicy {
+    /// Discard the oldest queued message to make room for the new one.
+    DropOldest,
+    /// Discard the new message.
+    #[default]
+    DropNewest,
+    /// Hand the new message back to the caller of [`BufferedMailbox::push_inbound`].
+    /// Messages queued through the [`Inbox`] and [`Outbox`] traits cannot be handed back, so they are discarded.
+    Reject,
+}
+
+/// The number of messages queued in each of the queues of a [`BufferedMailbox`].
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct QueueDepth {
+    pub inbound: usize,
+    pub replies: usize,
+    pub send: usize,
+    pub broadcast: usize,
+}
+
+impl QueueDepth {
+    pub fn total(&self) -> usize {
+        self.inbound + self.replies + self.send + self.broadcast
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+struct Capacity {
+    limit: Option<usize>,
+    policy: OverflowPolicy,
+    /// The number of inbound messages discarded due to the inbound queues being at capacity.
+    dropped: usize,
+    /// The number of outbound messages discarded due to their queue being at capacity.
+    dropped_outbound: usize,
+    /// The number of outbound messages discarded as duplicates when deduplication is enabled.
+    duplicates: Option<usize>,
+}
+
+impl Capacity {
+    fn is_full(&self, length: usize) -> bool {
+        self.limit.is_some_and(|limit| length >= limit)
+    }
+
+    fn push<T>(&mut self, queue: &mut VecDeque<T>, item: T) {
+        if self.is_full(queue.len()) {
+            self.dropped_outbound += 1;
+
+            match self.policy {
+                // A queue with no room at all has nothing older to discard, so the new message goes instead.
+                OverflowPolicy::DropOldest if queue.pop_front().is_some() => {}
+                OverflowPolicy::DropOldest
+                | OverflowPolicy::DropNewest
+                | OverflowPolicy::Reject => return,
+            }
+        }
+
+        queue.push_back(item);
+    }
+
+    /// Queues an outbound message unless deduplication is enabled and it repeats the last message queued
+    /// for the same destination, even when messages for other destinations were queued in between.
+    fn push_outbound<T>(&mut self, queue: &mut VecDeque<T>, item: T)
+    where
+        T: Duplicate,
+    {
+        if let Some(duplicates) = self.duplicates.as_mut() {
+            let last = queue
+                .iter()
+                .rev()
+                .find(|queued| item.shares_destination(queued));
+
+            if last.is_some_and(|last| item.is_duplicate_of(last)) {
+                *duplicates += 1;
+                return;
+            }
+        }
+
+        self.push(queue, item);
+    }
+}
+
+trait Duplicate {
+    fn is_duplicate_of(&self, previous: &Self) -> bool;
+
+    /// Whether both messages go to the same destination. Messages without one (e.g. broadcasts) all share a queue.
+    fn shares_destination(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl<P> Duplicate for ProtocolPayload<P>
+where
+    P: Protocol,
+{
+    fn is_duplicate_of(&self, previous: &Self) -> bool {
+        ProtocolPayload::is_duplicate_of(self, previous)
+    }
+}
+
+impl<D, T> Duplicate for Envelope<D, T>
+where
+    D: PartialEq,
+    T: Duplicate,
+{
+    fn is_duplicate_of(&self, previous: &Self) -> bool {
+        self.shares_destination(previous) && self.payload.is_duplicate_of(&previous.payload)
+    }
+
+    fn shares_destination(&self, other: &Self) -> bool {
+        self.destination == other.destination
+    }
+}
+
+impl<P> Duplicate for ClientPayload<P>
+where
+    P: Protocol,
+{
+    fn is_duplicate_of(&self, previous: &Self) -> bool {
+        ClientPayload::is_duplicate_of(self, previous)
+    }
 }
 
 pub struct BufferedMailbox<P>
 where
     P: Protocol,
 {
+    priority: VecDeque<ProtocolPayload<P>>,
     inbound: VecDeque<ProtocolPayload<P>>,
-    replies: VecDeque<Envelope<ClientIdentifier, Reply<P::Reply>>>,
+    replies: VecDeque<Envelope<ClientIdentifier, ClientPayload<P>>>,
     send: VecDeque<Envelope<usize, ProtocolPayload<P>>>,
     broadcast: VecDeque<ProtocolPayload<P>>,
+    capacity: Capacity,
+    /// Joins the frames of inbound messages split by the chunkers of other replicas.
+    reassembler: Reassembler,
 }
 
 impl<P> Default for BufferedMailbox<P>
@@ -122,10 +684,13 @@ where
 {
     fn default() -> Self {
         Self {
+            priority: Default::default(),
             inbound: Default::default(),
             replies: Default::default(),
             send: Default::default(),
             broadcast: Default::default(),
+            capacity: Default::default(),
+            reassembler: Default::default(),
         }
     }
 }
@@ -134,33 +699,213 @@ impl<P> BufferedMailbox<P>
 where
     P: Protocol,
 {
+    /// Creates a mailbox where each queue holds at most `capacity` messages, counting the priority and normal
+    /// inbound messages together. Messages that do not fit are discarded or rejected according to the given policy.
+    pub fn with_capacity(capacity: usize, policy: OverflowPolicy) -> Self {
+        Self {
+            capacity: Capacity {
+                limit: Some(capacity),
+                policy,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    /// Discards outbound messages that repeat the last message queued for the same destination
+    /// (e.g. identical commits broadcast by an idle primary), to cut redundant network traffic.
+    pub fn with_deduplication(mut self) -> Self {
+        self.capacity.duplicates = Some(self.capacity.duplicates.unwrap_or_default());
+        self
+    }
+
     pub fn is_empty(&self) -> bool {
-        self.inbound.is_empty()
+        self.priority.is_empty()
+            && self.inbound.is_empty()
             && self.replies.is_empty()
             && self.send.is_empty()
             && self.broadcast.is_empty()
     }
 
+    pub fn depth(&self) -> QueueDepth {
+        QueueDepth {
+            inbound: self.priority.len() + self.inbound.len(),
+            replies: self.replies.len(),
+            send: self.send.len(),
+            broadcast: self.broadcast.len(),
+        }
+    }
+
+    /// The total number of messages discarded due to a queue being at capacity.
+    pub fn dropped(&self) -> usize {
+        self.capacity.dropped + self.capacity.dropped_outbound
+    }
+
+    /// The number of outbound messages (to replicas or clients) discarded due to their queue being at capacity.
+    pub fn dropped_outbound(&self) -> usize {
+        self.capacity.dropped_outbound
+    }
+
+    /// The total number of outbound messages discarded as duplicates, or 0 when deduplication is disabled.
+    pub fn deduplicated(&self) -> usize {
+        self.capacity.duplicates.unwrap_or_default()
+    }
+
+    /// Queues an inbound message, discarding messages according to the overflow policy when at capacity.
+    /// Returns the message back to the caller when the policy is [`OverflowPolicy::Reject`].
+    pub fn push_inbound(
+        &mut self,
+        message: ProtocolPayload<P>,
+    ) -> Result<(), Box<ProtocolPayload<P>>> {
+        if self.is_inbound_full() {
+            match self.capacity.policy {
+                // Normal operations are retried by the protocol, so they make room before priority messages do.
+                OverflowPolicy::DropOldest
+                    if self.inbound.pop_front().is_some()
+                        || self.priority.pop_front().is_some() => {}
+                // Without any room, there is nothing older to discard, so the new message goes instead.
+                OverflowPolicy::DropOldest | OverflowPolicy::DropNewest => {
+                    self.capacity.dropped += 1;
+                    return Ok(());
+                }
+                OverflowPolicy::Reject => return Err(Box::new(message)),
+            }
+
+            self.capacity.dropped += 1;
+        }
+
+        if message.is_priority() {
+            self.priority.push_back(message);
+        } else {
+            self.inbound.push_back(message);
+        }
+
+        Ok(())
+    }
+
+    /// Queues an inbound message without discarding any messages, regardless of the overflow policy.
+    /// Returns the message back to the caller when the inbound queues are at capacity.
+    pub fn try_push_inbound(
+        &mut self,
+        message: ProtocolPayload<P>,
+    ) -> Result<(), Box<ProtocolPayload<P>>> {
+        if self.is_inbound_full() {
+            return Err(Box::new(message));
+        }
+
+        if message.is_priority() {
+            self.priority.push_back(message);
+        } else {
+            self.inbound.push_back(message);
+        }
+
+        Ok(())
+    }
+
+    fn is_inbound_full(&self) -> bool {
+        self.capacity
+            .is_full(self.priority.len() + self.inbound.len())
+    }
+
+    /// Queues a message delivered through the [`Inbox`] trait, which cannot hand a rejected message back,
+    /// so rejected messages count as dropped.
+    fn queue_inbound(&mut self, message: ProtocolPayload<P>) {
+        if self.push_inbound(message).is_err() {
+            self.capacity.dropped += 1;
+        }
+    }
+
+    /// Removes the next inbound message, visiting priority messages before normal operations.
     pub fn pop_inbound(&mut self) -> Option<ProtocolPayload<P>> {
-        self.inbound.pop_front()
+        self.priority
+            .pop_front()
+            .or_else(|| self.inbound.pop_front())
+    }
+
+    /// Discards the queued view change and recovery messages that a later message from the same sender supersedes,
+    /// keeping only the start-view-change and do-view-change messages with the highest view from each replica
+    /// and the latest recovery of each replica. Returns the number of discarded messages.
+    ///
+    /// When a partition heals, the messages a replica sent in every view it went through arrive at once,
+    /// so coalescing them before processing lets the receiver jump to the latest view in one pass.
+    pub fn coalesce_view_changes(&mut self) -> usize {
+        let mut latest = HashMap::new();
+
+        for (position, message) in self.priority.iter().enumerate() {
+            if let Some((kind, index, view)) = message.supersession() {
+                let entry = latest.entry((kind, index)).or_insert((view, position));
+
+                if view >= entry.0 {
+                    *entry = (view, position);
+                }
+            }
+        }
+
+        let before = self.priority.len();
+        let mut position = 0;
+
+        self.priority.retain(|message| {
+            let keep = match message.supersession() {
+                Some((kind, index, _)) => {
+                    latest.get(&(kind, index)).map(|(_, p)| *p) == Some(position)
+                }
+                None => true,
+            };
+
+            position += 1;
+            keep
+        });
+
+        before - self.priority.len()
+    }
+
+    /// Keeps at most `limit` partially received messages from [`BufferedMailbox::push_chunk`],
+    /// discarding the oldest one to make room (e.g. when a frame of it was lost).
+    pub fn with_reassembly_limit(mut self, limit: usize) -> Self {
+        self.reassembler = Reassembler::new(limit);
+        self
     }
 
     pub fn drain_inbound(
         &mut self,
     ) -> impl DoubleEndedIterator<Item = ProtocolPayload<P>> + ExactSizeIterator + FusedIterator + '_
     {
-        self.inbound.drain(..)
+        self.priority.extend(self.inbound.drain(..));
+        self.priority.drain(..)
     }
 
     pub fn drain_replies(
         &mut self,
-    ) -> impl DoubleEndedIterator<Item = Envelope<ClientIdentifier, Reply<P::Reply>>>
+    ) -> impl DoubleEndedIterator<Item = Envelope<ClientIdentifier, ClientPayload<P>>>
            + ExactSizeIterator
            + FusedIterator
            + '_ {
         self.replies.drain(..)
     }
 
+    /// Removes the queued client messages grouped by client, in the order each client was first sent a message.
+    /// Transports can coalesce each batch into a single send, e.g. after a quorum commits many operations at once.
+    pub fn drain_reply_batches(
+        &mut self,
+    ) -> Vec<Envelope<ClientIdentifier, Vec<ClientPayload<P>>>> {
+        let mut batches: Vec<Envelope<ClientIdentifier, Vec<ClientPayload<P>>>> = Vec::new();
+        let mut positions = HashMap::new();
+
+        for envelope in self.replies.drain(..) {
+            let position = *positions.entry(envelope.destination).or_insert_with(|| {
+                batches.push(Envelope {
+                    destination: envelope.destination,
+                    payload: Vec::new(),
+                });
+                batches.len() - 1
+            });
+
+            batches[position].payload.push(envelope.payload);
+        }
+
+        batches
+    }
+
     pub fn drain_send(
         &mut self,
     ) -> impl DoubleEndedIterator<Item = Envelope<usize, ProtocolPayload<P>>>
@@ -176,6 +921,112 @@ where
     {
         self.broadcast.drain(..)
     }
+
+    /// Removes the next inbound message of the given type, visiting priority messages first.
+    /// Messages of other types stay queued in their original order.
+    pub fn take_inbound<M>(&mut self) -> Option<M>
+    where
+        M: TryFrom<ProtocolPayload<P>, Error = ProtocolPayload<P>>,
+    {
+        select(&mut self.priority, 1, M::try_from)
+            .pop()
+            .or_else(|| select(&mut self.inbound, 1, M::try_from).pop())
+    }
+
+    /// Removes the first message of the given type queued for a single replica.
+    pub fn take_send<M>(&mut self) -> Option<Envelope<usize, M>>
+    where
+        M: TryFrom<ProtocolPayload<P>, Error = ProtocolPayload<P>>,
+    {
+        select(&mut self.send, 1, Envelope::try_map).pop()
+    }
+
+    /// Removes the first message of the given type queued for every replica.
+    pub fn take_broadcast<M>(&mut self) -> Option<M>
+    where
+        M: TryFrom<ProtocolPayload<P>, Error = ProtocolPayload<P>>,
+    {
+        select(&mut self.broadcast, 1, M::try_from).pop()
+    }
+
+    /// Removes every message of the given type queued for a single replica, in the order they were queued.
+    pub fn select_send<M>(&mut self) -> Vec<Envelope<usize, M>>
+    where
+        M: TryFrom<ProtocolPayload<P>, Error = ProtocolPayload<P>>,
+    {
+        select(&mut self.send, usize::MAX, Envelope::try_map)
+    }
+
+    /// Removes every message of the given type queued for every replica, in the order they were queued.
+    pub fn select_broadcast<M>(&mut self) -> Vec<M>
+    where
+        M: TryFrom<ProtocolPayload<P>, Error = ProtocolPayload<P>>,
+    {
+        select(&mut self.broadcast, usize::MAX, M::try_from)
+    }
+}
+
+impl<P> BufferedMailbox<P>
+where
+    P: Serializable,
+{
+    /// Adds a frame of a protocol message that a [`crate::Chunker`] of the replica with the given index split,
+    /// and queues the message like [`BufferedMailbox::push_inbound`] once every one of its frames arrived,
+    /// counting a rejected message as dropped. Returns whether the frame completed a message.
+    pub fn push_chunk(&mut self, source: usize, frame: &[u8]) -> Result<bool, ChunkError> {
+        match self.reassembler.decode(source, frame)? {
+            Some(message) => {
+                self.queue_inbound(message);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
+
+impl<D, T> Envelope<D, T> {
+    fn try_map<M>(self) -> Result<Envelope<D, M>, Self>
+    where
+        M: TryFrom<T, Error = T>,
+    {
+        match M::try_from(self.payload) {
+            Ok(payload) => Ok(Envelope {
+                destination: self.destination,
+                payload,
+            }),
+            Err(payload) => Err(Envelope {
+                destination: self.destination,
+                payload,
+            }),
+        }
+    }
+}
+
+/// Removes up to `limit` items from the queue that convert into the selected type,
+/// keeping the items that do not convert in their original order.
+fn select<T, M, F>(queue: &mut VecDeque<T>, limit: usize, mut convert: F) -> Vec<M>
+where
+    F: FnMut(T) -> Result<M, T>,
+{
+    let mut selected = Vec::new();
+
+    for _ in 0..queue.len() {
+        let Some(item) = queue.pop_front() else {
+            break;
+        };
+
+        if selected.len() >= limit {
+            queue.push_back(item);
+            continue;
+        }
+
+        match convert(item) {
+            Ok(message) => selected.push(message),
+            Err(item) => queue.push_back(item),
+        }
+    }
+
+    selected
 }
 
 impl<P> Outbox<P> for BufferedMailbox<P>
@@ -183,53 +1034,104 @@ where
     P: Protocol,
 {
     fn prepare(&mut self, message: Prepare<P::Request, P::Prediction>) {
-        self.broadcast.push_back(ProtocolPayload::Prepare(message));
+        self.capacity
+            .push_outbound(&mut self.broadcast, ProtocolPayload::Prepare(message));
     }
 
     fn prepare_ok(&mut self, index: usize, message: PrepareOk) {
-        self.send.push_back(Envelope {
-            destination: index,
-            payload: ProtocolPayload::PrepareOk(message),
-        });
+        self.capacity.push_outbound(
+            &mut self.send,
+            Envelope {
+                destination: index,
+                payload: ProtocolPayload::PrepareOk(message),
+            },
+        );
     }
 
     fn commit(&mut self, message: Commit) {
-        self.broadcast.push_back(ProtocolPayload::Commit(message));
+        self.capacity
+            .push_outbound(&mut self.broadcast, ProtocolPayload::Commit(message));
     }
 
     fn get_state(&mut self, index: usize, message: GetState) {
-        self.send.push_back(Envelope {
-            destination: index,
-            payload: ProtocolPayload::GetState(message),
-        });
+        self.capacity.push_outbound(
+            &mut self.send,
+            Envelope {
+                destination: index,
+                payload: ProtocolPayload::GetState(message),
+            },
+        );
     }
 
     fn new_state(&mut self, index: usize, message: NewState<P::Request, P::Prediction>) {
-        self.send.push_back(Envelope {
-            destination: index,
-            payload: ProtocolPayload::NewState(message),
-        });
+        self.capacity.push_outbound(
+            &mut self.send,
+            Envelope {
+                destination: index,
+                payload: ProtocolPayload::NewState(message),
+            },
+        );
+    }
+
+    fn install_snapshot(
+        &mut self,
+        index: usize,
+        message: InstallSnapshot<P::Checkpoint, P::Reply, P::Request, P::Prediction>,
+    ) {
+        self.capacity.push_outbound(
+            &mut self.send,
+            Envelope {
+                destination: index,
+                payload: ProtocolPayload::InstallSnapshot(message),
+            },
+        );
     }
 
     fn start_view_change(&mut self, message: StartViewChange) {
-        self.broadcast
-            .push_back(ProtocolPayload::StartViewChange(message));
+        self.capacity.push_outbound(
+            &mut self.broadcast,
+            ProtocolPayload::StartViewChange(message),
+        );
     }
 
     fn do_view_change(&mut self, index: usize, message: DoViewChange<P::Request, P::Prediction>) {
-        self.send.push_back(Envelope {
-            destination: index,
-            payload: ProtocolPayload::DoViewChange(message),
-        });
+        self.capacity.push_outbound(
+            &mut self.send,
+            Envelope {
+                destination: index,
+                payload: ProtocolPayload::DoViewChange(message),
+            },
+        );
+    }
+
+    fn get_log(&mut self, index: usize, message: GetLog) {
+        self.capacity.push_outbound(
+            &mut self.send,
+            Envelope {
+                destination: index,
+                payload: ProtocolPayload::GetLog(message),
+            },
+        );
+    }
+
+    fn new_log(&mut self, index: usize, message: NewLog<P::Request, P::Prediction>) {
+        self.capacity.push_outbound(
+            &mut self.send,
+            Envelope {
+                destination: index,
+                payload: ProtocolPayload::NewLog(message),
+            },
+        );
     }
 
     fn start_view(&mut self, message: StartView<P::Request, P::Prediction>) {
-        self.broadcast
-            .push_back(ProtocolPayload::StartView(message));
+        self.capacity
+            .push_outbound(&mut self.broadcast, ProtocolPayload::StartView(message));
     }
 
     fn recovery(&mut self, message: Recovery) {
-        self.broadcast.push_back(ProtocolPayload::Recovery(message));
+        self.capacity
+            .push_outbound(&mut self.broadcast, ProtocolPayload::Recovery(message));
     }
 
     fn recovery_response(
@@ -237,17 +1139,112 @@ where
         index: usize,
         message: RecoveryResponse<P::Request, P::Prediction>,
     ) {
-        self.send.push_back(Envelope {
-            destination: index,
-            payload: ProtocolPayload::RecoveryResponse(message),
-        });
+        self.capacity.push_outbound(
+            &mut self.send,
+            Envelope {
+                destination: index,
+                payload: ProtocolPayload::RecoveryResponse(message),
+            },
+        );
+    }
+
+    fn digest(&mut self, message: Digest) {
+        self.capacity
+            .push_outbound(&mut self.broadcast, ProtocolPayload::Digest(message));
     }
 
     fn reply(&mut self, client: ClientIdentifier, reply: &Reply<P::Reply>) {
-        self.replies.push_back(Envelope {
-            destination: client,
-            payload: reply.clone(),
-        });
+        self.capacity.push_outbound(
+            &mut self.replies,
+            Envelope {
+                destination: client,
+                payload: ClientPayload::Reply(reply.clone()),
+            },
+        );
+    }
+
+    fn busy(&mut self, client: ClientIdentifier, message: Busy) {
+        self.capacity.push_outbound(
+            &mut self.replies,
+            Envelope {
+                destination: client,
+                payload: ClientPayload::Busy(message),
+            },
+        );
+    }
+
+    fn expired(&mut self, client: ClientIdentifier, message: Expired) {
+        self.capacity.push_outbound(
+            &mut self.replies,
+            Envelope {
+                destination: client,
+                payload: ClientPayload::Expired(message),
+            },
+        );
+    }
+
+    fn session_accepted(&mut self, client: ClientIdentifier, message: SessionAccepted) {
+        self.capacity.push_outbound(
+            &mut self.replies,
+            Envelope {
+                destination: client,
+                payload: ClientPayload::SessionAccepted(message),
+            },
+        );
+    }
+
+    fn request_status(&mut self, client: ClientIdentifier, message: RequestStatus) {
+        self.capacity.push_outbound(
+            &mut self.replies,
+            Envelope {
+                destination: client,
+                payload: ClientPayload::RequestStatus(message),
+            },
+        );
+    }
+
+    fn outdated_view(&mut self, client: ClientIdentifier, message: OutdatedView) {
+        self.capacity.push_outbound(
+            &mut self.replies,
+            Envelope {
+                destination: client,
+                payload: ClientPayload::OutdatedView(message),
+            },
+        );
+    }
+
+    fn outdated_epoch(&mut self, client: ClientIdentifier, message: OutdatedEpoch) {
+        self.capacity.push_outbound(
+            &mut self.replies,
+            Envelope {
+                destination: client,
+                payload: ClientPayload::OutdatedEpoch(message),
+            },
+        );
+    }
+
+    fn discovered(&mut self, client: ClientIdentifier, message: Discovered) {
+        self.capacity.push_outbound(
+            &mut self.replies,
+            Envelope {
+                destination: client,
+                payload: ClientPayload::Discovered(message),
+            },
+        );
+    }
+
+    fn notify(&mut self, client: ClientIdentifier, message: &Notification<P::Reply>) {
+        self.capacity.push_outbound(
+            &mut self.replies,
+            Envelope {
+                destination: client,
+                payload: ClientPayload::Notification(message.clone()),
+            },
+        );
+    }
+
+    fn queued(&self) -> usize {
+        self.depth().inbound
     }
 }
 
@@ -256,47 +1253,477 @@ where
     P: Protocol,
 {
     fn push_prepare(&mut self, message: Prepare<P::Request, P::Prediction>) {
-        self.inbound.push_back(ProtocolPayload::Prepare(message));
+        self.queue_inbound(ProtocolPayload::Prepare(message));
     }
 
     fn push_prepare_ok(&mut self, message: PrepareOk) {
-        self.inbound.push_back(ProtocolPayload::PrepareOk(message));
+        self.queue_inbound(ProtocolPayload::PrepareOk(message));
     }
 
     fn push_commit(&mut self, message: Commit) {
-        self.inbound.push_back(ProtocolPayload::Commit(message));
+        self.queue_inbound(ProtocolPayload::Commit(message));
     }
 
     fn push_get_state(&mut self, message: GetState) {
-        self.inbound.push_back(ProtocolPayload::GetState(message));
+        self.queue_inbound(ProtocolPayload::GetState(message));
     }
 
     fn push_new_state(&mut self, message: NewState<P::Request, P::Prediction>) {
-        self.inbound.push_back(ProtocolPayload::NewState(message));
+        self.queue_inbound(ProtocolPayload::NewState(message));
+    }
+
+    fn push_install_snapshot(
+        &mut self,
+        message: InstallSnapshot<P::Checkpoint, P::Reply, P::Request, P::Prediction>,
+    ) {
+        self.queue_inbound(ProtocolPayload::InstallSnapshot(message));
     }
 
     fn push_start_view_change(&mut self, message: StartViewChange) {
-        self.inbound
-            .push_back(ProtocolPayload::StartViewChange(message));
+        self.queue_inbound(ProtocolPayload::StartViewChange(message));
     }
 
     fn push_do_view_change(&mut self, message: DoViewChange<P::Request, P::Prediction>) {
-        self.inbound
-            .push_back(ProtocolPayload::DoViewChange(message));
+        self.queue_inbound(ProtocolPayload::DoViewChange(message));
+    }
+
+    fn push_get_log(&mut self, message: GetLog) {
+        self.queue_inbound(ProtocolPayload::GetLog(message));
+    }
+
+    fn push_new_log(&mut self, message: NewLog<P::Request, P::Prediction>) {
+        self.queue_inbound(ProtocolPayload::NewLog(message));
     }
 
     fn push_start_view(&mut self, message: StartView<P::Request, P::Prediction>) {
-        self.inbound.push_back(ProtocolPayload::StartView(message));
+        self.queue_inbound(ProtocolPayload::StartView(message));
     }
 
     fn push_recovery(&mut self, message: Recovery) {
-        self.inbound.push_back(ProtocolPayload::Recovery(message));
+        self.queue_inbound(ProtocolPayload::Recovery(message));
     }
 
     fn push_recovery_response(&mut self, message: RecoveryResponse<P::Request, P::Prediction>) {
-        self.inbound
-            .push_back(ProtocolPayload::RecoveryResponse(message));
+        self.queue_inbound(ProtocolPayload::RecoveryResponse(message));
+    }
+
+    fn push_digest(&mut self, message: Digest) {
+        self.queue_inbound(ProtocolPayload::Digest(message));
     }
 }
 
 impl<P> Mailbox<P> for BufferedMailbox<P> where P: Protocol {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nonce::Nonce;
+    use crate::request::RequestIdentifier;
+
+    fn commit(committed: u128) -> Commit {
+        Commit {
+            view: Default::default(),
+            epoch: 0,
+            committed: committed.into(),
+            timestamp: Default::default(),
+        }
+    }
+
+    #[test]
+    fn select_by_type() {
+        let mut mailbox = BufferedMailbox::<i32>::default();
+
+        mailbox.commit(commit(1));
+        mailbox.start_view_change(StartViewChange {
+            view: Default::default(),
+            epoch: 0,
+            index: 1,
+        });
+        mailbox.commit(commit(2));
+        mailbox.get_state(
+            2,
+            GetState {
+                view: Default::default(),
+                epoch: 0,
+                op_number: Default::default(),
+                index: 0,
+            },
+        );
+        mailbox.push_commit(commit(3));
+        mailbox.push_start_view_change(StartViewChange {
+            view: Default::default(),
+            epoch: 0,
+            index: 2,
+        });
+
+        assert_eq!(mailbox.take_broadcast::<Commit>(), Some(commit(1)));
+        assert_eq!(mailbox.select_broadcast::<Commit>(), vec![commit(2)]);
+        assert_eq!(mailbox.take_broadcast::<Commit>(), None);
+        assert!(mailbox.take_send::<PrepareOk>().is_none());
+        assert_eq!(
+            mailbox
+                .take_send::<GetState>()
+                .map(|envelope| envelope.destination),
+            Some(2)
+        );
+        assert_eq!(mailbox.take_inbound::<Commit>(), Some(commit(3)));
+        assert_eq!(mailbox.depth().total(), 2);
+        assert_eq!(
+            mailbox
+                .take_inbound::<StartViewChange>()
+                .map(|message| message.index),
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn reply_batches() {
+        let mut mailbox = BufferedMailbox::<i32>::default();
+        let clients = [ClientIdentifier::from(1), ClientIdentifier::from(2)];
+        let ids = [
+            RequestIdentifier::default(),
+            RequestIdentifier::default().next(),
+        ];
+        let reply = |id| Reply {
+            view: Default::default(),
+            id,
+            payload: 0,
+        };
+
+        mailbox.reply(clients[0], &reply(ids[0]));
+        mailbox.reply(clients[1], &reply(ids[0]));
+        mailbox.reply(clients[0], &reply(ids[1]));
+
+        let batches = mailbox.drain_reply_batches();
+
+        assert_eq!(
+            Vec::from_iter(batches.iter().map(|batch| batch.destination)),
+            clients
+        );
+        assert_eq!(
+            Vec::from_iter(
+                batches[0]
+                    .payload
+                    .iter()
+                    .map(|payload| payload.clone().unwrap_reply().id)
+            ),
+            ids
+        );
+        assert_eq!(batches[1].payload.len(), 1);
+        assert!(mailbox.is_empty());
+    }
+
+    #[test]
+    fn deduplication() {
+        let mut mailbox = BufferedMailbox::<i32>::default().with_deduplication();
+        let client = ClientIdentifier::from(1);
+        let busy = Busy {
+            view: Default::default(),
+            id: Default::default(),
+            backoff: Default::default(),
+        };
+
+        mailbox.commit(commit(1));
+        mailbox.commit(commit(1));
+        mailbox.commit(commit(2));
+        mailbox.commit(commit(1));
+        mailbox.busy(client, busy.clone());
+        mailbox.busy(client, busy.clone());
+        mailbox.busy(ClientIdentifier::from(2), busy);
+        mailbox.push_commit(commit(3));
+        mailbox.push_commit(commit(3));
+
+        assert_eq!(mailbox.deduplicated(), 2);
+        assert_eq!(
+            mailbox.select_broadcast::<Commit>(),
+            vec![commit(1), commit(2), commit(1)]
+        );
+        assert_eq!(mailbox.drain_replies().count(), 2);
+        assert_eq!(mailbox.depth().inbound, 2);
+
+        let mut mailbox = BufferedMailbox::<i32>::default();
+
+        mailbox.commit(commit(1));
+        mailbox.commit(commit(1));
+
+        assert_eq!(mailbox.deduplicated(), 0);
+        assert_eq!(mailbox.depth().broadcast, 2);
+    }
+
+    #[test]
+    fn deduplication_per_destination() {
+        let mut mailbox = BufferedMailbox::<i32>::default().with_deduplication();
+        let get_state = |index| GetState {
+            view: Default::default(),
+            epoch: 0,
+            op_number: Default::default(),
+            index,
+        };
+
+        mailbox.get_state(1, get_state(0));
+        mailbox.get_state(2, get_state(0));
+        mailbox.get_state(1, get_state(0));
+        mailbox.get_state(2, get_state(0));
+        mailbox.get_state(1, get_state(2));
+        mailbox.get_state(2, get_state(0));
+
+        assert_eq!(mailbox.deduplicated(), 3);
+        assert_eq!(
+            Vec::from_iter(
+                mailbox
+                    .drain_send()
+                    .map(|envelope| (envelope.destination, envelope.payload.unwrap_get_state()))
+            ),
+            vec![(1, get_state(0)), (2, get_state(0)), (1, get_state(2))]
+        );
+    }
+
+    #[test]
+    fn coalesce_view_changes() {
+        let mut mailbox = BufferedMailbox::<i32>::default();
+        let start_view_change = |view: u128, index| StartViewChange {
+            view: View::from(view),
+            epoch: 0,
+            index,
+        };
+        let recovery = |index, nonce: u128| Recovery {
+            index,
+            epoch: 0,
+            committed: Default::default(),
+            nonce: Nonce::from(nonce),
+            digest: None,
+            generation: 0,
+        };
+
+        for view in [1, 3, 2] {
+            mailbox.push_start_view_change(start_view_change(view, 1));
+            mailbox.push_start_view_change(start_view_change(view, 2));
+        }
+
+        mailbox.push_recovery(recovery(0, 1));
+        mailbox.push_commit(commit(1));
+        mailbox.push_recovery(recovery(0, 2));
+
+        assert_eq!(mailbox.coalesce_view_changes(), 5);
+        assert_eq!(
+            mailbox.take_inbound::<StartViewChange>(),
+            Some(start_view_change(3, 1))
+        );
+        assert_eq!(
+            mailbox.take_inbound::<StartViewChange>(),
+            Some(start_view_change(3, 2))
+        );
+        assert_eq!(mailbox.take_inbound::<Recovery>(), Some(recovery(0, 2)));
+        assert_eq!(mailbox.take_inbound::<Commit>(), Some(commit(1)));
+        assert!(mailbox.is_empty());
+        assert_eq!(mailbox.coalesce_view_changes(), 0);
+    }
+
+    #[test]
+    fn drop_newest() {
+        let mut mailbox = BufferedMailbox::<i32>::with_capacity(1, OverflowPolicy::DropNewest);
+
+        mailbox.push_commit(commit(1));
+        mailbox.push_commit(commit(2));
+
+        assert_eq!(mailbox.dropped(), 1);
+        assert_eq!(mailbox.depth().inbound, 1);
+        assert_eq!(
+            mailbox.pop_inbound().map(ProtocolPayload::unwrap_commit),
+            Some(commit(1))
+        );
+    }
+
+    #[test]
+    fn drop_oldest() {
+        let mut mailbox = BufferedMailbox::<i32>::with_capacity(1, OverflowPolicy::DropOldest);
+
+        mailbox.commit(commit(1));
+        mailbox.commit(commit(2));
+
+        assert_eq!(mailbox.dropped(), 1);
+        assert_eq!(mailbox.dropped_outbound(), 1);
+        assert_eq!(mailbox.depth().total(), 1);
+        assert_eq!(
+            mailbox
+                .drain_broadcast()
+                .map(ProtocolPayload::unwrap_commit)
+                .collect::<Vec<_>>(),
+            vec![commit(2)]
+        );
+    }
+
+    #[test]
+    fn limit_holds_for_every_policy() {
+        for policy in [
+            OverflowPolicy::DropOldest,
+            OverflowPolicy::DropNewest,
+            OverflowPolicy::Reject,
+        ] {
+            for limit in [0, 1] {
+                let mut mailbox = BufferedMailbox::<i32>::with_capacity(limit, policy);
+
+                for operation in 1..=3 {
+                    let _ = mailbox.push_inbound(ProtocolPayload::Commit(commit(operation)));
+                    mailbox.push_start_view_change(StartViewChange {
+                        view: Default::default(),
+                        epoch: 0,
+                        index: operation as usize,
+                    });
+                    mailbox.commit(commit(operation));
+                    mailbox.get_state(
+                        operation as usize,
+                        GetState {
+                            view: Default::default(),
+                            epoch: 0,
+                            op_number: operation.into(),
+                            index: 1,
+                        },
+                    );
+
+                    let depth = mailbox.depth();
+
+                    assert!(depth.inbound <= limit, "{policy:?} at {limit}: {depth:?}");
+                    assert!(depth.send <= limit, "{policy:?} at {limit}: {depth:?}");
+                    assert!(depth.broadcast <= limit, "{policy:?} at {limit}: {depth:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn shared_inbound_limit() {
+        let mut mailbox = BufferedMailbox::<i32>::with_capacity(2, OverflowPolicy::DropOldest);
+        let start_view_change = |index| StartViewChange {
+            view: Default::default(),
+            epoch: 0,
+            index,
+        };
+
+        mailbox.push_commit(commit(1));
+        mailbox.push_start_view_change(start_view_change(1));
+        mailbox.push_start_view_change(start_view_change(2));
+
+        // The priority and normal queues share one limit, and normal operations make room first.
+        assert_eq!(mailbox.dropped(), 1);
+        assert_eq!(mailbox.depth().inbound, 2);
+        assert_eq!(
+            mailbox.drain_inbound().collect::<Vec<_>>(),
+            vec![
+                ProtocolPayload::StartViewChange(start_view_change(1)),
+                ProtocolPayload::StartViewChange(start_view_change(2))
+            ]
+        );
+
+        mailbox.push_commit(commit(2));
+        mailbox.push_commit(commit(3));
+        mailbox.push_start_view_change(start_view_change(1));
+
+        assert_eq!(mailbox.dropped(), 2);
+        assert_eq!(
+            mailbox.drain_inbound().collect::<Vec<_>>(),
+            vec![
+                ProtocolPayload::StartViewChange(start_view_change(1)),
+                ProtocolPayload::Commit(commit(3))
+            ]
+        );
+    }
+
+    #[test]
+    fn priority() {
+        let mut mailbox = BufferedMailbox::<i32>::default();
+        let start_view_change = StartViewChange {
+            view: Default::default(),
+            epoch: 0,
+            index: 1,
+        };
+
+        mailbox.push_commit(commit(1));
+        mailbox.push_start_view_change(start_view_change.clone());
+        mailbox.push_commit(commit(2));
+
+        assert_eq!(
+            mailbox.drain_inbound().collect::<Vec<_>>(),
+            vec![
+                ProtocolPayload::StartViewChange(start_view_change),
+                ProtocolPayload::Commit(commit(1)),
+                ProtocolPayload::Commit(commit(2))
+            ]
+        );
+        assert!(mailbox.is_empty());
+    }
+
+    #[test]
+    fn reject() {
+        let mut mailbox = BufferedMailbox::<i32>::with_capacity(1, OverflowPolicy::DropNewest);
+
+        assert!(mailbox
+            .try_push_inbound(ProtocolPayload::Commit(commit(1)))
+            .is_ok());
+        assert_eq!(
+            mailbox.try_push_inbound(ProtocolPayload::Commit(commit(2))),
+            Err(Box::new(ProtocolPayload::Commit(commit(2))))
+        );
+        assert_eq!(mailbox.dropped(), 0);
+    }
+
+    #[test]
+    fn reject_policy() {
+        let mut mailbox = BufferedMailbox::<i32>::with_capacity(1, OverflowPolicy::Reject);
+
+        assert!(mailbox
+            .push_inbound(ProtocolPayload::Commit(commit(1)))
+            .is_ok());
+        assert_eq!(
+            mailbox.push_inbound(ProtocolPayload::Commit(commit(2))),
+            Err(Box::new(ProtocolPayload::Commit(commit(2))))
+        );
+        assert_eq!(mailbox.dropped(), 0);
+
+        // The inbox and outbox traits cannot hand messages back, so they discard them instead.
+        mailbox.push_commit(commit(3));
+        mailbox.commit(commit(1));
+        mailbox.commit(commit(2));
+
+        assert_eq!(mailbox.dropped(), 2);
+        assert_eq!(mailbox.dropped_outbound(), 1);
+        assert_eq!(mailbox.take_inbound::<Commit>(), Some(commit(1)));
+        assert_eq!(mailbox.select_broadcast::<Commit>(), vec![commit(1)]);
+    }
+
+    #[test]
+    fn push_chunk() {
+        let mut mailbox = BufferedMailbox::<i32>::default().with_reassembly_limit(1);
+        let mut client =
+            crate::Client::new_with_identifier(Configuration::new(3, 1.into()), 1.into());
+        let mut log = crate::log::Log::default();
+
+        for payload in 0..50 {
+            log.push(View::default(), client.new_request(payload), ());
+        }
+
+        let mut chunker = crate::Chunker::new(512);
+        let frames = chunker
+            .encode(&ProtocolPayload::<i32>::NewState(NewState {
+                view: View::default(),
+                epoch: 0,
+                log,
+                committed: Default::default(),
+                op_number: Default::default(),
+            }))
+            .unwrap();
+
+        assert!(frames.len() > 1);
+
+        for frame in frames[1..].iter().rev() {
+            assert_eq!(mailbox.push_chunk(1, frame), Ok(false));
+        }
+
+        assert!(mailbox.is_empty());
+        assert_eq!(mailbox.push_chunk(1, &frames[0]), Ok(true));
+
+        let message = mailbox.take_inbound::<NewState<i32, ()>>().unwrap();
+
+        assert_eq!(message.log.len(), 50);
+    }
+}
@@ -0,0 +1,315 @@
+use crate::configuration::Configuration;
+use crate::listener::EventListener;
+use crate::replica::Replica;
+use crate::service::Service;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::error::Error;
+use core::fmt::{Display, Formatter};
+use core::time::Duration;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum BuildError {
+    /// No configuration was provided to the builder.
+    MissingConfiguration,
+    /// No service was provided to the builder.
+    MissingService,
+    /// The configuration has no replicas.
+    NoReplicas,
+    /// The configuration has an even number of replicas, which allows two quorums to not intersect.
+    EvenReplicas(usize),
+    /// The index of the replica is not part of the configuration, where replicas counts observers too.
+    IndexOutOfBounds { index: usize, replicas: usize },
+    /// The in-flight limit would prevent the primary from accepting any requests.
+    ZeroInFlightLimit,
+    /// The queue limit would prevent the primary from accepting any requests.
+    ZeroQueueLimit,
+    /// The state transfer limit would prevent lagging replicas from catching up.
+    ZeroStateTransferLimit,
+    /// The lease would expire before an idle primary could renew it.
+    LeaseShorterThanCommitInterval {
+        lease: Duration,
+        commit_interval: Duration,
+    },
+    /// Backups would time out waiting for the heartbeat of an idle primary (or its lease would expire) at the maximum interval.
+    HeartbeatOutlastsTimeout {
+        heartbeat: Duration,
+        timeout: Duration,
+    },
+}
+
+impl Display for BuildError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            BuildError::MissingConfiguration => write!(f, "a configuration is required"),
+            BuildError::MissingService => write!(f, "a service is required"),
+            BuildError::NoReplicas => write!(f, "the configuration must have at least 1 replica"),
+            BuildError::EvenReplicas(replicas) => write!(
+                f,
+                "the configuration must have an odd number of replicas, found {replicas}"
+            ),
+            BuildError::IndexOutOfBounds { index, replicas } => write!(
+                f,
+                "replica index {index} is out of bounds for a configuration of {replicas} replicas"
+            ),
+            BuildError::ZeroInFlightLimit => write!(f, "the in-flight limit must be at least 1"),
+            BuildError::ZeroQueueLimit => write!(f, "the queue limit must be at least 1"),
+            BuildError::ZeroStateTransferLimit => {
+                write!(f, "the state transfer limit must be at least 1")
+            }
+            BuildError::LeaseShorterThanCommitInterval {
+                lease,
+                commit_interval,
+            } => write!(
+                f,
+                "the lease of {lease:?} must be at least the commit interval of {commit_interval:?}"
+            ),
+            BuildError::HeartbeatOutlastsTimeout { heartbeat, timeout } => write!(
+                f,
+                "the maximum heartbeat interval of {heartbeat:?} must be shorter than the timeout of {timeout:?}"
+            ),
+        }
+    }
+}
+
+impl Error for BuildError {}
+
+/// Assembles a [`Replica`], validating the configuration before the replica is created.
+/// The index of the replica defaults to 0.
+pub struct ReplicaBuilder<S> {
+    configuration: Option<Configuration>,
+    index: usize,
+    service: Option<S>,
+    listeners: Vec<Box<dyn EventListener + Send>>,
+}
+
+impl<S> Default for ReplicaBuilder<S> {
+    fn default() -> Self {
+        Self {
+            configuration: None,
+            index: 0,
+            service: None,
+            listeners: Vec::new(),
+        }
+    }
+}
+
+impl<S> ReplicaBuilder<S>
+where
+    S: Service,
+{
+    pub fn configuration(mut self, configuration: Configuration) -> Self {
+        self.configuration = Some(configuration);
+        self
+    }
+
+    pub fn index(mut self, index: usize) -> Self {
+        self.index = index;
+        self
+    }
+
+    pub fn service(mut self, service: S) -> Self {
+        self.service = Some(service);
+        self
+    }
+
+    pub fn listener<L>(mut self, listener: L) -> Self
+    where
+        L: EventListener + Send + 'static,
+    {
+        self.listeners.push(Box::new(listener));
+        self
+    }
+
+    pub fn build(self) -> Result<Replica<S>, BuildError> {
+        let configuration = self.configuration.ok_or(BuildError::MissingConfiguration)?;
+
+        validate(configuration, self.index)?;
+
+        let service = self.service.ok_or(BuildError::MissingService)?;
+        let mut replica = Replica::new(configuration, self.index, service);
+
+        replica.extend_listeners(self.listeners);
+
+        Ok(replica)
+    }
+}
+
+pub(crate) fn validate(configuration: Configuration, index: usize) -> Result<(), BuildError> {
+    let replicas = configuration.replicas();
+
+    if replicas == 0 {
+        return Err(BuildError::NoReplicas);
+    }
+
+    if replicas.is_multiple_of(2) {
+        return Err(BuildError::EvenReplicas(replicas));
+    }
+
+    if index >= configuration.members() {
+        return Err(BuildError::IndexOutOfBounds {
+            index,
+            replicas: configuration.members(),
+        });
+    }
+
+    if configuration.in_flight_limit() == Some(0) {
+        return Err(BuildError::ZeroInFlightLimit);
+    }
+
+    if configuration.queue_limit() == Some(0) {
+        return Err(BuildError::ZeroQueueLimit);
+    }
+
+    if configuration.state_transfer_limit() == Some(0) {
+        return Err(BuildError::ZeroStateTransferLimit);
+    }
+
+    let commit_interval = configuration.commit_interval();
+
+    if let Some(lease) = configuration
+        .lease()
+        .filter(|&lease| lease < commit_interval)
+    {
+        return Err(BuildError::LeaseShorterThanCommitInterval {
+            lease,
+            commit_interval,
+        });
+    }
+
+    if let Some((_, heartbeat)) = configuration.heartbeat() {
+        let timeout = configuration
+            .lease()
+            .map_or(configuration.view_timeout(), |lease| {
+                lease.min(configuration.view_timeout())
+            });
+
+        if heartbeat >= timeout {
+            return Err(BuildError::HeartbeatOutlastsTimeout { heartbeat, timeout });
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(all(test, feature = "os"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build() {
+        let configuration = Configuration::from(3);
+        let replica = Replica::builder()
+            .configuration(configuration)
+            .index(2)
+            .service(0)
+            .build()
+            .unwrap();
+
+        assert_eq!(replica.configuration(), configuration);
+        assert_eq!(replica.index(), 2);
+    }
+
+    #[test]
+    fn invalid() {
+        assert_eq!(
+            Replica::<i32>::builder().service(0).build().err(),
+            Some(BuildError::MissingConfiguration)
+        );
+        assert_eq!(
+            Replica::<i32>::builder()
+                .configuration(Configuration::from(3))
+                .build()
+                .err(),
+            Some(BuildError::MissingService)
+        );
+        assert_eq!(
+            Replica::builder()
+                .configuration(Configuration::from(0))
+                .service(0)
+                .build()
+                .err(),
+            Some(BuildError::NoReplicas)
+        );
+        assert_eq!(
+            Replica::builder()
+                .configuration(Configuration::from(4))
+                .service(0)
+                .build()
+                .err(),
+            Some(BuildError::EvenReplicas(4))
+        );
+        assert_eq!(
+            Replica::builder()
+                .configuration(Configuration::from(3))
+                .index(3)
+                .service(0)
+                .build()
+                .err(),
+            Some(BuildError::IndexOutOfBounds {
+                index: 3,
+                replicas: 3
+            })
+        );
+        assert!(Replica::builder()
+            .configuration(Configuration::from(3).with_observers(1))
+            .index(3)
+            .service(0)
+            .build()
+            .is_ok());
+        assert_eq!(
+            Replica::builder()
+                .configuration(Configuration::from(3).with_in_flight_limit(0))
+                .service(0)
+                .build()
+                .err(),
+            Some(BuildError::ZeroInFlightLimit)
+        );
+        assert_eq!(
+            Replica::builder()
+                .configuration(Configuration::from(3).with_queue_limit(0))
+                .service(0)
+                .build()
+                .err(),
+            Some(BuildError::ZeroQueueLimit)
+        );
+        assert_eq!(
+            Replica::builder()
+                .configuration(Configuration::from(3).with_state_transfer_limit(0))
+                .service(0)
+                .build()
+                .err(),
+            Some(BuildError::ZeroStateTransferLimit)
+        );
+        assert_eq!(
+            Replica::builder()
+                .configuration(
+                    Configuration::from(3)
+                        .with_commit_interval(Duration::from_millis(10))
+                        .with_lease(Duration::from_millis(5))
+                )
+                .service(0)
+                .build()
+                .err(),
+            Some(BuildError::LeaseShorterThanCommitInterval {
+                lease: Duration::from_millis(5),
+                commit_interval: Duration::from_millis(10),
+            })
+        );
+        assert_eq!(
+            Replica::builder()
+                .configuration(
+                    Configuration::from(3)
+                        .with_heartbeat(Duration::from_millis(50), Duration::from_millis(400))
+                        .with_lease(Duration::from_millis(300))
+                )
+                .service(0)
+                .build()
+                .err(),
+            Some(BuildError::HeartbeatOutlastsTimeout {
+                heartbeat: Duration::from_millis(400),
+                timeout: Duration::from_millis(300),
+            })
+        );
+    }
+}
@@ -0,0 +1,332 @@
+use alloc::collections::VecDeque;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::error::Error;
+use core::fmt::{Display, Formatter};
+use hashbrown::HashMap;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// The number of bytes of the message number, chunk index and chunk count that precede the bytes of every frame.
+const HEADER: usize = 16;
+
+/// The number of partially received messages a reassembler keeps by default.
+const DEFAULT_LIMIT: usize = 64;
+
+/// Splits encoded messages into frames that fit in the datagrams of a transport (e.g. UDP),
+/// so a single large message (e.g. a `NewState` with many log entries) never exceeds the transport's limit.
+///
+/// Each frame starts with the number of the message it belongs to, its index and the number of frames of the message.
+/// Message numbers are only unique per chunker, so receivers reassemble frames separately for each sender.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Chunker {
+    mtu: usize,
+    next: u64,
+}
+
+impl Chunker {
+    /// Creates a chunker whose frames, header included, are at most `mtu` bytes long.
+    /// An MTU too small to hold the header and a single byte of the message is raised to fit them.
+    pub fn new(mtu: usize) -> Self {
+        Self {
+            mtu: mtu.max(HEADER + 1),
+            next: 0,
+        }
+    }
+
+    /// The maximum length of the frames of this chunker.
+    pub fn mtu(&self) -> usize {
+        self.mtu
+    }
+
+    /// Splits the bytes of a message into frames. Empty messages take a single frame.
+    pub fn split(&mut self, bytes: &[u8]) -> Vec<Vec<u8>> {
+        let number = self.next;
+        let chunks: Vec<&[u8]> = if bytes.is_empty() {
+            vec![bytes]
+        } else {
+            bytes.chunks(self.mtu - HEADER).collect()
+        };
+        let count =
+            u32::try_from(chunks.len()).expect("message exceeds the maximum number of chunks");
+
+        self.next = self.next.wrapping_add(1);
+
+        chunks
+            .into_iter()
+            .zip(0u32..)
+            .map(|(chunk, index)| {
+                let mut frame = Vec::with_capacity(HEADER + chunk.len());
+
+                frame.extend_from_slice(&number.to_le_bytes());
+                frame.extend_from_slice(&index.to_le_bytes());
+                frame.extend_from_slice(&count.to_le_bytes());
+                frame.extend_from_slice(chunk);
+                frame
+            })
+            .collect()
+    }
+
+    /// Encodes the message and splits the encoding into frames.
+    pub fn encode<M>(&mut self, message: &M) -> Result<Vec<Vec<u8>>, serde_json::Error>
+    where
+        M: Serialize,
+    {
+        Ok(self.split(&serde_json::to_vec(message)?))
+    }
+}
+
+/// The frames received so far of a message split by a [`Chunker`].
+struct Partial {
+    chunks: Vec<Option<Vec<u8>>>,
+    received: usize,
+}
+
+/// Joins the frames split by the [`Chunker`] of each sender back into messages, in whatever order the frames arrive.
+///
+/// Datagram transports may lose frames, which would leave a message incomplete forever,
+/// so the reassembler only keeps a bounded number of partial messages and discards the oldest one to make room.
+/// The sender recovers from the discarded message the same way it recovers from any lost message.
+pub struct Reassembler {
+    partial: HashMap<(usize, u64), Partial>,
+    /// The partial messages in the order their first frame arrived.
+    order: VecDeque<(usize, u64)>,
+    limit: usize,
+    evicted: usize,
+}
+
+impl Default for Reassembler {
+    fn default() -> Self {
+        Self::new(DEFAULT_LIMIT)
+    }
+}
+
+impl Reassembler {
+    /// Creates a reassembler that keeps at most `limit` partially received messages across all senders.
+    pub fn new(limit: usize) -> Self {
+        Self {
+            partial: Default::default(),
+            order: Default::default(),
+            limit: limit.max(1),
+            evicted: 0,
+        }
+    }
+
+    /// Adds a frame received from the sender with the given index (e.g. the index of the replica).
+    /// Returns the bytes of the message once every one of its frames arrived.
+    pub fn insert(&mut self, source: usize, frame: &[u8]) -> Result<Option<Vec<u8>>, ChunkError> {
+        let Some((header, chunk)) = frame.split_first_chunk::<HEADER>() else {
+            return Err(ChunkError::Truncated {
+                length: frame.len(),
+            });
+        };
+
+        let number = u64::from_le_bytes(header[..8].try_into().unwrap_or_default());
+        let index = u32::from_le_bytes(header[8..12].try_into().unwrap_or_default()) as usize;
+        let count = u32::from_le_bytes(header[12..].try_into().unwrap_or_default()) as usize;
+
+        if index >= count {
+            return Err(ChunkError::Inconsistent);
+        }
+
+        if count == 1 {
+            return Ok(Some(chunk.to_vec()));
+        }
+
+        let key = (source, number);
+
+        if !self.partial.contains_key(&key) {
+            if self.order.len() >= self.limit {
+                if let Some(oldest) = self.order.pop_front() {
+                    self.partial.remove(&oldest);
+                    self.evicted += 1;
+                }
+            }
+
+            self.order.push_back(key);
+            self.partial.insert(
+                key,
+                Partial {
+                    chunks: vec![None; count],
+                    received: 0,
+                },
+            );
+        }
+
+        let Some(partial) = self.partial.get_mut(&key) else {
+            return Ok(None);
+        };
+
+        if partial.chunks.len() != count {
+            return Err(ChunkError::Inconsistent);
+        }
+
+        // Transports may duplicate frames, which must not count twice.
+        if partial.chunks[index].is_none() {
+            partial.chunks[index] = Some(chunk.to_vec());
+            partial.received += 1;
+        }
+
+        if partial.received < count {
+            return Ok(None);
+        }
+
+        self.order.retain(|k| *k != key);
+
+        let message = self
+            .partial
+            .remove(&key)
+            .map(|partial| partial.chunks.into_iter().flatten().flatten().collect());
+
+        Ok(message)
+    }
+
+    /// Adds a frame like [`Reassembler::insert`] and decodes the message once every one of its frames arrived.
+    pub fn decode<M>(&mut self, source: usize, frame: &[u8]) -> Result<Option<M>, ChunkError>
+    where
+        M: DeserializeOwned,
+    {
+        match self.insert(source, frame)? {
+            Some(bytes) => serde_json::from_slice(&bytes)
+                .map(Some)
+                .map_err(|error| ChunkError::Malformed(error.to_string())),
+            None => Ok(None),
+        }
+    }
+
+    /// The number of messages with some but not all of their frames received.
+    pub fn pending(&self) -> usize {
+        self.partial.len()
+    }
+
+    /// The total number of partial messages discarded to make room for newer ones.
+    pub fn evicted(&self) -> usize {
+        self.evicted
+    }
+}
+
+/// A frame that could not be reassembled into a message.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ChunkError {
+    /// The frame is shorter than its header (e.g. it was cut off by the transport).
+    Truncated { length: usize },
+    /// The header of the frame contradicts itself or earlier frames of the same message.
+    Inconsistent,
+    /// Every frame of the message arrived but the message does not decode.
+    Malformed(String),
+}
+
+impl Display for ChunkError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ChunkError::Truncated { length } => {
+                write!(f, "frame of {length} bytes is shorter than its header")
+            }
+            ChunkError::Inconsistent => {
+                write!(
+                    f,
+                    "frame header does not match the other frames of its message"
+                )
+            }
+            ChunkError::Malformed(error) => {
+                write!(f, "reassembled message does not decode: {error}")
+            }
+        }
+    }
+}
+
+impl Error for ChunkError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reassemble() {
+        let mut chunker = Chunker::new(HEADER + 4);
+        let mut reassembler = Reassembler::default();
+        let message = b"hello, world".to_vec();
+        let frames = chunker.split(&message);
+
+        assert_eq!(frames.len(), 3);
+        assert!(frames.iter().all(|frame| frame.len() <= chunker.mtu()));
+
+        assert_eq!(reassembler.insert(1, &frames[2]), Ok(None));
+        assert_eq!(reassembler.insert(1, &frames[0]), Ok(None));
+        assert_eq!(reassembler.insert(1, &frames[0]), Ok(None));
+
+        // Message numbers are per sender, so frames from another sender do not mix in.
+        assert_eq!(reassembler.insert(2, &frames[1]), Ok(None));
+        assert_eq!(reassembler.pending(), 2);
+        assert_eq!(reassembler.insert(1, &frames[1]), Ok(Some(message)));
+        assert_eq!(reassembler.pending(), 1);
+
+        let empty = chunker.split(&[]);
+
+        assert_eq!(empty.len(), 1);
+        assert_eq!(reassembler.insert(1, &empty[0]), Ok(Some(Vec::new())));
+    }
+
+    #[test]
+    fn encode() {
+        let mut chunker = Chunker::new(0);
+        let mut reassembler = Reassembler::default();
+        let message = vec!["a".repeat(100), "b".repeat(100)];
+        let frames = chunker.encode(&message).unwrap();
+
+        assert_eq!(chunker.mtu(), HEADER + 1);
+
+        let decoded: Vec<Option<Vec<String>>> = frames
+            .iter()
+            .rev()
+            .map(|frame| reassembler.decode(0, frame).unwrap())
+            .collect();
+
+        assert!(decoded[..decoded.len() - 1].iter().all(Option::is_none));
+        assert_eq!(decoded.last(), Some(&Some(message)));
+    }
+
+    #[test]
+    fn evict() {
+        let mut chunker = Chunker::new(HEADER + 1);
+        let mut reassembler = Reassembler::new(2);
+        let first = chunker.split(b"ab");
+        let second = chunker.split(b"cd");
+        let third = chunker.split(b"ef");
+
+        reassembler.insert(0, &first[0]).unwrap();
+        reassembler.insert(0, &second[0]).unwrap();
+        reassembler.insert(0, &third[0]).unwrap();
+
+        assert_eq!(reassembler.evicted(), 1);
+        assert_eq!(reassembler.pending(), 2);
+        assert_eq!(reassembler.insert(0, &first[1]), Ok(None));
+        assert_eq!(reassembler.insert(0, &third[1]), Ok(Some(b"ef".to_vec())));
+    }
+
+    #[test]
+    fn invalid() {
+        let mut chunker = Chunker::new(HEADER + 1);
+        let mut reassembler = Reassembler::default();
+        let mut frame = chunker.split(b"ab").swap_remove(0);
+
+        assert_eq!(
+            reassembler.insert(0, &frame[..4]),
+            Err(ChunkError::Truncated { length: 4 })
+        );
+
+        reassembler.insert(0, &frame).unwrap();
+        frame[12] = 3;
+
+        assert_eq!(reassembler.insert(0, &frame), Err(ChunkError::Inconsistent));
+
+        frame[8] = 3;
+
+        assert_eq!(reassembler.insert(0, &frame), Err(ChunkError::Inconsistent));
+        assert!(matches!(
+            reassembler.decode::<u32>(0, &chunker.split(b"x")[0]),
+            Err(ChunkError::Malformed(_))
+        ));
+    }
+}
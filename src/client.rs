@@ -1,24 +1,107 @@
+use crate::batch::BatchBuilder;
 use crate::configuration::Configuration;
-use crate::request::{ClientIdentifier, Reply, Request, RequestIdentifier};
+use crate::request::{
+    Acknowledge, Busy, ClientIdentifier, Discover, Discovered, OpenSession, OutdatedEpoch,
+    OutdatedView, QueryRequestStatus, Reply, Request, RequestIdentifier, Resume, SessionAccepted,
+    Staleness,
+};
 use crate::viewstamp::View;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::net::SocketAddr;
+use core::time::Duration;
+use rand::Rng;
+
+mod connector;
+
+pub use connector::{Connection, Connector};
+
+/// Where a client sends read-only requests created with [`Client::new_read`].
+/// Writes always go to the primary regardless of the preference.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub enum ReadPreference {
+    /// Reads go to the primary, which executes them like any other request and never answers from stale state.
+    #[default]
+    Primary,
+    /// Reads go to the nearest replica, which answers from its committed state no matter how far behind it is.
+    Nearest,
+    /// Reads go to the nearest replica, which answers when it committed all but at most the given number
+    /// of the operations the primary told it were committed, and points the client at the primary otherwise.
+    Staleness(u64),
+}
 
 pub struct Client {
     configuration: Configuration,
     view: View,
     identifier: ClientIdentifier,
     last_request: RequestIdentifier,
+    read_preference: ReadPreference,
+    /// The index of the replica closest to the client (e.g. an observer in the same region), if known.
+    nearest: Option<usize>,
+    /// The address of each replica, ordered by replica index, when the group advertised them during discovery.
+    members: Vec<SocketAddr>,
+    #[cfg(feature = "signing")]
+    signing_key: Option<crate::signing::SigningKey>,
 }
 
 impl Client {
+    #[cfg(feature = "os")]
     pub fn new(configuration: Configuration) -> Self {
+        Self::new_with_identifier(configuration, Default::default())
+    }
+
+    /// Creates a client with the given identifier instead of a random one
+    /// (e.g. one drawn from a seeded random number generator in a deterministic simulation).
+    pub fn new_with_identifier(configuration: Configuration, identifier: ClientIdentifier) -> Self {
         Self {
             configuration,
             view: Default::default(),
-            identifier: Default::default(),
+            identifier,
             last_request: Default::default(),
+            read_preference: Default::default(),
+            nearest: None,
+            members: Vec::new(),
+            #[cfg(feature = "signing")]
+            signing_key: None,
         }
     }
 
+    /// Where to send the read-only requests created with [`Client::new_read`].
+    pub fn with_read_preference(mut self, preference: ReadPreference) -> Self {
+        self.read_preference = preference;
+        self
+    }
+
+    /// The index of the replica closest to the client, which answers reads unless the preference is the primary.
+    /// The replica may be an observer (see [`Configuration::with_observers`]), and needs follower reads enabled
+    /// (see [`crate::Replica::enable_follower_reads`]) to answer reads instead of pointing the client at the primary.
+    pub fn with_nearest(mut self, index: usize) -> Self {
+        self.nearest = Some(index);
+        self
+    }
+
+    /// Signs the requests created with [`Client::new_signed_request`] so a primary with a [`crate::KeyRing`]
+    /// can tell they came from this client.
+    #[cfg(feature = "signing")]
+    pub fn with_signing_key(mut self, key: crate::signing::SigningKey) -> Self {
+        self.signing_key = Some(key);
+        self
+    }
+
+    /// Signs subsequent requests with the new key. The replicas must accept the new key before the client uses it.
+    #[cfg(feature = "signing")]
+    pub fn rotate_signing_key(&mut self, key: crate::signing::SigningKey) {
+        self.signing_key = Some(key);
+    }
+
+    /// Uses a durable identifier (e.g. one stored on disk) instead of a random one, so the reply cached by the group
+    /// for the last request survives restarts of the client. A restarted client must resume its session with
+    /// [`Client::open_session`] and [`Client::resume`] before sending new requests, or the group ignores them as duplicates.
+    pub fn with_identifier(mut self, identifier: ClientIdentifier) -> Self {
+        self.identifier = identifier;
+        self
+    }
+
     pub fn identifier(&self) -> ClientIdentifier {
         self.identifier
     }
@@ -34,17 +117,187 @@ impl Client {
             payload,
             client: self.identifier,
             id: self.last_request,
+            deadline: None,
+            signature: None,
+            epoch: self.configuration.epoch(),
+            staleness: None,
         }
     }
 
+    /// Creates a read-only request to send to [`Client::read_target`], marked according to the read preference.
+    /// A replica that is too far behind the primary points the client at the primary instead,
+    /// which answers the same request like any other.
+    pub fn new_read<P>(&mut self, payload: P) -> Request<P> {
+        let request = self.new_request(payload);
+
+        match self.read_preference {
+            ReadPreference::Primary => request,
+            ReadPreference::Nearest => request.with_staleness(Staleness::Operations(u64::MAX)),
+            ReadPreference::Staleness(limit) => {
+                request.with_staleness(Staleness::Operations(limit))
+            }
+        }
+    }
+
+    /// The index of the replica to send read-only requests to. Falls back to the primary when the preference is
+    /// the primary, or no nearest replica is known or it is not part of the configuration of the client.
+    pub fn read_target(&self) -> usize {
+        match (self.read_preference, self.nearest) {
+            (ReadPreference::Primary, _) | (_, None) => self.primary(),
+            (_, Some(nearest)) if nearest >= self.configuration.members() => self.primary(),
+            (_, Some(nearest)) => nearest,
+        }
+    }
+
+    /// Creates a request signed with the key of the client, or an unsigned one if the client has no key.
+    #[cfg(feature = "signing")]
+    pub fn new_signed_request<P>(&mut self, payload: P) -> Request<P>
+    where
+        P: serde::Serialize,
+    {
+        let mut request = self.new_request(payload);
+
+        request.signature = self.signing_key.as_ref().map(|key| key.sign(&request));
+        request
+    }
+
+    /// Re-targets the client at the primary a backup pointed it to.
+    /// Returns whether the client learned of a newer view, in which case the pending request should be re-sent.
+    pub fn redirect(&mut self, outdated: &OutdatedView) -> bool {
+        let updated = outdated.view > self.view;
+
+        self.view = self.view.max(outdated.view);
+        updated
+    }
+
+    /// Whether the replica that redirected the client runs a later epoch of the group than the one the client knows of,
+    /// in which case the client needs to discover the group again.
+    pub fn is_stale(&self, outdated: &OutdatedView) -> bool {
+        outdated.epoch > self.configuration.epoch()
+    }
+
+    /// Whether the members of the group changed since the epoch of the configuration of the client,
+    /// in which case the client needs to discover the group again.
+    pub fn is_outdated(&self, outdated: &OutdatedEpoch) -> bool {
+        outdated.epoch > self.configuration.epoch()
+    }
+
+    /// The message that asks any replica for the configuration of the group.
+    pub fn discover(&self) -> Discover {
+        Discover {
+            client: self.identifier,
+        }
+    }
+
+    /// Adopts the configuration a replica handed out when it is from a later epoch than the one the client knows of.
+    /// Returns whether the configuration changed, in which case the pending request should be re-sent.
+    /// The client also learns where to reach each replica when the replica advertised the members of the group.
+    pub fn rediscover(&mut self, discovered: &Discovered) -> bool {
+        match discovered.epoch.cmp(&self.configuration.epoch()) {
+            Ordering::Greater => {
+                self.configuration = discovered.configuration();
+                self.view = discovered.view;
+                self.members = discovered.members.clone();
+                true
+            }
+            Ordering::Equal => {
+                self.view = self.view.max(discovered.view);
+
+                if !discovered.members.is_empty() {
+                    self.members = discovered.members.clone();
+                }
+
+                false
+            }
+            Ordering::Less => false,
+        }
+    }
+
+    pub fn configuration(&self) -> Configuration {
+        self.configuration
+    }
+
+    /// The message that asks the primary for the last request number of this client.
+    pub fn open_session(&self) -> OpenSession {
+        OpenSession {
+            client: self.identifier,
+        }
+    }
+
+    /// The message that reopens the session of this client after its connection dropped,
+    /// asking the primary to deliver again the replies after the latest one the client received.
+    pub fn resume_session(&self, acknowledged: RequestIdentifier) -> Resume {
+        Resume {
+            client: self.identifier,
+            acknowledged,
+        }
+    }
+
+    /// The message that tells the primary this client received the reply, so the primary stops buffering it.
+    pub fn acknowledge<P>(&self, reply: &Reply<P>) -> Acknowledge {
+        Acknowledge {
+            client: self.identifier,
+            request: reply.id,
+        }
+    }
+
+    /// The number of the latest request of this client.
+    pub fn last_request(&self) -> RequestIdentifier {
+        self.last_request
+    }
+
+    /// The message that asks the primary whether the group executed the given request of this client.
+    pub fn query_status(&self, request: RequestIdentifier) -> QueryRequestStatus {
+        QueryRequestStatus {
+            client: self.identifier,
+            request,
+        }
+    }
+
+    /// Continues numbering requests after the last one the group started for this client.
+    /// Request numbers never move backwards, so a stale acceptance cannot cause a request number to be reused.
+    pub fn resume(&mut self, accepted: &SessionAccepted) {
+        self.view = self.view.max(accepted.view);
+        self.last_request = self.last_request.max(accepted.last_request);
+    }
+
+    /// How long to wait before retrying a request the primary was too busy to start.
+    /// Follows the hint from the primary plus up to half of it again at random, so rejected clients do not retry in lockstep.
+    #[cfg(feature = "os")]
+    pub fn backoff(&mut self, busy: &Busy) -> Duration {
+        self.backoff_with(busy, &mut rand::thread_rng())
+    }
+
+    /// The backoff with the jitter drawn from the given random number generator (e.g. in a deterministic simulation).
+    pub fn backoff_with<R>(&mut self, busy: &Busy, rng: &mut R) -> Duration
+    where
+        R: Rng + ?Sized,
+    {
+        self.view = self.view.max(busy.view);
+
+        busy.backoff + rng.gen_range(Duration::ZERO..=busy.backoff / 2)
+    }
+
+    /// Starts a batch of operations sent as a single request to a [`crate::Batched`] service.
+    pub fn new_batch<P>(&mut self) -> BatchBuilder<'_, P> {
+        BatchBuilder::new(self)
+    }
+
     pub fn primary(&self) -> usize {
         self.configuration % self.view
     }
+
+    /// The address to reach the replica with the given index at, if the client discovered the members of the group.
+    pub fn address(&self, index: usize) -> Option<SocketAddr> {
+        self.members.get(index).copied()
+    }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "os"))]
 mod tests {
     use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
     use std::cmp::Ordering;
 
     #[test]
@@ -58,4 +311,136 @@ mod tests {
         assert_ne!(request_a.id, request_b.id);
         assert_eq!(request_a.id.cmp(&request_b.id), Ordering::Less);
     }
+
+    #[test]
+    fn resume() {
+        let configuration = Configuration::from(5);
+        let identifier = ClientIdentifier::from(42);
+        let mut client = Client::new(configuration).with_identifier(identifier);
+        let last_request = client.new_request(5).id.next();
+
+        let mut restarted = Client::new(configuration).with_identifier(identifier);
+
+        assert_eq!(restarted.open_session().client, identifier);
+
+        restarted.resume(&SessionAccepted {
+            view: View::default().next(),
+            last_request,
+        });
+
+        assert_eq!(restarted.new_request(5).id, last_request.next());
+        assert_eq!(restarted.primary(), 1);
+    }
+
+    #[test]
+    fn redirect() {
+        let mut client = Client::new(Configuration::from(5));
+        let view = View::default().next();
+
+        assert!(client.redirect(&OutdatedView {
+            view,
+            primary: 1,
+            epoch: 0
+        }));
+        assert!(!client.redirect(&OutdatedView {
+            view: View::default(),
+            primary: 0,
+            epoch: 0
+        }));
+        assert_eq!(client.primary(), 1);
+    }
+
+    #[test]
+    fn rediscover() {
+        let configuration = Configuration::from(3);
+        let mut client = Client::new(configuration);
+        let view = View::default().next();
+        let mut discovered = Discovered {
+            view,
+            epoch: 0,
+            group: configuration.group(),
+            replicas: 3,
+            members: Vec::new(),
+        };
+
+        assert!(!client.rediscover(&discovered));
+        assert_eq!(client.primary(), 1);
+        assert_eq!(client.address(1), None);
+
+        let address = "203.0.113.7:3001".parse().unwrap();
+
+        discovered.members = vec!["203.0.113.7:3000".parse().unwrap(), address];
+
+        assert!(!client.rediscover(&discovered));
+        assert_eq!(client.address(client.primary()), Some(address));
+
+        discovered.epoch = 1;
+        discovered.replicas = 5;
+        discovered.view = View::default();
+
+        assert!(client.is_stale(&OutdatedView {
+            view,
+            primary: 1,
+            epoch: 1
+        }));
+        assert!(client.rediscover(&discovered));
+        assert!(!client.rediscover(&discovered));
+        assert_eq!(client.configuration().replicas(), 5);
+        assert_eq!(client.configuration().epoch(), 1);
+        assert_eq!(client.primary(), 0);
+        assert_eq!(client.address(0), Some("203.0.113.7:3000".parse().unwrap()));
+    }
+
+    #[test]
+    fn backoff() {
+        let mut client = Client::new(Configuration::from(5));
+        let busy = Busy {
+            view: View::default().next(),
+            id: client.new_request(5).id,
+            backoff: Duration::from_millis(100),
+        };
+        let backoff = client.backoff(&busy);
+
+        assert!((busy.backoff..=busy.backoff * 3 / 2).contains(&backoff));
+        assert_eq!(client.primary(), 1);
+
+        let seeded = client.backoff_with(&busy, &mut StdRng::seed_from_u64(7));
+
+        assert_eq!(
+            client.backoff_with(&busy, &mut StdRng::seed_from_u64(7)),
+            seeded
+        );
+    }
+
+    #[test]
+    fn read_preference() {
+        let configuration = Configuration::from(3).with_observers(1);
+        let mut primary = Client::new(configuration).with_nearest(3);
+        let mut nearest = Client::new(configuration)
+            .with_read_preference(ReadPreference::Nearest)
+            .with_nearest(3);
+        let mut bounded = Client::new(configuration)
+            .with_read_preference(ReadPreference::Staleness(5))
+            .with_nearest(3);
+        let unknown = Client::new(configuration).with_read_preference(ReadPreference::Nearest);
+        let removed = Client::new(configuration)
+            .with_read_preference(ReadPreference::Nearest)
+            .with_nearest(4);
+
+        assert_eq!(primary.read_target(), 0);
+        assert_eq!(primary.new_read(1).staleness, None);
+        assert_eq!(nearest.read_target(), 3);
+        assert_eq!(
+            nearest.new_read(1).staleness,
+            Some(Staleness::Operations(u64::MAX))
+        );
+        assert_eq!(bounded.read_target(), 3);
+        assert_eq!(
+            bounded.new_read(1).staleness,
+            Some(Staleness::Operations(5))
+        );
+        assert_eq!(bounded.new_request(1).staleness, None);
+        assert_eq!(unknown.read_target(), 0);
+        assert_eq!(removed.read_target(), 0);
+    }
 }
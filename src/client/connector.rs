@@ -0,0 +1,391 @@
+use crate::buffer::ClientPayload;
+use crate::client::Client;
+use crate::configuration::Configuration;
+use crate::request::{ClientIdentifier, Request};
+use crate::service::Protocol;
+use crate::viewstamp::View;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use hashbrown::HashMap;
+
+/// A persistent connection from a client process to one replica of a group.
+/// The requests of every session of a [`Connector`] share the connection, so the transport must deliver each reply
+/// to the [`Connector`] along with the identifier of the client it is addressed to.
+pub trait Connection<P>
+where
+    P: Protocol,
+{
+    /// Sends the request to the replica without waiting for its reply.
+    /// Returns false when the connection broke before the request was sent.
+    fn send(&mut self, request: &Request<P::Request>) -> bool;
+
+    /// Re-establishes a broken connection. Returns whether the connection is usable again.
+    /// Defaults to false for transports that cannot reconnect.
+    fn reconnect(&mut self) -> bool {
+        false
+    }
+}
+
+struct Session<P>
+where
+    P: Protocol,
+{
+    client: Client,
+    /// The request sent to the group that has not been answered yet.
+    /// The group only caches the latest request of each client, so a session has at most one request in flight.
+    in_flight: Option<Request<P::Request>>,
+    /// Operations waiting for the request in flight to finish.
+    queued: VecDeque<P::Request>,
+}
+
+/// Multiplexes many [`Client`] sessions over a persistent [`Connection`] to each replica of a group.
+///
+/// Requests of different sessions are pipelined to the primary without waiting for each other's replies,
+/// while the requests of a session are sent one at a time in the order they were submitted.
+/// The connector re-resolves the primary for every session when a replica reports a newer view.
+///
+/// The connector does not wait on the connections itself. The transport hands it every message a replica sent
+/// to one of its sessions with [`Connector::handle`], and calls [`Connector::retry`] when no reply arrived
+/// within its retry interval.
+pub struct Connector<P, C>
+where
+    P: Protocol,
+{
+    configuration: Configuration,
+    view: View,
+    connections: Vec<C>,
+    sessions: HashMap<ClientIdentifier, Session<P>>,
+    replies: VecDeque<(ClientIdentifier, P::Reply)>,
+}
+
+impl<P, C> Connector<P, C>
+where
+    P: Protocol,
+    C: Connection<P>,
+{
+    /// Connects to every replica in the group, where each connection is created by the given factory.
+    pub fn new<F>(configuration: Configuration, connect: F) -> Self
+    where
+        F: FnMut(usize) -> C,
+    {
+        Self {
+            configuration,
+            view: Default::default(),
+            connections: (0..configuration.replicas()).map(connect).collect(),
+            sessions: Default::default(),
+            replies: Default::default(),
+        }
+    }
+
+    /// Opens a session for a new client of the group.
+    #[cfg(feature = "os")]
+    pub fn open(&mut self) -> ClientIdentifier {
+        self.insert(Client::new(self.configuration))
+    }
+
+    /// Opens a session for an existing client, e.g. one with a durable identifier that resumed its session.
+    /// Replaces any session of a client with the same identifier.
+    pub fn insert(&mut self, client: Client) -> ClientIdentifier {
+        let identifier = client.identifier();
+
+        self.sessions.insert(
+            identifier,
+            Session {
+                client,
+                in_flight: None,
+                queued: VecDeque::new(),
+            },
+        );
+
+        identifier
+    }
+
+    /// Closes the session, returning its client along with any operations that were not answered.
+    pub fn close(&mut self, session: ClientIdentifier) -> Option<(Client, Vec<P::Request>)> {
+        let session = self.sessions.remove(&session)?;
+        let unanswered = session
+            .in_flight
+            .map(|request| request.payload)
+            .into_iter()
+            .chain(session.queued)
+            .collect();
+
+        Some((session.client, unanswered))
+    }
+
+    /// Submits an operation on behalf of the session. The operation is sent to the primary right away
+    /// unless the session is waiting for the reply to an earlier operation.
+    /// Returns false when the session is not open.
+    pub fn submit(&mut self, session: ClientIdentifier, payload: P::Request) -> bool {
+        match self.sessions.get_mut(&session) {
+            Some(state) => {
+                state.queued.push_back(payload);
+                self.advance(session);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Handles a message a replica sent to one of the sessions.
+    pub fn handle(&mut self, session: ClientIdentifier, payload: ClientPayload<P>) {
+        let Some(state) = self.sessions.get_mut(&session) else {
+            return;
+        };
+
+        match payload {
+            ClientPayload::Reply(reply)
+                if state
+                    .in_flight
+                    .as_ref()
+                    .is_some_and(|request| request.id == reply.id) =>
+            {
+                state.client.update_view(&reply);
+                state.in_flight = None;
+
+                self.view = self.view.max(reply.view);
+                self.replies.push_back((session, reply.payload));
+                self.advance(session);
+            }
+            ClientPayload::OutdatedView(outdated) => {
+                state.client.redirect(&outdated);
+
+                if outdated.view > self.view {
+                    self.view = outdated.view;
+                    self.resend();
+                }
+            }
+            // Requests the primary was too busy to start stay in flight until the next retry.
+            _ => {}
+        }
+    }
+
+    /// Re-sends every request in flight to every replica, for when no reply arrived within the retry interval
+    /// (e.g. the primary failed and a backup needs to point the sessions at the primary of a newer view).
+    pub fn retry(&mut self) {
+        let requests: Vec<_> = self
+            .sessions
+            .values()
+            .filter_map(|session| session.in_flight.clone())
+            .collect();
+
+        for request in requests {
+            for index in 0..self.connections.len() {
+                self.send(index, &request);
+            }
+        }
+    }
+
+    /// Removes the next reply received by any of the sessions.
+    pub fn next_reply(&mut self) -> Option<(ClientIdentifier, P::Reply)> {
+        self.replies.pop_front()
+    }
+
+    /// The number of operations submitted by every session that have not been answered yet.
+    pub fn pending(&self) -> usize {
+        self.sessions
+            .values()
+            .map(|session| session.queued.len() + usize::from(session.in_flight.is_some()))
+            .sum()
+    }
+
+    /// The index of the replica the connector believes is the primary.
+    pub fn primary(&self) -> usize {
+        self.configuration % self.view
+    }
+
+    /// Sends the next queued operation of the session when it has no request in flight.
+    fn advance(&mut self, session: ClientIdentifier) {
+        let Some(state) = self.sessions.get_mut(&session) else {
+            return;
+        };
+
+        if state.in_flight.is_some() {
+            return;
+        }
+
+        if let Some(payload) = state.queued.pop_front() {
+            let request = state.client.new_request(payload);
+
+            state.in_flight = Some(request.clone());
+            self.send(self.primary(), &request);
+        }
+    }
+
+    /// Re-sends every request in flight to the primary after the view changed.
+    fn resend(&mut self) {
+        let primary = self.primary();
+        let requests: Vec<_> = self
+            .sessions
+            .values()
+            .filter_map(|session| session.in_flight.clone())
+            .collect();
+
+        for request in requests {
+            self.send(primary, &request);
+        }
+    }
+
+    /// Sends the request over the connection to the replica, reconnecting once if the connection broke.
+    fn send(&mut self, index: usize, request: &Request<P::Request>) -> bool {
+        let Some(connection) = self.connections.get_mut(index) else {
+            return false;
+        };
+
+        connection.send(request) || (connection.reconnect() && connection.send(request))
+    }
+}
+
+#[cfg(all(test, feature = "threads"))]
+mod tests {
+    use super::*;
+    use crate::driver::LocalDriver;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    type Sent = Rc<RefCell<Vec<(usize, Request<i32>)>>>;
+
+    struct Queue {
+        index: usize,
+        sent: Sent,
+        broken: bool,
+    }
+
+    impl Connection<i32> for Queue {
+        fn send(&mut self, request: &Request<i32>) -> bool {
+            if !self.broken {
+                self.sent.borrow_mut().push((self.index, request.clone()));
+            }
+
+            !self.broken
+        }
+
+        fn reconnect(&mut self) -> bool {
+            self.broken = false;
+            true
+        }
+    }
+
+    fn connect(sent: &Sent) -> impl FnMut(usize) -> Queue + '_ {
+        |index| Queue {
+            index,
+            sent: sent.clone(),
+            broken: false,
+        }
+    }
+
+    /// Delivers the sent requests to the group and hands the replies back to the connector.
+    fn exchange(
+        driver: &mut LocalDriver<i32>,
+        connector: &mut Connector<i32, Queue>,
+        sent: &Sent,
+        sessions: &[ClientIdentifier],
+    ) {
+        for (index, request) in sent.borrow_mut().drain(..) {
+            driver.request(index, request);
+        }
+
+        driver.drive();
+
+        for &session in sessions {
+            for payload in driver.take_replies(session) {
+                connector.handle(session, payload);
+            }
+        }
+    }
+
+    #[test]
+    fn pipelining() {
+        let configuration = Configuration::from(3);
+        let mut driver = LocalDriver::new(configuration, |_| 0);
+        let sent = Sent::default();
+        let mut connector = Connector::new(configuration, connect(&sent));
+        let sessions: Vec<_> = (0..3).map(|_| connector.open()).collect();
+
+        for &session in &sessions {
+            assert!(connector.submit(session, 1));
+            assert!(connector.submit(session, 2));
+        }
+
+        assert!(!connector.submit(ClientIdentifier::from(42), 1));
+        assert_eq!(connector.pending(), 6);
+
+        // Only the first request of each session is sent, but all the sessions share the connection to the primary.
+        assert_eq!(sent.borrow().len(), 3);
+        assert!(sent.borrow().iter().all(|(index, _)| *index == 0));
+
+        while connector.pending() > 0 {
+            exchange(&mut driver, &mut connector, &sent, &sessions);
+        }
+
+        let mut replies = Vec::from_iter(std::iter::from_fn(|| connector.next_reply()));
+
+        replies.sort_by_key(|(_, reply)| *reply);
+
+        assert_eq!(replies.len(), 6);
+        assert_eq!(replies.last().unwrap().1, 9);
+    }
+
+    #[test]
+    fn reresolve_primary() {
+        let configuration = Configuration::from(3);
+        let mut driver = LocalDriver::new(configuration, |_| 0);
+        let sent = Sent::default();
+        let mut connector = Connector::new(configuration, connect(&sent));
+        let sessions = [connector.open(), connector.open()];
+
+        driver.idle(1);
+        driver.idle(2);
+        driver.drive();
+
+        assert_eq!(driver.replica(1).view(), View::default().next());
+
+        for &session in &sessions {
+            connector.submit(session, 1);
+        }
+
+        // The former primary points the first session at the new primary, which every session then uses.
+        exchange(&mut driver, &mut connector, &sent, &sessions);
+
+        assert_eq!(connector.primary(), 1);
+
+        exchange(&mut driver, &mut connector, &sent, &sessions);
+
+        let mut replies = Vec::from_iter(std::iter::from_fn(|| connector.next_reply()));
+
+        replies.sort_by_key(|(_, reply)| *reply);
+
+        assert_eq!(connector.pending(), 0);
+        assert_eq!(
+            Vec::from_iter(replies.iter().map(|(_, reply)| *reply)),
+            [1, 2]
+        );
+    }
+
+    #[test]
+    fn reconnect() {
+        let configuration = Configuration::from(3);
+        let mut driver = LocalDriver::new(configuration, |_| 0);
+        let sent = Sent::default();
+        let mut connector = Connector::new(configuration, connect(&sent));
+        let session = connector.open();
+
+        connector.connections[0].broken = true;
+        connector.submit(session, 5);
+        exchange(&mut driver, &mut connector, &sent, &[session]);
+
+        assert_eq!(connector.next_reply(), Some((session, 5)));
+
+        connector.submit(session, 1);
+        connector.retry();
+
+        // The request went to the primary, then to every replica.
+        assert_eq!(sent.borrow().len(), 4);
+
+        let (client, unanswered) = connector.close(session).unwrap();
+
+        assert_eq!(client.identifier(), session);
+        assert_eq!(unanswered, vec![1]);
+        assert_eq!(connector.pending(), 0);
+    }
+}
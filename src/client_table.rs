@@ -1,7 +1,19 @@
-use crate::request::{ClientIdentifier, Reply, Request, RequestIdentifier};
-use std::cmp::Ordering;
-use std::collections::HashMap;
+use crate::request::{ClientIdentifier, Reply, Request, RequestIdentifier, RequestState};
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::fmt::Formatter;
+use core::hash::BuildHasher;
+use core::marker::PhantomData;
+use foldhash::fast::FixedState;
+use hashbrown::HashMap;
+use serde::de::{MapAccess, Visitor};
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
+/// The number of shards in a default client table.
+const DEFAULT_SHARDS: usize = 16;
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct CachedRequest<R> {
     request: RequestIdentifier,
     reply: Option<Reply<R>>,
@@ -20,11 +32,13 @@ impl<R> CachedRequest<R> {
     }
 }
 
-pub struct ClientTable<R> {
+/// The sessions of the clients assigned to one shard of a [`ClientTable`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ClientShard<R> {
     cache: HashMap<ClientIdentifier, CachedRequest<R>>,
 }
 
-impl<R> Default for ClientTable<R> {
+impl<R> Default for ClientShard<R> {
     fn default() -> Self {
         Self {
             cache: Default::default(),
@@ -32,7 +46,7 @@ impl<R> Default for ClientTable<R> {
     }
 }
 
-impl<R> ClientTable<R> {
+impl<R> ClientShard<R> {
     pub fn compare<T>(&self, request: &Request<T>) -> Result<Ordering, RequestIdentifier> {
         match self.cache.get(&request.client) {
             None => Ok(Ordering::Greater),
@@ -49,6 +63,29 @@ impl<R> ClientTable<R> {
             .and_then(CachedRequest::reply)
     }
 
+    /// The number of the latest request started for the client, if any.
+    pub fn last_request(&self, client: ClientIdentifier) -> Option<RequestIdentifier> {
+        self.cache.get(&client).map(|cached| cached.request)
+    }
+
+    /// The reply to the latest request of the client, once the request is executed.
+    pub fn last_reply(&self, client: ClientIdentifier) -> Option<&Reply<R>> {
+        self.cache.get(&client).and_then(CachedRequest::reply)
+    }
+
+    /// Whether the group executed the given request of the client.
+    /// A client only starts a request once its previous one is executed, so older requests are executed too.
+    pub fn state(&self, client: ClientIdentifier, request: RequestIdentifier) -> RequestState {
+        match self.cache.get(&client) {
+            Some(cached) if request < cached.request => RequestState::Executed,
+            Some(cached) if request == cached.request && cached.reply.is_some() => {
+                RequestState::Executed
+            }
+            Some(cached) if request == cached.request => RequestState::Pending,
+            _ => RequestState::NotStarted,
+        }
+    }
+
     pub fn finish<T>(&mut self, request: &Request<T>, reply: Reply<R>) {
         let last_request = self
             .cache
@@ -64,13 +101,168 @@ impl<R> ClientTable<R> {
     }
 }
 
+/// The latest request of each client along with its reply once the request is executed.
+/// The table is included in checkpoints so that restored replicas do not execute a request twice.
+///
+/// Clients are partitioned into shards by their identifier. Each shard can be updated independently
+/// (e.g. from multiple threads via [`ClientTable::shards_mut`]) without a lock over the whole table.
+/// The table serializes as a single map, so the number of shards is not part of the persisted format.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ClientTable<R> {
+    shards: Vec<ClientShard<R>>,
+}
+
+impl<R> Default for ClientTable<R> {
+    fn default() -> Self {
+        Self::with_shards(DEFAULT_SHARDS)
+    }
+}
+
+impl<R> ClientTable<R> {
+    /// Creates an empty table with the given number of shards (at least one).
+    pub fn with_shards(shards: usize) -> Self {
+        Self {
+            shards: (0..shards.max(1)).map(|_| ClientShard::default()).collect(),
+        }
+    }
+
+    /// The index of the shard holding the session of the client.
+    pub fn shard(&self, client: ClientIdentifier) -> usize {
+        (FixedState::default().hash_one(client) % self.shards.len() as u64) as usize
+    }
+
+    /// Mutable access to every shard at once, in the order of their indices.
+    pub fn shards_mut(&mut self) -> core::slice::IterMut<'_, ClientShard<R>> {
+        self.shards.iter_mut()
+    }
+
+    pub fn compare<T>(&self, request: &Request<T>) -> Result<Ordering, RequestIdentifier> {
+        self.shards[self.shard(request.client)].compare(request)
+    }
+
+    pub fn reply<T>(&self, request: &Request<T>) -> Option<&Reply<R>> {
+        self.shards[self.shard(request.client)].reply(request)
+    }
+
+    /// The number of the latest request started for the client, if any.
+    pub fn last_request(&self, client: ClientIdentifier) -> Option<RequestIdentifier> {
+        self.shards[self.shard(client)].last_request(client)
+    }
+
+    /// The reply to the latest request of the client, once the request is executed.
+    pub fn last_reply(&self, client: ClientIdentifier) -> Option<&Reply<R>> {
+        self.shards[self.shard(client)].last_reply(client)
+    }
+
+    /// Whether the group executed the given request of the client.
+    pub fn state(&self, client: ClientIdentifier, request: RequestIdentifier) -> RequestState {
+        self.shards[self.shard(client)].state(client, request)
+    }
+
+    pub fn finish<T>(&mut self, request: &Request<T>, reply: Reply<R>) {
+        let shard = self.shard(request.client);
+
+        self.shards[shard].finish(request, reply)
+    }
+
+    pub fn start<T>(&mut self, request: &Request<T>) {
+        let shard = self.shard(request.client);
+
+        self.shards[shard].start(request)
+    }
+
+    /// A copy of the table without the requests that have not been executed yet.
+    pub fn executed(&self) -> Self
+    where
+        R: Clone,
+    {
+        Self {
+            shards: self
+                .shards
+                .iter()
+                .map(|shard| ClientShard {
+                    cache: shard
+                        .cache
+                        .iter()
+                        .filter(|(_, cached)| cached.reply.is_some())
+                        .map(|(client, cached)| (*client, cached.clone()))
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+
+    fn insert(&mut self, client: ClientIdentifier, cached: CachedRequest<R>) {
+        let shard = self.shard(client);
+
+        self.shards[shard].cache.insert(client, cached);
+    }
+}
+
+impl<R> Serialize for ClientTable<R>
+where
+    R: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let length = self.shards.iter().map(|shard| shard.cache.len()).sum();
+        let mut map = serializer.serialize_map(Some(length))?;
+
+        for (client, cached) in self.shards.iter().flat_map(|shard| shard.cache.iter()) {
+            map.serialize_entry(client, cached)?;
+        }
+
+        map.end()
+    }
+}
+
+impl<'de, R> Deserialize<'de> for ClientTable<R>
+where
+    R: Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_map(ClientTableVisitor(PhantomData))
+    }
+}
+
+struct ClientTableVisitor<R>(PhantomData<R>);
+
+impl<'de, R> Visitor<'de> for ClientTableVisitor<R>
+where
+    R: Deserialize<'de>,
+{
+    type Value = ClientTable<R>;
+
+    fn expecting(&self, formatter: &mut Formatter) -> core::fmt::Result {
+        formatter.write_str("a map of client identifiers to cached requests")
+    }
+
+    fn visit_map<A>(self, mut access: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut table = ClientTable::default();
+
+        while let Some((client, cached)) = access.next_entry()? {
+            table.insert(client, cached);
+        }
+
+        Ok(table)
+    }
+}
+
 impl<R> PartialEq<RequestIdentifier> for CachedRequest<R> {
     fn eq(&self, other: &RequestIdentifier) -> bool {
         self.request == *other
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "os"))]
 mod tests {
     use super::*;
     use crate::viewstamp::View;
@@ -105,5 +297,66 @@ mod tests {
         assert_eq!(table.compare(&oldest), Ok(Ordering::Less));
         assert_eq!(table.compare(&current), Ok(Ordering::Equal));
         assert_eq!(table.compare(&newer), Err(current.id));
+        assert_eq!(
+            table.state(client.identifier(), oldest.id),
+            RequestState::Executed
+        );
+        assert_eq!(
+            table.state(client.identifier(), current.id),
+            RequestState::Pending
+        );
+        assert_eq!(
+            table.state(client.identifier(), newer.id),
+            RequestState::NotStarted
+        );
+    }
+
+    #[test]
+    fn shards() {
+        let configuration = Configuration::from(3);
+        let mut table = ClientTable::with_shards(4);
+        let requests = Vec::from_iter((0..32).map(|payload| {
+            let mut client = Client::new(configuration);
+            client.new_request(payload)
+        }));
+
+        let assignments = Vec::from_iter(requests.iter().map(|r| table.shard(r.client)));
+
+        std::thread::scope(|scope| {
+            for (index, shard) in table.shards_mut().enumerate() {
+                let requests = &requests;
+                let assignments = &assignments;
+
+                scope.spawn(move || {
+                    for (request, assignment) in requests.iter().zip(assignments) {
+                        if *assignment == index {
+                            shard.start(request);
+                            shard.finish(
+                                request,
+                                Reply {
+                                    view: View::default(),
+                                    id: request.id,
+                                    payload: request.payload,
+                                },
+                            );
+                        }
+                    }
+                });
+            }
+        });
+
+        let json = serde_json::to_string(&table).unwrap();
+        let restored: ClientTable<i32> = serde_json::from_str(&json).unwrap();
+
+        for request in &requests {
+            assert_eq!(
+                table.reply(request).map(|reply| reply.payload),
+                Some(request.payload)
+            );
+            assert_eq!(
+                restored.reply(request).map(|reply| reply.payload),
+                Some(request.payload)
+            );
+        }
     }
 }
@@ -0,0 +1,76 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+/// The time since the Unix epoch according to the operating system, which replicas compare request deadlines against.
+#[cfg(feature = "os")]
+pub(crate) fn unix_time() -> Duration {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+/// A source of the current time for time-dependent behavior (e.g. idle timers and client retries).
+pub trait Clock {
+    fn now(&self) -> Instant;
+}
+
+/// A clock that reads the time from the operating system.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A clock that only moves forward when advanced, so time-dependent behavior is deterministic.
+/// Clones share the same time.
+///
+/// The clock only reads the time from the operating system the first time [`Clock::now`] is called,
+/// so simulations that measure time with [`SimClock::elapsed`] run on targets without a system clock (e.g. wasm32).
+#[derive(Clone, Debug, Default)]
+pub struct SimClock {
+    start: Arc<OnceLock<Instant>>,
+    elapsed: Arc<AtomicU64>,
+}
+
+impl SimClock {
+    /// Moves the clock forward by the given duration, truncated to whole nanoseconds.
+    pub fn advance(&self, duration: Duration) {
+        let nanos = u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+
+        self.elapsed.fetch_add(nanos, Ordering::SeqCst);
+    }
+
+    /// The total time the clock has been advanced by.
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_nanos(self.elapsed.load(Ordering::SeqCst))
+    }
+}
+
+impl Clock for SimClock {
+    fn now(&self) -> Instant {
+        *self.start.get_or_init(Instant::now) + self.elapsed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn advance() {
+        let clock = SimClock::default();
+        let shared = clock.clone();
+        let start = clock.now();
+
+        assert_eq!(clock.now(), start);
+
+        shared.advance(Duration::from_millis(5));
+
+        assert_eq!(clock.now() - start, Duration::from_millis(5));
+        assert_eq!(clock.elapsed(), Duration::from_millis(5));
+    }
+}
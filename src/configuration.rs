@@ -1,9 +1,30 @@
+use crate::transfer::TransferPolicy;
+use core::time::Duration;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
 pub struct Configuration {
     replicas: usize,
+    observers: usize,
     group: Group,
+    epoch: u64,
+    in_flight_limit: Option<usize>,
+    queue_limit: Option<usize>,
+    state_transfer_limit: Option<usize>,
+    commit_interval: Duration,
+    view_timeout: Duration,
+    view_jitter: Duration,
+    lease: Option<Duration>,
+    heartbeat: Option<(Duration, Duration)>,
+    state_transfer_policy: TransferPolicy,
+    view_change_backoff: Option<Duration>,
+    view_change_timeout: Option<Duration>,
+    recovery_backoff: Option<Duration>,
+    recovery_attempts: Option<u32>,
 }
 
+#[cfg(feature = "os")]
 impl From<usize> for Configuration {
     fn from(value: usize) -> Self {
         Self::new(value, Default::default())
@@ -12,7 +33,133 @@ impl From<usize> for Configuration {
 
 impl Configuration {
     pub fn new(replicas: usize, group: Group) -> Self {
-        Self { replicas, group }
+        Self {
+            replicas,
+            observers: 0,
+            group,
+            epoch: 0,
+            in_flight_limit: None,
+            queue_limit: None,
+            state_transfer_limit: None,
+            commit_interval: Duration::from_millis(50),
+            view_timeout: Duration::from_millis(500),
+            view_jitter: Duration::from_millis(250),
+            lease: None,
+            heartbeat: None,
+            state_transfer_policy: TransferPolicy::Random,
+            view_change_backoff: None,
+            view_change_timeout: None,
+            recovery_backoff: None,
+            recovery_attempts: None,
+        }
+    }
+
+    /// The number of times operators replaced the members of the group, which starts at 0.
+    /// Replicas hand out the epoch along with the configuration to clients that discover the group through them,
+    /// so clients holding a configuration from an earlier epoch know to discover the group again.
+    pub fn with_epoch(mut self, epoch: u64) -> Self {
+        self.epoch = epoch;
+        self
+    }
+
+    /// Adds the given number of observers to the group, which follow the primary without voting.
+    /// Observers take the indices after the voting replicas. They receive prepares and commits and apply operations,
+    /// but never acknowledge operations, join view changes or become primary, so they do not count toward quorums.
+    pub fn with_observers(mut self, observers: usize) -> Self {
+        self.observers = observers;
+        self
+    }
+
+    /// Limits the number of uncommitted operations a primary will accept before replying to clients as busy.
+    pub fn with_in_flight_limit(mut self, limit: usize) -> Self {
+        self.in_flight_limit = Some(limit);
+        self
+    }
+
+    /// Limits the number of inbound messages waiting in the mailbox of a primary before it replies to new requests as busy.
+    pub fn with_queue_limit(mut self, limit: usize) -> Self {
+        self.queue_limit = Some(limit);
+        self
+    }
+
+    /// Limits the number of log entries sent in a single state transfer message.
+    /// Replicas that are further behind request the remaining entries in subsequent messages.
+    pub fn with_state_transfer_limit(mut self, limit: usize) -> Self {
+        self.state_transfer_limit = Some(limit);
+        self
+    }
+
+    /// The interval after which an idle primary re-sends its commit number to the backups.
+    pub fn with_commit_interval(mut self, interval: Duration) -> Self {
+        self.commit_interval = interval;
+        self
+    }
+
+    /// The bounds on how long a backup waits to hear from the primary before starting a view change.
+    /// Each timeout is chosen uniformly at random from `timeout` to `timeout + jitter`,
+    /// so backups are unlikely to start competing view changes at the same time.
+    pub fn with_view_timeout(mut self, timeout: Duration, jitter: Duration) -> Self {
+        self.view_timeout = timeout;
+        self.view_jitter = jitter;
+        self
+    }
+
+    /// Fences a primary that has not heard from a sub-majority of backups for longer than the lease,
+    /// so a partitioned primary stops serving requests instead of waiting for clients to find the new primary.
+    /// Backups acknowledge commit messages while a lease is configured so that idle primaries can renew their lease,
+    /// and tell outdated primaries about the view they promised to join.
+    pub fn with_lease(mut self, lease: Duration) -> Self {
+        self.lease = Some(lease);
+        self
+    }
+
+    /// Adapts how often an idle primary re-sends its commit number, from the commit interval on every idle period
+    /// to a heartbeat between the given bounds. The primary doubles the interval up to `max` while every backup
+    /// acknowledged its operations, and drops back to `min` when a backup falls behind or is suspected of failing
+    /// (see [`crate::Replica::suspect`]). The primary idles every commit interval either way, so `min` is rounded up
+    /// to a multiple of it, while `max` must stay below the view timeout so backups never mistake a quiet primary for a failed one.
+    pub fn with_heartbeat(mut self, min: Duration, max: Duration) -> Self {
+        self.heartbeat = Some((min, max.max(min)));
+        self
+    }
+
+    /// How lagging replicas choose the replica they request state from.
+    /// Replicas avoid peers that did not answer their previous request regardless of the policy.
+    pub fn with_state_transfer_policy(mut self, policy: TransferPolicy) -> Self {
+        self.state_transfer_policy = policy;
+        self
+    }
+
+    /// Doubles the idle timeout of a replica after each view change it starts on its own without the group
+    /// reaching a new view, up to the given limit. Damps storms of view changes when replicas keep timing out
+    /// (e.g. a flapping network), since a backed-off replica starts at most one view change per limit.
+    pub fn with_view_change_backoff(mut self, limit: Duration) -> Self {
+        self.view_change_backoff = Some(limit);
+        self
+    }
+
+    /// Bounds how long a replica stays in a view change before it gives up on the view and starts a change to the
+    /// next one, even when it is the primary of the view or has not heard from a sub-majority of replicas yet.
+    /// Keeps the group live when a view can never complete (e.g. its primary crashed during the view change).
+    /// Replicas measure the time as the number of idle periods times the shortest idle timeout of their role,
+    /// so a view change may last longer than the bound but never less.
+    pub fn with_view_change_timeout(mut self, timeout: Duration) -> Self {
+        self.view_change_timeout = Some(timeout);
+        self
+    }
+
+    /// Doubles the idle timeout of a recovering replica after each recovery request it re-sends without recovering,
+    /// up to the given limit, so a replica that cannot reach a primary does not flood the group with requests.
+    pub fn with_recovery_backoff(mut self, limit: Duration) -> Self {
+        self.recovery_backoff = Some(limit);
+        self
+    }
+
+    /// The number of times a recovering replica re-sends its recovery request before it reports that recovery stalled
+    /// through [`crate::EventListener::on_recovery_stalled`]. The replica keeps retrying after the report.
+    pub fn with_recovery_attempts(mut self, attempts: u32) -> Self {
+        self.recovery_attempts = Some(attempts);
+        self
     }
 
     pub fn replicas(&self) -> usize {
@@ -23,6 +170,97 @@ impl Configuration {
         self.group
     }
 
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    pub fn in_flight_limit(&self) -> Option<usize> {
+        self.in_flight_limit
+    }
+
+    pub fn queue_limit(&self) -> Option<usize> {
+        self.queue_limit
+    }
+
+    pub fn state_transfer_limit(&self) -> Option<usize> {
+        self.state_transfer_limit
+    }
+
+    pub fn commit_interval(&self) -> Duration {
+        self.commit_interval
+    }
+
+    pub fn view_timeout(&self) -> Duration {
+        self.view_timeout
+    }
+
+    pub fn view_jitter(&self) -> Duration {
+        self.view_jitter
+    }
+
+    pub fn lease(&self) -> Option<Duration> {
+        self.lease
+    }
+
+    pub fn heartbeat(&self) -> Option<(Duration, Duration)> {
+        self.heartbeat
+    }
+
+    pub fn state_transfer_policy(&self) -> TransferPolicy {
+        self.state_transfer_policy
+    }
+
+    pub fn view_change_backoff(&self) -> Option<Duration> {
+        self.view_change_backoff
+    }
+
+    pub fn view_change_timeout(&self) -> Option<Duration> {
+        self.view_change_timeout
+    }
+
+    pub fn recovery_backoff(&self) -> Option<Duration> {
+        self.recovery_backoff
+    }
+
+    pub fn recovery_attempts(&self) -> Option<u32> {
+        self.recovery_attempts
+    }
+
+    /// How long a replica should wait for a message before it is considered idle.
+    /// Backups get a new random timeout within the configured bounds on every call.
+    #[cfg(feature = "os")]
+    pub fn idle_timeout(&self, primary: bool) -> Duration {
+        self.idle_timeout_with(primary, &mut rand::thread_rng())
+    }
+
+    /// The idle timeout with the jitter of backups drawn from the given random number generator
+    /// (e.g. a seeded one in a deterministic simulation).
+    pub fn idle_timeout_with<R>(&self, primary: bool, rng: &mut R) -> Duration
+    where
+        R: Rng + ?Sized,
+    {
+        if primary {
+            self.commit_interval
+        } else {
+            self.view_timeout + rng.gen_range(Duration::ZERO..=self.view_jitter)
+        }
+    }
+
+    /// The number of observers following the voting replicas.
+    pub fn observers(&self) -> usize {
+        self.observers
+    }
+
+    /// The number of voting replicas and observers in the group.
+    pub fn members(&self) -> usize {
+        self.replicas + self.observers
+    }
+
+    /// Whether the replica with the given index is an observer rather than a voting replica.
+    pub fn is_observer(&self, index: usize) -> bool {
+        index >= self.replicas
+    }
+
     pub fn sub_majority(&self) -> usize {
         (self.replicas - 1) / 2
     }
@@ -30,13 +268,58 @@ impl Configuration {
     pub fn quorum(&self) -> usize {
         self.sub_majority() + 1
     }
+
+    /// The identifier of the replica with the given index, if the index is part of this configuration.
+    pub fn replica(&self, index: usize) -> Option<ReplicaIdentifier> {
+        (index < self.members()).then_some(ReplicaIdentifier {
+            group: self.group,
+            index,
+            observer: self.is_observer(index),
+        })
+    }
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub struct Group(u128);
 
+#[cfg(feature = "os")]
 impl Default for Group {
     fn default() -> Self {
         Self(uuid::Uuid::new_v4().as_u128())
     }
 }
+
+impl From<u128> for Group {
+    fn from(value: u128) -> Self {
+        Self(value)
+    }
+}
+
+impl From<Group> for u128 {
+    fn from(value: Group) -> Self {
+        value.0
+    }
+}
+
+/// Uniquely identifies a replica across groups.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct ReplicaIdentifier {
+    group: Group,
+    index: usize,
+    observer: bool,
+}
+
+impl ReplicaIdentifier {
+    pub fn group(&self) -> Group {
+        self.group
+    }
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Whether the replica follows the group without voting. See [`Configuration::with_observers`].
+    pub fn is_observer(&self) -> bool {
+        self.observer
+    }
+}
@@ -0,0 +1,179 @@
+use crate::replica::Replica;
+use crate::service::Service;
+use crate::viewstamp::OpNumber;
+use core::error::Error;
+use core::fmt::{Display, Formatter};
+
+/// Safely retires a replica after operators replaced the members of its group without it.
+///
+/// The replica stops taking part in the protocol right away, but its durable state (e.g. its log and checkpoints)
+/// may be the only copy of committed operations until the group that replaced it catches up.
+/// The state is only disposed of once a replica of a later epoch reports it committed every operation the retired
+/// replica committed. A single report is enough, since a replica only commits an operation a majority stored.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Decommission {
+    epoch: u64,
+    committed: OpNumber,
+    successor: Option<OpNumber>,
+}
+
+impl Decommission {
+    /// Retires the replica. See [`Replica::retire`].
+    pub fn begin<S>(replica: &mut Replica<S>) -> Self
+    where
+        S: Service,
+    {
+        Self {
+            epoch: replica.configuration().epoch(),
+            committed: replica.retire(),
+            successor: None,
+        }
+    }
+
+    /// The op-number of the latest operation the retired replica committed.
+    pub fn committed(&self) -> OpNumber {
+        self.committed
+    }
+
+    /// Records the latest committed op-number reported by a replica of the given epoch (e.g. from its status).
+    /// Reports from the epoch of the retired replica or earlier ones are ignored. Returns whether the group that
+    /// replaced the retired replica caught up.
+    pub fn observe(&mut self, epoch: u64, committed: OpNumber) -> bool {
+        if epoch > self.epoch {
+            self.successor = self.successor.max(Some(committed));
+        }
+
+        self.is_caught_up()
+    }
+
+    /// Whether a replica of a later epoch committed every operation the retired replica committed.
+    pub fn is_caught_up(&self) -> bool {
+        self.successor
+            .is_some_and(|committed| committed >= self.committed)
+    }
+
+    /// Deletes the durable state of the retired replica with the given function, once the group that replaced it
+    /// caught up. Reports the decommissioned replica on success, or returns the decommission to retry later.
+    pub fn dispose<F, E>(self, delete: F) -> Result<Decommissioned, DecommissionError<E>>
+    where
+        F: FnOnce() -> Result<(), E>,
+    {
+        match self.successor {
+            Some(successor) if successor >= self.committed => {
+                delete().map_err(DecommissionError::Dispose)?;
+
+                Ok(Decommissioned {
+                    epoch: self.epoch,
+                    committed: self.committed,
+                })
+            }
+            _ => Err(DecommissionError::Lagging(self)),
+        }
+    }
+}
+
+/// The report of a replica whose durable state was disposed of.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Decommissioned {
+    /// The epoch of the group the replica was a member of.
+    pub epoch: u64,
+    /// The op-number of the latest operation the replica committed.
+    pub committed: OpNumber,
+}
+
+/// Why the durable state of a retired replica was not disposed of.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum DecommissionError<E> {
+    /// No replica of a later epoch reported committing every operation the retired replica committed.
+    /// Holds the decommission to retry once more replicas report.
+    Lagging(Decommission),
+    /// Deleting the durable state failed. The state may be partially deleted, but the group no longer needs it.
+    Dispose(E),
+}
+
+impl<E> Display for DecommissionError<E>
+where
+    E: Display,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            DecommissionError::Lagging(decommission) => write!(
+                f,
+                "the group has not committed operation {:?} of the retired replica yet",
+                decommission.committed
+            ),
+            DecommissionError::Dispose(error) => {
+                write!(f, "unable to dispose of the durable state: {error}")
+            }
+        }
+    }
+}
+
+impl<E> Error for DecommissionError<E> where E: Error {}
+
+#[cfg(all(test, feature = "os"))]
+mod tests {
+    use super::*;
+    use crate::buffer::BufferedMailbox;
+    use crate::configuration::Configuration;
+    use crate::protocol::PrepareOk;
+    use crate::Client;
+
+    #[test]
+    fn decommission() {
+        let configuration = Configuration::from(3);
+        let mut replica = Replica::new(configuration, 0, 0);
+        let mut mailbox = BufferedMailbox::default();
+        let mut client = Client::new(configuration);
+        let mut other = Client::new(configuration);
+
+        replica.handle_request(client.new_request(1), &mut mailbox);
+        replica.handle_request(other.new_request(2), &mut mailbox);
+        replica.handle_prepare_ok(
+            PrepareOk {
+                view: replica.view(),
+                epoch: 0,
+                op_number: OpNumber::from(2),
+                index: 1,
+                committed: OpNumber::default(),
+                promised: replica.view(),
+            },
+            &mut mailbox,
+        );
+
+        let mut decommission = Decommission::begin(&mut replica);
+
+        assert!(replica.is_retired());
+        assert_eq!(decommission.committed(), OpNumber::from(2));
+
+        replica.handle_request(client.new_request(3), &mut mailbox);
+
+        assert_eq!(replica.committed(), OpNumber::from(2));
+
+        assert!(!decommission.observe(0, OpNumber::from(5)));
+        assert!(!decommission.observe(1, OpNumber::from(1)));
+
+        let lagging = decommission.clone().dispose(|| Ok::<_, String>(()));
+
+        assert_eq!(
+            lagging,
+            Err(DecommissionError::Lagging(decommission.clone()))
+        );
+        assert!(decommission.observe(1, OpNumber::from(2)));
+
+        let mut deleted = false;
+        let report = decommission.dispose(|| {
+            deleted = true;
+            Ok::<_, String>(())
+        });
+
+        assert!(deleted);
+        assert_eq!(
+            report,
+            Ok(Decommissioned {
+                epoch: 0,
+                committed: OpNumber::from(2)
+            })
+        );
+    }
+}
@@ -0,0 +1,128 @@
+use crate::request::{ClientIdentifier, Reply, RequestIdentifier};
+use alloc::collections::VecDeque;
+use hashbrown::HashMap;
+
+/// The number of unacknowledged replies the primary keeps for each client.
+const DEFAULT_LIMIT: usize = 16;
+
+/// The replies the primary sent to clients with a session that the clients have not acknowledged yet,
+/// so a client whose connection dropped (taking any replies in flight with it) receives them again when it resumes.
+///
+/// Replies are only buffered for clients that opened or resumed a session with this replica,
+/// and only a bounded number of them per client, discarding the oldest reply to make room.
+#[derive(Clone, Debug)]
+pub(crate) struct ReplyBuffer<R> {
+    sessions: HashMap<ClientIdentifier, VecDeque<Reply<R>>>,
+    limit: usize,
+}
+
+impl<R> Default for ReplyBuffer<R> {
+    fn default() -> Self {
+        Self {
+            sessions: Default::default(),
+            limit: DEFAULT_LIMIT,
+        }
+    }
+}
+
+impl<R> ReplyBuffer<R> {
+    /// Starts buffering the replies to the client, keeping any replies already buffered.
+    pub fn open(&mut self, client: ClientIdentifier) {
+        self.sessions.entry(client).or_default();
+    }
+
+    /// Whether the replies to the client are buffered.
+    pub fn is_open(&self, client: ClientIdentifier) -> bool {
+        self.sessions.contains_key(&client)
+    }
+
+    /// Buffers a reply sent to the client until it is acknowledged, if the client has a session.
+    pub fn push(&mut self, client: ClientIdentifier, reply: Reply<R>) {
+        let Some(replies) = self.sessions.get_mut(&client) else {
+            return;
+        };
+
+        if replies.len() >= self.limit {
+            replies.pop_front();
+        }
+
+        replies.push_back(reply);
+    }
+
+    /// Discards the buffered replies to the given request of the client and every request before it.
+    pub fn acknowledge(&mut self, client: ClientIdentifier, request: RequestIdentifier) {
+        if let Some(replies) = self.sessions.get_mut(&client) {
+            replies.retain(|reply| reply.id > request);
+        }
+    }
+
+    /// Discards the buffered replies to the requests of the client before the given one.
+    /// Clients only send a request once they received the reply to their previous one,
+    /// so a new request acknowledges every earlier reply.
+    pub fn acknowledge_before(&mut self, client: ClientIdentifier, request: RequestIdentifier) {
+        if let Some(replies) = self.sessions.get_mut(&client) {
+            replies.retain(|reply| reply.id >= request);
+        }
+    }
+
+    /// The buffered replies to the client in the order they were sent.
+    pub fn undelivered(&self, client: ClientIdentifier) -> impl Iterator<Item = &Reply<R>> {
+        self.sessions.get(&client).into_iter().flatten()
+    }
+
+    /// Forgets every session, e.g. when the replica stops being the primary.
+    pub fn clear(&mut self) {
+        self.sessions.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::viewstamp::View;
+
+    fn reply(id: u128) -> Reply<i32> {
+        let mut request = RequestIdentifier::default();
+
+        for _ in 0..id {
+            request.increment();
+        }
+
+        Reply {
+            view: View::default(),
+            id: request,
+            payload: id as i32,
+        }
+    }
+
+    #[test]
+    fn buffer() {
+        let mut buffer = ReplyBuffer::default();
+        let client = ClientIdentifier::from(1);
+        let other = ClientIdentifier::from(2);
+
+        buffer.open(client);
+        buffer.push(client, reply(1));
+        buffer.push(client, reply(2));
+        buffer.push(client, reply(3));
+        buffer.push(other, reply(1));
+
+        assert_eq!(buffer.undelivered(client).count(), 3);
+        assert_eq!(buffer.undelivered(other).count(), 0);
+
+        buffer.acknowledge(client, reply(1).id);
+        buffer.acknowledge_before(client, reply(3).id);
+
+        assert_eq!(
+            Vec::from_iter(buffer.undelivered(client).cloned()),
+            vec![reply(3)]
+        );
+
+        for id in 4..=DEFAULT_LIMIT as u128 + 4 {
+            buffer.push(client, reply(id));
+        }
+
+        assert_eq!(buffer.undelivered(client).count(), DEFAULT_LIMIT);
+        assert_eq!(buffer.undelivered(client).next(), Some(&reply(5)));
+    }
+}
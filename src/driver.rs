@@ -0,0 +1,1149 @@
+use crate::buffer::{BufferedMailbox, ClientPayload, ProtocolPayload};
+use crate::clock::SimClock;
+use crate::configuration::Configuration;
+use crate::durable::{seal, unseal, CorruptState, FaultyState, Remedy, SaveFault, State};
+use crate::nonce::Nonce;
+use crate::protocol::Checkpoint;
+use crate::replica::Replica;
+use crate::request::{ClientIdentifier, Request};
+use crate::service::{Serializable, Service};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+use trace::Recorder;
+
+#[cfg(feature = "threads")]
+mod cluster;
+#[cfg(feature = "threads")]
+mod controller;
+mod corruption;
+mod diagram;
+mod nemesis;
+mod scenario;
+mod trace;
+
+#[cfg(feature = "threads")]
+pub use cluster::{ClusterClient, ClusterRunner, ReplicaStatus, Timeout};
+#[cfg(feature = "threads")]
+pub use controller::{ClusterController, RestartError};
+pub use diagram::{export_trace, DiagramEvent, DiagramFormat};
+pub use nemesis::{shrink, Fault, History, Nemesis, NemesisEvent, Operation, Violation};
+pub use scenario::{Scenario, ScenarioError, Step, Target};
+pub use trace::TraceEvent;
+
+struct Node<S>
+where
+    S: Service,
+{
+    replica: Replica<S>,
+    checkpoint: Checkpoint<S::Checkpoint, S::Reply>,
+    /// The checkpoint the replica started with, which it recovers from when its persisted state is corrupt.
+    genesis: Checkpoint<S::Checkpoint, S::Reply>,
+    /// Persists the checkpoints of the replica when set, replacing the in-memory checkpoint as the one to recover from.
+    storage: Option<Box<dyn State + Send>>,
+    crashed: bool,
+    /// The time elapsed on the simulated clock when the replica becomes idle.
+    deadline: Duration,
+    inbox: BufferedMailbox<S>,
+    requests: VecDeque<Request<S::Request>>,
+    stalled: Vec<ProtocolPayload<S>>,
+    outbox: BufferedMailbox<S>,
+}
+
+impl<S> Node<S>
+where
+    S: Service + Serializable,
+{
+    /// Saves the latest checkpoint to the storage of the replica, if it has any.
+    fn persist(&mut self) {
+        if let (Some(storage), Ok(bytes)) = (self.storage.as_mut(), seal(&self.checkpoint)) {
+            storage.save(&bytes).ok();
+        }
+    }
+
+    /// The checkpoint to recover from after a crash, loaded from the storage of the replica if it has any.
+    /// Storage that has nothing saved yet recovers from the checkpoint the replica started with.
+    fn restore(&self) -> Result<Checkpoint<S::Checkpoint, S::Reply>, CorruptState> {
+        let Some(storage) = self.storage.as_ref() else {
+            return Ok(self.checkpoint.clone());
+        };
+
+        match storage.load() {
+            Ok(Some(bytes)) => unseal(&bytes),
+            Ok(None) => Ok(self.genesis.clone()),
+            Err(error) => panic!("unable to load the persisted state: {error}"),
+        }
+    }
+}
+
+/// Drives a group of replicas in a single thread by delivering messages between them in a deterministic order.
+/// Useful for testing and benchmarking the protocol without the non-determinism of a network.
+///
+/// Messages a replica re-queues for later (e.g. while waiting on a state transfer) are only re-delivered
+/// after the replica processes another message, so the group always reaches a quiescent state.
+///
+/// Time only passes when the driver's [`SimClock`] is advanced, at which point replicas that have not
+/// processed a message within their idle timeout are notified they are idle.
+///
+/// Every input the driver processes can be recorded to a trace and replayed to reproduce the execution.
+///
+/// Links between replicas can be made lossy or duplicate messages to test the protocol's tolerance of faulty networks.
+/// Messages can also be corrupted in transit to check that replicas ignore malformed input instead of panicking.
+/// Faults, the jitter of idle timeouts and the choices of replicas are decided by a seeded random number generator,
+/// so a seed always produces the same execution. The driver does not read the system clock or random number generator
+/// either, so it runs on targets without them (e.g. wasm32) when the crate is built without the `os` feature,
+/// which requires the configuration and clients to be created with explicit identifiers
+/// (i.e. [`Configuration::new`] and [`Client::new_with_identifier`](crate::Client::new_with_identifier)).
+pub struct LocalDriver<S>
+where
+    S: Service,
+{
+    configuration: Configuration,
+    clock: SimClock,
+    nodes: Vec<Node<S>>,
+    replies: HashMap<ClientIdentifier, VecDeque<ClientPayload<S>>>,
+    cursor: usize,
+    recorder: Option<Recorder>,
+    replaying: bool,
+    rng: StdRng,
+    /// The probability of dropping a message on the link from one replica to another.
+    loss_rates: HashMap<(usize, usize), f64>,
+    /// The number of upcoming messages on the link from one replica to another to deliver twice.
+    duplicates: HashMap<(usize, usize), usize>,
+    /// The probability of changing a random field of a message in transit.
+    corruption_rate: f64,
+    /// Whether to check that replicas agree on their committed operations after every step.
+    check_agreement: bool,
+    /// Whether to discard superseded view change and recovery messages before each delivery.
+    coalesce: bool,
+    /// The number of messages discarded by coalescing.
+    coalesced: usize,
+    /// The number of times a replica found its persisted state corrupt and recovered as a new replica.
+    recovered_as_new: usize,
+}
+
+impl<S> LocalDriver<S>
+where
+    S: Service + Serializable,
+{
+    /// Creates a driver for a group, including its observers, where each replica's service is created by the given factory.
+    pub fn new<F>(configuration: Configuration, mut factory: F) -> Self
+    where
+        F: FnMut(usize) -> S,
+    {
+        let clock = SimClock::default();
+        let mut rng = StdRng::seed_from_u64(0);
+        let nodes = (0..configuration.members())
+            .map(|index| {
+                let mut replica = Replica::new(configuration, index, factory(index));
+
+                replica.seed(index as u64);
+
+                Node {
+                    checkpoint: replica.checkpoint(),
+                    genesis: replica.checkpoint(),
+                    storage: None,
+                    deadline: replica.idle_timeout_with(&mut rng),
+                    replica,
+                    crashed: false,
+                    inbox: Default::default(),
+                    requests: Default::default(),
+                    stalled: Default::default(),
+                    outbox: Default::default(),
+                }
+            })
+            .collect();
+
+        Self {
+            configuration,
+            clock,
+            nodes,
+            replies: Default::default(),
+            cursor: 0,
+            recorder: None,
+            replaying: false,
+            rng,
+            loss_rates: Default::default(),
+            duplicates: Default::default(),
+            corruption_rate: 0.0,
+            check_agreement: false,
+            coalesce: false,
+            coalesced: 0,
+            recovered_as_new: 0,
+        }
+    }
+
+    /// Re-creates the execution recorded in the trace at the given path.
+    /// The configuration and service factory must match the ones used by the recorded driver.
+    /// Messages sent by replicas during the replay are only delivered as dictated by the trace.
+    pub fn replay<F>(
+        configuration: Configuration,
+        factory: F,
+        path: impl AsRef<Path>,
+    ) -> io::Result<Self>
+    where
+        F: FnMut(usize) -> S,
+    {
+        let mut driver = Self::new(configuration, factory);
+
+        driver.replaying = true;
+
+        for event in trace::read(path)? {
+            driver.apply(event);
+        }
+
+        Ok(driver)
+    }
+
+    /// Re-seeds the random number generator that decides which messages are dropped, the jitter of idle timeouts
+    /// and the nonces of recovering replicas.
+    pub fn seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    /// Drops messages sent from one replica to another with the given probability (between 0 and 1).
+    pub fn set_loss_rate(&mut self, from: usize, to: usize, probability: f64) {
+        self.loss_rates
+            .insert((from, to), probability.clamp(0.0, 1.0));
+    }
+
+    /// Delivers the next message sent from one replica to another twice.
+    pub fn duplicate_next(&mut self, from: usize, to: usize) {
+        *self.duplicates.entry((from, to)).or_default() += 1;
+    }
+
+    /// Changes a random field (e.g. a view, an op-number or part of a payload) of messages between replicas
+    /// with the given probability (between 0 and 1). Messages that no longer decode after the change are dropped.
+    pub fn set_corruption_rate(&mut self, probability: f64) {
+        self.corruption_rate = probability.clamp(0.0, 1.0);
+    }
+
+    /// Checks that running replicas agree on the operations they have all committed after every step,
+    /// panicking when two replicas committed different requests at the same op-number.
+    pub fn set_agreement_check(&mut self, enabled: bool) {
+        self.check_agreement = enabled;
+    }
+
+    /// Discards queued view change and recovery messages superseded by a later one from the same sender before
+    /// delivering each message, as with [`BufferedMailbox::coalesce_view_changes`].
+    pub fn set_coalescing(&mut self, enabled: bool) {
+        self.coalesce = enabled;
+    }
+
+    /// The number of messages discarded by coalescing so far.
+    pub fn coalesced(&self) -> usize {
+        self.coalesced
+    }
+
+    /// Records every input processed from now on to a trace at the given path, replacing any existing file.
+    pub fn record(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.recorder = Some(Recorder::create(path)?);
+        Ok(())
+    }
+
+    /// Stops recording, reporting any error encountered while writing the trace.
+    pub fn stop_recording(&mut self) -> io::Result<()> {
+        match self.recorder.take() {
+            Some(recorder) => recorder.finish(),
+            None => Ok(()),
+        }
+    }
+
+    pub fn configuration(&self) -> Configuration {
+        self.configuration
+    }
+
+    pub fn clock(&self) -> &SimClock {
+        &self.clock
+    }
+
+    pub fn replica(&self, index: usize) -> &Replica<S> {
+        &self.nodes[index].replica
+    }
+
+    pub fn replica_mut(&mut self, index: usize) -> &mut Replica<S> {
+        &mut self.nodes[index].replica
+    }
+
+    pub fn replicas(&self) -> impl Iterator<Item = &Replica<S>> {
+        self.nodes.iter().map(|node| &node.replica)
+    }
+
+    pub fn replicas_mut(&mut self) -> impl Iterator<Item = &mut Replica<S>> {
+        self.nodes.iter_mut().map(|node| &mut node.replica)
+    }
+
+    pub fn is_crashed(&self, index: usize) -> bool {
+        self.nodes[index].crashed
+    }
+
+    /// Queues a client request for the replica at the given index.
+    /// Request deadlines are measured against the time elapsed on the simulated clock.
+    pub fn request(&mut self, index: usize, request: Request<S::Request>) {
+        let node = &mut self.nodes[index];
+
+        if !node.crashed {
+            node.requests.push_back(request);
+        }
+    }
+
+    /// Queues a client request for every replica in the group.
+    pub fn broadcast_request(&mut self, request: Request<S::Request>) {
+        for index in 0..self.nodes.len() {
+            self.request(index, request.clone());
+        }
+    }
+
+    /// Removes all the messages sent to the given client so far.
+    pub fn take_replies(&mut self, client: ClientIdentifier) -> Vec<ClientPayload<S>> {
+        self.replies
+            .remove(&client)
+            .map(Vec::from)
+            .unwrap_or_default()
+    }
+
+    /// Notifies the replica that it has not received any messages within its timeout.
+    pub fn idle(&mut self, index: usize) {
+        if self.nodes[index].crashed {
+            return;
+        }
+
+        self.trace(TraceEvent::Idle { index });
+
+        let node = &mut self.nodes[index];
+
+        node.replica.idle_at(self.clock.elapsed(), &mut node.outbox);
+        node.deadline = self.clock.elapsed() + node.replica.idle_timeout_with(&mut self.rng);
+        node.replica.check_invariants();
+        self.route(index);
+    }
+
+    /// Moves the clock forward, notifying every replica whose idle timeout elapsed that it is idle.
+    pub fn advance(&mut self, duration: Duration) {
+        self.trace(TraceEvent::Advance { duration });
+        self.clock.advance(duration);
+
+        let now = self.clock.elapsed();
+
+        for index in 0..self.nodes.len() {
+            if self.nodes[index].deadline <= now {
+                self.idle(index);
+            }
+        }
+    }
+
+    /// Moves the clock forward to the earliest idle timeout of a running replica, notifies the replicas whose timeout
+    /// elapsed that they are idle, then processes messages until the group is idle again.
+    /// Returns how far the clock moved, which is zero when every replica crashed.
+    pub fn tick(&mut self) -> Duration {
+        let now = self.clock.elapsed();
+        let duration = self
+            .nodes
+            .iter()
+            .filter(|node| !node.crashed)
+            .map(|node| node.deadline.saturating_sub(now))
+            .min()
+            .unwrap_or_default();
+
+        self.advance(duration);
+        self.drive();
+
+        duration
+    }
+
+    /// Persists the checkpoints of the replica to the given state (e.g. a file or an embedded database),
+    /// starting with its latest checkpoint. The replica recovers from whatever the state loads after a crash,
+    /// so persistence implementations can be validated against the protocol. A failed save leaves the previous state in place.
+    pub fn set_state(&mut self, index: usize, state: Box<dyn State + Send>) {
+        let node = &mut self.nodes[index];
+
+        node.storage = Some(state);
+        node.persist();
+    }
+
+    /// The number of times a replica found its persisted state corrupt and recovered as a new replica.
+    pub fn recovered_as_new(&self) -> usize {
+        self.recovered_as_new
+    }
+
+    /// Takes a durable checkpoint of the replica that it will recover from after a crash.
+    pub fn checkpoint(&mut self, index: usize) {
+        if self.nodes[index].crashed {
+            return;
+        }
+
+        self.trace(TraceEvent::Checkpoint { index });
+
+        let node = &mut self.nodes[index];
+
+        node.checkpoint = node.replica.checkpoint();
+        node.persist();
+    }
+
+    /// Takes a durable checkpoint of the replica when at least the given number of operations remain in its log.
+    /// The log is compacted to the given suffix once a checkpoint is taken.
+    pub fn checkpoint_with_suffix(&mut self, index: usize, suffix: usize) {
+        if self.nodes[index].crashed {
+            return;
+        }
+
+        self.trace(TraceEvent::CheckpointWithSuffix { index, suffix });
+
+        let node = &mut self.nodes[index];
+
+        if let Some(checkpoint) = node.replica.checkpoint_with_suffix(suffix) {
+            node.checkpoint = checkpoint;
+            node.persist();
+        }
+    }
+
+    /// Crashes the replica while it saves a new checkpoint, with the save failing or only partially completing.
+    /// Replicas without a state set with [`LocalDriver::set_state`] save to memory.
+    pub fn crash_during_save(&mut self, index: usize, fault: SaveFault) {
+        if self.nodes[index].crashed {
+            return;
+        }
+
+        self.trace(TraceEvent::CrashDuringSave { index, fault });
+
+        let node = &mut self.nodes[index];
+        let checkpoint = node.replica.checkpoint();
+        let storage = match node.storage.take() {
+            Some(storage) => storage,
+            None => Box::new(seal(&node.checkpoint).unwrap_or_default()),
+        };
+        let mut state = FaultyState::new(storage);
+
+        state.inject(fault);
+
+        // The save fails by design, leaving whatever it wrote behind for the replica to recover from.
+        if let Ok(bytes) = seal(&checkpoint) {
+            state.save(&bytes).ok();
+        }
+
+        node.storage = Some(state.into_inner());
+
+        self.halt(index);
+    }
+
+    /// Crashes the replica, losing all of its volatile state and any messages sent to it.
+    pub fn crash(&mut self, index: usize) {
+        self.trace(TraceEvent::Crash { index });
+        self.halt(index);
+    }
+
+    fn halt(&mut self, index: usize) {
+        let node = &mut self.nodes[index];
+
+        node.crashed = true;
+        node.inbox = Default::default();
+        node.outbox = Default::default();
+        node.requests.clear();
+        node.stalled.clear();
+    }
+
+    /// Restarts a crashed replica from its last checkpoint using the recovery protocol.
+    pub fn recover(&mut self, index: usize) {
+        let nonce = Nonce::from(self.rng.gen::<u128>());
+
+        self.recover_with_nonce(index, nonce);
+    }
+
+    /// Whether any replica has a message or request waiting to be processed.
+    pub fn is_idle(&self) -> bool {
+        self.nodes
+            .iter()
+            .all(|node| node.inbox.is_empty() && node.requests.is_empty())
+    }
+
+    /// Processes a single message or request on the next replica (in round-robin order) with pending work.
+    /// Returns false when no replica had any pending work.
+    pub fn step(&mut self) -> bool {
+        let replicas = self.nodes.len();
+
+        for offset in 0..replicas {
+            let index = (self.cursor + offset) % replicas;
+
+            if self.process(index) {
+                if self.check_agreement {
+                    self.agree();
+                }
+
+                self.cursor = (index + 1) % replicas;
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Processes messages until the group is idle. Returns the number of steps taken.
+    pub fn drive(&mut self) -> usize {
+        let mut steps = 0;
+
+        while self.step() {
+            steps += 1;
+        }
+
+        steps
+    }
+
+    fn process(&mut self, index: usize) -> bool {
+        let node = &mut self.nodes[index];
+
+        if self.coalesce {
+            self.coalesced += node.inbox.coalesce_view_changes();
+        }
+
+        if let Some(message) = node.inbox.pop_inbound() {
+            if self.recorder.is_some() {
+                self.trace(TraceEvent::Deliver {
+                    index,
+                    message: message.clone(),
+                });
+            }
+
+            self.handle_message(index, message);
+        } else if let Some(request) = node.requests.pop_front() {
+            if self.recorder.is_some() {
+                self.trace(TraceEvent::Request {
+                    index,
+                    request: request.clone(),
+                });
+            }
+
+            self.handle_request(index, request);
+        } else {
+            return false;
+        }
+
+        true
+    }
+
+    /// Panics when two running replicas committed different requests at the same op-number.
+    fn agree(&self) {
+        let running = Vec::from_iter(self.nodes.iter().filter(|node| !node.crashed));
+
+        for (offset, a) in running.iter().enumerate() {
+            for b in running.iter().skip(offset + 1) {
+                let (a, b) = (&a.replica, &b.replica);
+                let committed = a.committed().min(b.committed());
+                let mut current = a.log().first_op_number().max(b.log().first_op_number());
+
+                while current <= committed {
+                    if let (Some(x), Some(y)) = (a.log().get(current), b.log().get(current)) {
+                        assert!(
+                            (x.request().client, x.request().id)
+                                == (y.request().client, y.request().id),
+                            "replicas {} and {} committed different requests at {current:?}",
+                            a.index(),
+                            b.index()
+                        );
+                    }
+
+                    current.increment();
+                }
+            }
+        }
+    }
+
+    fn handle_message(&mut self, index: usize, message: ProtocolPayload<S>) {
+        let node = &mut self.nodes[index];
+        let stalled = std::mem::take(&mut node.stalled);
+        let liveness = node.replica.liveness();
+
+        node.replica.handle_payload(message, &mut node.outbox);
+
+        for message in stalled {
+            node.replica.handle_payload(message, &mut node.outbox);
+        }
+
+        node.stalled.extend(node.outbox.drain_inbound());
+
+        if node.replica.liveness() != liveness {
+            node.deadline = self.clock.elapsed() + node.replica.idle_timeout_with(&mut self.rng);
+        }
+
+        node.replica.check_invariants();
+        self.route(index);
+    }
+
+    fn handle_request(&mut self, index: usize, request: Request<S::Request>) {
+        let node = &mut self.nodes[index];
+
+        node.replica
+            .handle_request_at(request, self.clock.elapsed(), &mut node.outbox);
+        node.replica.check_invariants();
+        self.route(index);
+    }
+
+    fn recover_with_nonce(&mut self, index: usize, nonce: Nonce) {
+        if !self.nodes[index].crashed {
+            return;
+        }
+
+        self.trace(TraceEvent::Recover { index, nonce });
+
+        let node = &mut self.nodes[index];
+
+        let mut checkpoint = match node.restore() {
+            Ok(checkpoint) => checkpoint,
+            Err(error) => match error.remedy() {
+                Remedy::RecoverAsNew => {
+                    self.recovered_as_new += 1;
+                    node.genesis.clone()
+                }
+                Remedy::Abort => panic!("replica {index} cannot recover: {error}"),
+            },
+        };
+
+        // Like a counter persisted apart from checkpoints, the generation keeps growing
+        // even when the replica restarts from an older checkpoint or from scratch.
+        checkpoint.generation = checkpoint.generation.max(node.replica.generation());
+
+        node.crashed = false;
+        node.replica = Replica::recovering_with_nonce(
+            self.configuration,
+            index,
+            checkpoint,
+            nonce,
+            &mut node.outbox,
+        );
+        node.replica.seed(index as u64);
+        node.deadline = self.clock.elapsed() + node.replica.idle_timeout_with(&mut self.rng);
+
+        self.route(index);
+    }
+
+    fn apply(&mut self, event: TraceEvent<S>) {
+        match event {
+            TraceEvent::Deliver { index, message } => self.handle_message(index, message),
+            TraceEvent::Request { index, request } => self.handle_request(index, request),
+            TraceEvent::Idle { index } => self.idle(index),
+            TraceEvent::Advance { duration } => self.clock.advance(duration),
+            TraceEvent::Checkpoint { index } => self.checkpoint(index),
+            TraceEvent::CheckpointWithSuffix { index, suffix } => {
+                self.checkpoint_with_suffix(index, suffix)
+            }
+            TraceEvent::Crash { index } => self.crash(index),
+            TraceEvent::CrashDuringSave { index, fault } => self.crash_during_save(index, fault),
+            TraceEvent::Recover { index, nonce } => self.recover_with_nonce(index, nonce),
+        }
+    }
+
+    fn trace(&mut self, event: TraceEvent<S>) {
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.write(&event);
+        }
+    }
+
+    fn route(&mut self, source: usize) {
+        let mut outbox = std::mem::take(&mut self.nodes[source].outbox);
+
+        for envelope in outbox.drain_replies() {
+            self.replies
+                .entry(envelope.destination)
+                .or_default()
+                .push_back(envelope.payload);
+        }
+
+        for envelope in outbox.drain_send() {
+            self.deliver(source, envelope.destination, envelope.payload);
+        }
+
+        for message in outbox.drain_broadcast() {
+            for index in 0..self.nodes.len() {
+                if index != source {
+                    self.deliver(source, index, message.clone());
+                }
+            }
+        }
+
+        self.nodes[source].stalled.extend(outbox.drain_inbound());
+    }
+
+    fn deliver(&mut self, source: usize, index: usize, message: ProtocolPayload<S>) {
+        // Deliveries are dictated by the trace during a replay.
+        if self.replaying {
+            return;
+        }
+
+        let link = (source, index);
+
+        if let Some(&probability) = self.loss_rates.get(&link) {
+            if self.rng.gen_bool(probability) {
+                return;
+            }
+        }
+
+        let message = if self.rng.gen_bool(self.corruption_rate) {
+            match corruption::corrupt(message, &mut self.rng) {
+                Some(message) => message,
+                None => return,
+            }
+        } else {
+            message
+        };
+
+        let duplicate = match self.duplicates.get_mut(&link) {
+            Some(remaining) if *remaining > 0 => {
+                *remaining -= 1;
+                true
+            }
+            _ => false,
+        };
+
+        // The inboxes of the simulated replicas are unbounded, so they never reject a message.
+        if let Some(node) = self.nodes.get_mut(index) {
+            if !node.crashed {
+                if duplicate {
+                    node.inbox.push_inbound(message.clone()).ok();
+                }
+
+                node.inbox.push_inbound(message).ok();
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "os"))]
+mod tests {
+    use super::*;
+    use crate::configuration::Group;
+    use crate::viewstamp::{OpNumber, View};
+    use crate::{Client, ReadPreference};
+
+    #[test]
+    fn single_request() {
+        let configuration = Configuration::from(3);
+        let mut driver = LocalDriver::new(configuration, |_| 0);
+        let mut client = Client::new(configuration);
+        let request = client.new_request(42);
+
+        driver.request(client.primary(), request.clone());
+        driver.drive();
+
+        let mut replies = driver.take_replies(client.identifier());
+        let reply = replies.pop().unwrap().unwrap_reply();
+
+        assert!(replies.is_empty());
+        assert_eq!(reply.id, request.id);
+        assert_eq!(reply.payload, 42);
+        assert!(driver.is_idle());
+    }
+
+    #[test]
+    fn primary_crash() {
+        let configuration = Configuration::from(3);
+        let mut driver = LocalDriver::new(configuration, |_| 0);
+        let mut client = Client::new(configuration);
+
+        driver.request(client.primary(), client.new_request(1));
+        driver.drive();
+        client.update_view(
+            &driver
+                .take_replies(client.identifier())
+                .pop()
+                .unwrap()
+                .unwrap_reply(),
+        );
+
+        driver.crash(client.primary());
+        driver.idle(1);
+        driver.idle(2);
+        driver.drive();
+
+        let request = client.new_request(2);
+
+        driver.broadcast_request(request.clone());
+        driver.drive();
+
+        // Backups point the client at the primary of the new view in addition to the primary replying.
+        let reply = driver
+            .take_replies(client.identifier())
+            .into_iter()
+            .rfind(|payload| matches!(payload, ClientPayload::Reply(_)))
+            .unwrap()
+            .unwrap_reply();
+
+        assert_eq!(reply.id, request.id);
+        assert_eq!(reply.payload, 3);
+        assert_eq!(reply.view, driver.replica(1).view());
+        assert_ne!(reply.view, Default::default());
+    }
+
+    #[test]
+    fn observers() {
+        let configuration = Configuration::from(3).with_observers(2);
+        let mut driver = LocalDriver::new(configuration, |_| 0);
+        let mut client = Client::new(configuration);
+
+        driver.request(client.primary(), client.new_request(1));
+        driver.drive();
+
+        // The primary skips the commit message on the first idle period after a prepare.
+        driver.idle(0);
+        driver.idle(0);
+        driver.drive();
+
+        assert_eq!(driver.replicas().count(), 5);
+        assert_eq!(driver.replica(4).committed(), driver.replica(0).committed());
+
+        // The observers neither start a view change nor make up for the crashed backup in the new view.
+        driver.crash(0);
+        driver.crash(1);
+        driver.idle(2);
+        driver.idle(3);
+        driver.idle(4);
+        driver.drive();
+
+        assert_ne!(driver.replica(2).view(), View::default());
+        assert_eq!(driver.replica(3).view(), View::default());
+        assert_eq!(driver.replica(4).view(), View::default());
+    }
+
+    #[test]
+    fn crash_during_save() {
+        let configuration = Configuration::from(3);
+        let mut driver = LocalDriver::new(configuration, |_| 0);
+        let mut client = Client::new(configuration);
+
+        driver.set_state(2, Box::new(Vec::new()));
+
+        for payload in 1..=2 {
+            driver.request(client.primary(), client.new_request(payload));
+            driver.drive();
+            driver.idle(0);
+            driver.idle(0);
+            driver.drive();
+            driver.checkpoint(1);
+            driver.checkpoint(2);
+        }
+
+        // A failed save leaves the previous checkpoint in place.
+        driver.crash_during_save(1, SaveFault::Fail);
+        driver.recover(1);
+        driver.drive();
+
+        assert_eq!(driver.recovered_as_new(), 0);
+        assert_eq!(driver.replica(1).committed(), driver.replica(0).committed());
+
+        // A torn save fails its checksum, so the replica recovers from scratch.
+        driver.crash_during_save(2, SaveFault::Partial(3));
+        driver.recover(2);
+        driver.drive();
+
+        assert_eq!(driver.recovered_as_new(), 1);
+        assert_eq!(driver.replica(2).committed(), driver.replica(0).committed());
+        assert_eq!(driver.replica(2).generation(), 1);
+
+        // Restarting without any persisted state does not reset the generation either.
+        driver.crash_during_save(2, SaveFault::Partial(0));
+        driver.recover(2);
+        driver.drive();
+
+        assert_eq!(driver.recovered_as_new(), 1);
+        assert_eq!(driver.replica(2).generation(), 2);
+    }
+
+    #[test]
+    fn nearest_reads() {
+        let configuration = Configuration::from(3).with_observers(1);
+        let mut driver = LocalDriver::new(configuration, |_| 0);
+        let mut client = Client::new(configuration)
+            .with_read_preference(ReadPreference::Staleness(0))
+            .with_nearest(3);
+
+        driver.replica_mut(3).enable_follower_reads();
+        driver.request(client.primary(), client.new_request(2));
+        driver.drive();
+
+        // The primary skips the commit message on the first idle period after a prepare.
+        driver.idle(0);
+        driver.idle(0);
+        driver.drive();
+        driver.take_replies(client.identifier());
+
+        let operations = driver.replica(0).log().len();
+
+        driver.request(client.read_target(), client.new_read(3));
+        driver.drive();
+
+        let reply = driver
+            .take_replies(client.identifier())
+            .pop()
+            .unwrap()
+            .unwrap_reply();
+
+        // The observer answered from its committed state without the read going through the log of the primary.
+        assert_eq!(reply.payload, 5);
+        assert_eq!(driver.replica(0).log().len(), operations);
+    }
+
+    #[test]
+    fn coalesce_after_heal() {
+        fn heal(coalesce: bool) -> (usize, usize, View) {
+            let configuration = Configuration::from(5);
+            let mut driver = LocalDriver::new(configuration, |_| 0);
+
+            driver.set_coalescing(coalesce);
+            driver.crash(0);
+
+            // Replicas that cannot hear each other keep starting view changes, and their messages pile up
+            // until the partition heals.
+            for _ in 0..4 {
+                for index in 1..configuration.replicas() {
+                    driver.idle(index);
+                }
+            }
+
+            let steps = driver.drive();
+            let view = driver.replica(1).view();
+
+            assert!(driver
+                .replicas()
+                .skip(1)
+                .all(|replica| replica.view() == view));
+            assert!(driver
+                .replicas()
+                .skip(1)
+                .any(|replica| replica.is_primary()));
+
+            (steps, driver.coalesced(), view)
+        }
+
+        let (steps, coalesced, view) = heal(false);
+        let (coalesced_steps, discarded, coalesced_view) = heal(true);
+
+        assert_eq!(coalesced, 0);
+        assert!(discarded > 0);
+        assert!(coalesced_steps + discarded <= steps);
+        assert!(coalesced_steps < steps);
+        assert_eq!(coalesced_view, view);
+    }
+
+    #[test]
+    fn replay() {
+        let configuration = Configuration::from(3);
+        let path = std::env::temp_dir().join(format!("vr-trace-{}.jsonl", std::process::id()));
+        let mut driver = LocalDriver::new(configuration, |_| 0);
+        let mut client = Client::new(configuration);
+
+        driver.record(&path).unwrap();
+        driver.request(client.primary(), client.new_request(1));
+        driver.drive();
+        driver.checkpoint(2);
+        driver.crash(2);
+        driver.request(client.primary(), client.new_request(2));
+        driver.drive();
+        driver.recover(2);
+        driver.drive();
+        driver.crash(0);
+        driver.advance(configuration.view_timeout() + configuration.view_jitter());
+        driver.drive();
+        driver.stop_recording().unwrap();
+
+        let mut replayed = LocalDriver::replay(configuration, |_| 0, &path).unwrap();
+
+        std::fs::remove_file(&path).unwrap();
+
+        for (expected, actual) in driver.replicas_mut().zip(replayed.replicas_mut()) {
+            assert_eq!(actual.view(), expected.view());
+            assert_eq!(actual.checkpoint(), expected.checkpoint());
+        }
+
+        assert_eq!(
+            replayed.take_replies(client.identifier()),
+            driver.take_replies(client.identifier())
+        );
+        assert_eq!(replayed.clock().elapsed(), driver.clock().elapsed());
+    }
+
+    #[test]
+    fn view_timeout() {
+        let configuration = Configuration::from(3)
+            .with_view_timeout(Duration::from_millis(100), Duration::from_millis(100));
+        let mut driver = LocalDriver::new(configuration, |_| 0);
+
+        driver.crash(0);
+        driver.advance(Duration::from_millis(99));
+
+        assert!(driver.is_idle());
+
+        driver.advance(Duration::from_millis(101));
+        driver.drive();
+
+        assert!(driver.replica(1).is_primary());
+        assert_ne!(driver.replica(1).view(), Default::default());
+        assert_eq!(driver.clock().elapsed(), Duration::from_millis(200));
+    }
+
+    #[test]
+    fn tick() {
+        let configuration = Configuration::from(3)
+            .with_view_timeout(Duration::from_millis(100), Duration::from_millis(100));
+        let mut driver = LocalDriver::new(configuration, |_| 0);
+
+        driver.crash(0);
+
+        let mut ticks = 0;
+
+        while !driver.replica(1).is_primary() && ticks < 10 {
+            assert_ne!(driver.tick(), Duration::ZERO);
+            ticks += 1;
+        }
+
+        assert!(driver.replica(1).is_primary());
+        assert!(driver.is_idle());
+        assert!(driver.clock().elapsed() >= Duration::from_millis(100));
+
+        for index in 1..3 {
+            driver.crash(index);
+        }
+
+        let elapsed = driver.clock().elapsed();
+
+        assert_eq!(driver.tick(), Duration::ZERO);
+        assert_eq!(driver.clock().elapsed(), elapsed);
+    }
+
+    #[test]
+    fn deterministic() {
+        let configuration = Configuration::new(3, Group::from(1))
+            .with_view_timeout(Duration::from_millis(100), Duration::from_millis(100));
+        let run = |seed| {
+            let mut driver = LocalDriver::new(configuration, |_| 0);
+
+            driver.seed(seed);
+            driver.crash(0);
+
+            for _ in 0..5 {
+                driver.tick();
+            }
+
+            driver.recover(0);
+            driver.tick();
+
+            (
+                driver.clock().elapsed(),
+                Vec::from_iter(driver.replicas().map(Replica::view)),
+            )
+        };
+
+        assert_eq!(run(7), run(7));
+    }
+
+    #[test]
+    fn lossy_links() {
+        let configuration = Configuration::from(3);
+        let mut driver = LocalDriver::new(configuration, |_| 0);
+        let mut client = Client::new(configuration);
+        let request = client.new_request(42);
+
+        driver.set_loss_rate(0, 1, 1.0);
+        driver.set_loss_rate(0, 2, 1.0);
+        driver.request(client.primary(), request.clone());
+        driver.drive();
+
+        assert!(driver.take_replies(client.identifier()).is_empty());
+
+        driver.set_loss_rate(0, 1, 0.0);
+        driver.set_loss_rate(0, 2, 0.5);
+
+        let mut replies = Vec::new();
+
+        for _ in 0..10 {
+            driver.tick();
+            replies.extend(driver.take_replies(client.identifier()));
+        }
+
+        assert_eq!(replies.len(), 1);
+        assert_eq!(replies.pop().unwrap().unwrap_reply().payload, 42);
+    }
+
+    #[test]
+    fn duplicate_messages() {
+        let configuration = Configuration::from(3);
+        let mut driver = LocalDriver::new(configuration, |_| 0);
+        let mut client = Client::new(configuration);
+
+        for index in 1..3 {
+            driver.duplicate_next(0, index);
+            driver.duplicate_next(index, 0);
+        }
+
+        driver.request(client.primary(), client.new_request(42));
+        driver.drive();
+
+        let replies = driver.take_replies(client.identifier());
+
+        assert_eq!(replies.len(), 1);
+
+        for _ in 0..3 {
+            driver.tick();
+        }
+
+        for index in 0..3 {
+            assert_eq!(driver.replica_mut(index).checkpoint().state, 42);
+        }
+    }
+
+    #[test]
+    fn corrupted_messages() {
+        let configuration = Configuration::from(3);
+
+        for seed in 0..20 {
+            let mut driver = LocalDriver::new(configuration, |_| 0);
+            let mut client = Client::new(configuration);
+
+            driver.seed(seed);
+            driver.set_corruption_rate(0.2);
+
+            for payload in 0..20 {
+                driver.broadcast_request(client.new_request(payload));
+                driver.tick();
+
+                if payload % 5 == 0 {
+                    driver.crash(payload as usize % 3);
+                    driver.tick();
+                    driver.recover(payload as usize % 3);
+                }
+
+                for _ in 0..3 {
+                    driver.tick();
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn agreement() {
+        let configuration = Configuration::from(5);
+        let mut driver = LocalDriver::new(configuration, |_| 0);
+        let mut client = Client::new(configuration);
+
+        driver.set_agreement_check(true);
+        driver.set_loss_rate(0, 1, 0.5);
+        driver.set_loss_rate(2, 0, 0.5);
+
+        for payload in 0..20 {
+            driver.broadcast_request(client.new_request(payload));
+            driver.tick();
+
+            if payload == 10 {
+                driver.crash(0);
+            }
+
+            for _ in 0..3 {
+                driver.tick();
+            }
+        }
+
+        let committed = driver.replicas().skip(1).map(Replica::committed).max();
+
+        assert!(committed > Some(OpNumber::default()));
+    }
+}
@@ -0,0 +1,764 @@
+use crate::buffer::{BufferedMailbox, ClientPayload, ProtocolPayload};
+use crate::client::Client;
+use crate::configuration::Configuration;
+use crate::replica::Replica;
+use crate::request::{
+    ClientIdentifier, Discover, Notification, QueryRequestStatus, Request, RequestIdentifier,
+    RequestState, Resume,
+};
+use crate::service::Service;
+use crate::viewstamp::{OpNumber, View};
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+type Clients<S> = Arc<Mutex<HashMap<ClientIdentifier, Sender<ClientPayload<S>>>>>;
+
+enum Event<S>
+where
+    S: Service,
+{
+    Request(Request<S::Request>),
+    Resume(Resume),
+    QueryRequestStatus(QueryRequestStatus),
+    Discover(Discover),
+    Protocol(ProtocolPayload<S>),
+    BeginShutdown,
+    Retire,
+    Restart,
+    Status(Sender<ReplicaStatus>),
+    Shutdown,
+}
+
+/// The state of a replica run by a [`ClusterRunner`] as reported to administrators.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct ReplicaStatus {
+    pub view: View,
+    pub committed: OpNumber,
+    /// Whether the replica is processing requests (i.e. it is neither in a view change nor recovering).
+    pub normal: bool,
+    pub primary: bool,
+    pub safe_to_terminate: bool,
+    /// The number of redundant outbound messages the replica did not send since it last (re)started.
+    pub deduplicated: usize,
+    /// Whether the replica was removed from the group and no longer takes part in the protocol.
+    pub retired: bool,
+    /// The incarnation of the replica, which grows every time it restarts.
+    pub generation: u64,
+}
+
+/// Runs each replica of a group on its own thread, exchanging messages over channels.
+/// Replicas are notified they are idle when no message arrives within their idle timeout.
+///
+/// A middle ground between the deterministic [`LocalDriver`](super::LocalDriver) and a real network,
+/// useful for integration tests and local demos.
+pub struct ClusterRunner<S>
+where
+    S: Service,
+{
+    configuration: Configuration,
+    senders: Vec<Sender<Event<S>>>,
+    clients: Clients<S>,
+    threads: Vec<JoinHandle<Replica<S>>>,
+}
+
+impl<S> ClusterRunner<S>
+where
+    S: Service + Send + 'static,
+    S::Request: Send + Sync + 'static,
+    S::Prediction: Send + Sync + 'static,
+    S::Reply: Send + Sync + 'static,
+    S::Checkpoint: Send + Sync + 'static,
+{
+    /// Starts a thread for each replica in the group, including its observers, where each replica's service is created by the given factory.
+    pub fn start<F>(configuration: Configuration, mut factory: F) -> Self
+    where
+        F: FnMut(usize) -> S,
+    {
+        let clients = Clients::default();
+        let (senders, receivers): (Vec<_>, Vec<_>) = (0..configuration.members())
+            .map(|_| mpsc::channel())
+            .unzip();
+        let threads = receivers
+            .into_iter()
+            .enumerate()
+            .map(|(index, receiver)| {
+                let node = Node {
+                    replica: Replica::new(configuration, index, factory(index)),
+                    receiver,
+                    senders: senders.clone(),
+                    clients: clients.clone(),
+                    mailbox: BufferedMailbox::default().with_deduplication(),
+                    stalled: Default::default(),
+                };
+
+                thread::spawn(move || node.run())
+            })
+            .collect();
+
+        Self {
+            configuration,
+            senders,
+            clients,
+            threads,
+        }
+    }
+
+    pub fn configuration(&self) -> Configuration {
+        self.configuration
+    }
+
+    /// Creates a handle for a new client of the group.
+    pub fn client(&self) -> ClusterClient<S> {
+        self.register(Client::new(self.configuration))
+    }
+
+    /// Creates a handle for a client with a durable identifier that resumes numbering its requests
+    /// after the last request the group started for it (e.g. after the client process restarts).
+    /// The primary delivers the replies the client may have missed again, which the handle ignores.
+    /// Returns `None` if no primary accepts the session within the timeout.
+    pub fn resume_client(
+        &self,
+        identifier: ClientIdentifier,
+        timeout: Duration,
+    ) -> Option<ClusterClient<S>> {
+        let mut client = self.register(Client::new(self.configuration).with_identifier(identifier));
+        let deadline = Instant::now() + timeout;
+
+        for sender in &self.senders {
+            let _ = sender.send(Event::Resume(
+                client.client.resume_session(RequestIdentifier::default()),
+            ));
+        }
+
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+
+            match client.receiver.recv_timeout(timeout) {
+                Ok(ClientPayload::SessionAccepted(accepted)) => {
+                    client.client.resume(&accepted);
+                    return Some(client);
+                }
+                Ok(_) => continue,
+                Err(_) => return None,
+            }
+        }
+    }
+
+    /// Creates a handle for a new client that learns the configuration of the group from the replica at the given index,
+    /// as clients of a real network do from a seed node, instead of being handed the configuration.
+    /// Returns `None` if the replica does not answer within the timeout.
+    pub fn discover(&self, seed: usize, timeout: Duration) -> Option<ClusterClient<S>> {
+        let identifier = ClientIdentifier::default();
+        let receiver = self.subscribe(identifier);
+        let deadline = Instant::now() + timeout;
+
+        self.senders
+            .get(seed)?
+            .send(Event::Discover(Discover { client: identifier }))
+            .ok()?;
+
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+
+            match receiver.recv_timeout(timeout) {
+                Ok(ClientPayload::Discovered(discovered)) => {
+                    let mut client =
+                        Client::new(discovered.configuration()).with_identifier(identifier);
+
+                    client.rediscover(&discovered);
+
+                    return Some(ClusterClient {
+                        client,
+                        senders: self.senders.clone(),
+                        receiver,
+                        notifications: VecDeque::new(),
+                    });
+                }
+                Ok(_) => continue,
+                Err(_) => return None,
+            }
+        }
+    }
+
+    fn register(&self, client: Client) -> ClusterClient<S> {
+        let receiver = self.subscribe(client.identifier());
+
+        ClusterClient {
+            client,
+            senders: self.senders.clone(),
+            receiver,
+            notifications: VecDeque::new(),
+        }
+    }
+
+    fn subscribe(&self, client: ClientIdentifier) -> Receiver<ClientPayload<S>> {
+        let (sender, receiver) = mpsc::channel();
+
+        if let Ok(mut clients) = self.clients.lock() {
+            clients.insert(client, sender);
+        }
+
+        receiver
+    }
+
+    /// Asks the replica at the given index for its status.
+    /// Returns `None` if the replica does not respond within the timeout.
+    pub fn status(&self, index: usize, timeout: Duration) -> Option<ReplicaStatus> {
+        let (sender, receiver) = mpsc::channel();
+
+        self.senders.get(index)?.send(Event::Status(sender)).ok()?;
+        receiver.recv_timeout(timeout).ok()
+    }
+
+    /// Asks the replica at the given index to stop accepting requests and hand off the primary role.
+    /// See [`Replica::begin_shutdown`].
+    pub fn begin_shutdown(&self, index: usize) {
+        if let Some(sender) = self.senders.get(index) {
+            let _ = sender.send(Event::BeginShutdown);
+        }
+    }
+
+    /// Stops the replica at the given index from taking part in the protocol once it was replaced.
+    /// See [`Replica::retire`].
+    pub fn retire(&self, index: usize) {
+        if let Some(sender) = self.senders.get(index) {
+            let _ = sender.send(Event::Retire);
+        }
+    }
+
+    /// Restarts the replica at the given index from a checkpoint of its committed state, as if its process restarted.
+    /// The replica runs the recovery protocol before it processes requests again.
+    pub fn restart(&self, index: usize) {
+        if let Some(sender) = self.senders.get(index) {
+            let _ = sender.send(Event::Restart);
+        }
+    }
+
+    /// Stops all the replicas and returns them in index order.
+    pub fn shutdown(mut self) -> Vec<Replica<S>> {
+        for sender in &self.senders {
+            let _ = sender.send(Event::Shutdown);
+        }
+
+        self.threads
+            .drain(..)
+            .filter_map(|thread| thread.join().ok())
+            .collect()
+    }
+}
+
+impl<S> Drop for ClusterRunner<S>
+where
+    S: Service,
+{
+    fn drop(&mut self) {
+        for sender in &self.senders {
+            let _ = sender.send(Event::Shutdown);
+        }
+
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// A client of a group run by a [`ClusterRunner`].
+pub struct ClusterClient<S>
+where
+    S: Service,
+{
+    client: Client,
+    senders: Vec<Sender<Event<S>>>,
+    receiver: Receiver<ClientPayload<S>>,
+    /// The notifications that arrived while the client waited for a reply.
+    notifications: VecDeque<Notification<S::Reply>>,
+}
+
+impl<S> ClusterClient<S>
+where
+    S: Service,
+{
+    pub fn identifier(&self) -> ClientIdentifier {
+        self.client.identifier()
+    }
+
+    /// Sends a request to the group and waits for the reply.
+    /// The request is sent to the primary, then to every replica if the primary does not reply within the retry interval.
+    /// Returns `None` if no reply arrives before the deadline.
+    pub fn invoke(
+        &mut self,
+        payload: S::Request,
+        retry: Duration,
+        deadline: Duration,
+    ) -> Option<S::Reply> {
+        let request = self.client.new_request(payload);
+
+        self.wait(request, retry, Instant::now() + deadline).ok()
+    }
+
+    /// Sends a request to the group and waits for the reply like [`ClusterClient::invoke`],
+    /// except the primary does not start the request once the deadline passed.
+    /// The error tells whether the group may still execute the request, so the caller knows whether retrying it
+    /// as a new request could execute it twice.
+    pub fn call_with_deadline(
+        &mut self,
+        payload: S::Request,
+        retry: Duration,
+        deadline: Duration,
+    ) -> Result<S::Reply, Timeout> {
+        let request = self
+            .client
+            .new_request(payload)
+            .with_deadline(crate::clock::unix_time() + deadline);
+
+        self.wait(request, retry, Instant::now() + deadline)
+    }
+
+    /// Asks the group whether it executed the latest request of this client, e.g. after
+    /// [`ClusterClient::call_with_deadline`] returned [`Timeout::MaybeExecuted`].
+    /// Returns `None` if no primary answers within the timeout.
+    pub fn request_status(&mut self, timeout: Duration) -> Option<RequestState> {
+        let request = self.client.last_request();
+        let deadline = Instant::now() + timeout;
+
+        for sender in &self.senders {
+            let _ = sender.send(Event::QueryRequestStatus(self.client.query_status(request)));
+        }
+
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+
+            match self.receiver.recv_timeout(timeout) {
+                Ok(ClientPayload::RequestStatus(status)) if status.request == request => {
+                    return Some(status.state);
+                }
+                // A late reply proves the group executed the request.
+                Ok(ClientPayload::Reply(reply)) if reply.id == request => {
+                    self.client.update_view(&reply);
+                    return Some(RequestState::Executed);
+                }
+                Ok(ClientPayload::Notification(notification)) => {
+                    self.notifications.push_back(notification);
+                }
+                Ok(_) => continue,
+                Err(_) => return None,
+            }
+        }
+    }
+
+    /// Waits for the next notification the primary pushed to this client (e.g. a change to a watched key),
+    /// including notifications that arrived while the client waited for replies.
+    /// Returns `None` if no notification arrives within the timeout.
+    pub fn next_notification(&mut self, timeout: Duration) -> Option<Notification<S::Reply>> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            if let Some(notification) = self.notifications.pop_front() {
+                return Some(notification);
+            }
+
+            let timeout = deadline.saturating_duration_since(Instant::now());
+
+            match self.receiver.recv_timeout(timeout) {
+                Ok(ClientPayload::Notification(notification)) => return Some(notification),
+                // Replies to abandoned requests have no one left waiting for them.
+                Ok(_) => continue,
+                Err(_) => return None,
+            }
+        }
+    }
+
+    fn wait(
+        &mut self,
+        request: Request<S::Request>,
+        retry: Duration,
+        deadline: Instant,
+    ) -> Result<S::Reply, Timeout> {
+        let mut delivered = self.send(self.client.primary(), &request);
+
+        loop {
+            let timeout = retry.min(deadline.saturating_duration_since(Instant::now()));
+
+            match self.receiver.recv_timeout(timeout) {
+                Ok(ClientPayload::Reply(reply)) if reply.id == request.id => {
+                    self.client.update_view(&reply);
+                    return Ok(reply.payload);
+                }
+                Ok(ClientPayload::Reply(_)) => continue,
+                Ok(ClientPayload::Busy(busy)) if busy.id == request.id => {
+                    let backoff = self.client.backoff(&busy);
+
+                    thread::sleep(backoff.min(deadline.saturating_duration_since(Instant::now())));
+                    delivered |= self.send(self.client.primary(), &request);
+                }
+                Ok(ClientPayload::Busy(_)) => continue,
+                // The primary only expires requests it has not started, so the group never executes it.
+                Ok(ClientPayload::Expired(expired)) if expired.id == request.id => {
+                    self.abandon();
+                    return Err(Timeout::NotExecuted);
+                }
+                Ok(ClientPayload::Expired(_))
+                | Ok(ClientPayload::SessionAccepted(_))
+                | Ok(ClientPayload::RequestStatus(_)) => continue,
+                Ok(ClientPayload::OutdatedView(outdated)) => {
+                    // The group moved to a later epoch, so the configuration of the client may be outdated as well.
+                    if self.client.is_stale(&outdated) {
+                        for sender in &self.senders {
+                            let _ = sender.send(Event::Discover(self.client.discover()));
+                        }
+                    }
+
+                    if self.client.redirect(&outdated) {
+                        delivered |= self.send(self.client.primary(), &request);
+                    }
+                }
+                Ok(ClientPayload::OutdatedEpoch(outdated)) => {
+                    if self.client.is_outdated(&outdated) {
+                        for sender in &self.senders {
+                            let _ = sender.send(Event::Discover(self.client.discover()));
+                        }
+                    }
+                }
+                Ok(ClientPayload::Discovered(discovered)) => {
+                    if self.client.rediscover(&discovered) {
+                        delivered |= self.send(self.client.primary(), &request);
+                    }
+                }
+                Ok(ClientPayload::Notification(notification)) => {
+                    self.notifications.push_back(notification);
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    for index in 0..self.senders.len() {
+                        delivered |= self.send(index, &request);
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+
+            if Instant::now() >= deadline {
+                break;
+            }
+        }
+
+        self.abandon();
+
+        match delivered {
+            true => Err(Timeout::MaybeExecuted),
+            false => Err(Timeout::NotExecuted),
+        }
+    }
+
+    /// Drops the replies already queued for an abandoned request, so they do not pile up behind later requests.
+    /// Replies arriving later are skipped by the next request, since they carry an older identifier.
+    /// Notifications are kept for [`ClusterClient::next_notification`].
+    fn abandon(&mut self) {
+        while let Ok(payload) = self.receiver.try_recv() {
+            if let ClientPayload::Notification(notification) = payload {
+                self.notifications.push_back(notification);
+            }
+        }
+    }
+
+    /// Sends the request to the replica at the given index. Returns whether the replica is running to receive it.
+    fn send(&self, index: usize, request: &Request<S::Request>) -> bool {
+        self.senders
+            .get(index)
+            .is_some_and(|sender| sender.send(Event::Request(request.clone())).is_ok())
+    }
+}
+
+/// Why [`ClusterClient::call_with_deadline`] returned without a reply.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Timeout {
+    /// The deadline passed without a reply. The group may have executed the request or may still do so
+    /// (e.g. the reply was lost or the primary started it just before the deadline).
+    MaybeExecuted,
+    /// The group will never execute the request, because the primary refused to start it after the deadline
+    /// or no replica was running to receive it.
+    NotExecuted,
+}
+
+impl Display for Timeout {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Timeout::MaybeExecuted => write!(
+                f,
+                "the request timed out and may have been executed by the group"
+            ),
+            Timeout::NotExecuted => {
+                write!(f, "the request timed out and was not executed by the group")
+            }
+        }
+    }
+}
+
+impl Error for Timeout {}
+
+struct Node<S>
+where
+    S: Service,
+{
+    replica: Replica<S>,
+    receiver: Receiver<Event<S>>,
+    senders: Vec<Sender<Event<S>>>,
+    clients: Clients<S>,
+    mailbox: BufferedMailbox<S>,
+    stalled: Vec<ProtocolPayload<S>>,
+}
+
+impl<S> Node<S>
+where
+    S: Service,
+{
+    fn run(mut self) -> Replica<S> {
+        let mut deadline = Instant::now() + self.replica.idle_timeout();
+
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+
+            match self.receiver.recv_timeout(timeout) {
+                Ok(Event::Request(request)) => {
+                    self.replica.handle_request(request, &mut self.mailbox);
+                }
+                Ok(Event::Resume(message)) => {
+                    self.replica.handle_resume(message, &mut self.mailbox);
+                }
+                Ok(Event::QueryRequestStatus(message)) => {
+                    self.replica
+                        .handle_query_request_status(message, &mut self.mailbox);
+                }
+                Ok(Event::Discover(message)) => {
+                    self.replica.handle_discover(message, &mut self.mailbox);
+                }
+                Ok(Event::Protocol(message)) => {
+                    let stalled = std::mem::take(&mut self.stalled);
+                    let liveness = self.replica.liveness();
+
+                    self.replica.handle_payload(message, &mut self.mailbox);
+
+                    for message in stalled {
+                        self.replica.handle_payload(message, &mut self.mailbox);
+                    }
+
+                    // Only hearing from the primary restarts the idle timer, so chatter from other replicas
+                    // cannot keep a backup from replacing a failed primary.
+                    if self.replica.liveness() != liveness {
+                        deadline = Instant::now() + self.replica.idle_timeout();
+                    }
+                }
+                Ok(Event::BeginShutdown) => self.replica.begin_shutdown(),
+                Ok(Event::Retire) => {
+                    self.replica.retire();
+                }
+                Ok(Event::Restart) => {
+                    self.restart();
+                    deadline = Instant::now() + self.replica.idle_timeout();
+                }
+                Ok(Event::Status(sender)) => {
+                    let _ = sender.send(ReplicaStatus {
+                        view: self.replica.view(),
+                        committed: self.replica.committed(),
+                        normal: self.replica.is_normal(),
+                        primary: self.replica.is_primary(),
+                        safe_to_terminate: self.replica.is_safe_to_terminate(),
+                        deduplicated: self.mailbox.deduplicated(),
+                        retired: self.replica.is_retired(),
+                        generation: self.replica.generation(),
+                    });
+                }
+                Ok(Event::Shutdown) | Err(RecvTimeoutError::Disconnected) => return self.replica,
+                Err(RecvTimeoutError::Timeout) => {
+                    self.replica.idle(&mut self.mailbox);
+                    deadline = Instant::now() + self.replica.idle_timeout();
+                }
+            }
+
+            self.route();
+        }
+    }
+
+    fn restart(&mut self) {
+        let configuration = self.replica.configuration();
+        let index = self.replica.index();
+        let checkpoint = self.replica.checkpoint();
+
+        // Messages buffered by the old process are lost along with it.
+        self.stalled.clear();
+        self.mailbox = BufferedMailbox::default().with_deduplication();
+        self.replica = Replica::recovering(configuration, index, checkpoint, &mut self.mailbox);
+    }
+
+    fn route(&mut self) {
+        self.stalled.extend(self.mailbox.drain_inbound());
+
+        if let Ok(clients) = self.clients.lock() {
+            for envelope in self.mailbox.drain_replies() {
+                if let Some(client) = clients.get(&envelope.destination) {
+                    let _ = client.send(envelope.payload);
+                }
+            }
+        }
+
+        for envelope in self.mailbox.drain_send() {
+            if let Some(sender) = self.senders.get(envelope.destination) {
+                let _ = sender.send(Event::Protocol(envelope.payload));
+            }
+        }
+
+        let source = self.replica.index();
+
+        for message in self.mailbox.drain_broadcast() {
+            for (index, sender) in self.senders.iter().enumerate() {
+                if index != source {
+                    let _ = sender.send(Event::Protocol(message.clone()));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests() {
+        let configuration = Configuration::from(3)
+            .with_commit_interval(Duration::from_millis(10))
+            .with_view_timeout(Duration::from_millis(50), Duration::from_millis(50));
+        let runner = ClusterRunner::start(configuration, |_| 0);
+        let mut client = runner.client();
+        let retry = Duration::from_millis(100);
+        let deadline = Duration::from_secs(5);
+
+        assert_eq!(client.invoke(1, retry, deadline), Some(1));
+        assert_eq!(client.invoke(2, retry, deadline), Some(3));
+
+        let replicas = runner.shutdown();
+
+        assert_eq!(replicas.len(), configuration.replicas());
+    }
+
+    #[test]
+    fn call_with_deadline() {
+        let configuration = Configuration::from(3)
+            .with_commit_interval(Duration::from_millis(10))
+            .with_view_timeout(Duration::from_millis(50), Duration::from_millis(50));
+        let runner = ClusterRunner::start(configuration, |_| 0);
+        let mut client = runner.client();
+        let retry = Duration::from_millis(100);
+
+        assert_eq!(
+            client.call_with_deadline(1, retry, Duration::from_secs(5)),
+            Ok(1)
+        );
+
+        // Every replica recovering at once leaves no primary to answer recovery, so the group stops responding.
+        for index in 0..configuration.replicas() {
+            runner.restart(index);
+        }
+
+        assert_eq!(
+            client.call_with_deadline(2, retry, Duration::from_millis(200)),
+            Err(Timeout::MaybeExecuted)
+        );
+
+        runner.shutdown();
+
+        assert_eq!(
+            client.call_with_deadline(3, retry, Duration::from_secs(5)),
+            Err(Timeout::NotExecuted)
+        );
+    }
+
+    #[test]
+    fn resume_client() {
+        let configuration = Configuration::from(3)
+            .with_commit_interval(Duration::from_millis(10))
+            .with_view_timeout(Duration::from_millis(50), Duration::from_millis(50));
+        let runner = ClusterRunner::start(configuration, |_| 0);
+        let identifier = ClientIdentifier::from(9);
+        let retry = Duration::from_millis(100);
+        let deadline = Duration::from_secs(5);
+        let mut client = runner.resume_client(identifier, deadline).unwrap();
+
+        assert_eq!(client.invoke(1, retry, deadline), Some(1));
+        assert_eq!(client.invoke(2, retry, deadline), Some(3));
+
+        drop(client);
+
+        let mut resumed = runner.resume_client(identifier, deadline).unwrap();
+
+        assert_eq!(resumed.identifier(), identifier);
+        assert_eq!(resumed.invoke(3, retry, deadline), Some(6));
+    }
+
+    #[test]
+    fn discover() {
+        let configuration = Configuration::from(3)
+            .with_epoch(1)
+            .with_commit_interval(Duration::from_millis(10))
+            .with_view_timeout(Duration::from_millis(50), Duration::from_millis(50));
+        let runner = ClusterRunner::start(configuration, |_| 0);
+        let timeout = Duration::from_secs(5);
+        let mut client = runner.discover(2, timeout).unwrap();
+
+        assert_eq!(client.client.configuration().epoch(), 1);
+        assert_eq!(client.client.configuration().group(), configuration.group());
+        assert_eq!(
+            client.invoke(1, Duration::from_millis(100), timeout),
+            Some(1)
+        );
+        assert!(runner.discover(3, timeout).is_none());
+    }
+
+    #[test]
+    fn retire() {
+        let configuration = Configuration::from(3)
+            .with_commit_interval(Duration::from_millis(10))
+            .with_view_timeout(Duration::from_millis(50), Duration::from_millis(50));
+        let runner = ClusterRunner::start(configuration, |_| 0);
+        let mut client = runner.client();
+        let retry = Duration::from_millis(100);
+        let timeout = Duration::from_secs(5);
+
+        assert_eq!(client.invoke(1, retry, timeout), Some(1));
+
+        runner.retire(2);
+
+        let retired = runner.status(2, timeout).unwrap();
+
+        assert!(retired.retired);
+        assert_eq!(client.invoke(2, retry, timeout), Some(3));
+        assert_eq!(
+            runner.status(2, timeout).unwrap().committed,
+            retired.committed
+        );
+        assert!(!runner.status(0, timeout).unwrap().retired);
+    }
+
+    #[test]
+    fn request_status() {
+        let configuration = Configuration::from(3)
+            .with_commit_interval(Duration::from_millis(10))
+            .with_view_timeout(Duration::from_millis(50), Duration::from_millis(50));
+        let runner = ClusterRunner::start(configuration, |_| 0);
+        let mut client = runner.client();
+        let timeout = Duration::from_secs(5);
+
+        assert_eq!(
+            client.request_status(timeout),
+            Some(RequestState::NotStarted)
+        );
+        assert_eq!(
+            client.invoke(1, Duration::from_millis(100), timeout),
+            Some(1)
+        );
+        assert_eq!(client.request_status(timeout), Some(RequestState::Executed));
+    }
+}
@@ -0,0 +1,163 @@
+use super::cluster::{ClusterRunner, ReplicaStatus};
+use crate::service::Service;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum RestartError {
+    /// Restarting the replica would leave fewer healthy replicas than a quorum.
+    QuorumAtRisk { index: usize, healthy: usize },
+    /// The replica did not hand off the primary role before the timeout.
+    HandoffTimeout { index: usize },
+    /// The replica did not recover and catch up with the rest of the group before the timeout.
+    RecoveryTimeout { index: usize },
+}
+
+impl Display for RestartError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RestartError::QuorumAtRisk { index, healthy } => write!(
+                f,
+                "restarting replica {index} would leave only {healthy} healthy replicas, fewer than a quorum"
+            ),
+            RestartError::HandoffTimeout { index } => {
+                write!(f, "replica {index} did not hand off the primary role in time")
+            }
+            RestartError::RecoveryTimeout { index } => {
+                write!(f, "replica {index} did not recover and catch up in time")
+            }
+        }
+    }
+}
+
+impl Error for RestartError {}
+
+/// Restarts the replicas of a [`ClusterRunner`] one at a time (e.g. to roll out an upgrade) without losing availability.
+///
+/// Each replica first hands off the primary role if it has it, then restarts and recovers.
+/// The controller waits for the replica to catch up with the rest of the group before moving on to the next one,
+/// and refuses to restart a replica when the others could not form a quorum without it.
+pub struct ClusterController<'a, S>
+where
+    S: Service,
+{
+    runner: &'a ClusterRunner<S>,
+    timeout: Duration,
+    poll_interval: Duration,
+}
+
+impl<'a, S> ClusterController<'a, S>
+where
+    S: Service + Send + 'static,
+    S::Request: Send + Sync + 'static,
+    S::Prediction: Send + Sync + 'static,
+    S::Reply: Send + Sync + 'static,
+    S::Checkpoint: Send + Sync + 'static,
+{
+    pub fn new(runner: &'a ClusterRunner<S>) -> Self {
+        Self {
+            runner,
+            timeout: Duration::from_secs(10),
+            poll_interval: Duration::from_millis(10),
+        }
+    }
+
+    /// Limits how long each step of a restart (i.e. the hand-off and the recovery) may take.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// How often to ask replicas for their status while waiting for a step to complete.
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Restarts every replica in index order, stopping at the first replica that fails to restart.
+    pub fn rolling_restart(&self) -> Result<(), RestartError> {
+        (0..self.runner.configuration().replicas()).try_for_each(|index| self.restart(index))
+    }
+
+    /// Restarts the replica at the given index once it is safe to do so and waits for it to catch up.
+    pub fn restart(&self, index: usize) -> Result<(), RestartError> {
+        let others = self.others(index);
+        let healthy = others.iter().filter(|(_, status)| status.normal).count();
+
+        if healthy < self.runner.configuration().quorum() {
+            return Err(RestartError::QuorumAtRisk { index, healthy });
+        }
+
+        self.runner.begin_shutdown(index);
+
+        if !self.wait_for(index, |status| status.safe_to_terminate) {
+            return Err(RestartError::HandoffTimeout { index });
+        }
+
+        let target = others
+            .iter()
+            .map(|(_, status)| status.committed)
+            .max()
+            .unwrap_or_default();
+
+        self.runner.restart(index);
+
+        if !self.wait_for(index, |status| status.normal && status.committed >= target) {
+            return Err(RestartError::RecoveryTimeout { index });
+        }
+
+        Ok(())
+    }
+
+    /// The status of every responsive replica other than the one at the given index.
+    fn others(&self, index: usize) -> Vec<(usize, ReplicaStatus)> {
+        (0..self.runner.configuration().replicas())
+            .filter(|&other| other != index)
+            .filter_map(|other| Some((other, self.runner.status(other, self.poll_interval)?)))
+            .collect()
+    }
+
+    fn wait_for<F>(&self, index: usize, condition: F) -> bool
+    where
+        F: Fn(&ReplicaStatus) -> bool,
+    {
+        let deadline = Instant::now() + self.timeout;
+
+        while Instant::now() < deadline {
+            match self.runner.status(index, self.poll_interval) {
+                Some(status) if condition(&status) => return true,
+                _ => thread::sleep(self.poll_interval),
+            }
+        }
+
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Configuration;
+
+    #[test]
+    fn rolling_restart() {
+        let configuration = Configuration::from(3)
+            .with_commit_interval(Duration::from_millis(10))
+            .with_view_timeout(Duration::from_millis(50), Duration::from_millis(50));
+        let runner = ClusterRunner::start(configuration, |_| 0);
+        let mut client = runner.client();
+        let retry = Duration::from_millis(100);
+        let deadline = Duration::from_secs(5);
+
+        assert_eq!(client.invoke(1, retry, deadline), Some(1));
+        assert_eq!(client.invoke(2, retry, deadline), Some(3));
+
+        let controller = ClusterController::new(&runner);
+
+        assert_eq!(controller.rolling_restart(), Ok(()));
+        assert_eq!(client.invoke(3, retry, deadline), Some(6));
+        assert!(runner.status(0, retry).is_some_and(|status| status.normal));
+    }
+}
@@ -0,0 +1,123 @@
+use crate::buffer::ProtocolPayload;
+use crate::service::{Serializable, Service};
+use rand::Rng;
+use std::ops::Range;
+
+/// Changes a random number in the message (e.g. a view, an op-number or part of a payload).
+/// Numbers are usually nudged by a small amount so the corrupted message is still plausible,
+/// but are sometimes set to an extreme value (0, `u64::MAX` or `u128::MAX`) to check that replicas never overflow.
+/// Returns None when the message no longer decodes after the change, in which case the message is lost.
+pub fn corrupt<S, R>(message: ProtocolPayload<S>, rng: &mut R) -> Option<ProtocolPayload<S>>
+where
+    S: Service + Serializable,
+    R: Rng,
+{
+    let mut json = serde_json::to_string(&message).ok()?;
+    let numbers = numbers(&json);
+
+    if numbers.is_empty() {
+        return Some(message);
+    }
+
+    let range = numbers[rng.gen_range(0..numbers.len())].clone();
+    let replacement = match rng.gen_range(0..8) {
+        0 => 0.to_string(),
+        1 => u64::MAX.to_string(),
+        2 => u128::MAX.to_string(),
+        _ => nudge(&json[range.clone()], rng),
+    };
+
+    json.replace_range(range, &replacement);
+    serde_json::from_str(&json).ok()
+}
+
+fn nudge<R: Rng>(number: &str, rng: &mut R) -> String {
+    let delta = rng.gen_range(-2..=2);
+
+    if let Ok(n) = number.parse::<u128>() {
+        n.saturating_add_signed(delta).to_string()
+    } else if let Ok(n) = number.parse::<i128>() {
+        n.saturating_add(delta).to_string()
+    } else if let Ok(n) = number.parse::<f64>() {
+        (n + delta as f64).to_string()
+    } else {
+        number.to_string()
+    }
+}
+
+/// The byte ranges of the numbers in the JSON text, skipping any digits inside strings.
+/// Works on the text rather than a JSON value, since values cannot hold numbers above `u64::MAX`.
+fn numbers(json: &str) -> Vec<Range<usize>> {
+    let bytes = json.as_bytes();
+    let mut numbers = Vec::new();
+    let mut string = false;
+    let mut index = 0;
+
+    while index < bytes.len() {
+        match bytes[index] {
+            b'\\' if string => index += 1,
+            b'"' => string = !string,
+            b'-' | b'0'..=b'9' if !string => {
+                let start = index;
+
+                while index < bytes.len()
+                    && matches!(bytes[index], b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9')
+                {
+                    index += 1;
+                }
+
+                numbers.push(start..index);
+                continue;
+            }
+            _ => {}
+        }
+
+        index += 1;
+    }
+
+    numbers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::Commit;
+    use crate::viewstamp::{OpNumber, View};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::time::Duration;
+
+    #[test]
+    fn skips_strings() {
+        let json = r#"{"a":"1\"2","b":[-3,4.5e1]}"#;
+        let numbers: Vec<&str> = numbers(json)
+            .into_iter()
+            .map(|range| &json[range])
+            .collect();
+
+        assert_eq!(numbers, vec!["-3", "4.5e1"]);
+    }
+
+    #[test]
+    fn extremes() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let message = ProtocolPayload::<i32>::Commit(Commit {
+            view: View::default(),
+            epoch: 0,
+            committed: OpNumber::default(),
+            timestamp: Duration::ZERO,
+        });
+
+        let extreme = (0..1000)
+            .filter_map(|_| corrupt(message.clone(), &mut rng))
+            .any(|corrupted| match corrupted {
+                ProtocolPayload::Commit(commit) => {
+                    u128::from(commit.view) == u128::MAX
+                        || u128::from(commit.committed) == u128::MAX
+                }
+                _ => false,
+            });
+
+        assert!(extreme);
+    }
+}
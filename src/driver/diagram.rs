@@ -0,0 +1,263 @@
+use super::trace::{self, TraceEvent};
+use crate::buffer::ProtocolPayload;
+use crate::configuration::Configuration;
+use crate::service::{Serializable, Service};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use std::io;
+use std::path::Path;
+
+/// The formats a recorded trace can be exported to with [`export_trace`].
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum DiagramFormat {
+    /// A JSON array of [`DiagramEvent`]s for custom visualizations.
+    Json,
+    /// A [Mermaid](https://mermaid.js.org) sequence diagram.
+    Mermaid,
+}
+
+/// A step of a recorded execution in a form suited for sequence diagrams.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DiagramEvent {
+    /// A replica received a protocol message. The sender is unknown for messages that do not name it (e.g. state transfers).
+    Message {
+        from: Option<usize>,
+        to: usize,
+        label: String,
+    },
+    /// A replica received a request from a client.
+    Request { to: usize, label: String },
+    /// Something happened to a replica without a message (e.g. it crashed, was idle, changed views or committed).
+    Note { replica: usize, text: String },
+    /// The simulated clock moved forward.
+    Advance { millis: u64 },
+}
+
+/// Converts the trace recorded by a [`LocalDriver`](super::LocalDriver) at the given path into a diagram
+/// of the messages replicas exchanged, their view changes and commits (e.g. to attach to a bug report).
+/// The configuration must match the one used by the recorded driver.
+pub fn export_trace<S>(
+    configuration: Configuration,
+    path: impl AsRef<Path>,
+    format: DiagramFormat,
+) -> io::Result<String>
+where
+    S: Service + Serializable,
+{
+    let events = diagram(configuration, trace::read::<S>(path)?);
+
+    match format {
+        DiagramFormat::Json => Ok(serde_json::to_string_pretty(&events)?),
+        DiagramFormat::Mermaid => Ok(mermaid(configuration, &events)),
+    }
+}
+
+fn diagram<S>(configuration: Configuration, events: Vec<TraceEvent<S>>) -> Vec<DiagramEvent>
+where
+    S: Service,
+{
+    let mut diagram = Vec::with_capacity(events.len());
+
+    for event in events {
+        match event {
+            TraceEvent::Deliver { index, message } => {
+                let from = match &message {
+                    ProtocolPayload::Recovery(message) => Some(message.index),
+                    _ => message.origin(configuration).map(|(_, replica)| replica),
+                };
+
+                diagram.push(DiagramEvent::Message {
+                    from,
+                    to: index,
+                    label: label(&message),
+                });
+
+                // Starting a view and committing are the milestones readers look for in a run.
+                match &message {
+                    ProtocolPayload::StartView(message) => diagram.push(DiagramEvent::Note {
+                        replica: index,
+                        text: format!("view {}", u128::from(message.view)),
+                    }),
+                    ProtocolPayload::Commit(message) => diagram.push(DiagramEvent::Note {
+                        replica: index,
+                        text: format!("committed {}", u128::from(message.committed)),
+                    }),
+                    _ => {}
+                }
+            }
+            TraceEvent::Request { index, request } => diagram.push(DiagramEvent::Request {
+                to: index,
+                label: format!("Request #{}", u128::from(request.id)),
+            }),
+            TraceEvent::Idle { index } => diagram.push(DiagramEvent::Note {
+                replica: index,
+                text: "idle".to_string(),
+            }),
+            TraceEvent::Advance { duration } => diagram.push(DiagramEvent::Advance {
+                millis: u64::try_from(duration.as_millis()).unwrap_or(u64::MAX),
+            }),
+            TraceEvent::Checkpoint { index } | TraceEvent::CheckpointWithSuffix { index, .. } => {
+                diagram.push(DiagramEvent::Note {
+                    replica: index,
+                    text: "checkpoint".to_string(),
+                })
+            }
+            TraceEvent::Crash { index } => diagram.push(DiagramEvent::Note {
+                replica: index,
+                text: "crash".to_string(),
+            }),
+            TraceEvent::CrashDuringSave { index, .. } => diagram.push(DiagramEvent::Note {
+                replica: index,
+                text: "crash during save".to_string(),
+            }),
+            TraceEvent::Recover { index, .. } => diagram.push(DiagramEvent::Note {
+                replica: index,
+                text: "recover".to_string(),
+            }),
+        }
+    }
+
+    diagram
+}
+
+/// The type of the message and the fields that identify it in a diagram.
+fn label<S>(message: &ProtocolPayload<S>) -> String
+where
+    S: Service,
+{
+    match message {
+        ProtocolPayload::Prepare(message) => format!(
+            "Prepare v{} op {}",
+            u128::from(message.view),
+            u128::from(message.op_number)
+        ),
+        ProtocolPayload::PrepareOk(message) => format!(
+            "PrepareOk v{} op {}",
+            u128::from(message.view),
+            u128::from(message.op_number)
+        ),
+        ProtocolPayload::Commit(message) => format!(
+            "Commit v{} op {}",
+            u128::from(message.view),
+            u128::from(message.committed)
+        ),
+        ProtocolPayload::GetState(message) => format!(
+            "GetState v{} after op {}",
+            u128::from(message.view),
+            u128::from(message.op_number)
+        ),
+        ProtocolPayload::NewState(message) => format!(
+            "NewState v{} {} ops",
+            u128::from(message.view),
+            message.log.len()
+        ),
+        ProtocolPayload::InstallSnapshot(message) => format!(
+            "InstallSnapshot v{} op {}",
+            u128::from(message.view),
+            u128::from(message.checkpoint.committed)
+        ),
+        ProtocolPayload::StartViewChange(message) => {
+            format!("StartViewChange v{}", u128::from(message.view))
+        }
+        ProtocolPayload::DoViewChange(message) => {
+            format!("DoViewChange v{}", u128::from(message.view))
+        }
+        ProtocolPayload::GetLog(message) => format!(
+            "GetLog v{} after op {}",
+            u128::from(message.view),
+            u128::from(message.op_number)
+        ),
+        ProtocolPayload::NewLog(message) => format!(
+            "NewLog v{} {} ops",
+            u128::from(message.view),
+            message.log.len()
+        ),
+        ProtocolPayload::StartView(message) => format!("StartView v{}", u128::from(message.view)),
+        ProtocolPayload::Recovery(message) => {
+            format!("Recovery op {}", u128::from(message.committed))
+        }
+        ProtocolPayload::RecoveryResponse(message) => {
+            format!("RecoveryResponse v{}", u128::from(message.view))
+        }
+        ProtocolPayload::Digest(message) => format!(
+            "Digest v{} {} ranges",
+            u128::from(message.view),
+            message.ranges.len()
+        ),
+    }
+}
+
+fn mermaid(configuration: Configuration, events: &[DiagramEvent]) -> String {
+    let mut diagram = String::from("sequenceDiagram\n    participant C as Clients\n");
+
+    for index in 0..configuration.members() {
+        let _ = writeln!(diagram, "    participant R{index}");
+    }
+
+    for event in events {
+        let _ = match event {
+            DiagramEvent::Message {
+                from: Some(from),
+                to,
+                label,
+            } => writeln!(diagram, "    R{from}->>R{to}: {label}"),
+            DiagramEvent::Message {
+                from: None,
+                to,
+                label,
+            } => writeln!(diagram, "    Note over R{to}: received {label}"),
+            DiagramEvent::Request { to, label } => writeln!(diagram, "    C->>R{to}: {label}"),
+            DiagramEvent::Note { replica, text } => {
+                writeln!(diagram, "    Note over R{replica}: {text}")
+            }
+            DiagramEvent::Advance { millis } => {
+                writeln!(diagram, "    Note over C: +{millis}ms")
+            }
+        };
+    }
+
+    diagram
+}
+
+#[cfg(all(test, feature = "os"))]
+mod tests {
+    use super::*;
+    use crate::driver::LocalDriver;
+    use crate::Client;
+
+    #[test]
+    fn export() {
+        let configuration = Configuration::from(3);
+        let path = std::env::temp_dir().join(format!("vr-diagram-{}.jsonl", std::process::id()));
+        let mut driver = LocalDriver::new(configuration, |_| 0);
+        let mut client = Client::new(configuration);
+
+        driver.record(&path).unwrap();
+        driver.request(client.primary(), client.new_request(1));
+        driver.drive();
+        driver.crash(0);
+        driver.advance(configuration.view_timeout() + configuration.view_jitter());
+        driver.drive();
+        driver.stop_recording().unwrap();
+
+        let mermaid = export_trace::<i32>(configuration, &path, DiagramFormat::Mermaid).unwrap();
+        let json = export_trace::<i32>(configuration, &path, DiagramFormat::Json).unwrap();
+
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(mermaid.starts_with("sequenceDiagram\n"));
+        assert!(mermaid.contains("    C->>R0: Request #1\n"));
+        assert!(mermaid.contains("    R0->>R1: Prepare v0 op 1\n"));
+        assert!(mermaid.contains("    R1->>R0: PrepareOk v0 op 1\n"));
+        assert!(mermaid.contains("    Note over R0: crash\n"));
+        assert!(mermaid.contains("    Note over R2: view 1\n"));
+
+        let events: Vec<DiagramEvent> = serde_json::from_str(&json).unwrap();
+
+        assert!(events.contains(&DiagramEvent::Note {
+            replica: 2,
+            text: "view 1".to_string()
+        }));
+    }
+}
@@ -0,0 +1,550 @@
+use super::LocalDriver;
+use crate::buffer::ClientPayload;
+use crate::client::Client;
+use crate::configuration::Configuration;
+use crate::request::{ClientIdentifier, Request, RequestIdentifier};
+use crate::service::{Context, Serializable, Service};
+use crate::viewstamp::OpNumber;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Debug, Display, Formatter};
+
+/// The number of view timeouts the group has to settle after a schedule heals its faults.
+const SETTLE: u32 = 10;
+
+/// The most steps the group takes to settle, in case replicas keep each other busy without the clock moving.
+const SETTLE_STEPS: usize = 1_000;
+
+/// A fault the nemesis injects into the group.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Fault {
+    /// Drops every message between the replica and the rest of the group,
+    /// unless as many replicas as the group tolerates already failed.
+    Partition(usize),
+    /// Delivers messages between every pair of replicas again.
+    Heal,
+    /// Crashes the replica unless as many replicas as the group tolerates already failed
+    /// (i.e. they are crashed, recovering or partitioned).
+    Crash(usize),
+    Recover(usize),
+    /// Notifies the replica that it is idle early, as if its clock ran ahead of the others.
+    Skew(usize),
+    /// Drops messages from one replica to another with the given probability in percent.
+    Loss {
+        from: usize,
+        to: usize,
+        percent: u8,
+    },
+}
+
+/// A step of a nemesis schedule.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum NemesisEvent<R> {
+    Fault(Fault),
+    /// The client sends a new request, or retries its outstanding one.
+    Request {
+        client: usize,
+        payload: R,
+    },
+    /// Advances the clock to the next idle timeout and processes messages until the group is idle.
+    Tick,
+}
+
+/// A request as observed by its client.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Operation<R> {
+    pub client: ClientIdentifier,
+    pub id: RequestIdentifier,
+    /// The step of the schedule at which the client sent the request.
+    pub invoked: usize,
+    /// The step at which the client received the reply and the reply, if it did.
+    pub completed: Option<(usize, R)>,
+}
+
+/// The operations clients invoked during a run, in the order they were invoked.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct History<R> {
+    pub operations: Vec<Operation<R>>,
+}
+
+impl<R> Default for History<R> {
+    fn default() -> Self {
+        Self {
+            operations: Vec::new(),
+        }
+    }
+}
+
+/// A run whose history is not linearizable with respect to the operations the group committed.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Violation {
+    /// Two replicas committed different requests at the same op-number.
+    Disagreement(OpNumber),
+    /// The same request was committed at two op-numbers.
+    Duplicate(OpNumber),
+    /// A client received a reply for a request the group did not commit.
+    Lost {
+        client: ClientIdentifier,
+        id: RequestIdentifier,
+    },
+    /// A client received a different reply than executing the committed operations in order produces.
+    WrongReply(OpNumber),
+    /// An operation was committed before one that completed before it was invoked.
+    Reordered(OpNumber),
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Violation::Disagreement(op_number) => {
+                write!(f, "replicas committed different requests at {op_number:?}")
+            }
+            Violation::Duplicate(op_number) => {
+                write!(f, "the request at {op_number:?} was committed twice")
+            }
+            Violation::Lost { client, id } => {
+                write!(
+                    f,
+                    "request {id:?} of {client:?} was acknowledged but not committed"
+                )
+            }
+            Violation::WrongReply(op_number) => {
+                write!(
+                    f,
+                    "the reply for {op_number:?} does not match the committed history"
+                )
+            }
+            Violation::Reordered(op_number) => write!(
+                f,
+                "the request at {op_number:?} was committed before a request that completed earlier"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Violation {}
+
+/// Runs randomized schedules of partitions, crashes, clock skew and message loss against a [`LocalDriver`]
+/// with concurrent clients, then checks that the replies the clients received are linearizable.
+///
+/// Runs are deterministic for a seed, so a failing schedule can be shrunk with [`Nemesis::shrink`]
+/// to a minimal reproduction.
+pub struct Nemesis<S, F>
+where
+    S: Service,
+{
+    configuration: Configuration,
+    clients: usize,
+    seed: u64,
+    factory: F,
+    service: std::marker::PhantomData<S>,
+}
+
+impl<S, F> Nemesis<S, F>
+where
+    S: Service + Serializable,
+    S::Reply: PartialEq,
+    F: FnMut(usize) -> S,
+{
+    /// Creates a nemesis for a group of replicas created by the factory with the given number of concurrent clients.
+    pub fn new(configuration: Configuration, clients: usize, seed: u64, factory: F) -> Self {
+        Self {
+            configuration,
+            clients: clients.max(1),
+            seed,
+            factory,
+            service: Default::default(),
+        }
+    }
+
+    /// Generates a random schedule of the given length, creating request payloads with the given function.
+    pub fn generate<P>(&self, length: usize, mut payload: P) -> Vec<NemesisEvent<S::Request>>
+    where
+        P: FnMut(&mut StdRng) -> S::Request,
+    {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let replicas = self.configuration.replicas();
+
+        (0..length)
+            .map(|_| match rng.gen_range(0..10) {
+                0..=3 => NemesisEvent::Request {
+                    client: rng.gen_range(0..self.clients),
+                    payload: payload(&mut rng),
+                },
+                4..=6 => NemesisEvent::Tick,
+                7 => NemesisEvent::Fault(match rng.gen_range(0..3) {
+                    0 => Fault::Partition(rng.gen_range(0..replicas)),
+                    1 => Fault::Crash(rng.gen_range(0..replicas)),
+                    _ => Fault::Loss {
+                        from: rng.gen_range(0..replicas),
+                        to: rng.gen_range(0..replicas),
+                        percent: rng.gen_range(0..=50),
+                    },
+                }),
+                8 => NemesisEvent::Fault(match rng.gen_bool(0.5) {
+                    true => Fault::Heal,
+                    false => Fault::Recover(rng.gen_range(0..replicas)),
+                }),
+                _ => NemesisEvent::Fault(Fault::Skew(rng.gen_range(0..replicas))),
+            })
+            .collect()
+    }
+
+    /// Runs the schedule, heals every fault, waits for outstanding requests and checks the resulting history.
+    pub fn run(
+        &mut self,
+        schedule: &[NemesisEvent<S::Request>],
+    ) -> Result<History<S::Reply>, Violation> {
+        let replicas = self.configuration.replicas();
+        let mut driver = LocalDriver::new(self.configuration, &mut self.factory);
+        let mut clients = Vec::from_iter((0..self.clients).map(|index| {
+            Client::new_with_identifier(
+                self.configuration,
+                ClientIdentifier::from(index as u128 + 1),
+            )
+        }));
+        let mut pending: HashMap<usize, (usize, Request<S::Request>)> = HashMap::new();
+        let mut history = History::default();
+        let mut isolated = HashSet::new();
+
+        driver.seed(self.seed);
+
+        for (step, event) in schedule.iter().enumerate() {
+            match event {
+                NemesisEvent::Fault(fault) => inject(&mut driver, &mut isolated, fault),
+                NemesisEvent::Request { client, payload } => {
+                    let index = client % self.clients;
+
+                    // Clients have at most one outstanding request, so a busy client retries instead.
+                    let request = match pending.get(&index) {
+                        Some((_, request)) => request.clone(),
+                        None => {
+                            let request = clients[index].new_request(payload.clone());
+
+                            pending.insert(index, (history.operations.len(), request.clone()));
+                            history.operations.push(Operation {
+                                client: request.client,
+                                id: request.id,
+                                invoked: step,
+                                completed: None,
+                            });
+
+                            request
+                        }
+                    };
+
+                    driver.broadcast_request(request);
+                    driver.drive();
+                }
+                NemesisEvent::Tick => {
+                    driver.tick();
+                }
+            }
+
+            collect(&mut driver, &mut clients, &mut pending, &mut history, step);
+        }
+
+        inject(&mut driver, &mut isolated, &Fault::Heal);
+
+        for index in 0..replicas {
+            inject(&mut driver, &mut isolated, &Fault::Recover(index));
+        }
+
+        // Give the group time for a few view changes and the clients a chance to retry their outstanding requests.
+        let settled = driver.clock().elapsed()
+            + (self.configuration.view_timeout() + self.configuration.view_jitter()) * SETTLE;
+        let mut step = schedule.len();
+
+        while driver.clock().elapsed() < settled && step < schedule.len() + SETTLE_STEPS {
+            for (_, request) in pending.values() {
+                driver.broadcast_request(request.clone());
+            }
+
+            driver.tick();
+            collect(&mut driver, &mut clients, &mut pending, &mut history, step);
+            step += 1;
+        }
+
+        self.check(&driver, &history)?;
+
+        Ok(history)
+    }
+
+    /// Shrinks a schedule that fails to a smaller one that still fails.
+    pub fn shrink(
+        &mut self,
+        schedule: Vec<NemesisEvent<S::Request>>,
+    ) -> Vec<NemesisEvent<S::Request>> {
+        shrink(schedule, |candidate| self.run(candidate).is_err())
+    }
+
+    /// Executes the operations committed by the replica that committed the most on a new service,
+    /// comparing the results with the replies clients received.
+    fn check(
+        &mut self,
+        driver: &LocalDriver<S>,
+        history: &History<S::Reply>,
+    ) -> Result<(), Violation> {
+        let reference = driver
+            .replicas()
+            .max_by_key(|replica| replica.committed())
+            .expect("the group should have replicas");
+        let log = reference.log();
+        let mut service = (self.factory)(0);
+        let mut positions = HashMap::new();
+        let mut op_number = log.first_op_number();
+
+        while op_number <= reference.committed() {
+            let Some(entry) = log.get(op_number) else {
+                break;
+            };
+            let request = entry.request();
+
+            for replica in driver.replicas() {
+                let other = replica
+                    .log()
+                    .get(op_number)
+                    .filter(|_| op_number <= replica.committed());
+
+                if other.is_some_and(|other| {
+                    (other.request().client, other.request().id) != (request.client, request.id)
+                }) {
+                    return Err(Violation::Disagreement(op_number));
+                }
+            }
+
+            if positions
+                .insert((request.client, request.id), op_number)
+                .is_some()
+            {
+                return Err(Violation::Duplicate(op_number));
+            }
+
+            let context = Context::new(
+                log.view_at(op_number).unwrap_or_default(),
+                op_number,
+                entry.timestamp(),
+            );
+            let reply = service.invoke_with_context(&request.payload, entry.prediction(), &context);
+            let observed = history
+                .operations
+                .iter()
+                .find(|operation| (operation.client, operation.id) == (request.client, request.id));
+
+            if let Some((_, expected)) = observed.and_then(|operation| operation.completed.as_ref())
+            {
+                if *expected != reply {
+                    return Err(Violation::WrongReply(op_number));
+                }
+            }
+
+            op_number.increment();
+        }
+
+        for operation in history.operations.iter() {
+            let Some((completed, _)) = operation.completed else {
+                continue;
+            };
+            let Some(&position) = positions.get(&(operation.client, operation.id)) else {
+                return Err(Violation::Lost {
+                    client: operation.client,
+                    id: operation.id,
+                });
+            };
+
+            // Operations invoked after this one completed must take effect after it.
+            for later in history.operations.iter() {
+                if later.invoked <= completed {
+                    continue;
+                }
+
+                if let Some(&other) = positions.get(&(later.client, later.id)) {
+                    if other < position {
+                        return Err(Violation::Reordered(other));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Removes events from a failing schedule as long as it keeps failing, first in large chunks then one at a time.
+pub fn shrink<E, P>(mut schedule: Vec<E>, mut fails: P) -> Vec<E>
+where
+    E: Clone,
+    P: FnMut(&[E]) -> bool,
+{
+    let mut chunk = schedule.len() / 2;
+
+    while chunk > 0 {
+        let mut start = 0;
+
+        while start < schedule.len() {
+            let end = (start + chunk).min(schedule.len());
+            let mut candidate = schedule[..start].to_vec();
+
+            candidate.extend_from_slice(&schedule[end..]);
+
+            if fails(&candidate) {
+                schedule = candidate;
+            } else {
+                start = end;
+            }
+        }
+
+        chunk /= 2;
+    }
+
+    schedule
+}
+
+fn inject<S>(driver: &mut LocalDriver<S>, isolated: &mut HashSet<usize>, fault: &Fault)
+where
+    S: Service + Serializable,
+{
+    let replicas = driver.configuration().replicas();
+
+    // A recovering replica has yet to restore its state, so it counts as failed until it finishes.
+    let failed = (0..replicas)
+        .filter(|&index| {
+            driver.is_crashed(index)
+                || driver.replica(index).is_recovering()
+                || isolated.contains(&index)
+        })
+        .count();
+
+    // Failing more replicas than the group tolerates could lose committed operations or stall the group for good.
+    let tolerated = failed < driver.configuration().sub_majority();
+
+    match *fault {
+        Fault::Partition(index) => {
+            if tolerated || isolated.contains(&index) {
+                isolated.insert(index);
+
+                for other in (0..replicas).filter(|&other| other != index) {
+                    driver.set_loss_rate(index, other, 1.0);
+                    driver.set_loss_rate(other, index, 1.0);
+                }
+            }
+        }
+        Fault::Heal => {
+            isolated.clear();
+
+            for from in 0..replicas {
+                for to in 0..replicas {
+                    driver.set_loss_rate(from, to, 0.0);
+                }
+            }
+        }
+        Fault::Crash(index) => {
+            if tolerated {
+                driver.crash(index);
+            }
+        }
+        Fault::Recover(index) => driver.recover(index),
+        Fault::Skew(index) => {
+            driver.idle(index);
+            driver.drive();
+        }
+        Fault::Loss { from, to, percent } => {
+            driver.set_loss_rate(from, to, f64::from(percent) / 100.0)
+        }
+    }
+}
+
+/// Records the replies clients received for their outstanding requests.
+fn collect<S>(
+    driver: &mut LocalDriver<S>,
+    clients: &mut [Client],
+    pending: &mut HashMap<usize, (usize, Request<S::Request>)>,
+    history: &mut History<S::Reply>,
+    step: usize,
+) where
+    S: Service + Serializable,
+{
+    for (index, client) in clients.iter_mut().enumerate() {
+        for payload in driver.take_replies(client.identifier()) {
+            let ClientPayload::Reply(reply) = payload else {
+                continue;
+            };
+
+            client.update_view(&reply);
+
+            if let Some((operation, request)) = pending.get(&index) {
+                if request.id == reply.id {
+                    history.operations[*operation].completed = Some((step, reply.payload));
+                    pending.remove(&index);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::Group;
+    use std::time::Duration;
+
+    #[test]
+    fn linearizable() {
+        for replicas in [3, 5] {
+            // Crashes can take down the primary of a view change, which only the view change timeout recovers from.
+            let configuration = Configuration::new(replicas, Group::from(1))
+                .with_view_change_timeout(Duration::from_secs(1));
+
+            for seed in 0..250 {
+                let mut nemesis = Nemesis::new(configuration, 3, seed, |_| 0);
+                let schedule = nemesis.generate(100, |rng| rng.gen_range(1..10));
+                let history = nemesis.run(&schedule).unwrap();
+
+                assert!(
+                    history
+                        .operations
+                        .iter()
+                        .all(|operation| operation.completed.is_some()),
+                    "{replicas} replicas stalled with seed {seed}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn wrong_reply() {
+        let configuration = Configuration::new(3, Group::from(1));
+        let mut nemesis = Nemesis::new(configuration, 1, 0, |_| 0);
+        let schedule = vec![
+            NemesisEvent::Request {
+                client: 0,
+                payload: 1,
+            },
+            NemesisEvent::Tick,
+        ];
+        let mut driver = LocalDriver::new(configuration, |_| 0);
+        let mut history = nemesis.run(&schedule).unwrap();
+        let mut client = Client::new_with_identifier(configuration, ClientIdentifier::from(1));
+
+        driver.broadcast_request(client.new_request(1));
+        driver.drive();
+        driver.tick();
+        history.operations[0].completed = Some((1, 2));
+
+        assert_eq!(
+            nemesis.check(&driver, &history),
+            Err(Violation::WrongReply(OpNumber::from(1)))
+        );
+    }
+
+    #[test]
+    fn shrinking() {
+        let schedule = Vec::from_iter(0..100);
+        let shrunk = shrink(schedule, |candidate| {
+            candidate.contains(&13) && candidate.contains(&42)
+        });
+
+        assert_eq!(shrunk, vec![13, 42]);
+    }
+}
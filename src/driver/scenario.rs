@@ -0,0 +1,333 @@
+use super::LocalDriver;
+use crate::buffer::ClientPayload;
+use crate::client::Client;
+use crate::configuration::Configuration;
+use crate::request::{ClientIdentifier, Request};
+use crate::service::{Protocol, Serializable, Service};
+use crate::viewstamp::OpNumber;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter};
+use std::time::Duration;
+
+/// The replica a step of a [`Scenario`] acts on.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum Target {
+    /// The replica with the given index.
+    Replica(usize),
+    /// The running replica that is the primary of the latest view when the step runs.
+    Primary,
+}
+
+/// A single action or expectation of a [`Scenario`].
+/// Steps are plain data, so scenarios can be stored and shared (e.g. as JSON reproductions from a fuzzer).
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Step<R, Y> {
+    /// The client of the scenario sends a request with the payload to the primary.
+    Request(R),
+    Crash(Target),
+    Recover(usize),
+    Idle(Target),
+    /// Every running replica becomes idle.
+    IdleAll,
+    /// Moves the simulated clock forward, letting replicas that reach their idle timeout become idle.
+    Advance(Duration),
+    /// The latest request of the client received a reply with the payload.
+    ExpectReply(Y),
+    /// The replica committed exactly the given number of operations.
+    ExpectCommitted(Target, u128),
+}
+
+/// A test case for a group of replicas, written as a sequence of steps that expand to [`LocalDriver`] operations.
+/// The driver delivers every pending message after each step, and checks that replicas agree on their committed operations.
+/// For example, `Scenario::new(configuration).client_request(1).crash(Target::Primary).idle_all().client_request(2).expect_reply(3)`
+/// checks that a request started after the primary crashed still reaches the new primary and commits.
+pub struct Scenario<S>
+where
+    S: Protocol,
+{
+    configuration: Configuration,
+    steps: Vec<Step<S::Request, S::Reply>>,
+}
+
+impl<S> Scenario<S>
+where
+    S: Protocol,
+{
+    pub fn new(configuration: Configuration) -> Self {
+        Self::from_steps(configuration, Vec::new())
+    }
+
+    /// Creates a scenario from steps recorded elsewhere (e.g. decoded from a shared reproduction).
+    pub fn from_steps(
+        configuration: Configuration,
+        steps: Vec<Step<S::Request, S::Reply>>,
+    ) -> Self {
+        Self {
+            configuration,
+            steps,
+        }
+    }
+
+    pub fn steps(&self) -> &[Step<S::Request, S::Reply>] {
+        &self.steps
+    }
+
+    pub fn step(mut self, step: Step<S::Request, S::Reply>) -> Self {
+        self.steps.push(step);
+        self
+    }
+
+    pub fn client_request(self, payload: S::Request) -> Self {
+        self.step(Step::Request(payload))
+    }
+
+    pub fn crash(self, target: Target) -> Self {
+        self.step(Step::Crash(target))
+    }
+
+    pub fn recover(self, index: usize) -> Self {
+        self.step(Step::Recover(index))
+    }
+
+    pub fn idle(self, target: Target) -> Self {
+        self.step(Step::Idle(target))
+    }
+
+    pub fn idle_all(self) -> Self {
+        self.step(Step::IdleAll)
+    }
+
+    pub fn advance(self, duration: Duration) -> Self {
+        self.step(Step::Advance(duration))
+    }
+
+    pub fn expect_reply(self, payload: S::Reply) -> Self {
+        self.step(Step::ExpectReply(payload))
+    }
+
+    pub fn expect_committed(self, target: Target, operations: u128) -> Self {
+        self.step(Step::ExpectCommitted(target, operations))
+    }
+}
+
+impl<S> Scenario<S>
+where
+    S: Service + Serializable,
+    S::Reply: PartialEq + Debug,
+{
+    /// Runs the steps against a new driver for the group, where each replica's service is created by the given factory.
+    /// Returns the driver for further inspection once every step ran, or the first expectation that did not hold.
+    pub fn run<F>(&self, factory: F) -> Result<LocalDriver<S>, ScenarioError>
+    where
+        F: FnMut(usize) -> S,
+    {
+        let mut driver = LocalDriver::new(self.configuration, factory);
+        let mut client = Client::new_with_identifier(self.configuration, ClientIdentifier::from(0));
+        let mut latest: Option<Request<S::Request>> = None;
+
+        driver.set_agreement_check(true);
+
+        for (step, action) in self.steps.iter().enumerate() {
+            match action {
+                Step::Request(payload) => {
+                    let request = client.new_request(payload.clone());
+                    let primary = primary(&driver).unwrap_or_else(|| client.primary());
+
+                    driver.request(primary, request.clone());
+                    latest = Some(request);
+                }
+                Step::Crash(target) => {
+                    if let Some(index) = resolve(&driver, *target) {
+                        driver.crash(index);
+                    }
+                }
+                Step::Recover(index) => driver.recover(*index),
+                Step::Idle(target) => {
+                    if let Some(index) = resolve(&driver, *target) {
+                        driver.idle(index);
+                    }
+                }
+                Step::IdleAll => {
+                    for index in 0..self.configuration.members() {
+                        driver.idle(index);
+                    }
+                }
+                Step::Advance(duration) => driver.advance(*duration),
+                Step::ExpectReply(expected) => {
+                    let reply = driver
+                        .take_replies(client.identifier())
+                        .into_iter()
+                        .filter_map(|payload| match payload {
+                            ClientPayload::Reply(reply) => Some(reply),
+                            _ => None,
+                        })
+                        .rfind(|reply| latest.as_ref().is_some_and(|r| r.id == reply.id));
+
+                    let Some(reply) = reply else {
+                        return Err(ScenarioError::MissingReply { step });
+                    };
+
+                    client.update_view(&reply);
+
+                    if &reply.payload != expected {
+                        return Err(ScenarioError::UnexpectedReply {
+                            step,
+                            expected: format!("{expected:?}"),
+                            actual: format!("{:?}", reply.payload),
+                        });
+                    }
+                }
+                Step::ExpectCommitted(target, expected) => {
+                    let Some(index) = resolve(&driver, *target) else {
+                        return Err(ScenarioError::NoPrimary { step });
+                    };
+                    let actual = driver.replica(index).committed();
+
+                    if actual != OpNumber::from(*expected) {
+                        return Err(ScenarioError::UnexpectedCommitted {
+                            step,
+                            index,
+                            expected: *expected,
+                            actual: actual.into(),
+                        });
+                    }
+                }
+            }
+
+            driver.drive();
+        }
+
+        Ok(driver)
+    }
+}
+
+/// The primary of the latest view among the running replicas in a normal status, if any.
+fn primary<S>(driver: &LocalDriver<S>) -> Option<usize>
+where
+    S: Service + Serializable,
+{
+    (0..driver.configuration().members())
+        .filter(|&index| !driver.is_crashed(index))
+        .filter(|&index| driver.replica(index).is_normal() && driver.replica(index).is_primary())
+        .max_by_key(|&index| driver.replica(index).view())
+}
+
+fn resolve<S>(driver: &LocalDriver<S>, target: Target) -> Option<usize>
+where
+    S: Service + Serializable,
+{
+    match target {
+        Target::Replica(index) => Some(index),
+        Target::Primary => primary(driver),
+    }
+}
+
+/// An expectation of a [`Scenario`] that did not hold, along with the index of its step.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ScenarioError {
+    /// The latest request of the client did not receive a reply.
+    MissingReply { step: usize },
+    /// The latest request of the client received a reply with a different payload.
+    UnexpectedReply {
+        step: usize,
+        expected: String,
+        actual: String,
+    },
+    /// The replica committed a different number of operations.
+    UnexpectedCommitted {
+        step: usize,
+        index: usize,
+        expected: u128,
+        actual: u128,
+    },
+    /// The step targets the primary, but no running replica is the primary of a normal view.
+    NoPrimary { step: usize },
+}
+
+impl Display for ScenarioError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScenarioError::MissingReply { step } => {
+                write!(f, "step {step}: the latest request received no reply")
+            }
+            ScenarioError::UnexpectedReply {
+                step,
+                expected,
+                actual,
+            } => write!(f, "step {step}: expected a reply of {expected}, found {actual}"),
+            ScenarioError::UnexpectedCommitted {
+                step,
+                index,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "step {step}: expected replica {index} to commit {expected} operations, found {actual}"
+            ),
+            ScenarioError::NoPrimary { step } => {
+                write!(f, "step {step}: no running replica is the primary")
+            }
+        }
+    }
+}
+
+impl Error for ScenarioError {}
+
+#[cfg(all(test, feature = "os"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn primary_crash() {
+        let driver = Scenario::<i32>::new(Configuration::from(3))
+            .client_request(1)
+            .expect_reply(1)
+            .crash(Target::Primary)
+            .idle_all()
+            .client_request(2)
+            .expect_reply(3)
+            .expect_committed(Target::Primary, 2)
+            .run(|_| 0)
+            .unwrap();
+
+        assert!(driver.is_crashed(0));
+        assert!(driver.replica(1).is_primary());
+    }
+
+    #[test]
+    fn failed_expectations() {
+        let scenario = Scenario::<i32>::new(Configuration::from(3))
+            .client_request(1)
+            .expect_reply(2);
+
+        assert_eq!(
+            scenario.run(|_| 0).err(),
+            Some(ScenarioError::UnexpectedReply {
+                step: 1,
+                expected: "2".to_string(),
+                actual: "1".to_string(),
+            })
+        );
+
+        // Steps round-trip through JSON, so failing scenarios can be shared.
+        let json = serde_json::to_string(scenario.steps()).unwrap();
+        let shared = Scenario::<i32>::from_steps(
+            Configuration::from(3),
+            serde_json::from_str(&json).unwrap(),
+        );
+
+        assert_eq!(shared.steps(), scenario.steps());
+
+        let stalled = Scenario::<i32>::new(Configuration::from(3))
+            .crash(Target::Replica(1))
+            .crash(Target::Replica(2))
+            .client_request(3)
+            .expect_reply(3);
+
+        assert_eq!(
+            stalled.run(|_| 0).err(),
+            Some(ScenarioError::MissingReply { step: 3 })
+        );
+    }
+}
@@ -0,0 +1,105 @@
+use crate::buffer::ProtocolPayload;
+use crate::durable::SaveFault;
+use crate::nonce::Nonce;
+use crate::request::Request;
+use crate::service::{Serializable, Service};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+
+/// An input to the group that the [`LocalDriver`](super::LocalDriver) decided to process.
+/// Replaying the events of a trace in order reproduces the recorded execution.
+#[derive(Serialize, Deserialize)]
+#[serde(bound(
+    serialize = "S::Request: Serialize, S::Prediction: Serialize, S::Reply: Serialize, S::Checkpoint: Serialize",
+    deserialize = "S::Request: Deserialize<'de>, S::Prediction: Deserialize<'de>, S::Reply: Deserialize<'de>, S::Checkpoint: Deserialize<'de>"
+))]
+pub enum TraceEvent<S>
+where
+    S: Service,
+{
+    Deliver {
+        index: usize,
+        message: ProtocolPayload<S>,
+    },
+    Request {
+        index: usize,
+        request: Request<S::Request>,
+    },
+    Idle {
+        index: usize,
+    },
+    Advance {
+        duration: Duration,
+    },
+    Checkpoint {
+        index: usize,
+    },
+    CheckpointWithSuffix {
+        index: usize,
+        suffix: usize,
+    },
+    Crash {
+        index: usize,
+    },
+    CrashDuringSave {
+        index: usize,
+        fault: SaveFault,
+    },
+    Recover {
+        index: usize,
+        nonce: Nonce,
+    },
+}
+
+/// Writes trace events to a file as JSON lines.
+/// The first error is kept and reported when the recording is finished.
+pub(super) struct Recorder {
+    writer: BufWriter<File>,
+    error: Option<io::Error>,
+}
+
+impl Recorder {
+    pub(super) fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            writer: BufWriter::new(File::create(path)?),
+            error: None,
+        })
+    }
+
+    pub(super) fn write<S>(&mut self, event: &TraceEvent<S>)
+    where
+        S: Service + Serializable,
+    {
+        if self.error.is_some() {
+            return;
+        }
+
+        let result = serde_json::to_writer(&mut self.writer, event)
+            .map_err(io::Error::from)
+            .and_then(|_| self.writer.write_all(b"\n"));
+
+        if let Err(error) = result {
+            self.error = Some(error);
+        }
+    }
+
+    pub(super) fn finish(mut self) -> io::Result<()> {
+        match self.error.take() {
+            Some(error) => Err(error),
+            None => self.writer.flush(),
+        }
+    }
+}
+
+pub(super) fn read<S>(path: impl AsRef<Path>) -> io::Result<Vec<TraceEvent<S>>>
+where
+    S: Service + Serializable,
+{
+    BufReader::new(File::open(path)?)
+        .lines()
+        .map(|line| Ok(serde_json::from_str(&line?)?))
+        .collect()
+}
@@ -0,0 +1,411 @@
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::error::Error;
+use core::fmt::{Display, Formatter};
+use core::time::Duration;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// The number of bytes of the checksum that precedes the encoded state.
+const HEADER: usize = 4;
+
+/// How often a durable log or state backend flushes its writes to storage (i.e. calls `fsync`).
+///
+/// The recovery protocol restores a crashed replica from the rest of the group, so the group stays correct without
+/// flushing as long as fewer than a majority of replicas lose their unflushed writes at the same time.
+/// Flushing more often only protects against correlated failures (e.g. a power loss across the whole group)
+/// at the cost of the latency of every commit.
+///
+/// The replica never writes to storage itself, so backends that persist its state (e.g. a file the application appends
+/// checkpoints to) ask [`FsyncPolicy::is_due`] after each write whether to flush.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub enum FsyncPolicy {
+    /// Flush before acknowledging every operation. Survives the whole group losing power at once.
+    #[default]
+    Always,
+    /// Flush once the given number of operations were written, losing at most that many on a correlated failure.
+    EveryNOps(usize),
+    /// Flush once the given duration passed since the last flush, losing at most that window on a correlated failure.
+    EveryDuration(Duration),
+    /// Leave flushing to the operating system. Relies entirely on the recovery protocol for durability.
+    Never,
+}
+
+impl FsyncPolicy {
+    /// Whether a backend should flush given the number of operations written and the time passed since its last flush.
+    pub fn is_due(&self, operations: usize, elapsed: Duration) -> bool {
+        match *self {
+            FsyncPolicy::Always => operations > 0,
+            FsyncPolicy::EveryNOps(limit) => operations >= limit.max(1),
+            FsyncPolicy::EveryDuration(interval) => operations > 0 && elapsed >= interval,
+            FsyncPolicy::Never => false,
+        }
+    }
+}
+
+/// Encodes state to persist (e.g. a checkpoint or a durable log) preceded by a checksum of the encoding,
+/// so loading it with [`unseal`] detects torn writes and corrupted storage.
+pub fn seal<T>(state: &T) -> Result<Vec<u8>, serde_json::Error>
+where
+    T: Serialize,
+{
+    let encoded = serde_json::to_vec(state)?;
+    let mut bytes = Vec::with_capacity(HEADER + encoded.len());
+
+    bytes.extend_from_slice(&crc32(&encoded).to_le_bytes());
+    bytes.extend_from_slice(&encoded);
+
+    Ok(bytes)
+}
+
+/// Decodes state persisted with [`seal`] after validating its checksum.
+pub fn unseal<T>(bytes: &[u8]) -> Result<T, CorruptState>
+where
+    T: DeserializeOwned,
+{
+    let Some((header, encoded)) = bytes.split_first_chunk::<HEADER>() else {
+        return Err(CorruptState::Truncated {
+            length: bytes.len(),
+        });
+    };
+
+    let expected = u32::from_le_bytes(*header);
+    let actual = crc32(encoded);
+
+    if expected != actual {
+        return Err(CorruptState::Checksum { expected, actual });
+    }
+
+    serde_json::from_slice(encoded).map_err(|error| CorruptState::Malformed(error.to_string()))
+}
+
+/// Persisted state that failed validation when it was loaded.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum CorruptState {
+    /// The state is shorter than its checksum (e.g. the write was interrupted).
+    Truncated { length: usize },
+    /// The checksum of the state does not match the one persisted with it (e.g. a torn write or bit rot).
+    Checksum { expected: u32, actual: u32 },
+    /// The checksum matches but the state does not decode (e.g. it was written by an incompatible version).
+    Malformed(String),
+}
+
+/// What a replica should do when its persisted state is corrupt.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Remedy {
+    /// Discard the state and recover as a new replica with an empty checkpoint,
+    /// so the recovery protocol and state transfer fetch the state from the rest of the group.
+    RecoverAsNew,
+    /// Stop the replica, since the state was written correctly and discarding it could hide a bug or a bad upgrade.
+    Abort,
+}
+
+impl CorruptState {
+    pub fn remedy(&self) -> Remedy {
+        match self {
+            CorruptState::Truncated { .. } | CorruptState::Checksum { .. } => Remedy::RecoverAsNew,
+            CorruptState::Malformed(_) => Remedy::Abort,
+        }
+    }
+}
+
+impl Display for CorruptState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            CorruptState::Truncated { length } => write!(
+                f,
+                "persisted state is truncated ({length} bytes); recover as a new replica"
+            ),
+            CorruptState::Checksum { expected, actual } => write!(
+                f,
+                "persisted state checksum {actual:#010x} does not match {expected:#010x}; recover as a new replica"
+            ),
+            CorruptState::Malformed(error) => {
+                write!(f, "persisted state does not decode ({error}); aborting")
+            }
+        }
+    }
+}
+
+impl Error for CorruptState {}
+
+#[cfg(feature = "std")]
+pub use storage::{FaultyState, FileState, SaveFault, State};
+
+#[cfg(feature = "std")]
+mod storage {
+    use serde::{Deserialize, Serialize};
+    use std::collections::VecDeque;
+    use std::fs::{self, File};
+    use std::io::{self, Write};
+    use std::path::{Path, PathBuf};
+
+    /// Storage for the sealed state of a replica (e.g. a file or an embedded database), which is saved and loaded as a whole.
+    /// Implementations should be crash consistent: a crash in the middle of a save must leave either the previous
+    /// or the new state behind, or at least a state that [`unseal`] rejects.
+    pub trait State {
+        /// Replaces the persisted state with the given bytes.
+        fn save(&mut self, bytes: &[u8]) -> io::Result<()>;
+
+        /// The persisted state, or `None` when nothing was saved yet.
+        fn load(&self) -> io::Result<Option<Vec<u8>>>;
+    }
+
+    /// Keeps the state in memory, where an empty vector means nothing was saved yet.
+    impl State for Vec<u8> {
+        fn save(&mut self, bytes: &[u8]) -> io::Result<()> {
+            self.clear();
+            self.extend_from_slice(bytes);
+
+            Ok(())
+        }
+
+        fn load(&self) -> io::Result<Option<Vec<u8>>> {
+            Ok((!self.is_empty()).then(|| self.clone()))
+        }
+    }
+
+    impl<T> State for Box<T>
+    where
+        T: State + ?Sized,
+    {
+        fn save(&mut self, bytes: &[u8]) -> io::Result<()> {
+            self.as_mut().save(bytes)
+        }
+
+        fn load(&self) -> io::Result<Option<Vec<u8>>> {
+            self.as_ref().load()
+        }
+    }
+
+    /// Keeps the state in a file, which is replaced as a whole by writing the new state to a temporary file
+    /// next to it and renaming the temporary file over it once flushed, so a crash leaves either state behind.
+    #[derive(Clone, Debug, Eq, PartialEq, Hash)]
+    pub struct FileState {
+        path: PathBuf,
+    }
+
+    impl FileState {
+        pub fn new(path: impl Into<PathBuf>) -> Self {
+            Self { path: path.into() }
+        }
+
+        pub fn path(&self) -> &Path {
+            &self.path
+        }
+    }
+
+    impl State for FileState {
+        fn save(&mut self, bytes: &[u8]) -> io::Result<()> {
+            let mut temporary = self.path.clone().into_os_string();
+            temporary.push(".tmp");
+
+            let mut file = File::create(&temporary)?;
+            file.write_all(bytes)?;
+            file.sync_all()?;
+
+            fs::rename(&temporary, &self.path)
+        }
+
+        fn load(&self) -> io::Result<Option<Vec<u8>>> {
+            match fs::read(&self.path) {
+                Ok(bytes) => Ok(Some(bytes)),
+                Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+                Err(error) => Err(error),
+            }
+        }
+    }
+
+    /// How a [`FaultyState`] interferes with a save.
+    #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+    pub enum SaveFault {
+        /// The save fails without writing anything (e.g. the disk is full).
+        Fail,
+        /// Only the given number of leading bytes are written before the save fails (e.g. the process crashed mid-write).
+        Partial(usize),
+    }
+
+    /// Wraps a [`State`] to fail or partially complete upcoming saves, for testing that replicas survive crashes during a save.
+    /// Saves without a pending fault, and every load, go straight to the wrapped state.
+    pub struct FaultyState<T> {
+        state: T,
+        faults: VecDeque<SaveFault>,
+        injected: usize,
+    }
+
+    impl<T> FaultyState<T>
+    where
+        T: State,
+    {
+        pub fn new(state: T) -> Self {
+            Self {
+                state,
+                faults: VecDeque::new(),
+                injected: 0,
+            }
+        }
+
+        /// Interferes with the next save that does not already have a fault pending.
+        pub fn inject(&mut self, fault: SaveFault) {
+            self.faults.push_back(fault);
+        }
+
+        /// The number of saves that failed because of an injected fault.
+        pub fn injected(&self) -> usize {
+            self.injected
+        }
+
+        pub fn get_ref(&self) -> &T {
+            &self.state
+        }
+
+        pub fn into_inner(self) -> T {
+            self.state
+        }
+    }
+
+    impl<T> State for FaultyState<T>
+    where
+        T: State,
+    {
+        fn save(&mut self, bytes: &[u8]) -> io::Result<()> {
+            let Some(fault) = self.faults.pop_front() else {
+                return self.state.save(bytes);
+            };
+
+            self.injected += 1;
+
+            match fault {
+                SaveFault::Fail => Err(io::Error::other("injected save failure")),
+                SaveFault::Partial(length) => {
+                    self.state.save(&bytes[..length.min(bytes.len())])?;
+
+                    Err(io::Error::new(
+                        io::ErrorKind::Interrupted,
+                        "injected partial save",
+                    ))
+                }
+            }
+        }
+
+        fn load(&self) -> io::Result<Option<Vec<u8>>> {
+            self.state.load()
+        }
+    }
+}
+
+/// The CRC-32 (IEEE) checksum of the bytes.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+
+    for &byte in bytes {
+        crc ^= byte as u32;
+
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::Checkpoint;
+    use crate::viewstamp::OpNumber;
+
+    #[test]
+    fn fsync_policy() {
+        let second = Duration::from_secs(1);
+
+        assert!(FsyncPolicy::Always.is_due(1, Duration::ZERO));
+        assert!(!FsyncPolicy::Always.is_due(0, second));
+        assert!(!FsyncPolicy::EveryNOps(3).is_due(2, second));
+        assert!(FsyncPolicy::EveryNOps(3).is_due(3, Duration::ZERO));
+        assert!(!FsyncPolicy::EveryDuration(second).is_due(5, Duration::ZERO));
+        assert!(FsyncPolicy::EveryDuration(second).is_due(1, second));
+        assert!(!FsyncPolicy::Never.is_due(100, second));
+    }
+
+    #[test]
+    fn crc() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn corrupt() {
+        let checkpoint = Checkpoint::<i32, i32> {
+            committed: OpNumber::from(3),
+            state: 42,
+            client_table: Default::default(),
+            generation: 0,
+        };
+        let mut bytes = seal(&checkpoint).unwrap();
+
+        assert_eq!(unseal(&bytes), Ok(checkpoint));
+        assert_eq!(
+            unseal::<Checkpoint<i32, i32>>(&bytes[..2]),
+            Err(CorruptState::Truncated { length: 2 })
+        );
+
+        let last = bytes.len() - 1;
+
+        bytes[last] ^= 1;
+
+        let error = unseal::<Checkpoint<i32, i32>>(&bytes).unwrap_err();
+
+        assert!(matches!(error, CorruptState::Checksum { .. }));
+        assert_eq!(error.remedy(), Remedy::RecoverAsNew);
+
+        let bytes = seal(&"not a checkpoint").unwrap();
+        let error = unseal::<Checkpoint<i32, i32>>(&bytes).unwrap_err();
+
+        assert_eq!(error.remedy(), Remedy::Abort);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn faulty_state() {
+        let mut state = FaultyState::new(Vec::new());
+        let first = seal(&1).unwrap();
+        let second = seal(&2).unwrap();
+
+        assert_eq!(state.load().unwrap(), None);
+
+        state.save(&first).unwrap();
+        state.inject(SaveFault::Fail);
+        state.inject(SaveFault::Partial(2));
+
+        assert!(state.save(&second).is_err());
+        assert_eq!(unseal::<i32>(&state.load().unwrap().unwrap()), Ok(1));
+        assert!(state.save(&second).is_err());
+        assert_eq!(
+            unseal::<i32>(&state.load().unwrap().unwrap()),
+            Err(CorruptState::Truncated { length: 2 })
+        );
+        assert_eq!(state.injected(), 2);
+
+        state.save(&second).unwrap();
+
+        assert_eq!(unseal::<i32>(&state.into_inner()), Ok(2));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn file_state() {
+        let directory = std::env::temp_dir().join(format!("vr-file-state-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+
+        let mut state = FileState::new(directory.join("replica-0.checkpoint"));
+
+        assert_eq!(state.load().unwrap(), None);
+
+        state.save(&seal(&1).unwrap()).unwrap();
+        state.save(&seal(&2).unwrap()).unwrap();
+
+        assert_eq!(unseal::<i32>(&state.load().unwrap().unwrap()), Ok(2));
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
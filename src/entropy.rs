@@ -0,0 +1,187 @@
+use crate::log::{Entry, Log};
+use crate::protocol::Digest;
+use crate::viewstamp::{OpNumber, View};
+use alloc::vec::Vec;
+use core::hash::{BuildHasher, Hash, Hasher};
+use foldhash::fast::{FixedState, FoldHasher};
+
+/// The number of consecutive operations covered by each hash in a digest.
+const RANGE: u128 = 64;
+
+/// Hashes committed log entries in fixed ranges of op-numbers, so replicas can compare their logs
+/// without sending the entries and repair the ranges that silently diverged (e.g. corrupted storage).
+pub(crate) struct AntiEntropy<R, P> {
+    hash: fn(&Entry<R, P>, &mut FoldHasher),
+    /// The number of idle periods between digests sent by the primary.
+    period: u32,
+    idle: u32,
+}
+
+impl<R, P> AntiEntropy<R, P>
+where
+    R: Hash,
+    P: Hash,
+{
+    pub fn new(period: u32) -> Self {
+        Self {
+            hash: hash_entry::<R, P>,
+            period: period.max(1),
+            idle: 0,
+        }
+    }
+}
+
+impl<R, P> AntiEntropy<R, P> {
+    /// Counts an idle period of the primary. Returns true when a digest is due.
+    pub fn tick(&mut self) -> bool {
+        self.idle += 1;
+
+        if self.idle < self.period {
+            return false;
+        }
+
+        self.idle = 0;
+        true
+    }
+
+    /// Hashes every complete range of committed operations the log still holds.
+    pub fn digest(
+        &self,
+        view: View,
+        epoch: u64,
+        index: usize,
+        log: &Log<R, P>,
+        committed: OpNumber,
+    ) -> Digest {
+        let first = u128::from(log.first_op_number()).max(1);
+        let committed = u128::from(committed);
+        // Ranges are aligned, so replicas that compacted their logs differently still hash the same ranges.
+        let mut start = (first - 1).div_ceil(RANGE) * RANGE + 1;
+        let mut ranges = Vec::new();
+        let mut hashes = Vec::new();
+
+        while start + RANGE - 1 <= committed {
+            let range = (OpNumber::from(start), OpNumber::from(start + RANGE - 1));
+
+            if let Some(hash) = self.hash_range(log, range) {
+                ranges.push(range);
+                hashes.push(hash);
+            }
+
+            start += RANGE;
+        }
+
+        Digest {
+            view,
+            epoch,
+            ranges,
+            hashes,
+            index,
+        }
+    }
+
+    /// The first range of committed operations whose entries hash differently in the log than in the digest.
+    pub fn diverged(
+        &self,
+        log: &Log<R, P>,
+        committed: OpNumber,
+        digest: &Digest,
+    ) -> Option<(OpNumber, OpNumber)> {
+        digest
+            .ranges
+            .iter()
+            .zip(&digest.hashes)
+            .filter(|((_, last), _)| *last <= committed)
+            .find(|(&range, &hash)| self.hash_range(log, range).is_some_and(|h| h != hash))
+            .map(|(&range, _)| range)
+    }
+
+    fn hash_range(&self, log: &Log<R, P>, (first, last): (OpNumber, OpNumber)) -> Option<u64> {
+        if first > last || !log.contains(&first) || !log.contains(&last) {
+            return None;
+        }
+
+        // A fixed seed, so every replica hashes the same entries to the same value.
+        let mut hasher = FixedState::default().build_hasher();
+        let mut op_number = first;
+
+        while op_number <= last {
+            (self.hash)(log.get(op_number)?, &mut hasher);
+            op_number.increment();
+        }
+
+        Some(hasher.finish())
+    }
+}
+
+fn hash_entry<R, P>(entry: &Entry<R, P>, hasher: &mut FoldHasher)
+where
+    R: Hash,
+    P: Hash,
+{
+    let request = entry.request();
+
+    request.payload.hash(hasher);
+    request.client.hash(hasher);
+    request.id.hash(hasher);
+    entry.prediction().hash(hasher);
+}
+
+#[cfg(all(test, feature = "os"))]
+mod tests {
+    use super::*;
+    use crate::request::{ClientIdentifier, Request, RequestIdentifier};
+
+    #[test]
+    fn diverged() {
+        let anti_entropy = AntiEntropy::<i32, ()>::new(1);
+        let client = ClientIdentifier::default();
+        let mut id = RequestIdentifier::default();
+        let mut log = Log::default();
+        let mut other = Log::default();
+
+        for payload in 0..200 {
+            id.increment();
+
+            let request = Request {
+                payload,
+                client,
+                id,
+                deadline: None,
+                signature: None,
+                epoch: 0,
+                staleness: None,
+            };
+
+            log.push(View::default(), request.clone(), ());
+            other.push(
+                View::default(),
+                Request {
+                    payload: if payload == 100 { -1 } else { payload },
+                    ..request
+                },
+                (),
+            );
+        }
+
+        let committed = OpNumber::from(150);
+        let digest = anti_entropy.digest(View::default(), 0, 0, &log, committed);
+
+        assert_eq!(
+            digest.ranges,
+            vec![
+                (OpNumber::from(1), OpNumber::from(64)),
+                (OpNumber::from(65), OpNumber::from(128))
+            ]
+        );
+        assert_eq!(anti_entropy.diverged(&log, committed, &digest), None);
+        assert_eq!(
+            anti_entropy.diverged(&other, committed, &digest),
+            Some((OpNumber::from(65), OpNumber::from(128)))
+        );
+        assert_eq!(
+            anti_entropy.diverged(&other, OpNumber::from(100), &digest),
+            None
+        );
+    }
+}
@@ -0,0 +1,177 @@
+use crate::log::Entry;
+use crate::service::{Conflicts, Protocol};
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::thread;
+
+/// Runs the committed operations in log order and returns their replies in the same order.
+pub type Executor<S> = fn(
+    &S,
+    &[&Entry<<S as Protocol>::Request, <S as Protocol>::Prediction>],
+    usize,
+) -> Vec<<S as Protocol>::Reply>;
+
+/// Splits the operations into consecutive batches of operations that do not conflict with each other,
+/// then runs each batch on up to the given number of threads.
+/// Batches run one after another, so every pair of conflicting operations runs in log order.
+pub fn execute_concurrently<S>(
+    service: &S,
+    entries: &[&Entry<S::Request, S::Prediction>],
+    workers: usize,
+) -> Vec<S::Reply>
+where
+    S: Conflicts,
+    S::Request: Sync,
+    S::Prediction: Sync,
+    S::Reply: Send,
+{
+    let mut replies = Vec::with_capacity(entries.len());
+    let mut start = 0;
+
+    while start < entries.len() {
+        let mut end = start + 1;
+
+        while end < entries.len()
+            && entries[start..end].iter().all(|entry| {
+                !service.conflicts(&entry.request().payload, &entries[end].request().payload)
+            })
+        {
+            end += 1;
+        }
+
+        replies.extend(execute_batch(service, &entries[start..end], workers));
+        start = end;
+    }
+
+    replies
+}
+
+#[cfg_attr(not(feature = "std"), allow(unused_variables))]
+fn execute_batch<S>(
+    service: &S,
+    batch: &[&Entry<S::Request, S::Prediction>],
+    workers: usize,
+) -> Vec<S::Reply>
+where
+    S: Conflicts,
+    S::Request: Sync,
+    S::Prediction: Sync,
+    S::Reply: Send,
+{
+    let invoke = |entry: &&Entry<S::Request, S::Prediction>| {
+        service.invoke_concurrently(&entry.request().payload, entry.prediction())
+    };
+
+    #[cfg(feature = "std")]
+    if batch.len() > 1 && workers > 1 {
+        let size = batch.len().div_ceil(workers);
+
+        return thread::scope(|scope| {
+            let handles = Vec::from_iter(
+                batch
+                    .chunks(size)
+                    .map(|chunk| scope.spawn(move || Vec::from_iter(chunk.iter().map(invoke)))),
+            );
+
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().expect("operation panicked"))
+                .collect()
+        });
+    }
+
+    // Without the standard library there are no threads, so batches run on the calling thread.
+    batch.iter().map(invoke).collect()
+}
+
+#[cfg(all(test, feature = "os"))]
+mod tests {
+    use super::*;
+    use crate::log::Log;
+    use crate::service::Service;
+    use crate::viewstamp::View;
+    use crate::Client;
+    use crate::Configuration;
+    use serde::{Deserialize, Serialize};
+    use std::sync::atomic::{AtomicI64, Ordering};
+
+    /// Counters that can be incremented concurrently as long as no two requests update the same counter.
+    struct Counters(Vec<AtomicI64>);
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    struct Increment {
+        counter: usize,
+        delta: i64,
+    }
+
+    impl Protocol for Counters {
+        type Request = Increment;
+        type Prediction = ();
+        type Reply = i64;
+        type Checkpoint = Vec<i64>;
+    }
+
+    impl From<Vec<i64>> for Counters {
+        fn from(values: Vec<i64>) -> Self {
+            Self(values.into_iter().map(AtomicI64::new).collect())
+        }
+    }
+
+    impl Service for Counters {
+        fn predict(&self, _: &Increment) {}
+
+        fn checkpoint(&self) -> Vec<i64> {
+            self.0
+                .iter()
+                .map(|counter| counter.load(Ordering::SeqCst))
+                .collect()
+        }
+
+        fn invoke(&mut self, request: &Increment, prediction: &()) -> i64 {
+            self.invoke_concurrently(request, prediction)
+        }
+    }
+
+    impl Conflicts for Counters {
+        fn conflicts(&self, a: &Increment, b: &Increment) -> bool {
+            a.counter == b.counter
+        }
+
+        fn invoke_concurrently(&self, request: &Increment, _: &()) -> i64 {
+            self.0[request.counter].fetch_add(request.delta, Ordering::SeqCst) + request.delta
+        }
+    }
+
+    #[test]
+    fn serial_equivalence() {
+        let mut client = Client::new(Configuration::from(3));
+        let mut log = Log::default();
+
+        for delta in 1..=100 {
+            let request = client.new_request(Increment {
+                counter: delta as usize % 4,
+                delta,
+            });
+
+            log.push(View::default(), request, ());
+        }
+
+        let entries = Vec::from_iter((0..log.len()).map(|offset| {
+            let mut op_number = log.first_op_number();
+            op_number.increment_by(offset);
+            &log[op_number]
+        }));
+        let concurrent = Counters::from(vec![0; 4]);
+        let mut serial = Counters::from(vec![0; 4]);
+
+        let replies = execute_concurrently(&concurrent, &entries, 4);
+        let expected = Vec::from_iter(
+            entries
+                .iter()
+                .map(|entry| serial.invoke(&entry.request().payload, &())),
+        );
+
+        assert_eq!(replies, expected);
+        assert_eq!(concurrent.checkpoint(), serial.checkpoint());
+    }
+}
@@ -0,0 +1,3 @@
+//! Gateways expose a running replica to clients and other replicas over a network protocol.
+
+pub mod http;
@@ -0,0 +1,649 @@
+//! An HTTP/JSON gateway to a replica built on axum.
+//!
+//! Client requests, discovery and protocol messages from other replicas are accepted as JSON and processed by a single
+//! [`EventLoop`] that owns the replica. Outbound protocol messages are sent through a user-provided [`Transport`],
+//! while replies are returned in the response to the client's HTTP request.
+//! Protocol messages must be wrapped in [`Versioned`] and are rejected when sent by a replica running an
+//! incompatible version of the protocol.
+//! Middleware can be added to the [`Router`] returned by [`Gateway::router`] using `Router::layer`.
+//! [`HttpTransport`] sends protocol messages to the gateways of the other replicas at their advertised addresses.
+
+use crate::buffer::{BufferedMailbox, ClientPayload, ProtocolPayload};
+use crate::durable::{self, seal};
+use crate::members::Members;
+use crate::request::{ClientIdentifier, Discover, Request};
+use crate::service::Payload;
+use crate::version::{Versioned, PROTOCOL_VERSION};
+use crate::viewstamp::{OpNumber, View};
+use crate::{Replica, Service};
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, oneshot};
+
+/// The bounds on the associated types of a service for its messages to be exchanged as JSON between tasks.
+pub trait JsonPayload: Payload + Send + Sync + 'static {}
+
+impl<P> JsonPayload for P where P: Payload + Send + Sync + 'static {}
+
+type Pending<S> = Arc<Mutex<HashMap<ClientIdentifier, oneshot::Sender<ClientPayload<S>>>>>;
+
+pub enum Event<S>
+where
+    S: Service,
+{
+    Request(Request<S::Request>),
+    Discover(Discover),
+    Protocol(ProtocolPayload<S>),
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ReplicaStatus {
+    /// The index of the replica in the configuration.
+    pub index: usize,
+    /// The current view of the replica.
+    pub view: View,
+    /// Whether the replica is the primary of the current view.
+    pub primary: bool,
+    /// The version of the protocol the replica runs.
+    #[serde(default)]
+    pub version: u32,
+}
+
+impl<S> From<&Replica<S>> for ReplicaStatus
+where
+    S: Service,
+{
+    fn from(replica: &Replica<S>) -> Self {
+        Self {
+            index: replica.index(),
+            view: replica.view(),
+            primary: replica.is_primary(),
+            version: PROTOCOL_VERSION,
+        }
+    }
+}
+
+/// Sends protocol messages to the other replicas in the group.
+/// Messages sent to another gateway must be wrapped in [`Versioned`].
+pub trait Transport<S>
+where
+    S: Service,
+{
+    fn send(
+        &mut self,
+        index: usize,
+        message: ProtocolPayload<S>,
+    ) -> impl Future<Output = ()> + Send;
+
+    fn broadcast(&mut self, message: ProtocolPayload<S>) -> impl Future<Output = ()> + Send;
+}
+
+/// A handle to a running replica that is shared by all HTTP connections.
+pub struct Gateway<S>
+where
+    S: Service,
+{
+    events: mpsc::UnboundedSender<Event<S>>,
+    pending: Pending<S>,
+    status: Arc<RwLock<ReplicaStatus>>,
+    reply_timeout: Duration,
+}
+
+impl<S> Clone for Gateway<S>
+where
+    S: Service,
+{
+    fn clone(&self) -> Self {
+        Self {
+            events: self.events.clone(),
+            pending: self.pending.clone(),
+            status: self.status.clone(),
+            reply_timeout: self.reply_timeout,
+        }
+    }
+}
+
+impl<S> Gateway<S>
+where
+    S: Service + Send + 'static,
+    S::Request: JsonPayload,
+    S::Prediction: JsonPayload,
+    S::Reply: JsonPayload,
+    S::Checkpoint: JsonPayload,
+{
+    /// Creates a gateway to the replica along with the event loop that must be run for the replica to make progress.
+    /// Requests wait at most the reply timeout for the replica to reply.
+    pub fn new(replica: Replica<S>, reply_timeout: Duration) -> (Self, EventLoop<S>) {
+        Self::with_mailbox(replica, BufferedMailbox::default(), reply_timeout)
+    }
+
+    /// Creates a gateway to a replica that already queued messages in the given mailbox
+    /// (e.g. a recovering replica's recovery request), which the event loop sends as soon as it runs.
+    pub fn with_mailbox(
+        replica: Replica<S>,
+        mailbox: BufferedMailbox<S>,
+        reply_timeout: Duration,
+    ) -> (Self, EventLoop<S>) {
+        let (events, receiver) = mpsc::unbounded_channel();
+        let pending = Pending::default();
+        let status = Arc::new(RwLock::new(ReplicaStatus::from(&replica)));
+        let gateway = Self {
+            events,
+            pending: pending.clone(),
+            status: status.clone(),
+            reply_timeout,
+        };
+        let event_loop = EventLoop {
+            saved: replica.committed(),
+            replica,
+            receiver,
+            pending,
+            status,
+            mailbox,
+            stalled: Default::default(),
+            storage: None,
+        };
+
+        (gateway, event_loop)
+    }
+
+    /// Queues a protocol message from another replica, as if it was received over HTTP.
+    pub fn deliver(&self, message: ProtocolPayload<S>) -> bool {
+        self.events.send(Event::Protocol(message)).is_ok()
+    }
+
+    pub fn router(&self) -> Router {
+        Router::new()
+            .route("/request", post(request::<S>))
+            .route("/discover", post(discover::<S>))
+            .route("/protocol", post(protocol::<S>))
+            .route("/status", get(status::<S>))
+            .with_state(self.clone())
+    }
+}
+
+async fn request<S>(
+    State(gateway): State<Gateway<S>>,
+    Json(request): Json<Request<S::Request>>,
+) -> Result<Json<ClientPayload<S>>, StatusCode>
+where
+    S: Service + Send + 'static,
+    S::Request: JsonPayload,
+    S::Prediction: JsonPayload,
+    S::Reply: JsonPayload,
+    S::Checkpoint: JsonPayload,
+{
+    let client = request.client;
+
+    exchange(gateway, client, Event::Request(request)).await
+}
+
+async fn discover<S>(
+    State(gateway): State<Gateway<S>>,
+    Json(discover): Json<Discover>,
+) -> Result<Json<ClientPayload<S>>, StatusCode>
+where
+    S: Service + Send + 'static,
+    S::Request: JsonPayload,
+    S::Prediction: JsonPayload,
+    S::Reply: JsonPayload,
+    S::Checkpoint: JsonPayload,
+{
+    exchange(gateway, discover.client, Event::Discover(discover)).await
+}
+
+/// Queues the event for the replica and waits for the replica to answer the client.
+async fn exchange<S>(
+    gateway: Gateway<S>,
+    client: ClientIdentifier,
+    event: Event<S>,
+) -> Result<Json<ClientPayload<S>>, StatusCode>
+where
+    S: Service + Send + 'static,
+    S::Request: JsonPayload,
+    S::Prediction: JsonPayload,
+    S::Reply: JsonPayload,
+    S::Checkpoint: JsonPayload,
+{
+    let (sender, receiver) = oneshot::channel();
+
+    gateway
+        .pending
+        .lock()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .insert(client, sender);
+
+    gateway
+        .events
+        .send(event)
+        .map_err(|_| StatusCode::SERVICE_UNAVAILABLE)?;
+
+    match tokio::time::timeout(gateway.reply_timeout, receiver).await {
+        Ok(Ok(payload)) => Ok(Json(payload)),
+        Ok(Err(_)) => Err(StatusCode::SERVICE_UNAVAILABLE),
+        Err(_) => {
+            if let Ok(mut pending) = gateway.pending.lock() {
+                pending.remove(&client);
+            }
+
+            Err(StatusCode::GATEWAY_TIMEOUT)
+        }
+    }
+}
+
+async fn protocol<S>(
+    State(gateway): State<Gateway<S>>,
+    body: Bytes,
+) -> Result<StatusCode, (StatusCode, String)>
+where
+    S: Service + Send + 'static,
+    S::Request: JsonPayload,
+    S::Prediction: JsonPayload,
+    S::Reply: JsonPayload,
+    S::Checkpoint: JsonPayload,
+{
+    // The format of the message depends on the version, so check the version before decoding the message.
+    // The message is decoded from the body rather than a JSON value, since values cannot hold identifiers above `u64::MAX`.
+    serde_json::from_slice::<Versioned<IgnoredAny>>(&body)
+        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()))?
+        .into_message()
+        .map_err(|e| (StatusCode::CONFLICT, e.to_string()))?;
+    let message = serde_json::from_slice::<Versioned<ProtocolPayload<S>>>(&body)
+        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()))?
+        .message;
+
+    if gateway.deliver(message) {
+        Ok(StatusCode::ACCEPTED)
+    } else {
+        Err((StatusCode::SERVICE_UNAVAILABLE, String::new()))
+    }
+}
+
+async fn status<S>(State(gateway): State<Gateway<S>>) -> Result<Json<ReplicaStatus>, StatusCode>
+where
+    S: Service + Send + 'static,
+    S::Request: JsonPayload,
+    S::Prediction: JsonPayload,
+    S::Reply: JsonPayload,
+    S::Checkpoint: JsonPayload,
+{
+    gateway
+        .status
+        .read()
+        .map(|status| Json(*status))
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+/// Owns the replica and processes the events queued by the gateway one at a time.
+pub struct EventLoop<S>
+where
+    S: Service,
+{
+    replica: Replica<S>,
+    receiver: mpsc::UnboundedReceiver<Event<S>>,
+    pending: Pending<S>,
+    status: Arc<RwLock<ReplicaStatus>>,
+    mailbox: BufferedMailbox<S>,
+    stalled: Vec<ProtocolPayload<S>>,
+    storage: Option<(Box<dyn durable::State + Send>, Instant)>,
+    saved: OpNumber,
+}
+
+impl<S> EventLoop<S>
+where
+    S: Service + Send + 'static,
+    S::Request: JsonPayload,
+    S::Prediction: JsonPayload,
+    S::Reply: JsonPayload,
+    S::Checkpoint: JsonPayload,
+{
+    /// Saves a sealed checkpoint of the replica to the given storage whenever its committed operations advanced,
+    /// at most once per idle timeout, so a restarted process can recover the replica from it.
+    pub fn with_storage<D>(mut self, storage: D) -> Self
+    where
+        D: durable::State + Send + 'static,
+    {
+        self.storage = Some((Box::new(storage), Instant::now()));
+        self
+    }
+
+    /// Processes events until every gateway to the replica is dropped.
+    /// The replica is considered idle when no event arrives within the idle timeout.
+    pub async fn run<T>(mut self, mut transport: T, idle_timeout: Duration) -> Replica<S>
+    where
+        T: Transport<S>,
+    {
+        self.flush(&mut transport).await;
+
+        loop {
+            match tokio::time::timeout(idle_timeout, self.receiver.recv()).await {
+                Ok(None) => return self.replica,
+                Ok(Some(Event::Request(request))) => {
+                    self.replica.handle_request(request, &mut self.mailbox);
+                }
+                Ok(Some(Event::Discover(discover))) => {
+                    self.replica.handle_discover(discover, &mut self.mailbox);
+                }
+                Ok(Some(Event::Protocol(message))) => {
+                    let stalled = std::mem::take(&mut self.stalled);
+
+                    self.replica.handle_payload(message, &mut self.mailbox);
+
+                    for message in stalled {
+                        self.replica.handle_payload(message, &mut self.mailbox);
+                    }
+                }
+                Err(_) => self.replica.idle(&mut self.mailbox),
+            }
+
+            self.flush(&mut transport).await;
+            self.persist(idle_timeout);
+        }
+    }
+
+    fn persist(&mut self, interval: Duration) {
+        let Some((storage, last)) = self.storage.as_mut() else {
+            return;
+        };
+
+        if self.replica.committed() <= self.saved || last.elapsed() < interval {
+            return;
+        }
+
+        let committed = self.replica.committed();
+        let saved = seal(&self.replica.checkpoint())
+            .map_err(io::Error::other)
+            .and_then(|bytes| storage.save(&bytes));
+
+        // A failed save is retried after the next interval, while the replica keeps serving from memory.
+        *last = Instant::now();
+        if saved.is_ok() {
+            self.saved = committed;
+        }
+    }
+
+    async fn flush<T>(&mut self, transport: &mut T)
+    where
+        T: Transport<S>,
+    {
+        self.stalled.extend(self.mailbox.drain_inbound());
+
+        if let Ok(mut pending) = self.pending.lock() {
+            for envelope in self.mailbox.drain_replies() {
+                // Each HTTP request gets a single response, leaving no stream to push notifications on.
+                if matches!(envelope.payload, ClientPayload::Notification(_)) {
+                    continue;
+                }
+
+                if let Some(sender) = pending.remove(&envelope.destination) {
+                    let _ = sender.send(envelope.payload);
+                }
+            }
+        }
+
+        let send = Vec::from_iter(self.mailbox.drain_send());
+        for envelope in send {
+            transport.send(envelope.destination, envelope.payload).await;
+        }
+
+        let broadcast = Vec::from_iter(self.mailbox.drain_broadcast());
+        for message in broadcast {
+            transport.broadcast(message).await;
+        }
+
+        if let Ok(mut status) = self.status.write() {
+            *status = ReplicaStatus::from(&self.replica);
+        }
+    }
+}
+
+/// Sends protocol messages to the gateways of the other replicas at their advertised addresses.
+/// Each message is posted from its own task so an unreachable replica does not hold up the event loop,
+/// and messages that fail to send are dropped as on any other lossy network.
+pub struct HttpTransport {
+    index: usize,
+    addresses: Vec<SocketAddr>,
+}
+
+impl HttpTransport {
+    /// A transport for the replica with the given index in the group.
+    pub fn new(members: &Members, index: usize) -> Self {
+        Self {
+            index,
+            addresses: members
+                .endpoints()
+                .iter()
+                .map(|endpoint| endpoint.advertise)
+                .collect(),
+        }
+    }
+
+    fn spawn<S>(&self, address: SocketAddr, message: ProtocolPayload<S>)
+    where
+        S: Service,
+        ProtocolPayload<S>: Serialize,
+    {
+        let Ok(body) = serde_json::to_vec(&Versioned::new(message)) else {
+            return;
+        };
+
+        tokio::spawn(async move {
+            let _ = post_json(address, "/protocol", &body).await;
+        });
+    }
+}
+
+impl<S> Transport<S> for HttpTransport
+where
+    S: Service + Send + 'static,
+    S::Request: JsonPayload,
+    S::Prediction: JsonPayload,
+    S::Reply: JsonPayload,
+    S::Checkpoint: JsonPayload,
+{
+    async fn send(&mut self, index: usize, message: ProtocolPayload<S>) {
+        if let Some(&address) = self.addresses.get(index) {
+            self.spawn(address, message);
+        }
+    }
+
+    async fn broadcast(&mut self, message: ProtocolPayload<S>) {
+        for (index, &address) in self.addresses.iter().enumerate() {
+            if index != self.index {
+                self.spawn(address, message.clone());
+            }
+        }
+    }
+}
+
+/// Posts the JSON body to the path of the gateway at the given address over HTTP/1.1,
+/// returning the status and body of the response.
+pub async fn post_json(
+    address: SocketAddr,
+    path: &str,
+    body: &[u8],
+) -> io::Result<(StatusCode, Vec<u8>)> {
+    let mut stream = TcpStream::connect(address).await?;
+    let head = format!(
+        "POST {path} HTTP/1.1\r\nhost: {address}\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+        body.len()
+    );
+
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body).await?;
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await?;
+
+    let malformed = || io::Error::new(io::ErrorKind::InvalidData, "malformed HTTP response");
+    let end = response
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .ok_or_else(malformed)?;
+    let status = response
+        .get(9..12)
+        .and_then(|code| StatusCode::from_bytes(code).ok())
+        .ok_or_else(malformed)?;
+
+    Ok((status, response.split_off(end + 4)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Client, Configuration};
+    use axum::body::Body;
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    struct Channels {
+        index: usize,
+        gateways: Vec<Gateway<i32>>,
+    }
+
+    impl Transport<i32> for Channels {
+        async fn send(&mut self, index: usize, message: ProtocolPayload<i32>) {
+            self.gateways[index].deliver(message);
+        }
+
+        async fn broadcast(&mut self, message: ProtocolPayload<i32>) {
+            for (index, gateway) in self.gateways.iter().enumerate() {
+                if index != self.index {
+                    gateway.deliver(message.clone());
+                }
+            }
+        }
+    }
+
+    fn group(configuration: Configuration) -> Vec<Gateway<i32>> {
+        let mut gateways = Vec::new();
+        let mut loops = Vec::new();
+
+        for index in 0..configuration.replicas() {
+            let replica = Replica::new(configuration, index, 0);
+            let (gateway, event_loop) = Gateway::new(replica, Duration::from_secs(5));
+
+            gateways.push(gateway);
+            loops.push(event_loop);
+        }
+
+        for (index, event_loop) in loops.into_iter().enumerate() {
+            let transport = Channels {
+                index,
+                gateways: gateways.clone(),
+            };
+
+            tokio::spawn(event_loop.run(transport, Duration::from_secs(60)));
+        }
+
+        gateways
+    }
+
+    #[tokio::test]
+    async fn request() {
+        let configuration = Configuration::from(3);
+        let gateways = group(configuration);
+        let mut client = Client::new(configuration);
+        let request = client.new_request(42);
+        let body = serde_json::to_vec(&request).unwrap();
+        let response = gateways[0]
+            .router()
+            .oneshot(
+                axum::http::Request::post("/request")
+                    .header("content-type", "application/json")
+                    .body(Body::from(body))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        let reply: ClientPayload<i32> = serde_json::from_slice(&bytes).unwrap();
+        let reply = reply.unwrap_reply();
+
+        assert_eq!(reply.id, request.id);
+        assert_eq!(reply.payload, 42);
+    }
+
+    #[tokio::test]
+    async fn status() {
+        let gateways = group(Configuration::from(3));
+        let response = gateways[1]
+            .router()
+            .oneshot(
+                axum::http::Request::get("/status")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        let status: ReplicaStatus = serde_json::from_slice(&bytes).unwrap();
+
+        assert_eq!(
+            status,
+            ReplicaStatus {
+                index: 1,
+                view: View::default(),
+                primary: false,
+                version: PROTOCOL_VERSION,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn incompatible_version() {
+        let gateways = group(Configuration::from(3));
+        let mut message = Versioned::new(ProtocolPayload::<i32>::Commit(crate::Commit {
+            view: View::default(),
+            epoch: 0,
+            committed: Default::default(),
+            timestamp: Default::default(),
+        }));
+
+        message.version += 1;
+
+        let body = serde_json::to_vec(&message).unwrap();
+        let response = gateways[1]
+            .router()
+            .oneshot(
+                axum::http::Request::post("/protocol")
+                    .header("content-type", "application/json")
+                    .body(Body::from(body))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+
+        assert_eq!(
+            String::from_utf8_lossy(&bytes),
+            crate::IncompatibleVersion {
+                expected: PROTOCOL_VERSION,
+                actual: PROTOCOL_VERSION + 1,
+            }
+            .to_string()
+        );
+    }
+}
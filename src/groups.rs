@@ -0,0 +1,314 @@
+use crate::buffer::{BufferedMailbox, ClientPayload, ProtocolPayload};
+use crate::configuration::Group;
+use crate::replica::Replica;
+use crate::request::{ClientIdentifier, Request};
+use crate::service::Service;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::time::Duration;
+use hashbrown::HashMap;
+#[cfg(feature = "std")]
+use std::thread;
+
+/// Sends the messages of replicas hosted by a [`GroupManager`] to their destinations.
+/// Every message is tagged with the group of the replica that sent it.
+pub trait GroupTransport<S>
+where
+    S: Service,
+{
+    fn send(&mut self, group: Group, index: usize, message: ProtocolPayload<S>);
+
+    /// Sends the message to every replica in the group other than the source.
+    fn broadcast(&mut self, group: Group, source: usize, message: ProtocolPayload<S>);
+
+    fn reply(&mut self, group: Group, client: ClientIdentifier, message: ClientPayload<S>);
+
+    /// Sends every message for the client at once.
+    /// Defaults to replying with each message in order for transports that cannot coalesce messages.
+    fn reply_batch(
+        &mut self,
+        group: Group,
+        client: ClientIdentifier,
+        messages: Vec<ClientPayload<S>>,
+    ) {
+        for message in messages {
+            self.reply(group, client, message);
+        }
+    }
+}
+
+struct Host<S>
+where
+    S: Service,
+{
+    replica: Replica<S>,
+    inbox: BufferedMailbox<S>,
+    requests: VecDeque<Request<S::Request>>,
+    stalled: Vec<ProtocolPayload<S>>,
+    outbox: BufferedMailbox<S>,
+}
+
+impl<S> Host<S>
+where
+    S: Service,
+{
+    fn process(&mut self, now: Duration) -> usize {
+        let mut processed = 0;
+
+        while let Some(message) = self.inbox.pop_inbound() {
+            let stalled = core::mem::take(&mut self.stalled);
+
+            self.replica.handle_payload(message, &mut self.outbox);
+
+            for message in stalled {
+                self.replica.handle_payload(message, &mut self.outbox);
+            }
+
+            self.stalled.extend(self.outbox.drain_inbound());
+            processed += 1;
+        }
+
+        while let Some(request) = self.requests.pop_front() {
+            self.replica
+                .handle_request_at(request, now, &mut self.outbox);
+            processed += 1;
+        }
+
+        processed
+    }
+}
+
+/// Hosts replicas of many independent groups (e.g. one per shard of a keyspace) in a single process.
+/// Messages are routed to replicas by the identifier of their group and processed on a bounded number of threads.
+pub struct GroupManager<S>
+where
+    S: Service,
+{
+    /// Unused without the standard library, where the hosts process their messages on the calling thread.
+    #[cfg_attr(not(feature = "std"), allow(dead_code))]
+    workers: usize,
+    hosts: HashMap<Group, Host<S>>,
+}
+
+impl<S> GroupManager<S>
+where
+    S: Service + Send,
+    S::Request: Send + Sync,
+    S::Prediction: Send + Sync,
+    S::Reply: Send + Sync,
+    S::Checkpoint: Send + Sync,
+{
+    /// Creates a manager that processes groups on at most the given number of threads.
+    pub fn new(workers: usize) -> Self {
+        Self {
+            workers: workers.max(1),
+            hosts: Default::default(),
+        }
+    }
+
+    /// Hosts the replica, returning the replica previously hosted for the same group.
+    pub fn insert(&mut self, replica: Replica<S>) -> Option<Replica<S>> {
+        let group = replica.configuration().group();
+        let host = Host {
+            replica,
+            inbox: Default::default(),
+            requests: Default::default(),
+            stalled: Default::default(),
+            outbox: Default::default(),
+        };
+
+        self.hosts.insert(group, host).map(|host| host.replica)
+    }
+
+    pub fn remove(&mut self, group: Group) -> Option<Replica<S>> {
+        self.hosts.remove(&group).map(|host| host.replica)
+    }
+
+    pub fn replica(&self, group: Group) -> Option<&Replica<S>> {
+        self.hosts.get(&group).map(|host| &host.replica)
+    }
+
+    pub fn groups(&self) -> impl Iterator<Item = Group> + '_ {
+        self.hosts.keys().copied()
+    }
+
+    /// Queues a protocol message for the replica of the given group.
+    /// Returns false when no replica of the group is hosted or its inbox rejected the message.
+    pub fn deliver(&mut self, group: Group, message: ProtocolPayload<S>) -> bool {
+        match self.hosts.get_mut(&group) {
+            Some(host) => host.inbox.push_inbound(message).is_ok(),
+            None => false,
+        }
+    }
+
+    /// Queues a client request for the replica of the given group.
+    /// Returns false when no replica of the group is hosted.
+    pub fn request(&mut self, group: Group, request: Request<S::Request>) -> bool {
+        match self.hosts.get_mut(&group) {
+            Some(host) => {
+                host.requests.push_back(request);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Notifies the replica of the given group that it has not received any messages within its timeout.
+    #[cfg(feature = "os")]
+    pub fn idle(&mut self, group: Group) {
+        self.idle_at(group, crate::clock::unix_time())
+    }
+
+    /// Notifies the replica of the given group that it is idle at the given time since the Unix epoch.
+    /// See [`Replica::idle_at`].
+    pub fn idle_at(&mut self, group: Group, now: Duration) {
+        if let Some(host) = self.hosts.get_mut(&group) {
+            host.replica.idle_at(now, &mut host.outbox);
+            host.stalled.extend(host.outbox.drain_inbound());
+        }
+    }
+
+    /// Processes all queued messages and requests, spreading the groups across the worker threads.
+    /// Returns the number of messages and requests processed.
+    #[cfg(feature = "os")]
+    pub fn process(&mut self) -> usize {
+        self.process_at(crate::clock::unix_time())
+    }
+
+    /// Processes all queued messages and requests like [`GroupManager::process`],
+    /// handling requests at the given time since the Unix epoch.
+    pub fn process_at(&mut self, now: Duration) -> usize {
+        #[cfg(feature = "std")]
+        if self.workers > 1 && self.hosts.len() > 1 {
+            let mut hosts = Vec::from_iter(self.hosts.values_mut());
+            let chunk = hosts.len().div_ceil(self.workers);
+
+            return thread::scope(|scope| {
+                let workers = Vec::from_iter(hosts.chunks_mut(chunk).map(|chunk| {
+                    scope.spawn(move || {
+                        chunk
+                            .iter_mut()
+                            .map(|host| host.process(now))
+                            .sum::<usize>()
+                    })
+                }));
+
+                workers
+                    .into_iter()
+                    .map(|worker| worker.join().unwrap_or_default())
+                    .sum()
+            });
+        }
+
+        // Without the standard library there are no threads, so the hosts process their messages one at a time.
+        self.hosts.values_mut().map(|host| host.process(now)).sum()
+    }
+
+    /// Hands every message sent by the hosted replicas to the transport.
+    pub fn flush<T>(&mut self, transport: &mut T)
+    where
+        T: GroupTransport<S>,
+    {
+        for (&group, host) in self.hosts.iter_mut() {
+            let source = host.replica.index();
+
+            for envelope in host.outbox.drain_reply_batches() {
+                transport.reply_batch(group, envelope.destination, envelope.payload);
+            }
+
+            for envelope in host.outbox.drain_send() {
+                transport.send(group, envelope.destination, envelope.payload);
+            }
+
+            for message in host.outbox.drain_broadcast() {
+                transport.broadcast(group, source, message);
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "os"))]
+mod tests {
+    use super::*;
+    use crate::{Client, Configuration};
+
+    #[derive(Default)]
+    struct Network {
+        messages: Vec<(usize, Group, ProtocolPayload<i32>)>,
+        replies: HashMap<Group, Vec<ClientPayload<i32>>>,
+    }
+
+    impl GroupTransport<i32> for Network {
+        fn send(&mut self, group: Group, index: usize, message: ProtocolPayload<i32>) {
+            self.messages.push((index, group, message));
+        }
+
+        fn broadcast(&mut self, group: Group, source: usize, message: ProtocolPayload<i32>) {
+            for index in 0..3 {
+                if index != source {
+                    self.messages.push((index, group, message.clone()));
+                }
+            }
+        }
+
+        fn reply(&mut self, group: Group, _: ClientIdentifier, message: ClientPayload<i32>) {
+            self.replies.entry(group).or_default().push(message);
+        }
+    }
+
+    #[test]
+    fn shards() {
+        let shards = [Configuration::from(3), Configuration::from(3)];
+        let mut managers = Vec::from_iter((0..3).map(|_| GroupManager::new(2)));
+        let mut network = Network::default();
+
+        for (index, manager) in managers.iter_mut().enumerate() {
+            for configuration in shards {
+                manager.insert(Replica::new(configuration, index, 0));
+            }
+        }
+
+        for (payload, configuration) in shards.iter().enumerate() {
+            let mut client = Client::new(*configuration);
+            let request = client.new_request(payload as i32 + 1);
+
+            assert!(managers[client.primary()].request(configuration.group(), request));
+        }
+
+        loop {
+            let processed: usize = managers.iter_mut().map(GroupManager::process).sum();
+
+            if processed == 0 {
+                break;
+            }
+
+            for manager in managers.iter_mut() {
+                manager.flush(&mut network);
+            }
+
+            for (index, group, message) in network.messages.drain(..) {
+                managers[index].deliver(group, message);
+            }
+        }
+
+        for (payload, configuration) in shards.iter().enumerate() {
+            let replies = network.replies.remove(&configuration.group()).unwrap();
+
+            assert_eq!(replies.len(), 1);
+            assert_eq!(
+                replies[0].clone().unwrap_reply().payload,
+                payload as i32 + 1
+            );
+        }
+
+        assert!(!managers[0].deliver(
+            Group::default(),
+            ProtocolPayload::Commit(crate::Commit {
+                view: Default::default(),
+                epoch: 0,
+                committed: Default::default(),
+                timestamp: Default::default(),
+            })
+        ));
+    }
+}
@@ -0,0 +1,218 @@
+use crate::buffer::{BufferedMailbox, ProtocolPayload};
+use crate::durable::{seal, unseal, Remedy, State};
+use crate::replica::Replica;
+use crate::request::Request;
+use crate::service::{Protocol, Serializable, Service};
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::time::Duration;
+
+/// A message a transport received for a replica.
+#[derive(Serialize, Deserialize)]
+#[serde(bound(
+    serialize = "P::Request: Serialize, P::Prediction: Serialize, P::Reply: Serialize, P::Checkpoint: Serialize",
+    deserialize = "P::Request: Deserialize<'de>, P::Prediction: Deserialize<'de>, P::Reply: Deserialize<'de>, P::Checkpoint: Deserialize<'de>"
+))]
+pub enum Inbound<P>
+where
+    P: Protocol,
+{
+    Request(Request<P::Request>),
+    Protocol(ProtocolPayload<P>),
+}
+
+/// A mailbox for transports that acknowledge messages to their senders before the replica processes them
+/// (e.g. a message queue with at-least-once delivery), which journals every inbound message until a poll processes it.
+///
+/// The transport records each message with [`DurableMailbox::record`] and only acknowledges it once recording succeeds.
+/// [`DurableMailbox::poll`] hands the recorded messages to the replica and truncates the journal afterwards,
+/// so a replica that crashes mid-poll reopens the journal with [`DurableMailbox::open`] and processes the messages again
+/// instead of losing client requests the transport already acknowledged.
+/// Messages may therefore be processed twice, which replicas tolerate like duplicates from the network.
+///
+/// The journal is saved as a whole on every record, so it suits transports that poll after small batches of messages.
+/// Acknowledged messages only survive a crash mid-record when the journal saves atomically
+/// (e.g. by writing to a temporary file and renaming it), since a torn journal is discarded.
+pub struct DurableMailbox<S, J>
+where
+    S: Protocol,
+{
+    mailbox: BufferedMailbox<S>,
+    journal: J,
+    pending: Vec<Inbound<S>>,
+}
+
+impl<S, J> DurableMailbox<S, J>
+where
+    S: Service + Serializable,
+    J: State,
+{
+    /// Opens the journal, keeping the messages recorded before a crash for the next poll.
+    /// A journal that fails to unseal because it was torn is discarded, while one that does not decode is an error.
+    pub fn open(journal: J) -> io::Result<Self> {
+        Self::with_mailbox(journal, BufferedMailbox::default())
+    }
+
+    /// Opens the journal like [`DurableMailbox::open`], processing messages through the given mailbox
+    /// (e.g. one with capacity limits).
+    pub fn with_mailbox(journal: J, mailbox: BufferedMailbox<S>) -> io::Result<Self> {
+        let pending = match journal.load()? {
+            None => Vec::new(),
+            Some(bytes) => match unseal(&bytes) {
+                Ok(pending) => pending,
+                Err(error) if error.remedy() == Remedy::RecoverAsNew => Vec::new(),
+                Err(error) => return Err(io::Error::new(io::ErrorKind::InvalidData, error)),
+            },
+        };
+
+        Ok(Self {
+            mailbox,
+            journal,
+            pending,
+        })
+    }
+
+    /// Journals the message for the next poll. The transport may acknowledge the message once this returns successfully.
+    pub fn record(&mut self, message: Inbound<S>) -> io::Result<()> {
+        self.pending.push(message);
+
+        if let Err(error) = self.save() {
+            self.pending.pop();
+            return Err(error);
+        }
+
+        Ok(())
+    }
+
+    /// Journals a client request like [`DurableMailbox::record`].
+    pub fn record_request(&mut self, request: Request<S::Request>) -> io::Result<()> {
+        self.record(Inbound::Request(request))
+    }
+
+    /// Journals a message from another replica like [`DurableMailbox::record`].
+    pub fn record_payload(&mut self, message: ProtocolPayload<S>) -> io::Result<()> {
+        self.record(Inbound::Protocol(message))
+    }
+
+    /// Hands every recorded message to the replica along with the messages the replica queued for itself,
+    /// then truncates the journal. Returns the number of recorded messages processed.
+    ///
+    /// When truncating fails the messages stay in the journal and are processed again after a restart.
+    #[cfg(feature = "os")]
+    pub fn poll(&mut self, replica: &mut Replica<S>) -> io::Result<usize> {
+        self.poll_at(replica, crate::clock::unix_time())
+    }
+
+    /// Hands the recorded messages to the replica like [`DurableMailbox::poll`],
+    /// handling requests at the given time since the Unix epoch.
+    pub fn poll_at(&mut self, replica: &mut Replica<S>, now: Duration) -> io::Result<usize> {
+        let processed = self.pending.len();
+
+        for message in &self.pending {
+            match message {
+                Inbound::Request(request) => {
+                    replica.handle_request_at(request.clone(), now, &mut self.mailbox)
+                }
+                Inbound::Protocol(message) => {
+                    replica.handle_payload(message.clone(), &mut self.mailbox)
+                }
+            }
+        }
+
+        while let Some(message) = self.mailbox.pop_inbound() {
+            replica.handle_payload(message, &mut self.mailbox);
+        }
+
+        self.pending.clear();
+        self.save()?;
+
+        Ok(processed)
+    }
+
+    /// The number of recorded messages waiting for the next poll.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// The mailbox holding the messages the replica sent while processing, for the transport to drain.
+    pub fn mailbox(&mut self) -> &mut BufferedMailbox<S> {
+        &mut self.mailbox
+    }
+
+    pub fn journal(&self) -> &J {
+        &self.journal
+    }
+
+    pub fn into_journal(self) -> J {
+        self.journal
+    }
+
+    fn save(&mut self) -> io::Result<()> {
+        let bytes = seal(&self.pending)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+
+        self.journal.save(&bytes)
+    }
+}
+
+#[cfg(all(test, feature = "os"))]
+mod tests {
+    use super::*;
+    use crate::client::Client;
+    use crate::configuration::Configuration;
+    use crate::durable::{FaultyState, SaveFault};
+    use crate::protocol::Prepare;
+    use crate::viewstamp::OpNumber;
+
+    #[test]
+    fn crash_mid_poll() {
+        let configuration = Configuration::from(3);
+        let mut client = Client::new(configuration);
+        let mut mailbox = DurableMailbox::<i32, _>::open(Vec::new()).unwrap();
+        let request = client.new_request(1);
+
+        mailbox.record_request(request.clone()).unwrap();
+
+        // The replica crashes before the poll completes, leaving only the journal behind.
+        let journal = mailbox.into_journal();
+        let mut mailbox = DurableMailbox::<i32, _>::open(journal).unwrap();
+        let mut primary = Replica::new(configuration, 0, 0);
+
+        assert_eq!(mailbox.pending(), 1);
+        assert_eq!(mailbox.poll(&mut primary).unwrap(), 1);
+        assert_eq!(
+            mailbox
+                .mailbox()
+                .take_broadcast::<Prepare<i32, ()>>()
+                .map(|prepare| prepare.op_number),
+            Some(OpNumber::from(1))
+        );
+
+        // The poll completed, so reopening the journal does not process the request again.
+        let mailbox = DurableMailbox::<i32, _>::open(mailbox.into_journal()).unwrap();
+
+        assert_eq!(mailbox.pending(), 0);
+    }
+
+    #[test]
+    fn failed_record() {
+        let configuration = Configuration::from(3);
+        let mut client = Client::new(configuration);
+        let mut mailbox = DurableMailbox::<i32, _>::open(FaultyState::new(Vec::new())).unwrap();
+
+        mailbox.record_request(client.new_request(1)).unwrap();
+        mailbox.journal.inject(SaveFault::Fail);
+
+        assert!(mailbox.record_request(client.new_request(2)).is_err());
+        assert_eq!(mailbox.pending(), 1);
+
+        // Storage that does not save atomically tears the journal on a crash mid-record, which reopens empty.
+        mailbox.journal.inject(SaveFault::Partial(2));
+
+        assert!(mailbox.record_request(client.new_request(3)).is_err());
+
+        let mailbox = DurableMailbox::<i32, _>::open(mailbox.into_journal()).unwrap();
+
+        assert_eq!(mailbox.pending(), 0);
+    }
+}
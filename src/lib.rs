@@ -1,22 +1,105 @@
 //! A Primary Copy Method to Support Highly-Available Distributed Systems.
+//!
+//! Without the `std` feature, the protocol core (e.g. replicas, clients, logs and messages) builds for `no_std`
+//! targets with an allocator. Time then comes from the caller through the methods that take the current time
+//! (e.g. [`Replica::idle_at`] and [`Replica::handle_request_at`]), since the core never reads a clock itself.
 
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+mod batch;
+#[cfg(feature = "threads")]
+pub mod bootstrap;
 pub mod buffer;
-mod client;
+mod builder;
+mod chunk;
+pub mod client;
 mod client_table;
+#[cfg(feature = "std")]
+mod clock;
 mod configuration;
+mod decommission;
+mod delivery;
+#[cfg(feature = "std")]
+pub mod driver;
+mod durable;
+mod entropy;
+mod execution;
+#[cfg(feature = "http")]
+pub mod gateway;
+mod groups;
+#[cfg(feature = "std")]
+mod journal;
+mod listener;
 mod log;
 mod mail;
+mod members;
 mod nonce;
 mod protocol;
+mod quorum;
 mod replica;
 mod request;
+mod router;
+mod routing;
+mod schedule;
 mod service;
+pub mod services;
+mod signing;
+mod speculation;
 mod status;
+mod transaction;
+mod transfer;
+mod version;
 mod viewstamp;
+#[cfg(test)]
+mod wire;
 
-pub use client::Client;
-pub use configuration::Configuration;
+pub use batch::{BatchBuilder, Batched, BatchedCheckpoint};
+pub use builder::{BuildError, ReplicaBuilder};
+pub use chunk::{ChunkError, Chunker, Reassembler};
+pub use client::{Client, ReadPreference};
+pub use client_table::{ClientShard, ClientTable};
+#[cfg(feature = "std")]
+pub use clock::{Clock, SimClock, SystemClock};
+pub use configuration::{Configuration, Group, ReplicaIdentifier};
+pub use decommission::{Decommission, DecommissionError, Decommissioned};
+pub use durable::{seal, unseal, CorruptState, FsyncPolicy, Remedy};
+#[cfg(feature = "std")]
+pub use durable::{FaultyState, FileState, SaveFault, State};
+pub use groups::{GroupManager, GroupTransport};
+#[cfg(feature = "std")]
+pub use journal::{DurableMailbox, Inbound};
+#[cfg(feature = "std")]
+pub use listener::LatencyTracker;
+pub use listener::{DropReason, EventListener};
+pub use log::{Entry, Log, LogDigest};
 pub use mail::{Inbox, Mailbox, Outbox};
+pub use members::{Members, MembersError, ReplicaEndpoint};
+pub use nonce::Nonce;
+pub use protocol::{
+    Checkpoint, Commit, Digest, DoViewChange, GetLog, GetState, InstallSnapshot, NewLog, NewState,
+    Prepare, PrepareOk, Recovery, RecoveryResponse, StartView, StartViewChange,
+};
 pub use replica::Replica;
-pub use request::{ClientIdentifier, Reply, Request};
-pub use service::{Protocol, Service};
+pub use request::{
+    Acknowledge, Busy, ClientIdentifier, Discover, Discovered, Expired, Notification, OpenSession,
+    OutdatedEpoch, OutdatedView, QueryRequestStatus, Reply, Request, RequestIdentifier,
+    RequestState, RequestStatus, Resume, SessionAccepted, Signature, Staleness,
+};
+pub use router::{Address, Endpoint, Router};
+pub use routing::{Routed, RoutedCheckpoint, RoutedRequest, UnknownService};
+pub use schedule::{PeerStats, SendScheduler};
+pub use service::{
+    Conflicts, Context, Payload, Protocol, Query, Role, Rollback, Serializable, Service,
+};
+pub use signing::Verifier;
+#[cfg(feature = "signing")]
+pub use signing::{KeyRing, SigningKey};
+pub use transaction::{
+    Coordinator, Outbound, TransactionError, TransactionIdentifier, TransactionPhase,
+    TransactionRequest, Vote,
+};
+pub use transfer::TransferPolicy;
+pub use version::{IncompatibleVersion, Versioned, PROTOCOL_VERSION};
+pub use viewstamp::{OpNumber, View};
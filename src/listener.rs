@@ -0,0 +1,279 @@
+use crate::request::{ClientIdentifier, RequestIdentifier};
+use crate::viewstamp::{OpNumber, View};
+
+/// Why a replica discarded a message without acting on it.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum DropReason {
+    /// The message belongs to a view older than the view of the replica.
+    StaleView,
+    /// The request or message belongs to a different epoch of the group than the replica (e.g. a client that has not
+    /// discovered the current members yet, or a replica operators have not replaced yet).
+    StaleEpoch,
+    /// A client sent a request to a backup (e.g. a misrouted client or one that missed a view change).
+    NotPrimary,
+    /// The replica is not in a status that handles the message (e.g. a prepare while it is recovering).
+    UnexpectedStatus,
+    /// The message repeats one the replica already handled (e.g. a duplicate prepare or an old request).
+    Duplicate,
+    /// The client sent a request while another one of its requests is still in progress.
+    ConcurrentRequest,
+    /// The request failed signature verification.
+    Unverified,
+    /// The primary is fenced and no longer accepts requests.
+    Fenced,
+    /// An observer sent a vote (e.g. a prepare-ok), which never counts toward a quorum.
+    NonVoting,
+    /// The replica was removed from the group (see [`crate::Replica::retire`]).
+    Retired,
+    /// The message belongs to an earlier incarnation of a replica that restarted since (see [`crate::Replica::generation`]).
+    StaleGeneration,
+    /// The message does not line up with the state of the replica (e.g. a log with a gap),
+    /// which points to a bug or corruption when it happens repeatedly.
+    Inconsistent,
+    /// The message refers to a view or op-number far beyond any the group could have reached,
+    /// which only a corrupt or malicious sender produces.
+    Implausible,
+}
+
+/// Observes protocol events on a replica (e.g. for audit logs, metrics or test assertions).
+/// Listeners are notified synchronously, after the replica has updated its state for the event.
+/// All methods do nothing by default.
+pub trait EventListener {
+    /// The replica added the operation to its log, either as the primary or as a backup.
+    fn on_prepare(&mut self, _view: View, _op_number: OpNumber) {}
+
+    /// The primary counted the acknowledgement from the backup at the given index for the operations up to the op-number.
+    fn on_prepare_ok(&mut self, _op_number: OpNumber, _replica: usize) {}
+
+    /// The replica executed the committed operation against its service.
+    fn on_commit(&mut self, _op_number: OpNumber) {}
+
+    /// The replica started a view change to the given view.
+    fn on_view_change_start(&mut self, _view: View) {}
+
+    /// The replica resumed normal operation in the given view after a view change.
+    fn on_view_change_finish(&mut self, _view: View) {}
+
+    /// The replica finished recovering and resumed normal operation in the given view.
+    fn on_recovery(&mut self, _view: View) {}
+
+    /// The recovering replica re-sent its recovery request the configured number of times without recovering
+    /// (e.g. no primary of a normal view can hear it), so an operator may need to step in.
+    /// Reported once per recovery; the replica keeps retrying.
+    fn on_recovery_stalled(&mut self, _attempts: u32) {}
+
+    /// The primary sent a reply to the client's request.
+    fn on_client_reply(&mut self, _client: ClientIdentifier, _id: RequestIdentifier) {}
+
+    /// The replica received a protocol message that the given replica sent in the given view.
+    /// Failure detectors can use the traffic between replicas as evidence of which replicas are alive.
+    fn on_message(&mut self, _view: View, _replica: usize) {}
+
+    /// The replica discarded a message for the given reason.
+    fn on_drop(&mut self, _reason: DropReason) {}
+}
+
+#[cfg(feature = "std")]
+pub use latency::LatencyTracker;
+
+#[cfg(feature = "std")]
+mod latency {
+    use super::EventListener;
+    use crate::clock::Clock;
+    use crate::viewstamp::{OpNumber, View};
+    use std::collections::{BTreeMap, HashMap, VecDeque};
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    /// Measures how long operations take to replicate, as a listener on a replica.
+    /// Records when the replica prepared each operation and samples the time until the operation committed and,
+    /// on the primary, until each backup acknowledged it. Keeps the given number of the latest samples per series.
+    ///
+    /// Clones share the same samples, so keep a clone to read the percentiles after registering the tracker
+    /// with [`crate::Replica::add_listener`].
+    #[derive(Clone, Debug)]
+    pub struct LatencyTracker<C> {
+        clock: C,
+        window: usize,
+        samples: Arc<Mutex<Samples>>,
+    }
+
+    #[derive(Debug, Default)]
+    struct Samples {
+        prepared: BTreeMap<OpNumber, Instant>,
+        commits: VecDeque<Duration>,
+        acknowledgements: HashMap<usize, VecDeque<Duration>>,
+        /// The latest op-number sampled for each backup, so duplicate acknowledgements are not sampled twice.
+        acknowledged: HashMap<usize, OpNumber>,
+    }
+
+    impl<C> LatencyTracker<C>
+    where
+        C: Clock,
+    {
+        pub fn new(clock: C, window: usize) -> Self {
+            Self {
+                clock,
+                window,
+                samples: Default::default(),
+            }
+        }
+
+        /// The latency from prepare to commit at the given quantile (between 0 and 1), if any operation committed.
+        pub fn commit_latency(&self, quantile: f64) -> Option<Duration> {
+            let samples = self.samples.lock().ok()?;
+
+            percentile(&samples.commits, quantile)
+        }
+
+        /// The latency from prepare to the acknowledgement of the backup at the given index at the given quantile.
+        pub fn acknowledgement_latency(&self, replica: usize, quantile: f64) -> Option<Duration> {
+            let samples = self.samples.lock().ok()?;
+
+            percentile(samples.acknowledgements.get(&replica)?, quantile)
+        }
+
+        /// The backup with the highest acknowledgement latency at the given quantile, along with that latency.
+        pub fn slowest_backup(&self, quantile: f64) -> Option<(usize, Duration)> {
+            let samples = self.samples.lock().ok()?;
+
+            samples
+                .acknowledgements
+                .iter()
+                .filter_map(|(&replica, latencies)| {
+                    Some((replica, percentile(latencies, quantile)?))
+                })
+                .max_by_key(|&(replica, latency)| (latency, replica))
+        }
+
+        fn sample(&self, op_number: OpNumber, record: impl FnOnce(&mut Samples, Duration)) {
+            let Ok(mut samples) = self.samples.lock() else {
+                return;
+            };
+
+            if let Some(&prepared) = samples.prepared.get(&op_number) {
+                let latency = self.clock.now().saturating_duration_since(prepared);
+
+                record(&mut samples, latency);
+            }
+        }
+    }
+
+    impl<C> EventListener for LatencyTracker<C>
+    where
+        C: Clock,
+    {
+        fn on_prepare(&mut self, _view: View, op_number: OpNumber) {
+            let now = self.clock.now();
+            let Ok(mut samples) = self.samples.lock() else {
+                return;
+            };
+
+            samples.prepared.insert(op_number, now);
+
+            // Backups may acknowledge operations after they commit, so prepare times outlive commits within the window.
+            while samples.prepared.len() > self.window {
+                samples.prepared.pop_first();
+            }
+        }
+
+        fn on_prepare_ok(&mut self, op_number: OpNumber, replica: usize) {
+            let window = self.window;
+
+            self.sample(op_number, |samples, latency| {
+                let acknowledged = samples.acknowledged.entry(replica).or_default();
+
+                if *acknowledged < op_number {
+                    *acknowledged = op_number;
+                    push_sample(
+                        samples.acknowledgements.entry(replica).or_default(),
+                        latency,
+                        window,
+                    );
+                }
+            });
+        }
+
+        fn on_commit(&mut self, op_number: OpNumber) {
+            let window = self.window;
+
+            self.sample(op_number, |samples, latency| {
+                push_sample(&mut samples.commits, latency, window);
+            });
+        }
+
+        fn on_view_change_start(&mut self, _view: View) {
+            // Operations are prepared again (or replaced) in the new view, so earlier prepare times no longer apply.
+            if let Ok(mut samples) = self.samples.lock() {
+                samples.prepared.clear();
+                samples.acknowledged.clear();
+            }
+        }
+    }
+
+    fn push_sample(samples: &mut VecDeque<Duration>, latency: Duration, window: usize) {
+        samples.push_back(latency);
+
+        while samples.len() > window {
+            samples.pop_front();
+        }
+    }
+
+    /// The sample at the given quantile (clamped between 0 and 1) using the nearest-rank method.
+    fn percentile(samples: &VecDeque<Duration>, quantile: f64) -> Option<Duration> {
+        let mut sorted = Vec::from_iter(samples.iter().copied());
+
+        sorted.sort_unstable();
+
+        let last = sorted.len().checked_sub(1)?;
+        let rank = (last as f64 * quantile.clamp(0.0, 1.0)).round() as usize;
+
+        sorted.get(rank).copied()
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::clock::SimClock;
+    use std::time::Duration;
+
+    #[test]
+    fn latency_tracker() {
+        let clock = SimClock::default();
+        let tracker = LatencyTracker::new(clock.clone(), 2);
+        let mut listener = tracker.clone();
+        let view = View::default();
+        let first = OpNumber::from(1);
+        let second = OpNumber::from(2);
+
+        assert_eq!(tracker.commit_latency(0.5), None);
+
+        listener.on_prepare(view, first);
+        clock.advance(Duration::from_millis(1));
+        listener.on_prepare(view, second);
+        clock.advance(Duration::from_millis(2));
+        listener.on_prepare_ok(second, 1);
+        listener.on_prepare_ok(second, 1);
+        listener.on_commit(first);
+        listener.on_commit(second);
+        clock.advance(Duration::from_millis(7));
+        listener.on_prepare_ok(second, 2);
+
+        assert_eq!(tracker.commit_latency(0.0), Some(Duration::from_millis(2)));
+        assert_eq!(tracker.commit_latency(1.0), Some(Duration::from_millis(3)));
+        assert_eq!(
+            tracker.acknowledgement_latency(1, 0.99),
+            Some(Duration::from_millis(2))
+        );
+        assert_eq!(
+            tracker.slowest_backup(0.99),
+            Some((2, Duration::from_millis(9)))
+        );
+
+        listener.on_view_change_start(view.next());
+        listener.on_commit(OpNumber::from(3));
+
+        assert_eq!(tracker.commit_latency(0.0), Some(Duration::from_millis(2)));
+    }
+}
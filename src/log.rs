@@ -1,21 +1,28 @@
 use crate::request::Request;
 use crate::viewstamp::{OpNumber, View};
+use alloc::collections::VecDeque;
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::ops::{Index, IndexMut};
+use core::time::Duration;
 use serde::{Deserialize, Serialize};
-use std::cmp::Ordering;
-use std::collections::VecDeque;
-use std::ops::{Index, IndexMut};
 
 #[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Entry<R, P> {
     request: Request<R>,
     prediction: P,
+    timestamp: Duration,
 }
 
 impl<R, P> Entry<R, P> {
-    fn new(request: Request<R>, prediction: P) -> Self {
+    fn new(request: Request<R>, prediction: P, timestamp: Duration) -> Self {
         Self {
             request,
             prediction,
+            timestamp,
         }
     }
 
@@ -26,13 +33,75 @@ impl<R, P> Entry<R, P> {
     pub fn prediction(&self) -> &P {
         &self.prediction
     }
+
+    /// The time since the Unix epoch at which the primary prepared the operation.
+    pub fn timestamp(&self) -> Duration {
+        self.timestamp
+    }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+/// Entries are shared behind an [`Arc`] so that messages carrying the log (i.e. view changes, state transfers
+/// and recovery responses) do not need to copy every request and prediction.
+///
+/// The view table records the view in which each run of entries was added as pairs of the view and the last
+/// op-number added in that view. Two logs whose tables agree up to an op-number hold the same entries up to it.
+///
+/// Replicas only change their logs through operations on ranges of op-numbers: view changes and state transfers
+/// send the suffix after an op-number ([`Log::after`]) and adopt it with [`Log::splice`] or [`Log::extend`].
+/// Replicas drop uncommitted entries with [`Log::truncate`] and compaction drops the oldest with [`Log::constrain`].
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(try_from = "LogParts<R, P>")]
 pub struct Log<R, P> {
     view: View,
     range: (OpNumber, OpNumber),
-    entries: VecDeque<Entry<R, P>>,
+    #[serde(default)]
+    views: Vec<(View, OpNumber)>,
+    entries: VecDeque<Arc<Entry<R, P>>>,
+}
+
+/// The fields of a log as received from another replica, before checking that they are consistent.
+#[derive(Deserialize)]
+struct LogParts<R, P> {
+    view: View,
+    range: (OpNumber, OpNumber),
+    #[serde(default)]
+    views: Vec<(View, OpNumber)>,
+    entries: VecDeque<Arc<Entry<R, P>>>,
+}
+
+impl<R, P> TryFrom<LogParts<R, P>> for Log<R, P> {
+    type Error = String;
+
+    fn try_from(parts: LogParts<R, P>) -> Result<Self, Self::Error> {
+        let log = Self {
+            view: parts.view,
+            range: parts.range,
+            views: parts.views,
+            entries: parts.entries,
+        };
+
+        if !log.is_consistent() {
+            let (first, last) = log.range;
+
+            return Err(format!(
+                "a log with {} entries cannot hold op-numbers {first:?} through {last:?}",
+                log.entries.len()
+            ));
+        }
+
+        Ok(log)
+    }
+}
+
+impl<R, P> Clone for Log<R, P> {
+    fn clone(&self) -> Self {
+        Self {
+            view: self.view,
+            range: self.range,
+            views: self.views.clone(),
+            entries: self.entries.clone(),
+        }
+    }
 }
 
 impl<R, P> Default for Log<R, P> {
@@ -40,6 +109,7 @@ impl<R, P> Default for Log<R, P> {
         Self {
             view: Default::default(),
             range: (Default::default(), Default::default()),
+            views: Default::default(),
             entries: Default::default(),
         }
     }
@@ -65,23 +135,109 @@ impl<R, P> PartialOrd for Log<R, P> {
     }
 }
 
-impl<R, P> Log<R, P>
-where
-    R: Clone,
-    P: Clone,
-{
+impl<R, P> Log<R, P> {
     pub fn after(&self, latest: OpNumber) -> Self {
-        let index = latest - self.range.0;
+        self.after_at_most(latest, self.entries.len())
+    }
+
+    /// An excerpt of at most the given number of entries following the latest op-number.
+    /// The excerpt is empty when the log no longer holds the entry following the latest op-number.
+    pub fn after_at_most(&self, latest: OpNumber, limit: usize) -> Self {
+        let skip = latest
+            .next()
+            .checked_sub(self.range.0)
+            .unwrap_or(self.entries.len());
+        let entries: VecDeque<_> = self
+            .entries
+            .iter()
+            .skip(skip)
+            .take(limit)
+            .cloned()
+            .collect();
+        let mut last = latest;
+
+        last.increment_by(entries.len());
+
+        let mut views = Vec::new();
+
+        if !entries.is_empty() {
+            for &(view, op_number) in self.views.iter().filter(|(_, o)| *o > latest) {
+                views.push((view, op_number.min(last)));
+
+                if op_number >= last {
+                    break;
+                }
+            }
+        }
 
         Self {
             view: self.view,
-            range: (latest.next(), self.range.1),
-            entries: self.entries.iter().skip(index + 1).cloned().collect(),
+            range: (latest.next(), last),
+            views,
+            entries,
         }
     }
-}
 
-impl<R, P> Log<R, P> {
+    /// An empty log in the given view whose next op-number follows the latest one.
+    pub fn empty(view: View, latest: OpNumber) -> Self {
+        Self {
+            view,
+            range: (latest, latest),
+            views: Default::default(),
+            entries: Default::default(),
+        }
+    }
+
+    /// A copy of the log without any entries.
+    /// The summary keeps the last normal view, op-number range and view table for comparing logs.
+    pub fn summary(&self) -> Self {
+        Self {
+            view: self.view,
+            range: self.range,
+            views: self.views.clone(),
+            entries: Default::default(),
+        }
+    }
+
+    pub fn views(&self) -> &[(View, OpNumber)] {
+        &self.views
+    }
+
+    /// The latest op-number up to which both logs hold entries added in the same views.
+    /// Entries before the returned op-number are the same in both logs and need not be transferred.
+    pub fn common_prefix(&self, other: &Self) -> OpNumber {
+        common_prefix(&self.views, &other.views)
+            .min(self.range.1)
+            .min(other.range.1)
+    }
+
+    /// Summarizes the log by its view table, so another replica can find the entries both logs share.
+    pub fn digest(&self) -> LogDigest {
+        LogDigest {
+            views: self.views.clone(),
+            last: self.range.1,
+        }
+    }
+
+    /// The latest op-number up to which this log and the summarized log hold entries added in the same views.
+    pub fn common_prefix_with(&self, digest: &LogDigest) -> OpNumber {
+        common_prefix(&self.views, &digest.views)
+            .min(self.range.1)
+            .min(digest.last)
+    }
+
+    /// The view in which the entry with the given op-number was added, if the log holds that entry.
+    pub fn view_at(&self, op_number: OpNumber) -> Option<View> {
+        if !self.contains(&op_number) {
+            return None;
+        }
+
+        self.views
+            .iter()
+            .find(|(_, last)| *last >= op_number)
+            .map(|(view, _)| *view)
+    }
+
     pub fn contains(&self, op_number: &OpNumber) -> bool {
         !self.entries.is_empty() && (self.range.0..=self.range.1).contains(op_number)
     }
@@ -91,18 +247,35 @@ impl<R, P> Log<R, P> {
         view: View,
         request: Request<R>,
         prediction: P,
+    ) -> (&Entry<R, P>, OpNumber) {
+        self.push_at(view, request, prediction, Duration::ZERO)
+    }
+
+    /// Appends an entry the primary prepared at the given time since the Unix epoch.
+    pub fn push_at(
+        &mut self,
+        view: View,
+        request: Request<R>,
+        prediction: P,
+        timestamp: Duration,
     ) -> (&Entry<R, P>, OpNumber) {
         self.view = view;
         self.range.1.increment();
 
+        // The range of an empty log may start anywhere (e.g. after a truncation), but the new entry starts it over.
         if self.entries.is_empty() {
-            self.range.0.increment();
+            self.range.0 = self.range.1;
         }
 
-        let entry = Entry::new(request, prediction);
+        match self.views.last_mut() {
+            Some((last, op_number)) if *last == view => *op_number = self.range.1,
+            _ => self.views.push((view, self.range.1)),
+        }
+
+        let entry = Entry::new(request, prediction, timestamp);
         let index = self.entries.len();
 
-        self.entries.push_back(entry);
+        self.entries.push_back(Arc::new(entry));
 
         (&self.entries[index], self.range.1)
     }
@@ -124,7 +297,18 @@ impl<R, P> Log<R, P> {
     }
 
     pub fn get(&self, index: OpNumber) -> Option<&Entry<R, P>> {
-        self.entries.get(index - self.range.0)
+        index
+            .checked_sub(self.range.0)
+            .and_then(|offset| self.entries.get(offset))
+            .map(Arc::as_ref)
+    }
+
+    /// Whether the range of op-numbers matches the entries. Empty logs only describe a range (e.g. a summary).
+    pub fn is_consistent(&self) -> bool {
+        let (first, last) = self.range;
+
+        // The range comes from other replicas, so it may be far wider than any log (e.g. corrupted to extremes).
+        self.entries.is_empty() || last.checked_sub(first) == Some(self.entries.len() - 1)
     }
 
     pub fn is_empty(&self) -> bool {
@@ -149,6 +333,8 @@ impl<R, P> Log<R, P> {
         } else {
             self.range.0.increment_by(drop);
         }
+
+        self.prune_views();
     }
 
     pub fn cut(&mut self, end: OpNumber) {
@@ -161,17 +347,123 @@ impl<R, P> Log<R, P> {
         } else {
             self.range.0 = end.next();
         }
+
+        self.prune_views();
     }
 
+    /// Removes the entries after the given op-number.
     pub fn truncate(&mut self, last: OpNumber) {
+        let last = last.min(self.range.1);
+
         self.range.1 = last;
-        self.entries.truncate((last - self.range.0) + 1);
+        self.entries
+            .truncate(last.next().max(self.range.0) - self.range.0);
+
+        if let Some(index) = self.views.iter().position(|(_, o)| *o >= last) {
+            self.views.truncate(index + 1);
+            self.views[index].1 = last;
+        }
     }
 
     pub fn extend(&mut self, tail: Self) {
         self.view = tail.view;
+
+        // The range of an empty tail does not describe any entries (e.g. a summary).
+        if tail.entries.is_empty() {
+            return;
+        }
+
+        if self.entries.is_empty() {
+            self.range.0 = tail.range.0;
+        }
+
         self.range.1 = tail.range.1;
         self.entries.extend(tail.entries);
+
+        for (view, op_number) in tail.views {
+            match self.views.last_mut() {
+                Some((last, o)) if *last == view => *o = op_number,
+                _ => self.views.push((view, op_number)),
+            }
+        }
+    }
+
+    /// Replaces the entries starting at the first op-number of the tail with the tail.
+    /// Returns false without modifying the log when the tail does not overlap or directly follow this log.
+    pub fn splice(&mut self, tail: Self) -> bool {
+        let first = tail.range.0;
+
+        if first < self.range.0 || first > self.next_op_number() {
+            return false;
+        }
+
+        self.truncate(first.previous());
+
+        if self.entries.is_empty() {
+            self.range.0 = first;
+        }
+
+        self.extend(tail);
+
+        if self.entries.is_empty() {
+            self.range.0 = self.range.1;
+        }
+
+        true
+    }
+
+    /// Replaces the entries up to the given op-number that both logs hold with the ones from the other log,
+    /// keeping the range and view table of this log (e.g. to repair committed entries that diverged).
+    pub fn overwrite(&mut self, other: Self, last: OpNumber) {
+        let mut op_number = self.range.0.max(other.range.0);
+        let last = last.min(self.range.1).min(other.range.1);
+
+        while op_number <= last && !self.entries.is_empty() && !other.entries.is_empty() {
+            self.entries[op_number - self.range.0] =
+                other.entries[op_number - other.range.0].clone();
+            op_number.increment();
+        }
+    }
+
+    /// Drops the view table entries for compacted operations.
+    fn prune_views(&mut self) {
+        let first = self.range.0;
+
+        self.views.retain(|(_, o)| *o >= first);
+    }
+}
+
+/// The latest op-number up to which both view tables hold entries added in the same views.
+fn common_prefix(left: &[(View, OpNumber)], right: &[(View, OpNumber)]) -> OpNumber {
+    let mut prefix = OpNumber::default();
+
+    for (&(x, last_x), &(y, last_y)) in left.iter().zip(right) {
+        if x != y {
+            break;
+        }
+
+        prefix = last_x.min(last_y);
+
+        if last_x != last_y {
+            break;
+        }
+    }
+
+    prefix
+}
+
+/// The view table of a log without its entries, which a recovering replica with a durable log sends
+/// so the primary only returns the entries after the prefix both logs share.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct LogDigest {
+    views: Vec<(View, OpNumber)>,
+    last: OpNumber,
+}
+
+impl LogDigest {
+    /// The op-number of the last entry in the digested log.
+    pub(crate) fn last_op_number(&self) -> OpNumber {
+        self.last
     }
 }
 
@@ -184,10 +476,14 @@ impl<R, P> Index<OpNumber> for Log<R, P> {
     }
 }
 
-impl<R, P> IndexMut<OpNumber> for Log<R, P> {
+impl<R, P> IndexMut<OpNumber> for Log<R, P>
+where
+    R: Clone,
+    P: Clone,
+{
     fn index_mut(&mut self, index: OpNumber) -> &mut Self::Output {
         let offset = index - self.range.0;
-        self.entries.index_mut(offset)
+        Arc::make_mut(self.entries.index_mut(offset))
     }
 }
 
@@ -202,8 +498,12 @@ mod tests {
         let view = View::default();
         let request = Request {
             payload: (),
-            client: ClientIdentifier::default(),
+            client: ClientIdentifier::from(1),
             id: RequestIdentifier::default(),
+            deadline: None,
+            signature: None,
+            epoch: 0,
+            staleness: None,
         };
 
         let mut log = Log::default();
@@ -243,8 +543,12 @@ mod tests {
         let view = View::default();
         let request = Request {
             payload: (),
-            client: ClientIdentifier::default(),
+            client: ClientIdentifier::from(1),
             id: RequestIdentifier::default(),
+            deadline: None,
+            signature: None,
+            epoch: 0,
+            staleness: None,
         };
 
         let mut log = Log::default();
@@ -271,4 +575,92 @@ mod tests {
         assert_eq!(log.range, (end.next(), end.next().next()));
         assert_eq!(log.entries.len(), 2);
     }
+
+    #[test]
+    fn view_table() {
+        let request = Request {
+            payload: (),
+            client: ClientIdentifier::from(1),
+            id: RequestIdentifier::default(),
+            deadline: None,
+            signature: None,
+            epoch: 0,
+            staleness: None,
+        };
+        let mut view = View::default();
+        let mut log = Log::default();
+
+        for _ in 1..=3 {
+            log.push(view, request.clone(), ());
+        }
+
+        let mut other = log.clone();
+
+        view.increment();
+
+        for _ in 1..=2 {
+            log.push(view, request.clone(), ());
+        }
+
+        view.increment();
+        other.truncate(OpNumber::default().next().next());
+        other.push(view, request.clone(), ());
+
+        let mut prefix = OpNumber::default();
+
+        prefix.increment_by(2);
+
+        assert_eq!(log.views().len(), 2);
+        assert_eq!(log.common_prefix(&other), prefix);
+        assert_eq!(other.common_prefix(&log), prefix);
+        assert_eq!(log.common_prefix_with(&other.digest()), prefix);
+        assert!(log.summary().is_empty());
+        assert_eq!(log.summary(), log);
+
+        assert!(other.splice(log.after(prefix)));
+        assert_eq!(other.views(), log.views());
+        assert_eq!(other.common_prefix(&log), log.last_op_number());
+        assert_eq!(other.len(), 5);
+    }
+
+    #[test]
+    fn inconsistent_range() {
+        let mut log = Log::default();
+        let mut client =
+            crate::Client::new_with_identifier(crate::Configuration::new(3, 1.into()), 1.into());
+
+        log.push(View::default(), client.new_request(1), ());
+
+        let json = serde_json::to_string(&log).unwrap();
+
+        assert!(serde_json::from_str::<Log<i32, ()>>(&json).is_ok());
+
+        let corrupted = json.replace("\"range\":[1,1]", "\"range\":[1,2]");
+
+        assert_ne!(corrupted, json);
+        assert!(serde_json::from_str::<Log<i32, ()>>(&corrupted).is_err());
+
+        let extreme = json.replace("\"range\":[1,1]", &format!("\"range\":[1,{}]", u128::MAX));
+
+        assert!(serde_json::from_str::<Log<i32, ()>>(&extreme).is_err());
+    }
+
+    #[test]
+    fn push_after_truncate() {
+        let mut log = Log::default();
+        let mut client =
+            crate::Client::new_with_identifier(crate::Configuration::new(3, 1.into()), 1.into());
+
+        log.push(View::default(), client.new_request(1), ());
+        log.push(View::default(), client.new_request(2), ());
+        log.truncate(OpNumber::default());
+
+        assert!(log.is_empty());
+
+        let (_, op_number) = log.push(View::default().next(), client.new_request(3), ());
+
+        assert_eq!(op_number, OpNumber::from(1));
+        assert!(log.is_consistent());
+        assert_eq!(log.first_op_number(), op_number);
+    }
 }
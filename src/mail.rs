@@ -1,8 +1,11 @@
 use crate::protocol::{
-    Commit, DoViewChange, GetState, NewState, Prepare, PrepareOk, Recovery, RecoveryResponse,
-    StartView, StartViewChange,
+    Commit, Digest, DoViewChange, GetLog, GetState, InstallSnapshot, NewLog, NewState, Prepare,
+    PrepareOk, Recovery, RecoveryResponse, StartView, StartViewChange,
+};
+use crate::request::{
+    Busy, ClientIdentifier, Discovered, Expired, Notification, OutdatedEpoch, OutdatedView, Reply,
+    RequestStatus, SessionAccepted,
 };
-use crate::request::{ClientIdentifier, Reply};
 use crate::service::Protocol;
 
 pub trait Outbox<P>
@@ -19,10 +22,20 @@ where
 
     fn new_state(&mut self, index: usize, message: NewState<P::Request, P::Prediction>);
 
+    fn install_snapshot(
+        &mut self,
+        index: usize,
+        message: InstallSnapshot<P::Checkpoint, P::Reply, P::Request, P::Prediction>,
+    );
+
     fn start_view_change(&mut self, message: StartViewChange);
 
     fn do_view_change(&mut self, index: usize, message: DoViewChange<P::Request, P::Prediction>);
 
+    fn get_log(&mut self, index: usize, message: GetLog);
+
+    fn new_log(&mut self, index: usize, message: NewLog<P::Request, P::Prediction>);
+
     fn start_view(&mut self, message: StartView<P::Request, P::Prediction>);
 
     fn recovery(&mut self, message: Recovery);
@@ -33,7 +46,34 @@ where
         message: RecoveryResponse<P::Request, P::Prediction>,
     );
 
+    fn digest(&mut self, message: Digest);
+
     fn reply(&mut self, client: ClientIdentifier, reply: &Reply<P::Reply>);
+
+    fn busy(&mut self, client: ClientIdentifier, message: Busy);
+
+    fn expired(&mut self, client: ClientIdentifier, message: Expired);
+
+    fn session_accepted(&mut self, client: ClientIdentifier, message: SessionAccepted);
+
+    fn request_status(&mut self, client: ClientIdentifier, message: RequestStatus);
+
+    fn outdated_view(&mut self, client: ClientIdentifier, message: OutdatedView);
+
+    fn outdated_epoch(&mut self, client: ClientIdentifier, message: OutdatedEpoch);
+
+    fn discovered(&mut self, client: ClientIdentifier, message: Discovered);
+
+    /// Pushes a notification from the service to a client outside of the reply to any of its requests.
+    /// Streaming transports forward notifications to clients that stay connected, while transports that answer
+    /// each request with a single response (the default) discard them.
+    fn notify(&mut self, _client: ClientIdentifier, _message: &Notification<P::Reply>) {}
+
+    /// The number of inbound messages waiting to be processed, which a primary uses to shed load.
+    /// Defaults to 0 for mailboxes that do not track their depth.
+    fn queued(&self) -> usize {
+        0
+    }
 }
 
 pub trait Inbox<P>
@@ -50,15 +90,26 @@ where
 
     fn push_new_state(&mut self, message: NewState<P::Request, P::Prediction>);
 
+    fn push_install_snapshot(
+        &mut self,
+        message: InstallSnapshot<P::Checkpoint, P::Reply, P::Request, P::Prediction>,
+    );
+
     fn push_start_view_change(&mut self, message: StartViewChange);
 
     fn push_do_view_change(&mut self, message: DoViewChange<P::Request, P::Prediction>);
 
+    fn push_get_log(&mut self, message: GetLog);
+
+    fn push_new_log(&mut self, message: NewLog<P::Request, P::Prediction>);
+
     fn push_start_view(&mut self, message: StartView<P::Request, P::Prediction>);
 
     fn push_recovery(&mut self, message: Recovery);
 
     fn push_recovery_response(&mut self, message: RecoveryResponse<P::Request, P::Prediction>);
+
+    fn push_digest(&mut self, message: Digest);
 }
 
 pub trait Mailbox<P>: Inbox<P> + Outbox<P>
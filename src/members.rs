@@ -0,0 +1,251 @@
+use crate::configuration::{Configuration, Group, ReplicaIdentifier};
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::error::Error;
+use core::fmt::{Display, Formatter};
+use core::net::SocketAddr;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Where a replica accepts connections and where its peers and clients reach it.
+/// The two addresses differ when the replica runs behind NAT or in a container
+/// (e.g. it listens on `0.0.0.0:3000` while others connect to the public address mapped to that port).
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct ReplicaEndpoint {
+    /// The index of the replica in the group.
+    pub id: usize,
+    /// The address the transport of the replica binds to.
+    pub listen: SocketAddr,
+    /// The address other replicas and clients connect to.
+    pub advertise: SocketAddr,
+    /// The server name of the replica's TLS certificate, or `None` if connections to it do not use TLS.
+    #[serde(default)]
+    pub tls: Option<String>,
+}
+
+impl ReplicaEndpoint {
+    /// An endpoint that listens on the same address it advertises, without TLS.
+    pub fn new(id: usize, address: SocketAddr) -> Self {
+        Self {
+            id,
+            listen: address,
+            advertise: address,
+            tls: None,
+        }
+    }
+
+    pub fn with_listen(mut self, listen: SocketAddr) -> Self {
+        self.listen = listen;
+        self
+    }
+
+    pub fn with_tls(mut self, server_name: impl Into<String>) -> Self {
+        self.tls = Some(server_name.into());
+        self
+    }
+}
+
+/// The network endpoints of the replicas in a group, ordered by replica index.
+/// The group identifier is derived from the advertised addresses so that every process given the same members
+/// agrees on the configuration without further coordination, regardless of where each replica listens.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Members {
+    group: Group,
+    endpoints: Vec<ReplicaEndpoint>,
+}
+
+impl From<Vec<SocketAddr>> for Members {
+    fn from(addresses: Vec<SocketAddr>) -> Self {
+        let endpoints = addresses
+            .into_iter()
+            .enumerate()
+            .map(|(id, address)| ReplicaEndpoint::new(id, address))
+            .collect();
+
+        Self::from_sorted(endpoints)
+    }
+}
+
+/// Orders the endpoints by replica index, which must cover every index of the group exactly once.
+impl TryFrom<Vec<ReplicaEndpoint>> for Members {
+    type Error = MembersError;
+
+    fn try_from(mut endpoints: Vec<ReplicaEndpoint>) -> Result<Self, Self::Error> {
+        endpoints.sort_by_key(|endpoint| endpoint.id);
+
+        for (index, endpoint) in endpoints.iter().enumerate() {
+            match endpoint.id.cmp(&index) {
+                core::cmp::Ordering::Less => return Err(MembersError::Duplicate(endpoint.id)),
+                core::cmp::Ordering::Greater => return Err(MembersError::Missing(index)),
+                core::cmp::Ordering::Equal => {}
+            }
+        }
+
+        Ok(Self::from_sorted(endpoints))
+    }
+}
+
+impl Members {
+    fn from_sorted(endpoints: Vec<ReplicaEndpoint>) -> Self {
+        let name = endpoints
+            .iter()
+            .map(|endpoint| endpoint.advertise.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        let group = Uuid::new_v5(&Uuid::NAMESPACE_URL, format!("vr://{name}").as_bytes());
+
+        Self {
+            group: Group::from(group.as_u128()),
+            endpoints,
+        }
+    }
+
+    pub fn group(&self) -> Group {
+        self.group
+    }
+
+    pub fn configuration(&self) -> Configuration {
+        Configuration::new(self.endpoints.len(), self.group)
+    }
+
+    /// The endpoint of the given replica, if the replica is a member of this group.
+    pub fn endpoint(&self, replica: ReplicaIdentifier) -> Option<&ReplicaEndpoint> {
+        if replica.group() != self.group {
+            return None;
+        }
+
+        self.endpoints.get(replica.index())
+    }
+
+    /// The address to connect to the given replica at, if the replica is a member of this group.
+    pub fn resolve(&self, replica: ReplicaIdentifier) -> Option<SocketAddr> {
+        self.endpoint(replica).map(|endpoint| endpoint.advertise)
+    }
+
+    /// The address the transport of the given replica binds to, if the replica is a member of this group.
+    pub fn listen(&self, replica: ReplicaIdentifier) -> Option<SocketAddr> {
+        self.endpoint(replica).map(|endpoint| endpoint.listen)
+    }
+
+    /// The identifier of the replica advertising the given address.
+    pub fn identify(&self, address: SocketAddr) -> Option<ReplicaIdentifier> {
+        self.endpoints
+            .iter()
+            .position(|endpoint| endpoint.advertise == address)
+            .and_then(|index| self.configuration().replica(index))
+    }
+
+    /// The advertised address of every replica in the group.
+    pub fn iter(&self) -> impl Iterator<Item = (ReplicaIdentifier, SocketAddr)> + '_ {
+        let configuration = self.configuration();
+
+        self.endpoints.iter().filter_map(move |endpoint| {
+            Some((configuration.replica(endpoint.id)?, endpoint.advertise))
+        })
+    }
+
+    pub fn endpoints(&self) -> &[ReplicaEndpoint] {
+        &self.endpoints
+    }
+}
+
+/// Endpoints that do not describe every replica of a group exactly once.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum MembersError {
+    /// More than one endpoint has the given replica index.
+    Duplicate(usize),
+    /// No endpoint has the given replica index, while an endpoint has a higher one.
+    Missing(usize),
+}
+
+impl Display for MembersError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            MembersError::Duplicate(id) => write!(f, "replica {id} has more than one endpoint"),
+            MembersError::Missing(id) => write!(f, "replica {id} has no endpoint"),
+        }
+    }
+}
+
+impl Error for MembersError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addresses() -> Vec<SocketAddr> {
+        vec![
+            "127.0.0.1:3000".parse().unwrap(),
+            "127.0.0.1:3001".parse().unwrap(),
+            "127.0.0.1:3002".parse().unwrap(),
+        ]
+    }
+
+    #[test]
+    fn stable_group() {
+        let members = Members::from(addresses());
+        let mut reversed = addresses();
+
+        reversed.reverse();
+
+        assert_eq!(members.group(), Members::from(addresses()).group());
+        assert_ne!(members.group(), Members::from(reversed).group());
+        assert_eq!(members.configuration().replicas(), 3);
+    }
+
+    #[test]
+    fn resolve() {
+        let members = Members::from(addresses());
+        let configuration = members.configuration();
+        let replica = configuration.replica(1).unwrap();
+
+        assert_eq!(members.resolve(replica), Some(addresses()[1]));
+        assert_eq!(members.identify(addresses()[1]), Some(replica));
+        assert_eq!(configuration.replica(3), None);
+        assert_eq!(
+            members.resolve(Configuration::new(3, 1.into()).replica(1).unwrap()),
+            None
+        );
+        assert_eq!(members.iter().count(), 3);
+    }
+
+    #[test]
+    fn listen_and_advertise() {
+        let listen: SocketAddr = "0.0.0.0:3000".parse().unwrap();
+        let endpoints = Vec::from_iter(addresses().into_iter().enumerate().rev().map(
+            |(id, address)| {
+                ReplicaEndpoint::new(id, address)
+                    .with_listen(listen)
+                    .with_tls(format!("replica-{id}.example.com"))
+            },
+        ));
+        let members = Members::try_from(endpoints.clone()).unwrap();
+        let replica = members.configuration().replica(2).unwrap();
+
+        // Only the advertised addresses identify the group, so replicas may listen anywhere.
+        assert_eq!(members.group(), Members::from(addresses()).group());
+        assert_eq!(members.resolve(replica), Some(addresses()[2]));
+        assert_eq!(members.listen(replica), Some(listen));
+        assert_eq!(members.identify(listen), None);
+        assert_eq!(
+            members
+                .endpoint(replica)
+                .and_then(|endpoint| endpoint.tls.as_deref()),
+            Some("replica-2.example.com")
+        );
+
+        let mut duplicate = endpoints.clone();
+
+        duplicate[0].id = 1;
+
+        assert_eq!(
+            Members::try_from(duplicate),
+            Err(MembersError::Duplicate(1))
+        );
+        assert_eq!(
+            Members::try_from(endpoints[..2].to_vec()),
+            Err(MembersError::Missing(0))
+        );
+    }
+}
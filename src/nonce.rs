@@ -3,8 +3,21 @@ use serde::{Deserialize, Serialize};
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Nonce(u128);
 
+#[cfg(feature = "os")]
 impl Default for Nonce {
     fn default() -> Self {
         Self(uuid::Uuid::new_v4().as_u128())
     }
 }
+
+impl From<u128> for Nonce {
+    fn from(value: u128) -> Self {
+        Self(value)
+    }
+}
+
+impl From<Nonce> for u128 {
+    fn from(value: Nonce) -> Self {
+        value.0
+    }
+}
@@ -1,13 +1,19 @@
-use crate::log::Log;
+use crate::client_table::ClientTable;
+use crate::log::{Log, LogDigest};
 use crate::nonce::Nonce;
 use crate::request::Request;
 use crate::viewstamp::{OpNumber, View};
+use alloc::vec::Vec;
+use core::time::Duration;
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Prepare<R, P> {
     /// The current view of the replica.
     pub view: View,
+    /// The epoch of the sender's configuration. Replicas drop messages from other epochs.
+    #[serde(default)]
+    pub epoch: u64,
     /// The op-number assigned to the request.
     pub op_number: OpNumber,
     /// The message received from the client along with a prediction for supporting non-deterministic behavior.
@@ -16,30 +22,54 @@ pub struct Prepare<R, P> {
     pub prediction: P,
     /// The op-number of the last committed log entry.
     pub committed: OpNumber,
+    /// The view in which the preceding log entry was added, if the primary still holds that entry.
+    pub previous: Option<View>,
+    /// The time since the Unix epoch at which the primary prepared the operation.
+    pub timestamp: Duration,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct PrepareOk {
     /// The current view of the replica.
     pub view: View,
+    /// The epoch of the sender's configuration. Replicas drop messages from other epochs.
+    #[serde(default)]
+    pub epoch: u64,
     /// The op-number assigned to the request.
     pub op_number: OpNumber,
     /// The index of the replica that prepared the operation.
     pub index: usize,
+    /// The op-number of the latest request committed by the replica that prepared the operation.
+    #[serde(default)]
+    pub committed: OpNumber,
+    /// The highest view the replica has promised to join, which is newer than the view of the message
+    /// when an outdated primary needs to stop serving requests.
+    #[serde(default)]
+    pub promised: View,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Commit {
     /// The current view of the replica.
     pub view: View,
+    /// The epoch of the sender's configuration. Replicas drop messages from other epochs.
+    #[serde(default)]
+    pub epoch: u64,
     /// The op-number of the latest committed request known to the replica.
     pub committed: OpNumber,
+    /// The time since the Unix epoch at which the primary sent the commit number,
+    /// which bounds the staleness of the reads backups answer (see [`crate::Staleness::Time`]).
+    #[serde(default)]
+    pub timestamp: Duration,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct GetState {
     /// The current view of the replica.
     pub view: View,
+    /// The epoch of the sender's configuration. Replicas drop messages from other epochs.
+    #[serde(default)]
+    pub epoch: u64,
     /// The latest op-number the replica is aware of.
     pub op_number: OpNumber,
     /// The index of the replica that needs to get the new state.
@@ -50,16 +80,42 @@ pub struct GetState {
 pub struct NewState<R, P> {
     /// The current view of the replica.
     pub view: View,
+    /// The epoch of the sender's configuration. Replicas drop messages from other epochs.
+    #[serde(default)]
+    pub epoch: u64,
     /// An excerpt of the log based on the last known op number.
     pub log: Log<R, P>,
     /// The op-number of the latest committed request known to the replica.
     pub committed: OpNumber,
+    /// The op-number of the latest entry in the sender's log.
+    /// The receiver requests the next excerpt when this is past the end of the included log.
+    #[serde(default)]
+    pub op_number: OpNumber,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct InstallSnapshot<C, T, R, P> {
+    /// The current view of the replica.
+    pub view: View,
+    /// The epoch of the sender's configuration. Replicas drop messages from other epochs.
+    #[serde(default)]
+    pub epoch: u64,
+    /// The checkpoint of the sender as of its latest committed operation.
+    pub checkpoint: Checkpoint<C, T>,
+    /// An excerpt of the log following the checkpoint.
+    pub log: Log<R, P>,
+    /// The op-number of the latest entry in the sender's log.
+    /// The receiver requests the next excerpt when this is past the end of the included log.
+    pub op_number: OpNumber,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct StartViewChange {
     /// The current view of the replica.
     pub view: View,
+    /// The epoch of the sender's configuration. Replicas drop messages from other epochs.
+    #[serde(default)]
+    pub epoch: u64,
     /// The index of the replica that needs to get the new state.
     pub index: usize,
 }
@@ -68,7 +124,11 @@ pub struct StartViewChange {
 pub struct DoViewChange<R, P> {
     /// The current view of the replica.
     pub view: View,
-    /// The log of the replica from its last normal view.
+    /// The epoch of the sender's configuration. Replicas drop messages from other epochs.
+    #[serde(default)]
+    pub epoch: u64,
+    /// A summary of the log of the replica from its last normal view.
+    /// The summary includes the view table, but none of the entries.
     pub log: Log<R, P>,
     /// The op-number of the latest committed request known to the replica.
     pub committed: OpNumber,
@@ -76,44 +136,113 @@ pub struct DoViewChange<R, P> {
     pub index: usize,
 }
 
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct GetLog {
+    /// The current view of the replica.
+    pub view: View,
+    /// The epoch of the sender's configuration. Replicas drop messages from other epochs.
+    #[serde(default)]
+    pub epoch: u64,
+    /// The latest op-number up to which the log of the replica matches the log of the receiver.
+    pub op_number: OpNumber,
+    /// The index of the replica that needs the missing entries.
+    pub index: usize,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct NewLog<R, P> {
+    /// The current view of the replica.
+    pub view: View,
+    /// The epoch of the sender's configuration. Replicas drop messages from other epochs.
+    #[serde(default)]
+    pub epoch: u64,
+    /// An excerpt of the log following the requested op-number.
+    pub log: Log<R, P>,
+    /// The index of the replica that sent the message.
+    pub index: usize,
+}
+
 #[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct StartView<R, P> {
     /// The current view of the replica.
     pub view: View,
+    /// The epoch of the sender's configuration. Replicas drop messages from other epochs.
+    #[serde(default)]
+    pub epoch: u64,
     /// The log to use in the new view.
     pub log: Log<R, P>,
     /// The op-number of the latest committed request known to the replica.
     pub committed: OpNumber,
 }
 
+/// Hashes of ranges of committed log entries that the primary sends occasionally when anti-entropy is enabled,
+/// so backups detect entries that silently diverged (e.g. corrupted storage) and fetch the range again.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Digest {
+    /// The current view of the replica.
+    pub view: View,
+    /// The epoch of the sender's configuration. Replicas drop messages from other epochs.
+    #[serde(default)]
+    pub epoch: u64,
+    /// The first and last op-number of each range of entries.
+    pub ranges: Vec<(OpNumber, OpNumber)>,
+    /// The hash of the entries in each range.
+    pub hashes: Vec<u64>,
+    /// The index of the sender.
+    pub index: usize,
+}
+
 #[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Recovery {
     /// The index of the replica that needs to get the new state.
     pub index: usize,
+    /// The epoch of the sender's configuration. Replicas drop messages from other epochs.
+    #[serde(default)]
+    pub epoch: u64,
     /// The last committed operation included in the checkpoint the replica used to recover.
     pub committed: OpNumber,
     /// A value coined for single use to detect replays of previous recovery requests.
     pub nonce: Nonce,
+    /// The view table of the durable log the replica recovered with, if any.
+    /// The primary only sends the entries after the prefix its log shares with the recovered one.
+    #[serde(default)]
+    pub digest: Option<LogDigest>,
+    /// The incarnation of the replica, which grows every time it restarts from persisted state.
+    #[serde(default)]
+    pub generation: u64,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct RecoveryResponse<R, P> {
     /// The current view of the replica.
     pub view: View,
+    /// The epoch of the sender's configuration. Replicas drop messages from other epochs.
+    #[serde(default)]
+    pub epoch: u64,
     /// A value coined for single use to detect replays of previous recovery requests.
     pub nonce: Nonce,
-    /// The log to use in the new view.
-    pub log: Log<R, P>,
-    /// The op-number of the latest committed request known to the replica.
-    pub committed: OpNumber,
+    /// The log to use in the new view. Only the primary of the view includes its log.
+    pub log: Option<Log<R, P>>,
+    /// The op-number of the latest committed request known to the primary of the view.
+    pub committed: Option<OpNumber>,
     /// The index of the sender.
     pub index: usize,
+    /// The incarnation of the recovering replica the response is for.
+    #[serde(default)]
+    pub generation: u64,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
-pub struct Checkpoint<C> {
+pub struct Checkpoint<C, R> {
     /// The last committed operation reflected in the application state.
     pub committed: OpNumber,
     /// The application state when the checkpoint was taken.
     pub state: C,
+    /// The latest request and reply of each client reflected in the application state.
+    #[serde(default = "ClientTable::default")]
+    pub client_table: ClientTable<R>,
+    /// The incarnation of the replica that took the checkpoint. A replica restarting from the checkpoint becomes
+    /// the next incarnation, so persisting checkpoints persists the generation across restarts too.
+    #[serde(default)]
+    pub generation: u64,
 }
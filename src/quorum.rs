@@ -0,0 +1,93 @@
+use crate::viewstamp::View;
+use hashbrown::hash_map::Values;
+use hashbrown::HashMap;
+
+/// Tracks the votes of distinct replicas in a view until enough replicas voted to reach the threshold.
+/// Each replica holds at most one vote, so duplicate messages replace the earlier vote instead of counting twice.
+/// Votes for an older view are stale and ignored, while a vote for a newer view discards the votes counted so far.
+#[derive(Debug)]
+pub struct QuorumTracker<V> {
+    view: View,
+    threshold: usize,
+    votes: HashMap<usize, V>,
+}
+
+impl<V> QuorumTracker<V> {
+    pub fn new(view: View, threshold: usize) -> Self {
+        Self {
+            view,
+            threshold,
+            votes: Default::default(),
+        }
+    }
+
+    /// Counts the vote of the replica at the given index.
+    /// Returns false when the vote is stale.
+    pub fn insert(&mut self, view: View, index: usize, vote: V) -> bool {
+        if view < self.view {
+            return false;
+        }
+
+        if view > self.view {
+            self.view = view;
+            self.votes.clear();
+        }
+
+        self.votes.insert(index, vote);
+
+        true
+    }
+
+    pub fn contains(&self, index: usize) -> bool {
+        self.votes.contains_key(&index)
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<V> {
+        self.votes.remove(&index)
+    }
+
+    pub fn is_reached(&self) -> bool {
+        self.votes.len() >= self.threshold
+    }
+
+    pub fn values(&self) -> Values<'_, usize, V> {
+        self.votes.values()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn duplicate_votes() {
+        let mut tracker = QuorumTracker::new(View::default(), 2);
+
+        assert!(tracker.insert(View::default(), 1, 1));
+        assert!(tracker.insert(View::default(), 1, 2));
+        assert!(!tracker.is_reached());
+        assert_eq!(Vec::from_iter(tracker.values()), vec![&2]);
+
+        assert!(tracker.insert(View::default(), 2, 3));
+        assert!(tracker.is_reached());
+        assert_eq!(tracker.values().count(), 2);
+    }
+
+    #[test]
+    fn stale_views() {
+        let mut view = View::default();
+        let mut tracker = QuorumTracker::new(view.next(), 2);
+
+        assert!(!tracker.insert(view, 1, ()));
+        assert!(!tracker.contains(1));
+
+        view.increment();
+
+        assert!(tracker.insert(view, 1, ()));
+        assert!(tracker.insert(view.next(), 2, ()));
+        assert!(!tracker.contains(1));
+        assert!(tracker.contains(2));
+        assert!(!tracker.insert(view, 1, ()));
+        assert!(!tracker.is_reached());
+    }
+}
@@ -1,23 +1,54 @@
+use crate::buffer::ProtocolPayload;
+use crate::builder::ReplicaBuilder;
 use crate::client_table::ClientTable;
-use crate::configuration::Configuration;
+use crate::configuration::{Configuration, ReplicaIdentifier};
+use crate::delivery::ReplyBuffer;
+use crate::entropy::AntiEntropy;
+use crate::execution::{execute_concurrently, Executor};
+use crate::listener::{DropReason, EventListener};
 use crate::log::Log;
 use crate::mail::{Mailbox, Outbox};
+use crate::members::Members;
 use crate::nonce::Nonce;
 use crate::protocol::{
-    Checkpoint, Commit, DoViewChange, GetState, NewState, Prepare, PrepareOk, Recovery,
-    RecoveryResponse, StartView, StartViewChange,
+    Checkpoint, Commit, Digest, DoViewChange, GetLog, GetState, InstallSnapshot, NewLog, NewState,
+    Prepare, PrepareOk, Recovery, RecoveryResponse, StartView, StartViewChange,
 };
-use crate::request::{Reply, Request};
-use crate::service::Service;
+use crate::quorum::QuorumTracker;
+use crate::request::{
+    Acknowledge, Busy, ClientIdentifier, Discover, Discovered, Expired, Notification, OpenSession,
+    OutdatedEpoch, OutdatedView, QueryRequestStatus, Reply, Request, RequestStatus, Resume,
+    SessionAccepted, Staleness,
+};
+use crate::service::{Conflicts, Context, Protocol, Query, Role, Rollback, Service};
+use crate::signing::Verifier;
+use crate::speculation::{Speculation, UndoLog};
 use crate::status::Status;
+use crate::transfer::TransferTargets;
 use crate::viewstamp::{OpNumber, View};
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::cmp::{Ordering, Reverse};
+use core::hash::Hash;
+use core::net::SocketAddr;
+use core::time::Duration;
+use hashbrown::{HashMap, HashSet};
 use rand::Rng;
-use std::cmp::Ordering;
-use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// How far past its own view and log a message may reach before the replica treats it as implausible.
+/// No group gets through this many views or operations, yet the replica stays far below overflowing either.
+const HORIZON: u128 = u64::MAX as u128;
+
+/// Answers a read-only request from the state of the service.
+type Reader<S> = fn(&S, &<S as Protocol>::Request) -> <S as Protocol>::Reply;
 
 /// A replica may perform the role of a primary or backup depending on the configuration and the current view.
 /// Implements a message-based viewstamped replication revisited protocol that does not wait for messages to arrive.
 /// Instead, the protocol requires returned messages to be re-queued for later (i.e. after a new message comes in).
+///
+/// This is the only implementation of the protocol: the drivers, the group manager and the HTTP gateway all drive
+/// it through the same message handlers and idle timer, so protocol fixes land in one place.
 pub struct Replica<S>
 where
     S: Service,
@@ -30,19 +61,96 @@ where
     log: Log<S::Request, S::Prediction>,
     committed: OpNumber,
     client_table: ClientTable<S::Reply>,
-    prepared: BTreeMap<OpNumber, HashSet<usize>>,
-    start_view_changes: HashSet<usize>,
-    do_view_changes: HashMap<usize, DoViewChange<S::Request, S::Prediction>>,
-    recovery_responses: HashMap<usize, RecoveryResponse<S::Request, S::Prediction>>,
+    /// The replies the primary sent to clients with a session that they have not acknowledged yet.
+    undelivered: ReplyBuffer<S::Reply>,
+    prepared: BTreeMap<OpNumber, QuorumTracker<()>>,
+    backup_commits: HashMap<usize, OpNumber>,
+    start_view_changes: QuorumTracker<()>,
+    do_view_changes: QuorumTracker<DoViewChange<S::Request, S::Prediction>>,
+    /// The index of the replica with the most up-to-date log and the latest committed op-number
+    /// while a new primary waits for the entries it is missing to complete a view change.
+    missing_log: Option<(usize, OpNumber)>,
+    recovery_responses: QuorumTracker<RecoveryResponse<S::Request, S::Prediction>>,
     nonce: Nonce,
+    /// The view of the last prepare the primary sent for a new request since it was last idle.
+    /// Backups learn the commit number from prepares, so an idle primary can skip the standalone commit.
+    prepared_view: Option<View>,
+    listeners: Vec<Box<dyn EventListener + Send>>,
+    /// The view and op-number of the last state transfer request since the replica was last idle.
+    /// Re-queued messages ask for the same state, so the request is only re-sent once the replica is idle
+    /// or learns about an even newer view.
+    requested_state: Option<(View, OpNumber)>,
+    /// Chooses which replica to request state from, avoiding peers that did not answer.
+    transfer_targets: TransferTargets,
+    /// The clients a backup told about the primary of each view since it was last idle.
+    /// A client that keeps sending requests to a backup (e.g. retries while reconnecting) is only told once.
+    informed_clients: HashSet<(View, ClientIdentifier)>,
+    /// The backups that acknowledged the primary since it was last idle when a lease is configured.
+    lease_acknowledgements: HashSet<usize>,
+    /// The number of consecutive idle periods in which the primary did not hear from a sub-majority of backups.
+    lease_misses: u32,
+    /// Whether the primary stopped serving requests because its lease expired or a backup promised to join a newer view.
+    fenced: bool,
+    /// The number of view changes the replica started on its own since it was last in a normal view.
+    view_change_attempts: u32,
+    /// The number of idle periods the replica spent in its current view change, for the view change timeout.
+    view_change_idles: u32,
+    /// The number of times the replica re-sent its recovery request in its current recovery.
+    recovery_attempts: u32,
+    /// Grows whenever the replica hears from the primary of its view or moves to another view or status.
+    liveness: u64,
+    /// Applies committed operations concurrently with the given number of workers when parallel execution is enabled.
+    executor: Option<(Executor<S>, usize)>,
+    /// The operations the primary applied before they committed when speculative execution is enabled.
+    speculation: Option<Box<dyn Speculation<S> + Send>>,
+    /// Whether the replica stopped accepting new requests in order to hand off to a backup.
+    shutting_down: bool,
+    /// Whether the replica was removed from the group and no longer takes part in the protocol.
+    retired: bool,
+    /// The role and view the service was last told about.
+    role: Option<(Role, View)>,
+    /// Checks that requests came from the client they claim to come from before the primary starts them.
+    verifier: Option<Box<dyn Verifier<S::Request> + Send>>,
+    /// Compares hashes of committed entries with the primary when anti-entropy is enabled.
+    anti_entropy: Option<AntiEntropy<S::Request, S::Prediction>>,
+    /// The first op-number of the range of committed entries a backup requested to repair since it was last idle.
+    repairing: Option<OpNumber>,
+    /// Answers read-only requests from the committed state of a backup when follower reads are enabled.
+    query: Option<Reader<S>>,
+    /// The latest commit number the primary told the backup about and the time the primary sent it at.
+    advertised: (OpNumber, Duration),
+    /// The latest op-number each backup acknowledged, for adapting the heartbeat interval of the primary.
+    acknowledged: HashMap<usize, OpNumber>,
+    /// How long an idle primary currently waits between heartbeats when a heartbeat is configured.
+    heartbeat_interval: Duration,
+    /// The last time an idle primary sent its commit number to the backups, either on its own or with a prepare.
+    last_heartbeat: Option<Duration>,
+    /// The incarnation of the replica, which grows every time it restarts from a checkpoint.
+    generation: u64,
+    /// The latest incarnation of each replica that asked to recover, so requests from earlier incarnations are ignored.
+    generations: HashMap<usize, u64>,
+    /// The addresses clients reach the replicas at, which are handed out to clients that discover the group.
+    members: Vec<SocketAddr>,
 }
 
 impl<S> Replica<S>
 where
     S: Service,
 {
+    /// Creates a builder that validates the configuration before creating a replica.
+    pub fn builder() -> ReplicaBuilder<S> {
+        ReplicaBuilder::default()
+    }
+
     /// Creates a new instance of a replica.
     pub fn new(configuration: Configuration, index: usize, service: S) -> Self {
+        let mut replica = Self::create(configuration, index, service);
+
+        replica.notify_role();
+        replica
+    }
+
+    fn create(configuration: Configuration, index: usize, service: S) -> Self {
         Self {
             configuration,
             index,
@@ -52,39 +160,243 @@ where
             log: Default::default(),
             committed: Default::default(),
             client_table: Default::default(),
+            undelivered: Default::default(),
             prepared: Default::default(),
-            start_view_changes: Default::default(),
-            do_view_changes: Default::default(),
-            recovery_responses: Default::default(),
-            nonce: Default::default(),
+            backup_commits: Default::default(),
+            start_view_changes: QuorumTracker::new(View::default(), configuration.sub_majority()),
+            do_view_changes: QuorumTracker::new(View::default(), configuration.quorum()),
+            missing_log: None,
+            recovery_responses: QuorumTracker::new(View::default(), configuration.quorum()),
+            // Only a recovering replica uses its nonce, so a new one does not need a random nonce.
+            nonce: Nonce::from(0),
+            prepared_view: None,
+            listeners: Vec::new(),
+            requested_state: None,
+            transfer_targets: TransferTargets::new(
+                configuration.state_transfer_policy(),
+                index,
+                configuration.replicas(),
+            ),
+            informed_clients: Default::default(),
+            lease_acknowledgements: Default::default(),
+            lease_misses: 0,
+            fenced: false,
+            view_change_attempts: 0,
+            view_change_idles: 0,
+            recovery_attempts: 0,
+            liveness: 0,
+            executor: None,
+            speculation: None,
+            shutting_down: false,
+            retired: false,
+            role: None,
+            verifier: None,
+            anti_entropy: None,
+            repairing: None,
+            query: None,
+            advertised: Default::default(),
+            acknowledged: Default::default(),
+            heartbeat_interval: configuration
+                .heartbeat()
+                .map(|(min, _)| min)
+                .unwrap_or_default(),
+            last_heartbeat: None,
+            generation: 0,
+            generations: Default::default(),
+            members: Vec::new(),
         }
     }
 
     /// Creates a new instance of a replica running the recovery protocol.
     /// The caller is responsible for determining when a replica needs to recover.
+    #[cfg(feature = "os")]
     pub fn recovering<O>(
         configuration: Configuration,
         index: usize,
-        checkpoint: Checkpoint<S::Checkpoint>,
+        checkpoint: Checkpoint<S::Checkpoint, S::Reply>,
+        outbox: &mut O,
+    ) -> Self
+    where
+        O: Outbox<S>,
+    {
+        Self::recovering_with_nonce(configuration, index, checkpoint, Nonce::default(), outbox)
+    }
+
+    /// Creates a new instance of a replica running the recovery protocol with the given nonce.
+    /// Useful for reproducing a recovery exactly (e.g. when replaying a trace); otherwise prefer [`Replica::recovering`].
+    pub fn recovering_with_nonce<O>(
+        configuration: Configuration,
+        index: usize,
+        checkpoint: Checkpoint<S::Checkpoint, S::Reply>,
+        nonce: Nonce,
+        outbox: &mut O,
+    ) -> Self
+    where
+        O: Outbox<S>,
+    {
+        Self::recover(
+            configuration,
+            index,
+            checkpoint,
+            Log::default(),
+            nonce,
+            outbox,
+        )
+    }
+
+    /// Creates a new instance of a replica running the recovery protocol with the log it kept in durable storage.
+    /// The replica sends a digest of the log, so the primary only sends the entries the replica is missing
+    /// or holds from a different view instead of its entire log.
+    /// The log is ignored when it does not continue from the checkpoint.
+    pub fn recovering_with_log<O>(
+        configuration: Configuration,
+        index: usize,
+        checkpoint: Checkpoint<S::Checkpoint, S::Reply>,
+        log: Log<S::Request, S::Prediction>,
+        nonce: Nonce,
+        outbox: &mut O,
+    ) -> Self
+    where
+        O: Outbox<S>,
+    {
+        Self::recover(configuration, index, checkpoint, log, nonce, outbox)
+    }
+
+    fn recover<O>(
+        configuration: Configuration,
+        index: usize,
+        checkpoint: Checkpoint<S::Checkpoint, S::Reply>,
+        log: Log<S::Request, S::Prediction>,
+        nonce: Nonce,
         outbox: &mut O,
     ) -> Self
     where
         O: Outbox<S>,
     {
-        let mut replica = Self::new(configuration, index, checkpoint.state.into());
+        let mut replica = Self::create(configuration, index, checkpoint.state.into());
 
         replica.committed = checkpoint.committed;
+        replica.client_table = checkpoint.client_table;
         replica.status = Status::Recovering;
+        replica.nonce = nonce;
+        replica.generation = checkpoint.generation.saturating_add(1);
 
-        outbox.recovery(Recovery {
-            index,
-            committed: replica.committed,
-            nonce: replica.nonce,
-        });
+        if !log.is_empty() && log.first_op_number() <= replica.committed.next() {
+            replica.log = log;
+        }
+
+        outbox.recovery(replica.recovery());
 
         replica
     }
 
+    /// Registers a listener to be notified of protocol events on this replica.
+    pub fn add_listener<L>(&mut self, listener: L)
+    where
+        L: EventListener + Send + 'static,
+    {
+        self.listeners.push(Box::new(listener));
+    }
+
+    /// Applies consecutive committed operations that do not conflict with each other on up to the given number of threads.
+    /// Conflicting operations are still applied in log order, so the state of the service matches serial execution.
+    pub fn enable_parallel_execution(&mut self, workers: usize)
+    where
+        S: Conflicts,
+        S::Request: Sync,
+        S::Prediction: Sync,
+        S::Reply: Send,
+    {
+        self.executor = Some((execute_concurrently::<S>, workers.max(1)));
+    }
+
+    /// Applies operations on the primary as soon as they are prepared instead of waiting for them to commit.
+    /// The primary still replies only once an operation commits, and rolls back the operations that have not committed
+    /// whenever its status changes (e.g. a view change may discard them) or it takes a checkpoint.
+    pub fn enable_speculative_execution(&mut self)
+    where
+        S: Rollback + 'static,
+        S::Reply: Send,
+        S::Undo: Send,
+    {
+        self.speculation = Some(Box::new(UndoLog::<S>::default()));
+    }
+
+    /// Drops requests that fail verification (e.g. with the `KeyRing` of the `signing` feature) instead of starting them as a primary,
+    /// so a peer on the network cannot forge requests on behalf of a client.
+    /// Backups trust the requests in prepares, since those come from the primary.
+    pub fn enable_verification<V>(&mut self, verifier: V)
+    where
+        V: Verifier<S::Request> + Send + 'static,
+    {
+        self.verifier = Some(Box::new(verifier));
+    }
+
+    /// Answers read-only requests (see [`Request::with_staleness`]) as a backup when its committed state is within
+    /// the staleness bound of the request, and points the client at the primary otherwise.
+    pub fn enable_follower_reads(&mut self)
+    where
+        S: Query,
+    {
+        self.query = Some(S::query);
+    }
+
+    /// Sends hashes of ranges of committed entries to the backups every given number of idle periods as a primary,
+    /// and fetches the ranges whose entries hash differently as a backup (e.g. after silent corruption of storage).
+    /// Every replica in the group needs anti-entropy enabled, since backups without it ignore the hashes.
+    pub fn enable_anti_entropy(&mut self, period: u32)
+    where
+        S::Request: Hash,
+        S::Prediction: Hash,
+    {
+        self.anti_entropy = Some(AntiEntropy::new(period));
+    }
+
+    /// Hands out the advertised addresses of the members to clients that discover the group along with its configuration,
+    /// so clients only need the address of a single replica to reach the others.
+    pub fn advertise_members(&mut self, members: &Members) {
+        self.members = members
+            .endpoints()
+            .iter()
+            .map(|endpoint| endpoint.advertise)
+            .collect();
+    }
+
+    /// Makes the random choices of the replica (e.g. the target of a state transfer) deterministic.
+    pub fn seed(&mut self, seed: u64) {
+        self.transfer_targets.seed(seed);
+    }
+
+    /// Stops accepting new requests so the replica can be terminated without waiting for a view change timeout
+    /// (e.g. during a rolling restart). A primary replies to new requests as busy, finishes committing the operations
+    /// it already started and then starts a view change to the backup that has committed the most operations.
+    /// Use [`Replica::is_safe_to_terminate`] to find out when the hand-off is complete.
+    pub fn begin_shutdown(&mut self) {
+        self.shutting_down = true;
+    }
+
+    /// Whether the replica began shutting down and is a backup in a normal view, so no operations depend on it.
+    pub fn is_safe_to_terminate(&self) -> bool {
+        self.shutting_down && self.status == Status::Normal && self.is_backup()
+    }
+
+    /// Stops the replica from taking part in the protocol after the members of the group were replaced without it.
+    /// The replica drops every request and message from then on, so it cannot help commit operations the group it
+    /// left no longer tracks. Returns the op-number of the latest committed operation, which the group that replaced
+    /// this one must commit before the durable state of the replica can be disposed of (see [`crate::Decommission`]).
+    pub fn retire(&mut self) -> OpNumber {
+        self.retired = true;
+        self.committed
+    }
+
+    pub fn is_retired(&self) -> bool {
+        self.retired
+    }
+
+    pub(crate) fn extend_listeners(&mut self, listeners: Vec<Box<dyn EventListener + Send>>) {
+        self.listeners.extend(listeners);
+    }
+
     pub fn configuration(&self) -> Configuration {
         self.configuration
     }
@@ -93,28 +405,81 @@ where
         self.index
     }
 
+    pub fn identifier(&self) -> Option<ReplicaIdentifier> {
+        self.configuration.replica(self.index)
+    }
+
+    /// How long the replica should wait for a message before calling [`Replica::idle`].
+    /// Backs off exponentially after each view change the replica started on its own, or each recovery request
+    /// it re-sent, when configured.
+    #[cfg(feature = "os")]
+    pub fn idle_timeout(&self) -> Duration {
+        self.idle_timeout_with(&mut rand::thread_rng())
+    }
+
+    /// The idle timeout with the jitter drawn from the given random number generator (e.g. in a deterministic simulation).
+    pub fn idle_timeout_with<R>(&self, rng: &mut R) -> Duration
+    where
+        R: Rng + ?Sized,
+    {
+        // A recovering replica cannot act as the primary, so it retries at the pace of a backup instead of
+        // resetting the idle timeouts of the backups that need to replace it with a view change.
+        let primary = self.is_primary() && self.status != Status::Recovering;
+        let timeout = self.configuration.idle_timeout_with(primary, rng);
+
+        let (attempts, backoff) = match self.status {
+            Status::Recovering => (
+                self.recovery_attempts,
+                self.configuration.recovery_backoff(),
+            ),
+            _ => (
+                self.view_change_attempts,
+                self.configuration.view_change_backoff(),
+            ),
+        };
+
+        match backoff {
+            Some(limit) if attempts > 0 => timeout
+                .checked_mul(2u32.saturating_pow(attempts))
+                .unwrap_or(limit)
+                .min(limit.max(timeout)),
+            _ => timeout,
+        }
+    }
+
     pub fn view(&self) -> View {
         self.view
     }
 
-    pub fn checkpoint(&self) -> Checkpoint<S::Checkpoint> {
+    /// The incarnation of the replica, which is 0 for a new replica and grows by 1 every time it restarts from a checkpoint.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Checkpoints the committed state of the service.
+    /// Rolls back speculative operations first, so they are applied again normally once they commit.
+    pub fn checkpoint(&mut self) -> Checkpoint<S::Checkpoint, S::Reply> {
+        self.rollback_speculation();
+
         Checkpoint {
             committed: self.committed,
             state: self.service.checkpoint(),
+            client_table: self.client_table.executed(),
+            generation: self.generation,
         }
     }
 
-    pub fn checkpoint_with_suffix(&mut self, suffix: usize) -> Option<Checkpoint<S::Checkpoint>> {
+    pub fn checkpoint_with_suffix(
+        &mut self,
+        suffix: usize,
+    ) -> Option<Checkpoint<S::Checkpoint, S::Reply>> {
         let mut new_start = self.log.first_op_number();
-        let trimmed = self.log.len().checked_sub(suffix).unwrap_or_default();
+        let trimmed = self.log.len().saturating_sub(suffix);
 
         new_start.increment_by(trimmed);
 
         if self.committed >= new_start {
-            let checkpoint = Checkpoint {
-                committed: self.committed,
-                state: self.service.checkpoint(),
-            };
+            let checkpoint = self.checkpoint();
 
             self.log.constrain(suffix);
 
@@ -124,39 +489,104 @@ where
         }
     }
 
+    #[cfg(feature = "os")]
     pub fn idle<O>(&mut self, outbox: &mut O)
     where
         O: Outbox<S>,
     {
+        self.idle_at(crate::clock::unix_time(), outbox)
+    }
+
+    /// Handles the replica becoming idle at the given time since the Unix epoch, which an idle primary sends along
+    /// with its commit number. Useful for controlling time (e.g. in a simulation); otherwise prefer [`Replica::idle`].
+    pub fn idle_at<O>(&mut self, now: Duration, outbox: &mut O)
+    where
+        O: Outbox<S>,
+    {
+        if self.retired {
+            return;
+        }
+
+        self.requested_state = None;
+        self.repairing = None;
+        self.transfer_targets.expire();
+        self.informed_clients.clear();
+
         match self.status {
             Status::Normal => {
                 if self.is_primary() {
+                    let prepared_view = self.prepared_view.take();
+
+                    self.renew_lease();
+
+                    if let Some(anti_entropy) = self.anti_entropy.as_mut() {
+                        if anti_entropy.tick() {
+                            outbox.digest(anti_entropy.digest(
+                                self.view,
+                                self.configuration.epoch(),
+                                self.index,
+                                &self.log,
+                                self.committed,
+                            ));
+                        }
+                    }
+
                     if self.committed == self.log.last_op_number() {
+                        if self.shutting_down {
+                            if let Some(view) = self.handoff_view() {
+                                self.start_view_change(view, outbox);
+                            }
+
+                            return;
+                        }
+
+                        // Backups heard from the primary recently and the next prepare will carry the commit number.
+                        if prepared_view == Some(self.view) {
+                            self.last_heartbeat = Some(now);
+                            return;
+                        }
+
+                        if !self.is_heartbeat_due(now) {
+                            return;
+                        }
+
                         outbox.commit(Commit {
                             view: self.view,
+                            epoch: self.configuration.epoch(),
                             committed: self.committed,
+                            timestamp: now,
                         });
                     } else {
                         self.prepare_pending(outbox);
                     }
-                } else {
-                    self.start_view_change(self.view.next(), outbox);
+                } else if !self.is_observer() {
+                    self.start_next_view_change(outbox);
                 }
             }
             Status::Recovering => {
-                outbox.recovery(Recovery {
-                    index: self.index,
-                    committed: self.committed,
-                    nonce: self.nonce,
-                });
+                self.recovery_attempts = self.recovery_attempts.saturating_add(1);
+
+                outbox.recovery(self.recovery());
+
+                if self.configuration.recovery_attempts() == Some(self.recovery_attempts) {
+                    for listener in self.listeners.iter_mut() {
+                        listener.on_recovery_stalled(self.recovery_attempts);
+                    }
+                }
             }
             Status::ViewChange => {
-                if self.is_backup() && self.should_do_view_change() {
+                self.view_change_idles = self.view_change_idles.saturating_add(1);
+
+                if self.is_view_change_overdue() {
+                    // The view did not complete in time, so move on to the next one.
+                    self.start_next_view_change(outbox);
+                } else if self.is_backup() && self.should_do_view_change() {
                     // The new primary is unresponsive. Start a new view change.
-                    self.start_view_change(self.view.next(), outbox);
+                    self.start_next_view_change(outbox);
                 } else {
                     outbox.start_view_change(StartViewChange {
                         view: self.view,
+                        epoch: self.configuration.epoch(),
                         index: self.index,
                     });
                 }
@@ -173,718 +603,4532 @@ where
                 self.prepare_pending(outbox);
             }
             Status::Recovering => {
-                outbox.recovery(Recovery {
-                    index: self.index,
-                    committed: self.committed,
-                    nonce: self.nonce,
-                });
+                outbox.recovery(self.recovery());
             }
             Status::ViewChange => {
                 outbox.start_view_change(StartViewChange {
                     view: self.view,
+                    epoch: self.configuration.epoch(),
                     index: self.index,
                 });
             }
         }
     }
 
+    #[cfg(feature = "os")]
     pub fn handle_request<O>(&mut self, request: Request<S::Request>, outbox: &mut O)
     where
         O: Outbox<S>,
     {
-        if self.is_backup() {
-            return;
-        }
-
-        match self.client_table.compare(&request) {
-            Ok(Ordering::Greater) => {
-                let prediction = self.service.predict(&request.payload);
-                let (entry, op_number) = self.log.push(self.view, request, prediction);
-
-                self.client_table.start(entry.request());
-
-                outbox.prepare(Prepare {
-                    view: self.view,
-                    op_number,
-                    request: entry.request().clone(),
-                    prediction: entry.prediction().clone(),
-                    committed: self.committed,
-                });
-            }
-            Ok(Ordering::Equal) => {
-                if let Some(reply) = self.client_table.reply(&request) {
-                    outbox.reply(request.client, reply);
-                }
-            }
-            Ok(Ordering::Less) => (),
-            Err(_) => (),
-        }
+        self.handle_request_at(request, crate::clock::unix_time(), outbox)
     }
 
-    pub fn handle_prepare<M>(
+    /// Handles the request at the given time since the Unix epoch.
+    /// The primary replies with [`Expired`] instead of starting a new request whose deadline has passed,
+    /// so requests that queued up (e.g. during a view change) are not executed after the client gave up on them.
+    /// Useful for controlling time (e.g. in a simulation); otherwise prefer [`Replica::handle_request`].
+    pub fn handle_request_at<O>(
         &mut self,
-        message: Prepare<S::Request, S::Prediction>,
-        mailbox: &mut M,
+        request: Request<S::Request>,
+        now: Duration,
+        outbox: &mut O,
     ) where
-        M: Mailbox<S>,
+        O: Outbox<S>,
     {
-        if self.need_state_transfer(message.view) {
-            self.state_transfer(message.view, mailbox);
-            mailbox.push_prepare(message);
+        if self.retired {
+            self.discard(DropReason::Retired);
             return;
         }
 
-        if self.should_ignore_normal(message.view) || self.log.contains(&message.op_number) {
+        if request.epoch != self.configuration.epoch() {
+            self.filter_epoch(&request, outbox);
             return;
         }
 
-        let next = self.log.next_op_number();
-        if next < message.op_number || next < message.committed {
-            self.state_transfer(message.view, mailbox);
-            mailbox.push_prepare(message);
+        if self.is_backup() {
+            if self.read(&request, now, outbox) {
+                return;
+            }
+
+            self.inform_outdated(request.client, outbox);
+            self.discard(DropReason::NotPrimary);
             return;
         }
 
-        self.client_table.start(&message.request);
-        self.log
-            .push(self.view, message.request, message.prediction);
-        mailbox.prepare_ok(
-            self.configuration % self.view,
-            PrepareOk {
-                view: self.view,
-                op_number: message.op_number,
-                index: self.index,
-            },
-        );
-        self.commit_operations(message.committed, mailbox);
-    }
-
-    pub fn handle_prepare_ok<M>(&mut self, message: PrepareOk, mailbox: &mut M)
-    where
-        M: Mailbox<S>,
-    {
-        if self.need_state_transfer(message.view) {
-            self.state_transfer(message.view, mailbox);
-            mailbox.push_prepare_ok(message);
+        // The primary of a view that is still changing (or a recovering one) does not have the log of the view yet,
+        // so adding to its log would make it look more up-to-date than the logs of the other replicas.
+        if self.status != Status::Normal {
+            self.discard(DropReason::UnexpectedStatus);
             return;
         }
 
-        if self.should_ignore_normal(message.view) || message.op_number <= self.committed {
+        self.undelivered
+            .acknowledge_before(request.client, request.id);
+
+        if self.fenced {
+            self.discard(DropReason::Fenced);
             return;
         }
 
-        let prepared = self.prepared.entry(message.op_number).or_default();
-
-        prepared.insert(message.index);
+        if let Some(verifier) = &self.verifier {
+            if !verifier.verify(&request) {
+                self.discard(DropReason::Unverified);
+                return;
+            }
+        }
 
-        if prepared.len() >= self.configuration.sub_majority() {
-            self.prepared.retain(|&o, _| o > message.op_number);
-            self.commit_operations(message.op_number, mailbox);
+        match self.client_table.compare(&request) {
+            Ok(Ordering::Greater) => self.start_request(request, now, outbox),
+            Ok(Ordering::Equal) => match self.client_table.reply(&request) {
+                Some(reply) => {
+                    outbox.reply(request.client, reply);
+                    self.listeners
+                        .iter_mut()
+                        .for_each(|listener| listener.on_client_reply(request.client, request.id));
+                }
+                // A view change replaced the log that held the request, so it only commits if it is prepared again.
+                None if !self.is_prepared(&request) => self.start_request(request, now, outbox),
+                None => self.discard(DropReason::Duplicate),
+            },
+            Ok(Ordering::Less) => self.discard(DropReason::Duplicate),
+            Err(_) => self.discard(DropReason::ConcurrentRequest),
         }
     }
 
-    pub fn handle_commit<M>(&mut self, message: Commit, mailbox: &mut M)
+    /// Prepares a new request, unless its deadline passed or the primary is busy.
+    fn start_request<O>(&mut self, request: Request<S::Request>, now: Duration, outbox: &mut O)
     where
-        M: Mailbox<S>,
+        O: Outbox<S>,
     {
-        if self.need_state_transfer(message.view) {
-            self.state_transfer(message.view, mailbox);
-            mailbox.push_commit(message);
-            return;
-        }
+        if request.is_expired(now) {
+            outbox.expired(
+                request.client,
+                Expired {
+                    view: self.view,
+                    id: request.id,
+                },
+            );
+        } else if self.is_busy(outbox) {
+            outbox.busy(
+                request.client,
+                Busy {
+                    view: self.view,
+                    id: request.id,
+                    backoff: self.backoff(outbox),
+                },
+            );
+        } else {
+            let prediction = self.service.predict(&request.payload);
+            let previous = self.log.view_at(self.log.last_op_number());
+            let (entry, op_number) = self.log.push_at(self.view, request, prediction, now);
 
-        if self.should_ignore_normal(message.view) || message.committed <= self.committed {
-            return;
-        }
+            self.client_table.start(entry.request());
+            self.listeners
+                .iter_mut()
+                .for_each(|listener| listener.on_prepare(self.view, op_number));
 
-        if !self.log.contains(&message.committed) {
-            self.state_transfer(message.view, mailbox);
-            mailbox.push_commit(message);
-            return;
-        }
+            outbox.prepare(Prepare {
+                view: self.view,
+                epoch: self.configuration.epoch(),
+                op_number,
+                request: entry.request().clone(),
+                prediction: entry.prediction().clone(),
+                committed: self.committed,
+                previous,
+                timestamp: entry.timestamp(),
+            });
 
-        self.commit_operations(message.committed, mailbox);
+            self.prepared_view = Some(self.view);
+            self.speculate(op_number);
+        }
     }
 
-    pub fn handle_get_state<M>(&mut self, message: GetState, mailbox: &mut M)
-    where
-        M: Mailbox<S>,
-    {
-        if self.need_state_transfer(message.view) {
-            self.state_transfer(message.view, mailbox);
-            mailbox.push_get_state(message);
-            return;
-        }
+    /// Whether the request is in the log waiting to be committed.
+    fn is_prepared(&self, request: &Request<S::Request>) -> bool {
+        let mut current = self.committed.next();
 
-        if self.should_ignore_normal(message.view) {
-            return;
-        }
+        while let Some(entry) = self.log.get(current) {
+            if entry.request().client == request.client && entry.request().id == request.id {
+                return true;
+            }
 
-        if !self.log.contains(&message.op_number) {
-            return;
+            current.increment();
         }
 
-        mailbox.new_state(
-            message.index,
-            NewState {
-                view: self.view,
-                log: self.log.after(message.op_number),
-                committed: self.committed,
-            },
-        );
+        false
     }
 
-    pub fn handle_recovery<O>(&mut self, message: Recovery, outbox: &mut O)
+    /// Answers a read-only request from the committed state of a backup. Returns false when follower reads are disabled
+    /// or the backup is not within the staleness bound of the request, in which case only the primary may answer it.
+    fn read<O>(&mut self, request: &Request<S::Request>, now: Duration, outbox: &mut O) -> bool
     where
         O: Outbox<S>,
     {
+        let (Some(query), Some(staleness)) = (self.query, request.staleness) else {
+            return false;
+        };
+
         if self.status != Status::Normal {
-            return;
+            return false;
         }
 
-        let mut response = RecoveryResponse {
-            view: self.view,
-            nonce: message.nonce,
-            log: Default::default(),
-            committed: Default::default(),
-            index: self.index,
+        let (advertised, timestamp) = self.advertised;
+        let fresh = match staleness {
+            Staleness::Operations(limit) => {
+                u128::from(advertised).saturating_sub(u128::from(self.committed))
+                    <= u128::from(limit)
+            }
+            Staleness::Time(limit) => {
+                self.committed >= advertised && now.saturating_sub(timestamp) <= limit
+            }
         };
 
-        if self.is_primary() {
-            response.log = self.log.clone();
-            response.committed = self.committed;
+        if fresh {
+            outbox.reply(
+                request.client,
+                &Reply {
+                    view: self.view,
+                    id: request.id,
+                    payload: query(&self.service, &request.payload),
+                },
+            );
         }
 
-        outbox.recovery_response(message.index, response);
+        fresh
     }
 
-    pub fn handle_recovery_response<O>(
-        &mut self,
-        message: RecoveryResponse<S::Request, S::Prediction>,
-        outbox: &mut O,
-    ) where
+    /// Tells the primary of an outdated view about the newer view this replica promised to join,
+    /// so the outdated primary stops serving requests. Only used when a lease is configured.
+    fn reject_outdated<O>(&mut self, view: View, outbox: &mut O)
+    where
         O: Outbox<S>,
     {
-        if self.status != Status::Recovering || self.nonce != message.nonce {
+        if self.configuration.lease().is_none() || view >= self.view || self.is_observer() {
             return;
         }
 
-        self.recovery_responses.insert(message.index, message);
-
-        if self.recovery_responses.len() >= self.configuration.quorum() {
-            let view = self
-                .recovery_responses
-                .values()
-                .map(|r| r.view)
-                .max()
-                .unwrap_or_default();
-            let primary = self.configuration % view;
+        outbox.prepare_ok(
+            self.configuration % view,
+            PrepareOk {
+                view,
+                epoch: self.configuration.epoch(),
+                op_number: Default::default(),
+                index: self.index,
+                committed: Default::default(),
+                promised: self.view,
+            },
+        );
+    }
 
-            if let Some(primary_response) = self.recovery_responses.remove(&primary) {
-                self.view = primary_response.view;
-                self.log = primary_response.log;
-                self.set_status(Status::Normal);
-                self.commit_operations(primary_response.committed, outbox);
-                self.prepare_pending(outbox);
-            }
+    /// Fences the primary once it goes longer than its lease without hearing from a sub-majority of backups,
+    /// and lifts the fence as soon as a sub-majority acknowledges it again.
+    fn renew_lease(&mut self) {
+        let Some(lease) = self.configuration.lease() else {
+            return;
+        };
+
+        if self.lease_acknowledgements.len() >= self.configuration.sub_majority() {
+            self.lease_misses = 0;
+            self.fenced = false;
+        } else {
+            self.lease_misses = self.lease_misses.saturating_add(1);
+            self.fenced |= self
+                .configuration
+                .commit_interval()
+                .saturating_mul(self.lease_misses)
+                >= lease;
         }
+
+        self.lease_acknowledgements.clear();
     }
 
-    pub fn handle_new_state<O>(
-        &mut self,
-        message: NewState<S::Request, S::Prediction>,
-        outbox: &mut O,
-    ) where
+    /// Drops a request from another epoch of the group. Clients from an earlier epoch are told about the current one,
+    /// while requests from a later epoch mean this replica is no longer a member of the group and must not answer.
+    fn filter_epoch<O>(&mut self, request: &Request<S::Request>, outbox: &mut O)
+    where
         O: Outbox<S>,
     {
-        if message.view < self.view
-            || self.status != Status::Normal
-            || message.log.first_op_number() != self.log.next_op_number()
-        {
-            return;
+        if request.epoch < self.configuration.epoch() {
+            outbox.outdated_epoch(
+                request.client,
+                OutdatedEpoch {
+                    view: self.view,
+                    epoch: self.configuration.epoch(),
+                },
+            );
         }
 
-        self.view = message.view;
-        self.log.extend(message.log);
-        self.commit_operations(message.committed, outbox);
-        self.prepare_pending(outbox);
+        self.discard(DropReason::StaleEpoch);
     }
 
-    pub fn handle_start_view_change<O>(&mut self, message: StartViewChange, outbox: &mut O)
+    /// Points a client that sent a request to this backup at the primary, once per view until the replica is idle.
+    fn inform_outdated<O>(&mut self, client: ClientIdentifier, outbox: &mut O)
     where
         O: Outbox<S>,
     {
-        if self.need_view_change(message.view) {
-            self.start_view_change(message.view, outbox);
+        if self.status == Status::Normal && self.informed_clients.insert((self.view, client)) {
+            outbox.outdated_view(
+                client,
+                OutdatedView {
+                    view: self.view,
+                    primary: self.configuration % self.view,
+                    epoch: self.configuration.epoch(),
+                },
+            );
         }
+    }
 
-        if self.should_ignore_view_change(message.view) {
+    /// Tells a client with a durable identifier the number of its latest request, so it can resume numbering
+    /// its requests after a restart. Only a primary in a normal view answers, since its client table includes
+    /// every request the group may have started.
+    pub fn handle_open_session<O>(&mut self, message: OpenSession, outbox: &mut O)
+    where
+        O: Outbox<S>,
+    {
+        if self.retired {
+            self.discard(DropReason::Retired);
             return;
         }
 
-        self.start_view_changes.insert(message.index);
+        if self.is_backup() {
+            self.discard(DropReason::NotPrimary);
+            return;
+        }
 
-        if self.should_do_view_change() {
-            outbox.do_view_change(
-                self.configuration % self.view,
-                DoViewChange {
-                    view: self.view,
-                    log: self.log.clone(),
-                    committed: self.committed,
-                    index: self.index,
-                },
-            )
+        if self.status != Status::Normal {
+            self.discard(DropReason::UnexpectedStatus);
+            return;
         }
+
+        self.undelivered.open(message.client);
+        outbox.session_accepted(
+            message.client,
+            SessionAccepted {
+                view: self.view,
+                last_request: self
+                    .client_table
+                    .last_request(message.client)
+                    .unwrap_or_default(),
+            },
+        );
     }
 
-    pub fn handle_do_view_change<O>(
-        &mut self,
-        message: DoViewChange<S::Request, S::Prediction>,
-        outbox: &mut O,
-    ) where
+    /// Reopens the session of a client whose connection dropped like [`Replica::handle_open_session`],
+    /// then delivers again the replies the client did not acknowledge. A primary without a session for the client
+    /// (e.g. one that took over after a view change) has not buffered its replies, so it delivers the reply to the
+    /// latest request of the client instead.
+    pub fn handle_resume<O>(&mut self, message: Resume, outbox: &mut O)
+    where
         O: Outbox<S>,
     {
-        if self.need_view_change(message.view) {
-            self.start_view_change(message.view, outbox);
+        if self.retired {
+            self.discard(DropReason::Retired);
+            return;
         }
 
-        if self.should_ignore_view_change(message.view) {
+        if self.is_backup() {
+            self.discard(DropReason::NotPrimary);
             return;
         }
 
-        self.do_view_changes.insert(message.index, message);
+        if self.status != Status::Normal {
+            self.discard(DropReason::UnexpectedStatus);
+            return;
+        }
 
-        if self.do_view_changes.contains_key(&self.index)
-            && self.do_view_changes.len() >= self.configuration.quorum()
-        {
-            let committed = self
-                .do_view_changes
-                .values()
-                .map(|v| v.committed)
-                .max()
-                .unwrap_or(self.committed);
-            if let Some(do_view_change) = self
-                .do_view_changes
-                .drain()
-                .map(|(_, v)| v)
-                .max_by(|x, y| x.log.cmp(&y.log))
-            {
-                self.log = do_view_change.log;
-                self.view = do_view_change.view;
-                self.set_status(Status::Normal);
+        let known = self.undelivered.is_open(message.client);
 
-                outbox.start_view(StartView {
-                    view: self.view,
-                    log: self.log.clone(),
-                    committed,
-                });
+        self.handle_open_session(
+            OpenSession {
+                client: message.client,
+            },
+            outbox,
+        );
+        self.undelivered
+            .acknowledge(message.client, message.acknowledged);
 
-                self.commit_operations(committed, outbox);
-                self.prepare_pending(outbox);
+        let mut undelivered = self.undelivered.undelivered(message.client).peekable();
+
+        if !known && undelivered.peek().is_none() {
+            if let Some(reply) = self
+                .client_table
+                .last_reply(message.client)
+                .filter(|reply| reply.id > message.acknowledged)
+            {
+                outbox.reply(message.client, reply);
             }
         }
+
+        for reply in undelivered {
+            outbox.reply(message.client, reply);
+        }
     }
 
-    pub fn handle_start_view<O>(
-        &mut self,
-        message: StartView<S::Request, S::Prediction>,
-        outbox: &mut O,
-    ) where
+    /// Stops buffering the replies the client received for delivery on a [`Resume`].
+    pub fn handle_acknowledge(&mut self, message: Acknowledge) {
+        self.undelivered
+            .acknowledge(message.client, message.request);
+    }
+
+    /// Tells a client whether the group executed one of its requests, so it can decide whether to retry
+    /// a request it gave up waiting on. Only a primary in a normal view answers, since its client table includes
+    /// every request the group may have started.
+    pub fn handle_query_request_status<O>(&mut self, message: QueryRequestStatus, outbox: &mut O)
+    where
         O: Outbox<S>,
     {
-        if message.view < self.view {
+        if self.retired {
+            self.discard(DropReason::Retired);
             return;
         }
 
-        if message.view == self.view && self.status == Status::Normal {
+        if self.is_backup() {
+            self.discard(DropReason::NotPrimary);
             return;
         }
 
-        self.view = message.view;
-        self.log = message.log;
+        if self.status != Status::Normal {
+            self.discard(DropReason::UnexpectedStatus);
+            return;
+        }
 
-        self.set_status(Status::Normal);
-        self.commit_operations(message.committed, outbox);
-        self.prepare_pending(outbox);
+        outbox.request_status(
+            message.client,
+            RequestStatus {
+                view: self.view,
+                request: message.request,
+                state: self.client_table.state(message.client, message.request),
+            },
+        );
     }
 
-    fn start_view_change<O>(&mut self, view: View, outbox: &mut O)
+    /// Tells a client the configuration of the group, so clients only need the address of a single replica.
+    /// Every replica that was not retired answers regardless of its role or status, since the configuration
+    /// does not change within an epoch. The view in the answer may be outdated, in which case the client learns
+    /// of the current view the first time it sends a request.
+    pub fn handle_discover<O>(&mut self, message: Discover, outbox: &mut O)
     where
         O: Outbox<S>,
     {
-        self.view = view;
+        if self.retired {
+            self.discard(DropReason::Retired);
+            return;
+        }
 
-        self.set_status(Status::ViewChange);
+        outbox.discovered(
+            message.client,
+            Discovered {
+                view: self.view,
+                epoch: self.configuration.epoch(),
+                group: self.configuration.group(),
+                replicas: self.configuration.replicas(),
+                members: self.members.clone(),
+            },
+        );
+    }
 
-        outbox.start_view_change(StartViewChange {
-            view: self.view,
-            index: self.index,
-        });
+    /// Whether the views and op-numbers of the message are within reach of the replica,
+    /// so handling the message can never take either past the largest value they hold.
+    fn is_plausible(&self, message: &ProtocolPayload<S>) -> bool {
+        let (view, op_number) = message.reach();
+
+        u128::from(view) <= u128::from(self.view).saturating_add(HORIZON)
+            && u128::from(op_number)
+                <= u128::from(self.log.last_op_number()).saturating_add(HORIZON)
     }
 
-    fn state_transfer<O>(&mut self, view: View, outbox: &mut O)
+    /// Dispatches the protocol message to the handler for its type.
+    pub fn handle_payload<M>(&mut self, message: ProtocolPayload<S>, mailbox: &mut M)
     where
-        O: Outbox<S>,
+        M: Mailbox<S>,
     {
-        if self.view < view {
-            self.log.truncate(self.committed);
+        if self.retired {
+            self.discard(DropReason::Retired);
+            return;
         }
 
-        let replicas = self.configuration.replicas();
+        // Members of different epochs may share indices, so votes across epochs could form a quorum of neither.
+        if message.epoch() != self.configuration.epoch() {
+            self.discard(DropReason::StaleEpoch);
+            return;
+        }
 
-        let mut replica = self.index;
-        while replica == self.index {
-            replica = rand::thread_rng().gen_range(0..replicas);
+        if !self.is_plausible(&message) {
+            self.discard(DropReason::Implausible);
+            return;
         }
 
-        outbox.get_state(
-            replica,
-            GetState {
+        let origin = message.origin(self.configuration);
+        let before = (self.view, self.status);
+
+        if let Some((view, replica)) = origin {
+            for listener in self.listeners.iter_mut() {
+                listener.on_message(view, replica);
+            }
+        }
+
+        // A replica in a view change only makes progress by completing it, even if it hears from the new primary.
+        if self.status == Status::Normal
+            && !self.is_primary()
+            && origin == Some((self.view, self.configuration % self.view))
+        {
+            self.liveness += 1;
+        }
+
+        match message {
+            ProtocolPayload::Prepare(message) => self.handle_prepare(message, mailbox),
+            ProtocolPayload::PrepareOk(message) => self.handle_prepare_ok(message, mailbox),
+            ProtocolPayload::Commit(message) => self.handle_commit(message, mailbox),
+            ProtocolPayload::GetState(message) => self.handle_get_state(message, mailbox),
+            ProtocolPayload::NewState(message) => self.handle_new_state(message, mailbox),
+            ProtocolPayload::InstallSnapshot(message) => {
+                self.handle_install_snapshot(message, mailbox)
+            }
+            ProtocolPayload::StartViewChange(message) => {
+                self.handle_start_view_change(message, mailbox)
+            }
+            ProtocolPayload::DoViewChange(message) => self.handle_do_view_change(message, mailbox),
+            ProtocolPayload::GetLog(message) => self.handle_get_log(message, mailbox),
+            ProtocolPayload::NewLog(message) => self.handle_new_log(message, mailbox),
+            ProtocolPayload::StartView(message) => self.handle_start_view(message, mailbox),
+            ProtocolPayload::Recovery(message) => self.handle_recovery(message, mailbox),
+            ProtocolPayload::RecoveryResponse(message) => {
+                self.handle_recovery_response(message, mailbox)
+            }
+            ProtocolPayload::Digest(message) => self.handle_digest(message, mailbox),
+        }
+
+        if (self.view, self.status) != before {
+            self.liveness += 1;
+        }
+    }
+
+    pub fn handle_prepare<M>(
+        &mut self,
+        message: Prepare<S::Request, S::Prediction>,
+        mailbox: &mut M,
+    ) where
+        M: Mailbox<S>,
+    {
+        if self.need_state_transfer(message.view) {
+            self.state_transfer(message.view, mailbox);
+            mailbox.push_prepare(message);
+            return;
+        }
+
+        if self.should_ignore_normal(message.view) {
+            self.reject_outdated(message.view, mailbox);
+            return;
+        }
+
+        self.advertise(message.committed, message.timestamp);
+
+        // The network may duplicate prepares, including ones for operations compacted out of the log.
+        let next = self.log.next_op_number();
+        if message.op_number < next {
+            self.discard(DropReason::Duplicate);
+            return;
+        }
+
+        if next < message.op_number || next < message.committed {
+            self.state_transfer(message.view, mailbox);
+            mailbox.push_prepare(message);
+            return;
+        }
+
+        // The log diverged from the primary's if the entry before this one was added in a different view.
+        let previous = message.op_number.previous();
+        match (message.previous, self.log.view_at(previous)) {
+            (Some(expected), Some(actual)) if expected != actual => {
+                // Committed entries never diverge, so the prepare itself must be bad.
+                if previous <= self.committed {
+                    self.discard(DropReason::Inconsistent);
+                    return;
+                }
+
+                self.log.truncate(self.committed);
+                self.state_transfer(message.view, mailbox);
+                mailbox.push_prepare(message);
+                return;
+            }
+            _ => {}
+        }
+
+        self.client_table.start(&message.request);
+        self.log.push_at(
+            self.view,
+            message.request,
+            message.prediction,
+            message.timestamp,
+        );
+        self.listeners
+            .iter_mut()
+            .for_each(|listener| listener.on_prepare(self.view, message.op_number));
+        self.commit_operations(message.committed, mailbox);
+
+        if self.is_observer() {
+            return;
+        }
+
+        mailbox.prepare_ok(
+            self.configuration % self.view,
+            PrepareOk {
                 view: self.view,
-                op_number: self.log.last_op_number(),
+                epoch: self.configuration.epoch(),
+                op_number: message.op_number,
                 index: self.index,
+                committed: self.committed,
+                promised: self.view,
             },
         );
     }
 
-    fn commit_operations<O>(&mut self, committed: OpNumber, outbox: &mut O)
+    pub fn handle_prepare_ok<M>(&mut self, message: PrepareOk, mailbox: &mut M)
     where
-        O: Outbox<S>,
+        M: Mailbox<S>,
     {
-        while self.committed < committed {
-            self.committed.increment();
+        if self.configuration.is_observer(message.index) {
+            self.discard(DropReason::NonVoting);
+            return;
+        }
 
-            let entry = &self.log[self.committed];
-            let request = entry.request();
-            let reply = Reply {
-                view: self.view,
-                id: request.id,
-                payload: self.service.invoke(&request.payload, entry.prediction()),
-            };
+        if self.need_state_transfer(message.view) {
+            self.state_transfer(message.view, mailbox);
+            mailbox.push_prepare_ok(message);
+            return;
+        }
 
-            if self.is_primary() {
-                outbox.reply(request.client, &reply);
+        if self.should_ignore_normal(message.view) {
+            return;
+        }
+
+        if message.promised > self.view {
+            self.fenced = true;
+            return;
+        }
+
+        if self.configuration.lease().is_some() {
+            self.lease_acknowledgements.insert(message.index);
+        }
+
+        let backup_committed = self.backup_commits.entry(message.index).or_default();
+
+        *backup_committed = message.committed.max(*backup_committed);
+
+        // Backups only acknowledge operations the primary prepared.
+        if message.op_number > self.log.last_op_number() {
+            return;
+        }
+
+        let acknowledged = self.acknowledged.entry(message.index).or_default();
+
+        *acknowledged = message.op_number.max(*acknowledged);
+
+        // Slow backups acknowledge operations after they committed, which still counts as their latency.
+        for listener in self.listeners.iter_mut() {
+            listener.on_prepare_ok(message.op_number, message.index);
+        }
+
+        if message.op_number <= self.committed {
+            return;
+        }
+
+        // Backups prepare operations in order, so the acknowledgement counts for every uncommitted operation up to it.
+        let mut reached = None;
+        let mut op_number = self.committed.next();
+
+        while op_number <= message.op_number {
+            let prepared = self.prepared.entry(op_number).or_insert_with(|| {
+                QuorumTracker::new(self.view, self.configuration.sub_majority())
+            });
+
+            prepared.insert(message.view, message.index, ());
+
+            if prepared.is_reached() {
+                reached = Some(op_number);
             }
 
-            self.client_table.finish(request, reply);
+            op_number.increment();
+        }
+
+        if let Some(op_number) = reached {
+            self.commit_operations(op_number, mailbox);
         }
     }
 
-    fn prepare_pending<O>(&mut self, outbox: &mut O)
+    pub fn handle_commit<M>(&mut self, message: Commit, mailbox: &mut M)
     where
-        O: Outbox<S>,
+        M: Mailbox<S>,
     {
-        let mut current = self.committed.next();
-
-        while self.log.contains(&current) {
-            let entry = &self.log[current];
-            let request = entry.request();
+        if self.need_state_transfer(message.view) {
+            self.state_transfer(message.view, mailbox);
+            mailbox.push_commit(message);
+            return;
+        }
 
-            self.client_table.start(request);
+        if self.should_ignore_normal(message.view) {
+            self.reject_outdated(message.view, mailbox);
+            return;
+        }
 
-            if self.is_primary() {
-                outbox.prepare(Prepare {
+        self.advertise(message.committed, message.timestamp);
+
+        if message.committed > self.committed {
+            if !self.log.contains(&message.committed) {
+                self.state_transfer(message.view, mailbox);
+                mailbox.push_commit(message);
+                return;
+            }
+
+            self.commit_operations(message.committed, mailbox);
+        }
+
+        // Idle primaries only hear from backups that acknowledge their commits.
+        if self.configuration.lease().is_some() && !self.is_observer() {
+            mailbox.prepare_ok(
+                self.configuration % self.view,
+                PrepareOk {
                     view: self.view,
-                    op_number: current,
-                    request: entry.request().clone(),
-                    prediction: entry.prediction().clone(),
+                    epoch: self.configuration.epoch(),
+                    op_number: self.log.last_op_number(),
+                    index: self.index,
                     committed: self.committed,
-                });
-            } else {
-                outbox.prepare_ok(
-                    self.configuration % self.view,
-                    PrepareOk {
+                    promised: self.view,
+                },
+            );
+        }
+    }
+
+    pub fn handle_get_state<M>(&mut self, message: GetState, mailbox: &mut M)
+    where
+        M: Mailbox<S>,
+    {
+        if self.need_state_transfer(message.view) {
+            self.state_transfer(message.view, mailbox);
+            mailbox.push_get_state(message);
+            return;
+        }
+
+        if self.should_ignore_normal(message.view) {
+            return;
+        }
+
+        let limit = self
+            .configuration
+            .state_transfer_limit()
+            .unwrap_or(usize::MAX);
+
+        let Some(next) = message.op_number.checked_next() else {
+            self.discard(DropReason::Inconsistent);
+            return;
+        };
+
+        // The requester only needs the entries after its op-number, which may start the log after a compaction.
+        if !self.log.contains(&message.op_number) && !self.log.contains(&next) {
+            // The replica is missing entries that are no longer in the log (e.g. a new or very stale replica).
+            if message.op_number < self.log.last_op_number() {
+                mailbox.install_snapshot(
+                    message.index,
+                    InstallSnapshot {
                         view: self.view,
-                        op_number: current,
-                        index: self.index,
+                        epoch: self.configuration.epoch(),
+                        checkpoint: self.checkpoint(),
+                        log: self.log.after_at_most(self.committed, limit),
+                        op_number: self.log.last_op_number(),
                     },
                 );
             }
 
-            current.increment();
+            return;
+        }
+
+        mailbox.new_state(
+            message.index,
+            NewState {
+                view: self.view,
+                epoch: self.configuration.epoch(),
+                log: self.log.after_at_most(message.op_number, limit),
+                committed: self.committed,
+                op_number: self.log.last_op_number(),
+            },
+        );
+    }
+
+    pub fn handle_recovery<O>(&mut self, message: Recovery, outbox: &mut O)
+    where
+        O: Outbox<S>,
+    {
+        // A response from an observer would count toward the quorum the recovering replica waits for.
+        if self.is_observer() {
+            return;
+        }
+
+        if self.status != Status::Normal {
+            self.discard(DropReason::UnexpectedStatus);
+            return;
+        }
+
+        let generation = self.generations.entry(message.index).or_default();
+
+        // A request the replica sent before it restarted again (e.g. delayed in the network).
+        if message.generation < *generation {
+            self.discard(DropReason::StaleGeneration);
+            return;
+        }
+
+        *generation = message.generation;
+
+        let mut response = RecoveryResponse {
+            view: self.view,
+            epoch: self.configuration.epoch(),
+            nonce: message.nonce,
+            log: None,
+            committed: None,
+            index: self.index,
+            generation: message.generation,
+        };
+
+        if self.is_primary() {
+            // Only send the entries after the prefix shared with the durable log of the recovering replica.
+            let log = match &message.digest {
+                Some(digest) => {
+                    let op_number = self.log.common_prefix_with(digest);
+
+                    if op_number.next() < self.log.first_op_number() {
+                        self.log.clone()
+                    } else {
+                        self.log.after(op_number)
+                    }
+                }
+                None => self.log.clone(),
+            };
+
+            response.log = Some(log);
+            response.committed = Some(self.committed);
+        }
+
+        outbox.recovery_response(message.index, response);
+    }
+
+    pub fn handle_recovery_response<O>(
+        &mut self,
+        message: RecoveryResponse<S::Request, S::Prediction>,
+        outbox: &mut O,
+    ) where
+        O: Outbox<S>,
+    {
+        if self.configuration.is_observer(message.index) {
+            self.discard(DropReason::NonVoting);
+            return;
+        }
+
+        if self.status != Status::Recovering {
+            self.discard(DropReason::UnexpectedStatus);
+            return;
+        }
+
+        // A response meant for an earlier incarnation of the replica.
+        if self.generation != message.generation {
+            self.discard(DropReason::StaleGeneration);
+            return;
+        }
+
+        // A response to an earlier recovery attempt.
+        if self.nonce != message.nonce {
+            self.discard(DropReason::Duplicate);
+            return;
+        }
+
+        // Responses come from replicas in any view, so they are matched by the nonce instead.
+        self.recovery_responses
+            .insert(self.view, message.index, message);
+
+        if self.recovery_responses.is_reached() {
+            let view = self
+                .recovery_responses
+                .values()
+                .map(|r| r.view)
+                .max()
+                .unwrap_or_default();
+            let primary = self.configuration % view;
+
+            // The primary may have answered before it learned about the latest view,
+            // in which case its log is outdated and the replica waits for a response from the new primary.
+            let answered = self.recovery_responses.values().any(|response| {
+                response.index == primary
+                    && response.view == view
+                    && response.log.is_some()
+                    && response.committed.is_some()
+            });
+
+            if !answered {
+                return;
+            }
+
+            if let Some(RecoveryResponse {
+                view,
+                log: Some(log),
+                committed: Some(committed),
+                ..
+            }) = self.recovery_responses.remove(primary)
+            {
+                // The log is a suffix after the prefix shared with the durable log when the replica recovered with one.
+                if self.log.is_empty() || log.first_op_number() <= self.log.first_op_number() {
+                    self.log = log;
+                } else if !self.log.splice(log) {
+                    self.discard(DropReason::Inconsistent);
+                    return;
+                }
+
+                self.view = view;
+                self.set_status(Status::Normal);
+                self.commit_operations(committed, outbox);
+                self.prepare_pending(outbox);
+            }
+        }
+    }
+
+    pub fn handle_new_state<O>(
+        &mut self,
+        message: NewState<S::Request, S::Prediction>,
+        outbox: &mut O,
+    ) where
+        O: Outbox<S>,
+    {
+        if self.should_ignore_state(message.view) {
+            return;
+        }
+
+        // Repairs start at or before the committed op-number, so they never overlap a regular state transfer.
+        if self.repairing == Some(message.log.first_op_number()) {
+            self.repairing = None;
+            self.log.overwrite(message.log, self.committed);
+            return;
+        }
+
+        if message.log.first_op_number() != self.log.next_op_number() {
+            self.discard(DropReason::Inconsistent);
+            return;
+        }
+
+        self.transfer_targets.answered();
+
+        self.view = message.view;
+        self.notify_role();
+        self.log.extend(message.log);
+        self.commit_operations(message.committed.min(self.log.last_op_number()), outbox);
+
+        if self.log.last_op_number() < message.op_number {
+            self.state_transfer(self.view, outbox);
+        } else {
+            self.prepare_pending(outbox);
+        }
+    }
+
+    /// Requests the first range of committed entries that hashes differently than on the primary.
+    pub fn handle_digest<M>(&mut self, message: Digest, mailbox: &mut M)
+    where
+        M: Mailbox<S>,
+    {
+        if self.should_ignore_normal(message.view) {
+            return;
+        }
+
+        let Some(anti_entropy) = self.anti_entropy.as_ref() else {
+            return;
+        };
+
+        if self.repairing.is_some() {
+            self.discard(DropReason::ConcurrentRequest);
+            return;
+        }
+
+        if let Some((first, _)) = anti_entropy.diverged(&self.log, self.committed, &message) {
+            self.repairing = Some(first);
+
+            mailbox.get_state(
+                message.index,
+                GetState {
+                    view: self.view,
+                    epoch: self.configuration.epoch(),
+                    op_number: first.previous(),
+                    index: self.index,
+                },
+            );
+        }
+    }
+
+    /// Replaces the service state and log with the snapshot when it includes operations this replica has not committed.
+    pub fn handle_install_snapshot<O>(
+        &mut self,
+        message: InstallSnapshot<S::Checkpoint, S::Reply, S::Request, S::Prediction>,
+        outbox: &mut O,
+    ) where
+        O: Outbox<S>,
+    {
+        if self.should_ignore_state(message.view) {
+            return;
+        }
+
+        if message.checkpoint.committed <= self.committed {
+            self.discard(DropReason::Duplicate);
+            return;
+        }
+
+        if !message.log.is_empty()
+            && Some(message.log.first_op_number()) != message.checkpoint.committed.checked_next()
+        {
+            self.discard(DropReason::Inconsistent);
+            return;
+        }
+
+        self.transfer_targets.answered();
+
+        self.view = message.view;
+        self.committed = message.checkpoint.committed;
+        self.service = message.checkpoint.state.into();
+        self.client_table = message.checkpoint.client_table;
+        // The service was replaced, so it needs to learn its role again.
+        self.role = None;
+        self.notify_role();
+        self.log = if message.log.is_empty() {
+            Log::empty(message.log.last_normal_view(), self.committed)
+        } else {
+            message.log
+        };
+
+        if self.log.last_op_number() < message.op_number {
+            self.state_transfer(self.view, outbox);
+        } else {
+            self.prepare_pending(outbox);
+        }
+    }
+
+    pub fn handle_start_view_change<O>(&mut self, message: StartViewChange, outbox: &mut O)
+    where
+        O: Outbox<S>,
+    {
+        if self.configuration.is_observer(message.index) {
+            self.discard(DropReason::NonVoting);
+            return;
+        }
+
+        // Observers wait for the start-view of the new primary instead of joining the view change.
+        if self.is_observer() {
+            return;
+        }
+
+        if self.need_view_change(message.view) {
+            self.start_view_change(message.view, outbox);
+        }
+
+        if self.should_ignore_view_change(message.view) {
+            return;
+        }
+
+        self.start_view_changes
+            .insert(message.view, message.index, ());
+
+        if self.should_do_view_change() {
+            self.do_view_change(outbox);
+        }
+    }
+
+    pub fn handle_do_view_change<O>(
+        &mut self,
+        message: DoViewChange<S::Request, S::Prediction>,
+        outbox: &mut O,
+    ) where
+        O: Outbox<S>,
+    {
+        if self.configuration.is_observer(message.index) {
+            self.discard(DropReason::NonVoting);
+            return;
+        }
+
+        if self.is_observer() {
+            return;
+        }
+
+        if self.need_view_change(message.view) {
+            self.start_view_change(message.view, outbox);
+        }
+
+        if self.should_ignore_view_change(message.view) {
+            return;
+        }
+
+        // Replicas only send a do-view-change after starting the view change, so the message also stands in for
+        // a start-view-change that was lost (e.g. while the new primary was partitioned).
+        if !self.should_do_view_change() {
+            self.start_view_changes
+                .insert(message.view, message.index, ());
+
+            if self.should_do_view_change() {
+                self.do_view_change(outbox);
+            }
+        }
+
+        self.do_view_changes
+            .insert(message.view, message.index, message);
+
+        if self.do_view_changes.contains(self.index) && self.do_view_changes.is_reached() {
+            let committed = self
+                .do_view_changes
+                .values()
+                .map(|v| v.committed)
+                .max()
+                .unwrap_or(self.committed);
+            let best = self
+                .do_view_changes
+                .values()
+                .max_by(|x, y| x.log.cmp(&y.log))
+                .filter(|v| v.log > self.log);
+
+            match best {
+                None => self.finish_view_change(committed, outbox),
+                Some(do_view_change) => {
+                    // Only request the entries after the prefix shared with the most up-to-date log.
+                    let index = do_view_change.index;
+                    let op_number = self.log.common_prefix(&do_view_change.log);
+
+                    self.missing_log = Some((index, committed));
+
+                    outbox.get_log(
+                        index,
+                        GetLog {
+                            view: self.view,
+                            epoch: self.configuration.epoch(),
+                            op_number,
+                            index: self.index,
+                        },
+                    );
+                }
+            }
+        }
+    }
+
+    pub fn handle_get_log<O>(&mut self, message: GetLog, outbox: &mut O)
+    where
+        O: Outbox<S>,
+    {
+        if self.should_ignore_view_change(message.view) {
+            return;
+        }
+
+        let Some(next) = message.op_number.checked_next() else {
+            self.discard(DropReason::Inconsistent);
+            return;
+        };
+
+        let log = if next < self.log.first_op_number() {
+            self.log.clone()
+        } else {
+            self.log.after(message.op_number)
+        };
+
+        outbox.new_log(
+            message.index,
+            NewLog {
+                view: self.view,
+                epoch: self.configuration.epoch(),
+                log,
+                index: self.index,
+            },
+        );
+    }
+
+    pub fn handle_new_log<O>(&mut self, message: NewLog<S::Request, S::Prediction>, outbox: &mut O)
+    where
+        O: Outbox<S>,
+    {
+        if self.should_ignore_view_change(message.view) {
+            return;
+        }
+
+        let Some((index, committed)) = self.missing_log else {
+            self.discard(DropReason::Duplicate);
+            return;
+        };
+
+        if index != message.index {
+            self.discard(DropReason::Inconsistent);
+            return;
+        }
+
+        // Committed operations are never discarded, so a log that ends before them is malformed.
+        if message.log.last_op_number() < self.committed {
+            self.discard(DropReason::Inconsistent);
+            return;
+        }
+
+        // A log without entries has no operations to replace ours with, so it may only follow it.
+        if !message.log.is_empty() && message.log.first_op_number() <= self.log.first_op_number() {
+            self.log = message.log;
+        } else if !self.log.splice(message.log) {
+            self.discard(DropReason::Inconsistent);
+            return;
+        }
+
+        self.finish_view_change(committed, outbox);
+    }
+
+    pub fn handle_start_view<O>(
+        &mut self,
+        message: StartView<S::Request, S::Prediction>,
+        outbox: &mut O,
+    ) where
+        O: Outbox<S>,
+    {
+        if message.view < self.view {
+            self.discard(DropReason::StaleView);
+            return;
+        }
+
+        if message.view == self.view && self.status == Status::Normal {
+            self.discard(DropReason::Duplicate);
+            return;
+        }
+
+        // The log of a new view always holds every committed operation, so a shorter log is malformed.
+        if message.log.last_op_number() < self.committed {
+            self.discard(DropReason::Inconsistent);
+            return;
+        }
+
+        self.view = message.view;
+        self.log = message.log;
+
+        self.set_status(Status::Normal);
+        self.commit_operations(message.committed, outbox);
+        self.prepare_pending(outbox);
+    }
+
+    fn finish_view_change<O>(&mut self, committed: OpNumber, outbox: &mut O)
+    where
+        O: Outbox<S>,
+    {
+        self.set_status(Status::Normal);
+
+        outbox.start_view(StartView {
+            view: self.view,
+            epoch: self.configuration.epoch(),
+            log: self.log.clone(),
+            committed,
+        });
+
+        self.commit_operations(committed, outbox);
+        self.prepare_pending(outbox);
+    }
+
+    /// Starts a view change to the view after the current one, counting it as an attempt of this replica.
+    /// The current view may come from a message, so a replica whose view has no successor stays in it.
+    fn start_next_view_change<O>(&mut self, outbox: &mut O)
+    where
+        O: Outbox<S>,
+    {
+        if let Some(view) = self.view.checked_next() {
+            self.view_change_attempts = self.view_change_attempts.saturating_add(1);
+            self.start_view_change(view, outbox);
+        }
+    }
+
+    fn start_view_change<O>(&mut self, view: View, outbox: &mut O)
+    where
+        O: Outbox<S>,
+    {
+        self.view = view;
+
+        self.set_status(Status::ViewChange);
+
+        outbox.start_view_change(StartViewChange {
+            view: self.view,
+            epoch: self.configuration.epoch(),
+            index: self.index,
+        });
+    }
+
+    fn do_view_change<O>(&self, outbox: &mut O)
+    where
+        O: Outbox<S>,
+    {
+        outbox.do_view_change(
+            self.configuration % self.view,
+            DoViewChange {
+                view: self.view,
+                epoch: self.configuration.epoch(),
+                log: self.log.summary(),
+                committed: self.committed,
+                index: self.index,
+            },
+        )
+    }
+
+    fn recovery(&self) -> Recovery {
+        Recovery {
+            index: self.index,
+            epoch: self.configuration.epoch(),
+            committed: self.committed,
+            nonce: self.nonce,
+            digest: (!self.log.is_empty()).then(|| self.log.digest()),
+            generation: self.generation,
+        }
+    }
+
+    fn state_transfer<O>(&mut self, view: View, outbox: &mut O)
+    where
+        O: Outbox<S>,
+    {
+        if self.view < view {
+            self.log.truncate(self.committed);
+        }
+
+        let op_number = self.log.last_op_number();
+
+        // Messages claiming different newer views would otherwise have replicas ask each other for state in turns.
+        if let Some((requested, requested_op_number)) = self.requested_state {
+            if requested >= view && requested_op_number == op_number {
+                return;
+            }
+        }
+
+        let Some(replica) = self.transfer_targets.select(self.configuration % view) else {
+            return;
+        };
+
+        self.requested_state = Some((view, op_number));
+
+        // Replicas only answer requests for their own view, which is the newer view the replica learned about.
+        outbox.get_state(
+            replica,
+            GetState {
+                view,
+                epoch: self.configuration.epoch(),
+                op_number,
+                index: self.index,
+            },
+        );
+    }
+
+    fn commit_operations<O>(&mut self, committed: OpNumber, outbox: &mut O)
+    where
+        O: Outbox<S>,
+    {
+        // Only operations in the log can be committed, even when a message claims otherwise.
+        if !self.log.contains(&self.committed.next()) {
+            return;
+        }
+
+        let committed = committed.min(self.log.last_op_number());
+
+        while self.committed < committed {
+            let op_number = self.committed.next();
+
+            match self.speculation.as_mut().and_then(|s| s.take(op_number)) {
+                Some(reply) => self.finish_operation(reply, outbox),
+                None => break,
+            }
+        }
+
+        match self.executor {
+            Some((executor, workers)) if self.committed < committed => {
+                let mut current = self.committed;
+                let mut entries = Vec::new();
+
+                while current < committed {
+                    current.increment();
+                    entries.push(&self.log[current]);
+                }
+
+                let replies = executor(&self.service, &entries, workers);
+
+                for reply in replies {
+                    self.finish_operation(reply, outbox);
+                }
+            }
+            _ => {
+                while self.committed < committed {
+                    let op_number = self.committed.next();
+                    let entry = &self.log[op_number];
+                    let view = self.log.view_at(op_number).unwrap_or(self.view);
+                    let context = Context::new(view, op_number, entry.timestamp());
+                    let reply = self.service.invoke_with_context(
+                        &entry.request().payload,
+                        entry.prediction(),
+                        &context,
+                    );
+
+                    self.finish_operation(reply, outbox);
+                }
+            }
+        }
+
+        // Counts are only kept for operations that are not committed yet, whichever message committed them.
+        let committed = self.committed;
+
+        self.prepared.retain(|&o, _| o > committed);
+    }
+
+    /// Records the reply to the operation after the committed one and advances the commit number.
+    fn finish_operation<O>(&mut self, payload: S::Reply, outbox: &mut O)
+    where
+        O: Outbox<S>,
+    {
+        self.committed.increment();
+
+        let request = self.log[self.committed].request();
+        let client = request.client;
+        let reply = Reply {
+            view: self.view,
+            id: request.id,
+            payload,
+        };
+
+        if self.is_primary() {
+            outbox.reply(client, &reply);
+            self.undelivered.push(client, reply.clone());
+        }
+
+        for (client, payload) in self.service.take_notifications() {
+            if self.is_primary() {
+                let notification = Notification {
+                    view: self.view,
+                    op_number: self.committed,
+                    payload,
+                };
+
+                outbox.notify(client, &notification);
+            }
+        }
+
+        let primary = self.is_primary();
+
+        for listener in self.listeners.iter_mut() {
+            listener.on_commit(self.committed);
+
+            if primary {
+                listener.on_client_reply(request.client, request.id);
+            }
+        }
+
+        self.client_table.finish(request, reply);
+    }
+
+    fn prepare_pending<O>(&mut self, outbox: &mut O)
+    where
+        O: Outbox<S>,
+    {
+        let mut current = self.committed.next();
+
+        while self.log.contains(&current) {
+            let entry = &self.log[current];
+            let request = entry.request();
+
+            self.client_table.start(request);
+
+            if self.is_primary() {
+                outbox.prepare(Prepare {
+                    view: self.view,
+                    epoch: self.configuration.epoch(),
+                    op_number: current,
+                    request: entry.request().clone(),
+                    prediction: entry.prediction().clone(),
+                    committed: self.committed,
+                    previous: self.log.view_at(current.previous()),
+                    timestamp: entry.timestamp(),
+                });
+
+                self.speculate(current);
+            } else if !self.is_observer() {
+                outbox.prepare_ok(
+                    self.configuration % self.view,
+                    PrepareOk {
+                        view: self.view,
+                        epoch: self.configuration.epoch(),
+                        op_number: current,
+                        index: self.index,
+                        committed: self.committed,
+                        promised: self.view,
+                    },
+                );
+            }
+
+            current.increment();
+        }
+    }
+
+    /// Applies the operation speculatively when it directly follows the committed and speculative operations.
+    fn speculate(&mut self, op_number: OpNumber) {
+        if self.status != Status::Normal || !self.is_primary() {
+            return;
+        }
+
+        if let Some(speculation) = self.speculation.as_mut() {
+            if speculation.last().unwrap_or(self.committed).next() == op_number {
+                speculation.invoke(&mut self.service, op_number, &self.log[op_number]);
+            }
+        }
+    }
+
+    fn rollback_speculation(&mut self) {
+        if let Some(speculation) = self.speculation.as_mut() {
+            speculation.rollback(&mut self.service);
+        }
+    }
+
+    fn set_status(&mut self, status: Status) {
+        let previous = core::mem::replace(&mut self.status, status);
+
+        self.rollback_speculation();
+
+        self.prepared = Default::default();
+        self.backup_commits = Default::default();
+        self.acknowledged = Default::default();
+        self.undelivered.clear();
+        self.heartbeat_interval = self
+            .configuration
+            .heartbeat()
+            .map(|(min, _)| min)
+            .unwrap_or_default();
+        self.last_heartbeat = None;
+        self.missing_log = None;
+        self.lease_acknowledgements = Default::default();
+        self.lease_misses = 0;
+        self.fenced = false;
+        self.view_change_idles = 0;
+        self.recovery_attempts = 0;
+
+        if status == Status::Normal {
+            self.view_change_attempts = 0;
+        }
+
+        // Votes only count towards the quorum of the protocol that collected them.
+        self.recovery_responses = QuorumTracker::new(self.view, self.configuration.quorum());
+        self.start_view_changes = QuorumTracker::new(self.view, self.configuration.sub_majority());
+        self.do_view_changes = QuorumTracker::new(self.view, self.configuration.quorum());
+
+        for listener in self.listeners.iter_mut() {
+            match (previous, self.status) {
+                (_, Status::ViewChange) => listener.on_view_change_start(self.view),
+                (Status::Recovering, Status::Normal) => listener.on_recovery(self.view),
+                (_, Status::Normal) => listener.on_view_change_finish(self.view),
+                (_, Status::Recovering) => {}
+            }
+        }
+
+        if self.status == Status::Normal {
+            self.notify_role();
+        }
+    }
+
+    /// Tells the service its role if the role or view changed since the service was last told.
+    fn notify_role(&mut self) {
+        let role = if self.is_primary() {
+            Role::Primary
+        } else {
+            Role::Backup
+        };
+
+        if self.role != Some((role, self.view)) {
+            self.role = Some((role, self.view));
+            self.service.on_role_change(role, self.view);
+        }
+    }
+
+    /// Whether the replica is processing requests (i.e. it is neither in a view change nor recovering).
+    pub fn is_normal(&self) -> bool {
+        self.status == Status::Normal
+    }
+
+    /// Whether the replica is still restoring its state from the group after a crash.
+    pub fn is_recovering(&self) -> bool {
+        self.status == Status::Recovering
+    }
+
+    /// Changes whenever a protocol message shows the replica that the group is making progress, i.e. it heard from
+    /// the primary of its view or moved to another view or status. Drivers restart the idle timer of the replica
+    /// only when this changes, so that messages from other replicas (e.g. a stale replica retrying an old view change
+    /// or a recovery) cannot keep a backup from replacing a failed primary.
+    pub fn liveness(&self) -> u64 {
+        self.liveness
+    }
+
+    /// The op-number of the latest operation applied to the service.
+    pub fn committed(&self) -> OpNumber {
+        self.committed
+    }
+
+    pub fn log(&self) -> &Log<S::Request, S::Prediction> {
+        &self.log
+    }
+
+    /// Panics when the state of the replica is inconsistent (e.g. the commit number is past the end of the log).
+    /// Only checked when debug assertions are enabled, so drivers can call it after every message.
+    pub fn check_invariants(&self) {
+        if !cfg!(debug_assertions) {
+            return;
+        }
+
+        assert!(
+            self.log.is_consistent(),
+            "replica {} has a log whose range does not match its entries",
+            self.index
+        );
+
+        // A recovering replica only knows the state of its checkpoint.
+        if self.status == Status::Recovering {
+            return;
+        }
+
+        assert!(
+            self.committed <= self.log.last_op_number(),
+            "replica {} committed {:?} past the end of its log at {:?}",
+            self.index,
+            self.committed,
+            self.log.last_op_number()
+        );
+        assert!(
+            self.log.is_empty() || self.log.first_op_number() <= self.committed.next(),
+            "replica {} is missing the operations between {:?} and {:?}",
+            self.index,
+            self.committed,
+            self.log.first_op_number()
+        );
+        assert!(
+            self.prepared
+                .keys()
+                .all(|op_number| *op_number > self.committed),
+            "replica {} is counting prepare-ok messages for committed operations",
+            self.index
+        );
+        assert!(
+            self.missing_log.is_none() || self.status == Status::ViewChange,
+            "replica {} is waiting for a log outside of a view change",
+            self.index
+        );
+
+        if self.status == Status::Normal {
+            let mut current = self.committed.next();
+
+            while self.log.contains(&current) {
+                let request = self.log[current].request();
+
+                assert!(
+                    self.client_table.compare(request) != Ok(Ordering::Greater),
+                    "replica {} did not start the request at {:?} in its client table",
+                    self.index,
+                    current
+                );
+
+                current.increment();
+            }
+        }
+    }
+
+    pub fn is_primary(&self) -> bool {
+        (self.configuration % self.view) == self.index
+    }
+
+    pub fn is_backup(&self) -> bool {
+        !self.is_primary()
+    }
+
+    /// Whether the replica follows the group without voting. See [`Configuration::with_observers`].
+    pub fn is_observer(&self) -> bool {
+        self.configuration.is_observer(self.index)
+    }
+
+    /// Records the commit number the primary sent at the given time, for bounding the staleness of follower reads.
+    fn advertise(&mut self, committed: OpNumber, timestamp: Duration) {
+        self.advertised = self.advertised.max((committed, timestamp));
+    }
+
+    /// The number of operations committed by the primary that the backup at the given index is not known to have committed.
+    /// Only the primary tracks the lag of backups, based on the commit number included in their prepare-ok messages.
+    pub fn lag(&self, index: usize) -> Option<usize> {
+        if self.is_backup() || self.status != Status::Normal || index == self.index {
+            return None;
+        }
+
+        let committed = self.backup_commits.get(&index)?;
+
+        Some(self.committed - (*committed).min(self.committed))
+    }
+
+    /// Tells the primary that a failure detector suspects the replica at the given index (e.g. it went quiet),
+    /// so an idle primary sends heartbeats at the minimum interval until the replica acknowledges it again.
+    /// Only affects primaries with a heartbeat configured.
+    pub fn suspect(&mut self, index: usize) {
+        let Some((min, _)) = self.configuration.heartbeat() else {
+            return;
+        };
+
+        if self.is_primary() && index != self.index {
+            self.acknowledged.remove(&index);
+            self.heartbeat_interval = min;
+        }
+    }
+
+    /// Whether an idle primary with nothing to prepare should re-send its commit number at the given time.
+    /// Always true without a heartbeat. Otherwise, the interval drops to its minimum while a backup has not acknowledged
+    /// every operation, and doubles up to its maximum on every heartbeat sent while all of them have.
+    fn is_heartbeat_due(&mut self, now: Duration) -> bool {
+        let Some((min, max)) = self.configuration.heartbeat() else {
+            return true;
+        };
+
+        let last = self.log.last_op_number();
+        let caught_up = (0..self.configuration.replicas())
+            .filter(|&index| index != self.index)
+            .all(|index| {
+                self.acknowledged
+                    .get(&index)
+                    .is_some_and(|&op_number| op_number >= last)
+            });
+
+        if !caught_up {
+            self.heartbeat_interval = min;
+        }
+
+        let due = self
+            .last_heartbeat
+            .is_none_or(|sent| now.saturating_sub(sent) >= self.heartbeat_interval);
+
+        if due {
+            self.last_heartbeat = Some(now);
+
+            if caught_up {
+                self.heartbeat_interval = self.heartbeat_interval.saturating_mul(2).clamp(min, max);
+            }
+        }
+
+        due
+    }
+
+    fn is_busy<O>(&self, outbox: &O) -> bool
+    where
+        O: Outbox<S>,
+    {
+        self.shutting_down || self.load(outbox) > 0
+    }
+
+    /// How long clients should wait before retrying a request the primary is too busy to start.
+    /// Grows by a commit interval for each multiple of a limit the primary is at, so clients back off further
+    /// the more work is queued ahead of them. A primary that is shutting down asks clients to wait out a view change.
+    fn backoff<O>(&self, outbox: &O) -> Duration
+    where
+        O: Outbox<S>,
+    {
+        if self.shutting_down {
+            return self.configuration.view_timeout();
+        }
+
+        let load = u32::try_from(self.load(outbox)).unwrap_or(u32::MAX);
+
+        self.configuration.commit_interval().saturating_mul(load)
+    }
+
+    /// The number of times the primary is over the tightest of its in-flight and queue limits, rounded down.
+    fn load<O>(&self, outbox: &O) -> usize
+    where
+        O: Outbox<S>,
+    {
+        let in_flight = self
+            .log
+            .last_op_number()
+            .checked_sub(self.committed)
+            .unwrap_or_default();
+
+        [
+            (in_flight, self.configuration.in_flight_limit()),
+            (outbox.queued(), self.configuration.queue_limit()),
+        ]
+        .into_iter()
+        .filter_map(|(depth, limit)| Some(depth / limit?.max(1)))
+        .max()
+        .unwrap_or_default()
+    }
+
+    /// The earliest view led by the backup that has committed the most operations, preferring lower indices on ties.
+    /// Falls back to the next view when no backup has reported its commit number yet.
+    /// Returns `None` when the views run out before reaching one led by the backup.
+    fn handoff_view(&self) -> Option<View> {
+        let mut view = self.view.checked_next()?;
+        let successor = self
+            .backup_commits
+            .iter()
+            .filter(|(&index, _)| index != self.index && index < self.configuration.replicas())
+            .max_by_key(|(&index, &committed)| (committed, Reverse(index)))
+            .map(|(&index, _)| index);
+
+        if let Some(successor) = successor {
+            while self.configuration % view != successor {
+                view = view.checked_next()?;
+            }
+        }
+
+        Some(view)
+    }
+
+    fn should_ignore_normal(&mut self, view: View) -> bool {
+        self.should_ignore(view, Status::Normal)
+    }
+
+    /// State transfers may come from a newer view, since they are how a lagging replica learns about it.
+    fn should_ignore_state(&mut self, view: View) -> bool {
+        self.should_ignore(view.min(self.view), Status::Normal)
+    }
+
+    fn need_state_transfer(&self, view: View) -> bool {
+        self.status == Status::Normal && view > self.view
+    }
+
+    fn should_ignore_view_change(&mut self, view: View) -> bool {
+        self.should_ignore(view, Status::ViewChange)
+    }
+
+    /// Whether a message for the given view is dropped by a replica expecting it in the given status.
+    /// Listeners are told why the message was dropped.
+    fn should_ignore(&mut self, view: View, status: Status) -> bool {
+        let reason = if view < self.view {
+            DropReason::StaleView
+        } else if view > self.view || self.status != status {
+            DropReason::UnexpectedStatus
+        } else {
+            return false;
+        };
+
+        self.discard(reason);
+
+        true
+    }
+
+    fn discard(&mut self, reason: DropReason) {
+        for listener in self.listeners.iter_mut() {
+            listener.on_drop(reason);
+        }
+    }
+
+    fn need_view_change(&self, view: View) -> bool {
+        self.status != Status::Recovering && view > self.view
+    }
+
+    fn should_do_view_change(&self) -> bool {
+        self.start_view_changes.is_reached()
+    }
+
+    /// Whether the replica spent longer than the view change timeout in the current view change,
+    /// counting each idle period as the shortest idle timeout of its role.
+    fn is_view_change_overdue(&self) -> bool {
+        let Some(timeout) = self.configuration.view_change_timeout() else {
+            return false;
+        };
+        let period = match self.is_primary() {
+            true => self.configuration.commit_interval(),
+            false => self.configuration.view_timeout(),
+        };
+
+        period.saturating_mul(self.view_change_idles) >= timeout
+    }
+}
+
+#[cfg(all(test, feature = "os"))]
+mod tests {
+    use super::*;
+    use crate::buffer::BufferedMailbox;
+    use crate::mail::Inbox;
+    use crate::service::Protocol;
+    use crate::transfer::TransferPolicy;
+
+    #[test]
+    fn sender_behind_prepare() {
+        let configuration = Configuration::from(3);
+        let mut replica = Replica::new(configuration, 0, 0);
+        let mut mailbox = BufferedMailbox::default();
+
+        replica.view.increment();
+        replica.view.increment();
+
+        let message = Prepare {
+            view: View::default().next(),
+            epoch: 0,
+            op_number: OpNumber::default().next(),
+            request: Request {
+                payload: 2,
+                client: Default::default(),
+                id: Default::default(),
+                deadline: None,
+                signature: None,
+                epoch: 0,
+                staleness: None,
+            },
+            prediction: (),
+            committed: OpNumber::default(),
+            previous: None,
+            timestamp: Duration::ZERO,
+        };
+
+        replica.handle_prepare(message, &mut mailbox);
+
+        assert_eq!(Vec::from_iter(mailbox.drain_inbound()), vec![]);
+        assert!(mailbox.is_empty());
+    }
+
+    #[test]
+    fn sender_ahead_prepare() {
+        let configuration = Configuration::from(3);
+        let mut replica = Replica::new(configuration, 1, 0);
+        let mut mailbox = BufferedMailbox::default();
+
+        let message = Prepare {
+            view: View::default().next(),
+            epoch: 0,
+            op_number: OpNumber::default().next(),
+            request: Request {
+                payload: 2,
+                client: Default::default(),
+                id: Default::default(),
+                deadline: None,
+                signature: None,
+                epoch: 0,
+                staleness: None,
+            },
+            prediction: (),
+            committed: OpNumber::default(),
+            previous: None,
+            timestamp: Duration::ZERO,
+        };
+
+        replica.handle_prepare(message.clone(), &mut mailbox);
+
+        assert_eq!(
+            mailbox.pop_inbound().map(ProtocolPayload::unwrap_prepare),
+            Some(message)
+        );
+
+        let mut messages = Vec::from_iter(mailbox.drain_send());
+        let outbound = GetState {
+            view: View::default().next(),
+            epoch: 0,
+            op_number: replica.log.last_op_number(),
+            index: replica.index,
+        };
+        let envelope = messages.pop().unwrap();
+
+        assert_ne!(envelope.destination, replica.index);
+        assert_eq!(envelope.payload.unwrap_get_state(), outbound);
+        assert!(messages.is_empty());
+        assert!(mailbox.is_empty());
+    }
+
+    #[test]
+    fn divergent_prepare() {
+        let configuration = Configuration::from(3);
+        let mut replica = Replica::new(configuration, 1, 0);
+        let mut mailbox = BufferedMailbox::default();
+        let request = Request {
+            payload: 2,
+            client: Default::default(),
+            id: Default::default(),
+            deadline: None,
+            signature: None,
+            epoch: 0,
+            staleness: None,
+        };
+
+        replica.log.push(View::default(), request.clone(), ());
+        replica.client_table.start(&request);
+        replica.view.increment();
+        replica.view.increment();
+
+        let message = Prepare {
+            view: replica.view,
+            epoch: 0,
+            op_number: OpNumber::default().next().next(),
+            request,
+            prediction: (),
+            committed: OpNumber::default(),
+            previous: Some(View::default().next()),
+            timestamp: Duration::ZERO,
+        };
+
+        replica.handle_prepare(message.clone(), &mut mailbox);
+
+        assert!(replica.log.is_empty());
+        assert_eq!(
+            mailbox.pop_inbound().map(ProtocolPayload::unwrap_prepare),
+            Some(message)
+        );
+
+        let mut messages = Vec::from_iter(mailbox.drain_send());
+        let envelope = messages.pop().unwrap();
+
+        assert_eq!(
+            envelope.payload.unwrap_get_state(),
+            GetState {
+                view: replica.view,
+                epoch: 0,
+                op_number: OpNumber::default(),
+                index: replica.index,
+            }
+        );
+        assert!(messages.is_empty());
+        assert!(mailbox.is_empty());
+    }
+
+    #[test]
+    fn cumulative_prepare_ok() {
+        let configuration = Configuration::from(5);
+        let mut primary = Replica::new(configuration, 0, 0);
+        let mut mailbox = BufferedMailbox::default();
+        let mut client = crate::Client::new(configuration);
+
+        for payload in 1..=3 {
+            primary
+                .log
+                .push(primary.view, client.new_request(payload), ());
+        }
+
+        let prepare_ok = |index, op_number| PrepareOk {
+            view: View::default(),
+            epoch: 0,
+            op_number: OpNumber::from(op_number),
+            index,
+            committed: OpNumber::default(),
+            promised: View::default(),
+        };
+
+        primary.handle_prepare_ok(prepare_ok(1, 2), &mut mailbox);
+        primary.handle_prepare_ok(prepare_ok(2, 1), &mut mailbox);
+
+        // The first backup prepared the first operation before preparing the second one.
+        assert_eq!(primary.committed, OpNumber::from(1));
+        assert_eq!(
+            Vec::from_iter(primary.prepared.keys().copied()),
+            vec![OpNumber::from(2)]
+        );
+
+        primary.handle_prepare_ok(prepare_ok(3, 3), &mut mailbox);
+
+        assert_eq!(primary.committed, OpNumber::from(2));
+
+        primary.handle_prepare_ok(prepare_ok(1, 3), &mut mailbox);
+
+        assert_eq!(primary.committed, OpNumber::from(3));
+        assert!(primary.prepared.is_empty());
+    }
+
+    #[test]
+    fn sender_behind_prepare_ok() {
+        let configuration = Configuration::from(3);
+        let mut replica = Replica::new(configuration, 2, 0);
+        let mut mailbox = BufferedMailbox::default();
+
+        replica.view.increment();
+        replica.view.increment();
+
+        let message = PrepareOk {
+            view: View::default().next(),
+            epoch: 0,
+            op_number: OpNumber::default().next(),
+            index: 0,
+            committed: OpNumber::default(),
+            promised: View::default().next(),
+        };
+
+        replica.handle_prepare_ok(message, &mut mailbox);
+
+        assert_eq!(Vec::from_iter(mailbox.drain_inbound()), vec![]);
+        assert!(mailbox.is_empty());
+    }
+
+    #[test]
+    fn sender_ahead_prepare_ok() {
+        let configuration = Configuration::from(3);
+        let mut replica = Replica::new(configuration, 1, 0);
+        let mut mailbox = BufferedMailbox::default();
+
+        let message = PrepareOk {
+            view: View::default().next(),
+            epoch: 0,
+            op_number: OpNumber::default().next(),
+            index: 0,
+            committed: OpNumber::default(),
+            promised: View::default().next(),
+        };
+
+        replica.handle_prepare_ok(message.clone(), &mut mailbox);
+
+        assert_eq!(
+            mailbox
+                .pop_inbound()
+                .map(ProtocolPayload::unwrap_prepare_ok),
+            Some(message)
+        );
+
+        let mut messages = Vec::from_iter(mailbox.drain_send());
+        let outbound = GetState {
+            view: View::default().next(),
+            epoch: 0,
+            op_number: replica.log.last_op_number(),
+            index: replica.index,
+        };
+        let envelope = messages.pop().unwrap();
+
+        assert_ne!(envelope.destination, replica.index);
+        assert_eq!(envelope.payload.unwrap_get_state(), outbound);
+        assert!(messages.is_empty());
+        assert!(mailbox.is_empty());
+    }
+
+    #[test]
+    fn sender_behind_commit() {
+        let configuration = Configuration::from(3);
+        let mut replica = Replica::new(configuration, 0, 0);
+        let mut mailbox = BufferedMailbox::default();
+
+        replica.view.increment();
+        replica.view.increment();
+
+        let message = Commit {
+            view: View::default().next(),
+            epoch: 0,
+            committed: OpNumber::default().next(),
+            timestamp: Default::default(),
+        };
+
+        replica.handle_commit(message, &mut mailbox);
+
+        assert_eq!(Vec::from_iter(mailbox.drain_inbound()), vec![]);
+        assert!(mailbox.is_empty());
+    }
+
+    #[test]
+    fn sender_ahead_commit() {
+        let configuration = Configuration::from(3);
+        let mut replica = Replica::new(configuration, 0, 0);
+        let mut mailbox = BufferedMailbox::default();
+
+        let message = Commit {
+            view: View::default().next(),
+            epoch: 0,
+            committed: OpNumber::default().next(),
+            timestamp: Default::default(),
+        };
+
+        replica.handle_commit(message.clone(), &mut mailbox);
+
+        assert_eq!(
+            mailbox.pop_inbound().map(ProtocolPayload::unwrap_commit),
+            Some(message)
+        );
+
+        let mut messages = Vec::from_iter(mailbox.drain_send());
+        let outbound = GetState {
+            view: View::default().next(),
+            epoch: 0,
+            op_number: replica.log.last_op_number(),
+            index: replica.index,
+        };
+        let envelope = messages.pop().unwrap();
+
+        assert_ne!(envelope.destination, replica.index);
+        assert_eq!(envelope.payload.unwrap_get_state(), outbound);
+        assert!(messages.is_empty());
+        assert!(mailbox.is_empty());
+    }
+
+    #[test]
+    fn sender_behind_get_state() {
+        let configuration = Configuration::from(3);
+        let mut replica = Replica::new(configuration, 0, 0);
+        let mut mailbox = BufferedMailbox::default();
+
+        replica.view.increment();
+        replica.view.increment();
+
+        let message = GetState {
+            view: View::default().next(),
+            epoch: 0,
+            op_number: OpNumber::default(),
+            index: 1,
+        };
+
+        replica.handle_get_state(message, &mut mailbox);
+
+        assert_eq!(Vec::from_iter(mailbox.drain_inbound()), vec![]);
+        assert!(mailbox.is_empty());
+    }
+
+    #[test]
+    fn sender_ahead_get_state() {
+        let configuration = Configuration::from(3);
+        let mut replica = Replica::new(configuration, 0, 0);
+        let mut mailbox = BufferedMailbox::default();
+
+        let message = GetState {
+            view: View::default().next(),
+            epoch: 0,
+            op_number: OpNumber::default().next(),
+            index: 1,
+        };
+
+        replica.handle_get_state(message.clone(), &mut mailbox);
+
+        assert_eq!(
+            mailbox.pop_inbound().map(ProtocolPayload::unwrap_get_state),
+            Some(message)
+        );
+
+        let mut messages = Vec::from_iter(mailbox.drain_send());
+        let outbound = GetState {
+            view: View::default().next(),
+            epoch: 0,
+            op_number: replica.log.last_op_number(),
+            index: replica.index,
+        };
+        let envelope = messages.pop().unwrap();
+
+        assert_ne!(envelope.destination, replica.index);
+        assert_eq!(envelope.payload.unwrap_get_state(), outbound);
+        assert!(messages.is_empty());
+        assert!(mailbox.is_empty());
+    }
+
+    #[test]
+    fn busy_at_in_flight_limit() {
+        let configuration = Configuration::from(3).with_in_flight_limit(1);
+        let mut replica = Replica::new(configuration, 0, 0);
+        let mut mailbox = BufferedMailbox::default();
+        let mut client = crate::Client::new(configuration);
+        let mut other = crate::Client::new(configuration);
+
+        replica.handle_request(client.new_request(1), &mut mailbox);
+
+        let request = other.new_request(2);
+
+        replica.handle_request(request.clone(), &mut mailbox);
+
+        let mut replies = Vec::from_iter(mailbox.drain_replies());
+        let envelope = replies.pop().unwrap();
+
+        assert_eq!(envelope.destination, request.client);
+        assert_eq!(
+            envelope.payload.unwrap_busy(),
+            Busy {
+                view: replica.view,
+                id: request.id,
+                backoff: configuration.commit_interval(),
+            }
+        );
+        assert!(replies.is_empty());
+        assert_eq!(mailbox.drain_broadcast().count(), 1);
+        assert!(mailbox.is_empty());
+    }
+
+    #[test]
+    fn busy_at_queue_limit() {
+        let configuration = Configuration::from(3).with_queue_limit(1);
+        let mut replica = Replica::new(configuration, 0, 0);
+        let mut mailbox = BufferedMailbox::default();
+        let mut client = crate::Client::new(configuration);
+
+        for _ in 0..2 {
+            mailbox.push_commit(Commit {
+                view: replica.view,
+                epoch: 0,
+                committed: OpNumber::default(),
+                timestamp: Default::default(),
+            });
+        }
+
+        let request = client.new_request(1);
+
+        replica.handle_request(request.clone(), &mut mailbox);
+
+        assert_eq!(
+            mailbox
+                .drain_replies()
+                .last()
+                .unwrap()
+                .payload
+                .unwrap_busy(),
+            Busy {
+                view: replica.view,
+                id: request.id,
+                backoff: configuration.commit_interval() * 2,
+            }
+        );
+        assert!(replica.log.is_empty());
+    }
+
+    #[test]
+    fn lease_fencing() {
+        let configuration = Configuration::from(3).with_lease(Duration::from_millis(100));
+        let mut primary = Replica::new(configuration, 0, 0);
+        let mut backup = Replica::new(configuration, 1, 0);
+        let mut mailbox = BufferedMailbox::default();
+        let mut client = crate::Client::new(configuration);
+
+        primary.idle(&mut mailbox);
+        primary.idle(&mut mailbox);
+        primary.handle_request(client.new_request(1), &mut mailbox);
+
+        assert!(primary.log.is_empty());
+
+        let commit = mailbox.take_broadcast::<Commit>().unwrap();
+
+        backup.handle_commit(commit, &mut mailbox);
+
+        let acknowledgement = mailbox.take_send::<PrepareOk>().unwrap();
+
+        assert_eq!(acknowledgement.destination, 0);
+
+        primary.handle_prepare_ok(acknowledgement.payload, &mut mailbox);
+        primary.idle(&mut mailbox);
+        primary.handle_request(client.new_request(1), &mut mailbox);
+
+        assert_eq!(primary.log.last_op_number(), OpNumber::from(1));
+    }
+
+    #[test]
+    fn lease_with_large_commit_interval() {
+        let configuration = Configuration::from(3)
+            .with_commit_interval(Duration::from_secs(u64::MAX))
+            .with_lease(Duration::MAX);
+        let mut primary = Replica::new(configuration, 0, 0);
+        let mut mailbox = BufferedMailbox::default();
+        let mut client = crate::Client::new(configuration);
+
+        primary.idle(&mut mailbox);
+        primary.handle_request(client.new_request(1), &mut mailbox);
+
+        assert_eq!(primary.log.last_op_number(), OpNumber::from(1));
+
+        // The missed intervals add up to more than a duration can hold, which saturates at the lease.
+        primary.idle(&mut mailbox);
+        primary.handle_request(
+            crate::Client::new(configuration).new_request(2),
+            &mut mailbox,
+        );
+
+        assert_eq!(primary.log.last_op_number(), OpNumber::from(1));
+    }
+
+    #[test]
+    fn promised_view() {
+        let configuration = Configuration::from(3).with_lease(Duration::from_millis(100));
+        let mut primary = Replica::new(configuration, 0, 0);
+        let mut backup = Replica::new(configuration, 1, 0);
+        let mut mailbox = BufferedMailbox::default();
+        let mut client = crate::Client::new(configuration);
+
+        backup.start_view_change(backup.view.next(), &mut mailbox);
+        backup.handle_commit(
+            Commit {
+                view: primary.view,
+                epoch: 0,
+                committed: primary.committed,
+                timestamp: Default::default(),
+            },
+            &mut mailbox,
+        );
+
+        let rejection = mailbox.take_send::<PrepareOk>().unwrap();
+
+        assert_eq!(rejection.destination, 0);
+        assert_eq!(rejection.payload.promised, backup.view);
+
+        primary.handle_prepare_ok(rejection.payload, &mut mailbox);
+        primary.handle_request(client.new_request(1), &mut mailbox);
+
+        assert!(primary.log.is_empty());
+    }
+
+    #[test]
+    fn outdated_view() {
+        let configuration = Configuration::from(3);
+        let mut backup = Replica::new(configuration, 1, 0);
+        let mut mailbox = BufferedMailbox::default();
+        let mut client = crate::Client::new(configuration);
+
+        backup.handle_request(client.new_request(1), &mut mailbox);
+        backup.handle_request(client.new_request(2), &mut mailbox);
+
+        let mut replies = Vec::from_iter(mailbox.drain_replies());
+
+        assert_eq!(replies.len(), 1);
+        assert_eq!(
+            replies.pop().unwrap().payload.unwrap_outdated_view(),
+            OutdatedView {
+                view: backup.view,
+                primary: 0,
+                epoch: 0,
+            }
+        );
+
+        backup.idle(&mut mailbox);
+        mailbox.drain_broadcast().for_each(drop);
+        backup.handle_request(client.new_request(3), &mut mailbox);
+
+        assert_eq!(mailbox.drain_replies().count(), 0);
+    }
+
+    #[test]
+    fn discover() {
+        let configuration = Configuration::from(3).with_epoch(2);
+        let mut backup = Replica::new(configuration, 1, 0);
+        let mut mailbox = BufferedMailbox::default();
+        let seed = crate::Client::new(Configuration::from(1));
+        let members = Members::from(vec![
+            "203.0.113.7:3000".parse().unwrap(),
+            "203.0.113.7:3001".parse().unwrap(),
+            "203.0.113.7:3002".parse().unwrap(),
+        ]);
+
+        backup.advertise_members(&members);
+        backup.handle_discover(seed.discover(), &mut mailbox);
+
+        let envelope = mailbox.drain_replies().next().unwrap();
+        let discovered = envelope.payload.unwrap_discovered();
+        let mut client = crate::Client::new(discovered.configuration());
+
+        assert_eq!(envelope.destination, seed.identifier());
+        assert_eq!(discovered.view, backup.view);
+        assert_eq!(
+            discovered.members,
+            Vec::from_iter(members.iter().map(|(_, address)| address))
+        );
+        assert_eq!(client.configuration().replicas(), 3);
+        assert_eq!(client.configuration().epoch(), 2);
+        assert_eq!(client.configuration().group(), configuration.group());
+
+        backup.handle_request(client.new_request(1), &mut mailbox);
+
+        let outdated = mailbox
+            .drain_replies()
+            .next()
+            .unwrap()
+            .payload
+            .unwrap_outdated_view();
+
+        assert!(!client.is_stale(&outdated));
+    }
+
+    #[test]
+    fn outdated_epoch() {
+        let configuration = Configuration::from(3).with_epoch(1);
+        let mut primary = Replica::new(configuration, 0, 0);
+        let mut mailbox = BufferedMailbox::default();
+        let mut client = crate::Client::new(Configuration::new(3, configuration.group()));
+
+        primary.handle_request(client.new_request(1), &mut mailbox);
+
+        assert!(primary.log.is_empty());
+        assert_eq!(
+            mailbox
+                .drain_replies()
+                .next()
+                .unwrap()
+                .payload
+                .unwrap_outdated_epoch(),
+            OutdatedEpoch {
+                view: primary.view,
+                epoch: 1
+            }
+        );
+
+        let mut retired = Replica::new(Configuration::from(3), 0, 0);
+        let mut client = crate::Client::new(configuration);
+
+        retired.handle_request(client.new_request(1), &mut mailbox);
+
+        assert!(retired.log.is_empty());
+        assert_eq!(mailbox.drain_replies().count(), 0);
+
+        primary.handle_request(client.new_request(2), &mut mailbox);
+
+        assert_eq!(primary.log.len(), 1);
+    }
+
+    #[test]
+    fn follower_reads() {
+        let configuration = Configuration::from(3);
+        let mut primary = Replica::new(configuration, 0, 0);
+        let mut backup = Replica::new(configuration, 1, 0);
+        let mut mailbox = BufferedMailbox::default();
+        let mut client = crate::Client::new(configuration);
+        let read =
+            |client: &mut crate::Client, staleness| client.new_request(0).with_staleness(staleness);
+
+        backup.enable_follower_reads();
+        primary.handle_request(client.new_request(5), &mut mailbox);
+        backup.handle_prepare(
+            mailbox.take_broadcast::<Prepare<_, _>>().unwrap(),
+            &mut mailbox,
+        );
+        backup.handle_commit(
+            Commit {
+                view: backup.view,
+                epoch: 0,
+                committed: OpNumber::from(1),
+                timestamp: Duration::from_secs(10),
+            },
+            &mut mailbox,
+        );
+        mailbox.drain_send().for_each(drop);
+
+        let staleness = Staleness::Time(Duration::from_secs(1));
+
+        backup.handle_request_at(
+            read(&mut client, staleness),
+            Duration::from_millis(10_500),
+            &mut mailbox,
+        );
+
+        assert_eq!(
+            mailbox
+                .drain_replies()
+                .next()
+                .unwrap()
+                .payload
+                .unwrap_reply()
+                .payload,
+            5
+        );
+
+        // Backups only point each client at the primary once between idle periods.
+        let mut other = crate::Client::new(configuration);
+
+        backup.handle_request_at(
+            read(&mut client, staleness),
+            Duration::from_secs(12),
+            &mut mailbox,
+        );
+
+        assert_eq!(
+            mailbox
+                .drain_replies()
+                .next()
+                .unwrap()
+                .payload
+                .unwrap_outdated_view()
+                .primary,
+            0
+        );
+
+        // The backup falls behind the commit number the primary advertised until it transfers the missing state.
+        backup.handle_commit(
+            Commit {
+                view: backup.view,
+                epoch: 0,
+                committed: OpNumber::from(3),
+                timestamp: Duration::from_secs(11),
+            },
+            &mut mailbox,
+        );
+        backup.handle_request_at(
+            read(&mut other, Staleness::Operations(1)),
+            Duration::from_secs(11),
+            &mut mailbox,
+        );
+
+        assert_eq!(
+            mailbox
+                .drain_replies()
+                .next()
+                .unwrap()
+                .payload
+                .unwrap_outdated_view()
+                .primary,
+            0
+        );
+
+        backup.handle_request_at(
+            read(&mut client, Staleness::Operations(2)),
+            Duration::from_secs(11),
+            &mut mailbox,
+        );
+
+        assert_eq!(
+            mailbox
+                .drain_replies()
+                .next()
+                .unwrap()
+                .payload
+                .unwrap_reply()
+                .payload,
+            5
+        );
+
+        // Without follower reads, the backup only points clients at the primary.
+        let mut replica = Replica::new(configuration, 2, 0);
+
+        replica.handle_request_at(
+            read(&mut client, Staleness::Operations(2)),
+            Duration::from_secs(11),
+            &mut mailbox,
+        );
+
+        assert_eq!(
+            mailbox
+                .drain_replies()
+                .next()
+                .unwrap()
+                .payload
+                .unwrap_outdated_view()
+                .primary,
+            0
+        );
+    }
+
+    #[test]
+    fn observer() {
+        let configuration = Configuration::from(3).with_observers(1);
+        let mut primary = Replica::new(configuration, 0, 0);
+        let mut observer = Replica::new(configuration, 3, 0);
+        let mut mailbox = BufferedMailbox::default();
+        let mut client = crate::Client::new(configuration);
+
+        assert!(observer.is_observer());
+        assert!(!primary.is_observer());
+        assert!(configuration.replica(3).unwrap().is_observer());
+
+        primary.handle_request(client.new_request(1), &mut mailbox);
+
+        let prepare = mailbox.take_broadcast::<Prepare<_, _>>().unwrap();
+
+        observer.handle_prepare(prepare, &mut mailbox);
+
+        assert_eq!(observer.log.len(), 1);
+        assert_eq!(mailbox.drain_send().count(), 0);
+
+        // Acknowledgements from observers do not count toward the quorum.
+        primary.handle_prepare_ok(
+            PrepareOk {
+                view: primary.view,
+                epoch: 0,
+                op_number: OpNumber::from(1),
+                index: 3,
+                committed: OpNumber::default(),
+                promised: primary.view,
+            },
+            &mut mailbox,
+        );
+
+        assert_eq!(primary.committed, OpNumber::default());
+
+        observer.handle_commit(
+            Commit {
+                view: primary.view,
+                epoch: 0,
+                committed: OpNumber::from(1),
+                timestamp: Default::default(),
+            },
+            &mut mailbox,
+        );
+
+        assert_eq!(observer.committed, OpNumber::from(1));
+
+        observer.idle(&mut mailbox);
+        observer.handle_start_view_change(
+            StartViewChange {
+                view: observer.view.next(),
+                epoch: 0,
+                index: 1,
+            },
+            &mut mailbox,
+        );
+
+        assert!(observer.status == Status::Normal);
+        assert!(mailbox.is_empty());
+    }
+
+    #[test]
+    fn short_start_view() {
+        let configuration = Configuration::from(3);
+        let mut backup = Replica::new(configuration, 1, 0);
+        let mut mailbox = BufferedMailbox::default();
+        let mut client = crate::Client::new(configuration);
+
+        for payload in 1..=2 {
+            backup
+                .log
+                .push(backup.view, client.new_request(payload), ());
+        }
+
+        backup.commit_operations(backup.log.last_op_number(), &mut mailbox);
+        backup.start_view_change(backup.view.next(), &mut mailbox);
+
+        let mut log = Log::default();
+
+        log.push(backup.view, client.new_request(1), ());
+        backup.handle_start_view(
+            StartView {
+                view: backup.view,
+                epoch: 0,
+                log,
+                committed: OpNumber::default(),
+            },
+            &mut mailbox,
+        );
+
+        assert!(!backup.is_normal());
+        assert_eq!(backup.log.last_op_number(), backup.committed);
+    }
+
+    #[test]
+    fn session_resumption() {
+        let configuration = Configuration::from(3);
+        let mut replica = Replica::new(configuration, 0, 0);
+        let mut mailbox = BufferedMailbox::default();
+        let identifier = crate::ClientIdentifier::from(7);
+        let mut client = crate::Client::new(configuration).with_identifier(identifier);
+        let request = client.new_request(1);
+
+        replica.handle_request(request.clone(), &mut mailbox);
+
+        let mut restarted = crate::Client::new(configuration).with_identifier(identifier);
+
+        replica.handle_open_session(restarted.open_session(), &mut mailbox);
+
+        let envelope = mailbox.drain_replies().last().unwrap();
+
+        assert_eq!(envelope.destination, identifier);
+
+        restarted.resume(&envelope.payload.unwrap_session_accepted());
+
+        assert_eq!(restarted.new_request(1).id, request.id.next());
+
+        let mut backup = Replica::new(configuration, 1, 0);
+
+        backup.handle_open_session(restarted.open_session(), &mut mailbox);
+
+        assert_eq!(mailbox.drain_replies().count(), 0);
+    }
+
+    #[test]
+    fn reply_redelivery() {
+        let configuration = Configuration::from(3);
+        let mut primary = Replica::new(configuration, 0, 0);
+        let mut backup = Replica::new(configuration, 1, 0);
+        let mut mailbox = BufferedMailbox::default();
+        let mut client = crate::Client::new(configuration);
+        let mut commit = |primary: &mut Replica<i32>, backup: &mut Replica<i32>, request| {
+            primary.handle_request(request, &mut mailbox);
+
+            let prepare = mailbox.take_broadcast::<Prepare<i32, ()>>().unwrap();
+
+            backup.handle_payload(ProtocolPayload::Prepare(prepare), &mut mailbox);
+
+            let prepare_ok = mailbox.take_send::<PrepareOk>().unwrap().payload;
+
+            primary.handle_payload(ProtocolPayload::PrepareOk(prepare_ok), &mut mailbox);
+
+            Vec::from_iter(
+                mailbox
+                    .drain_replies()
+                    .map(|envelope| envelope.payload.unwrap_reply()),
+            )
+        };
+
+        let reply = commit(&mut primary, &mut backup, client.new_request(1)).remove(0);
+        let mut outbox = BufferedMailbox::default();
+
+        // Only clients with a session have their replies buffered.
+        primary.handle_open_session(client.open_session(), &mut outbox);
+
+        // The connection drops before the client receives the reply.
+        let lost = commit(&mut primary, &mut backup, client.new_request(2)).remove(0);
+
+        primary.handle_resume(client.resume_session(reply.id), &mut outbox);
+
+        let redelivered = Vec::from_iter(outbox.drain_replies().map(|envelope| envelope.payload));
+
+        assert_eq!(redelivered.len(), 3);
+        assert_eq!(redelivered[2].clone().unwrap_reply(), lost);
+
+        // A new request acknowledges the replies to earlier ones.
+        let latest = commit(&mut primary, &mut backup, client.new_request(3)).remove(0);
+
+        primary.handle_resume(client.resume_session(reply.id), &mut outbox);
+
+        let redelivered = Vec::from_iter(outbox.drain_replies().map(|envelope| envelope.payload));
+
+        assert_eq!(redelivered.len(), 2);
+        assert_eq!(redelivered[1].clone().unwrap_reply(), latest);
+
+        // Acknowledged replies are not delivered again.
+        primary.handle_acknowledge(client.acknowledge(&latest));
+        primary.handle_resume(client.resume_session(reply.id), &mut outbox);
+
+        assert_eq!(outbox.drain_replies().count(), 1);
+
+        // A primary without a session for the client (e.g. after a view change) delivers the latest reply instead.
+        let mut restarted = Replica::new(configuration, 0, 0);
+
+        commit(
+            &mut restarted,
+            &mut Replica::new(configuration, 1, 0),
+            client.new_request(4),
+        );
+        restarted.handle_resume(client.resume_session(latest.id), &mut outbox);
+
+        let redelivered = Vec::from_iter(outbox.drain_replies().map(|envelope| envelope.payload));
+
+        assert_eq!(redelivered.len(), 2);
+        assert_eq!(redelivered[1].clone().unwrap_reply().payload, 4);
+    }
+
+    #[test]
+    fn request_status() {
+        let configuration = Configuration::from(3);
+        let mut replica = Replica::new(configuration, 0, 0);
+        let mut mailbox = BufferedMailbox::default();
+        let mut client = crate::Client::new(configuration);
+        let request = client.new_request(1);
+        let next = request.id.next();
+
+        replica.handle_request(request.clone(), &mut mailbox);
+        replica.handle_query_request_status(client.query_status(request.id), &mut mailbox);
+        replica.handle_query_request_status(client.query_status(next), &mut mailbox);
+
+        let states = Vec::from_iter(mailbox.drain_replies().map(|envelope| {
+            let status = envelope.payload.unwrap_request_status();
+            (status.request, status.state)
+        }));
+
+        assert_eq!(
+            states,
+            vec![
+                (request.id, crate::RequestState::Pending),
+                (next, crate::RequestState::NotStarted)
+            ]
+        );
+
+        let mut backup = Replica::new(configuration, 1, 0);
+
+        backup.handle_query_request_status(client.query_status(request.id), &mut mailbox);
+
+        assert_eq!(mailbox.drain_replies().count(), 0);
+    }
+
+    #[test]
+    fn unverified_request() {
+        let configuration = Configuration::from(3);
+        let mut replica = Replica::new(configuration, 0, 0);
+        let mut mailbox = BufferedMailbox::default();
+        let mut client = crate::Client::new(configuration);
+
+        replica.enable_verification(|request: &Request<i32>| request.signature.is_some());
+
+        let forged = client.new_request(1);
+        let mut signed = client.new_request(2);
+
+        signed.signature = Some(crate::Signature {
+            key: 1,
+            mac: vec![1],
+        });
+
+        replica.handle_request(forged, &mut mailbox);
+
+        assert!(replica.log.is_empty());
+        assert_eq!(mailbox.drain_broadcast().count(), 0);
+
+        replica.handle_request(signed.clone(), &mut mailbox);
+
+        assert_eq!(replica.log.last_op_number(), OpNumber::default().next());
+        assert_eq!(mailbox.drain_broadcast().count(), 1);
+    }
+
+    #[test]
+    fn request_during_view_change() {
+        let configuration = Configuration::from(3);
+        let mut replica = Replica::new(configuration, 1, 0);
+        let mut mailbox = BufferedMailbox::default();
+        let mut client = crate::Client::new(configuration);
+
+        replica.handle_start_view_change(
+            StartViewChange {
+                view: View::default().next(),
+                epoch: 0,
+                index: 2,
+            },
+            &mut mailbox,
+        );
+        mailbox.drain_broadcast().for_each(drop);
+        mailbox.drain_send().for_each(drop);
+
+        assert!(replica.is_primary());
+        assert!(!replica.is_normal());
+
+        replica.handle_request(client.new_request(1), &mut mailbox);
+
+        assert!(replica.log.is_empty());
+        assert!(mailbox.is_empty());
+    }
+
+    #[test]
+    fn state_transfer_for_newer_view() {
+        let configuration = Configuration::from(3);
+        let newer = View::default().next().next();
+        let mut lagging = Replica::new(configuration, 1, 0);
+        let mut mailbox = BufferedMailbox::default();
+        let commit = Commit {
+            view: newer,
+            epoch: 0,
+            committed: OpNumber::default(),
+            timestamp: Default::default(),
+        };
+
+        lagging.handle_commit(commit.clone(), &mut mailbox);
+        mailbox.drain_inbound().for_each(drop);
+
+        let envelope = mailbox.take_send::<GetState>().unwrap();
+
+        // Replicas in the newer view drop requests for older views, so the request asks for the newer view.
+        assert_eq!(envelope.payload.view, newer);
+
+        let mut responder = Replica::new(configuration, envelope.destination, 0);
+
+        responder.view = newer;
+        responder
+            .log
+            .push(newer, crate::Client::new(configuration).new_request(1), ());
+        responder.handle_get_state(envelope.payload, &mut mailbox);
+
+        assert!(mailbox.take_send::<NewState<i32, ()>>().is_some());
+
+        // Another message from the same view does not ask again until the replica is idle.
+        lagging.handle_commit(commit, &mut mailbox);
+        mailbox.drain_inbound().for_each(drop);
+
+        assert!(mailbox.take_send::<GetState>().is_none());
+    }
+
+    #[test]
+    fn do_view_change_without_start_view_change() {
+        let configuration = Configuration::from(5);
+        let view = View::default().next();
+        let mut primary = Replica::new(configuration, 1, 0);
+        let mut mailbox = BufferedMailbox::default();
+
+        // The start-view-change messages of the other replicas were lost, but their do-view-change messages arrive.
+        for index in [2, 3] {
+            primary.handle_do_view_change(
+                DoViewChange {
+                    view,
+                    epoch: 0,
+                    log: primary.log.summary(),
+                    committed: OpNumber::default(),
+                    index,
+                },
+                &mut mailbox,
+            );
+        }
+
+        let own = mailbox.take_send::<DoViewChange<i32, ()>>().unwrap();
+
+        assert_eq!(own.destination, 1);
+
+        primary.handle_do_view_change(own.payload, &mut mailbox);
+
+        assert!(primary.is_normal());
+        assert_eq!(primary.view, view);
+        assert!(mailbox.take_broadcast::<StartView<i32, ()>>().is_some());
+    }
+
+    #[test]
+    fn request_dropped_by_view_change() {
+        let configuration = Configuration::from(3);
+        let mut primary = Replica::new(configuration, 0, 0);
+        let mut mailbox = BufferedMailbox::default();
+        let mut client = crate::Client::new(configuration);
+        let request = client.new_request(1);
+
+        // The request started in an earlier view, but the log of the new view does not hold it.
+        primary.client_table.start(&request);
+        primary.handle_request(request.clone(), &mut mailbox);
+
+        let prepare = mailbox.take_broadcast::<Prepare<_, _>>().unwrap();
+
+        assert_eq!(prepare.request, request);
+
+        // Once prepared again, retries are duplicates.
+        primary.handle_request(request, &mut mailbox);
+
+        assert!(mailbox.take_broadcast::<Prepare<i32, ()>>().is_none());
+    }
+
+    #[test]
+    fn liveness() {
+        let configuration = Configuration::from(3);
+        let mut backup = Replica::new(configuration, 1, 0);
+        let mut mailbox = BufferedMailbox::<i32>::default();
+        let liveness = backup.liveness();
+
+        // Other backups do not keep a backup from noticing that the primary failed.
+        backup.handle_payload(
+            ProtocolPayload::StartViewChange(StartViewChange {
+                view: View::default(),
+                epoch: 0,
+                index: 2,
+            }),
+            &mut mailbox,
+        );
+
+        assert_eq!(backup.liveness(), liveness);
+
+        backup.handle_payload(
+            ProtocolPayload::Commit(Commit {
+                view: View::default(),
+                epoch: 0,
+                committed: OpNumber::default(),
+                timestamp: Default::default(),
+            }),
+            &mut mailbox,
+        );
+
+        assert_ne!(backup.liveness(), liveness);
+    }
+
+    #[test]
+    fn shutdown_handoff() {
+        let configuration = Configuration::from(5);
+        let mut replica = Replica::new(configuration, 0, 0);
+        let mut mailbox = BufferedMailbox::default();
+        let mut client = crate::Client::new(configuration);
+        let mut other = crate::Client::new(configuration);
+
+        replica.handle_request(client.new_request(1), &mut mailbox);
+        replica.begin_shutdown();
+
+        let request = other.new_request(2);
+
+        replica.handle_request(request.clone(), &mut mailbox);
+
+        assert_eq!(
+            mailbox
+                .drain_replies()
+                .last()
+                .unwrap()
+                .payload
+                .unwrap_busy(),
+            Busy {
+                view: replica.view,
+                id: request.id,
+                backoff: configuration.view_timeout(),
+            }
+        );
+        assert!(!replica.is_safe_to_terminate());
+
+        for (index, committed) in [(1, 0), (3, 0), (3, 1)] {
+            replica.handle_prepare_ok(
+                PrepareOk {
+                    view: replica.view,
+                    epoch: 0,
+                    op_number: replica.log.last_op_number(),
+                    index,
+                    committed: OpNumber::from(committed),
+                    promised: replica.view,
+                },
+                &mut mailbox,
+            );
+        }
+
+        assert_eq!(replica.committed, replica.log.last_op_number());
+
+        mailbox.drain_replies().for_each(drop);
+        mailbox.drain_broadcast().for_each(drop);
+        replica.idle(&mut mailbox);
+
+        assert_eq!(replica.view, View::from(3));
+        assert!(matches!(
+            mailbox.drain_broadcast().last(),
+            Some(ProtocolPayload::StartViewChange(StartViewChange { view, index: 0, .. })) if view == replica.view
+        ));
+        assert!(!replica.is_safe_to_terminate());
+
+        replica.set_status(Status::Normal);
+
+        assert!(replica.is_safe_to_terminate());
+    }
+
+    #[test]
+    fn expired_request() {
+        let configuration = Configuration::from(3);
+        let mut replica = Replica::new(configuration, 0, 0);
+        let mut mailbox = BufferedMailbox::default();
+        let mut client = crate::Client::new(configuration);
+        let request = client
+            .new_request(1)
+            .with_deadline(Duration::from_millis(5));
+
+        replica.handle_request_at(request.clone(), Duration::from_millis(10), &mut mailbox);
+
+        assert!(replica.log.is_empty());
+        assert_eq!(
+            mailbox
+                .drain_replies()
+                .next()
+                .map(|envelope| envelope.payload.unwrap_expired()),
+            Some(Expired {
+                view: replica.view,
+                id: request.id,
+            })
+        );
+        assert!(mailbox.is_empty());
+
+        replica.handle_request_at(request, Duration::from_millis(1), &mut mailbox);
+
+        assert_eq!(replica.log.len(), 1);
+        assert_eq!(mailbox.drain_broadcast().count(), 1);
+    }
+
+    #[test]
+    fn speculative_execution() {
+        let configuration = Configuration::from(3);
+        let mut replica = Replica::new(configuration, 0, 0);
+        let mut mailbox = BufferedMailbox::default();
+        let mut clients = [
+            crate::Client::new(configuration),
+            crate::Client::new(configuration),
+        ];
+
+        replica.enable_speculative_execution();
+        replica.handle_request(clients[0].new_request(2), &mut mailbox);
+
+        assert_eq!(replica.service, 2);
+        assert_eq!(replica.checkpoint().state, 0);
+        assert_eq!(replica.service, 0);
+
+        replica.handle_request(clients[1].new_request(3), &mut mailbox);
+
+        for op_number in [replica.log.first_op_number(), replica.log.last_op_number()] {
+            replica.handle_prepare_ok(
+                PrepareOk {
+                    view: replica.view,
+                    epoch: 0,
+                    op_number,
+                    index: 1,
+                    committed: OpNumber::default(),
+                    promised: replica.view,
+                },
+                &mut mailbox,
+            );
+        }
+
+        assert_eq!(replica.service, 5);
+        assert_eq!(
+            Vec::from_iter(
+                mailbox
+                    .drain_replies()
+                    .map(|envelope| envelope.payload.unwrap_reply().payload)
+            ),
+            vec![2, 5]
+        );
+
+        replica.handle_request(clients[0].new_request(4), &mut mailbox);
+
+        assert_eq!(replica.service, 9);
+
+        replica.set_status(Status::ViewChange);
+
+        assert_eq!(replica.service, 5);
+        assert_eq!(replica.committed, replica.log.last_op_number().previous());
+    }
+
+    #[test]
+    fn unserializable_payloads() {
+        /// A service whose requests are functions, which replicas pass around without serializing.
+        #[derive(Clone)]
+        struct Apply(i64);
+
+        impl From<i64> for Apply {
+            fn from(value: i64) -> Self {
+                Self(value)
+            }
+        }
+
+        impl Protocol for Apply {
+            type Request = fn(i64) -> i64;
+            type Prediction = ();
+            type Reply = i64;
+            type Checkpoint = i64;
+        }
+
+        impl Service for Apply {
+            fn predict(&self, _: &<Self as Protocol>::Request) {}
+
+            fn checkpoint(&self) -> i64 {
+                self.0
+            }
+
+            fn invoke(&mut self, request: &<Self as Protocol>::Request, _: &()) -> i64 {
+                self.0 = request(self.0);
+                self.0
+            }
+        }
+
+        let configuration = Configuration::from(3);
+        let mut replica = Replica::new(configuration, 0, Apply(3));
+        let mut mailbox = BufferedMailbox::default();
+        let mut client = crate::Client::new(configuration);
+
+        replica.handle_request(client.new_request(|value| value * 2), &mut mailbox);
+        replica.handle_prepare_ok(
+            PrepareOk {
+                view: replica.view,
+                epoch: 0,
+                op_number: replica.log.last_op_number(),
+                index: 1,
+                committed: OpNumber::default(),
+                promised: replica.view,
+            },
+            &mut mailbox,
+        );
+
+        assert_eq!(
+            Vec::from_iter(
+                mailbox
+                    .drain_replies()
+                    .map(|envelope| envelope.payload.unwrap_reply().payload)
+            ),
+            vec![6]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "past the end of its log")]
+    fn invariants() {
+        let mut replica = Replica::new(Configuration::from(3), 0, 0);
+
+        replica.check_invariants();
+        replica.committed.increment();
+        replica.check_invariants();
+    }
+
+    #[test]
+    fn recovery_stale_primary() {
+        let configuration = Configuration::from(5);
+        let mut replicas =
+            Vec::from_iter((0..5).map(|index| Replica::new(configuration, index, 0)));
+        let mut mailbox = BufferedMailbox::default();
+        let mut client = crate::Client::new(configuration);
+        let view = View::from(6);
+
+        replicas[1].view = View::from(1);
+        replicas[1]
+            .log
+            .push(View::from(1), client.new_request(1), ());
+
+        for replica in replicas.iter_mut().skip(3) {
+            replica.view = view;
+        }
+
+        let checkpoint = replicas[0].checkpoint();
+
+        replicas[0] = Replica::recovering(configuration, 0, checkpoint, &mut mailbox);
+
+        let recovery = mailbox.take_broadcast::<Recovery>().unwrap();
+
+        for index in [1, 3, 4] {
+            replicas[index].handle_recovery(recovery.clone(), &mut mailbox);
+        }
+
+        while let Some(envelope) = mailbox.take_send::<RecoveryResponse<_, _>>() {
+            let response = envelope.payload;
+
+            assert_eq!(response.log.is_some(), response.index == 1);
+
+            replicas[0].handle_recovery_response(response, &mut mailbox);
+        }
+
+        // The replica that answered with its log was the primary of an older view.
+        assert!(replicas[0].status == Status::Recovering);
+
+        replicas[1].view = view;
+        replicas[1].log = Log::empty(view, OpNumber::default());
+        replicas[1].handle_recovery(recovery, &mut mailbox);
+
+        let response = mailbox.take_send::<RecoveryResponse<_, _>>().unwrap();
+
+        replicas[0].handle_recovery_response(response.payload, &mut mailbox);
+
+        assert!(replicas[0].status == Status::Normal);
+        assert_eq!(replicas[0].view, view);
+        assert!(replicas[0].log.is_empty());
+    }
+
+    #[test]
+    fn recovery_with_log() {
+        let configuration = Configuration::from(3);
+        let mut replicas =
+            Vec::from_iter((0..3).map(|index| Replica::new(configuration, index, 0)));
+        let mut mailbox = BufferedMailbox::default();
+        let mut client = crate::Client::new(configuration);
+
+        for payload in 1..=5 {
+            let request = client.new_request(payload);
+
+            if payload <= 3 {
+                replicas[1].log.push(View::default(), request.clone(), ());
+            }
+
+            replicas[0].log.push(View::default(), request, ());
+        }
+
+        replicas[0].commit_operations(OpNumber::from(5), &mut mailbox);
+        replicas[1].commit_operations(OpNumber::from(2), &mut mailbox);
+        mailbox.drain_replies().for_each(drop);
+
+        // The durable log holds an uncommitted entry from a view the group never adopted.
+        let checkpoint = replicas[1].checkpoint();
+        let mut log = replicas[1].log.clone();
+
+        log.push(View::from(1), client.new_request(6), ());
+        replicas[1] = Replica::recovering_with_log(
+            configuration,
+            1,
+            checkpoint,
+            log,
+            Nonce::default(),
+            &mut mailbox,
+        );
+
+        let recovery = mailbox.take_broadcast::<Recovery>().unwrap();
+
+        assert!(recovery.digest.is_some());
+
+        for index in [0, 2] {
+            replicas[index].handle_recovery(recovery.clone(), &mut mailbox);
+        }
+
+        while let Some(envelope) = mailbox.take_send::<RecoveryResponse<_, _>>() {
+            if let Some(log) = &envelope.payload.log {
+                assert_eq!(log.len(), 2);
+            }
+
+            replicas[1].handle_recovery_response(envelope.payload, &mut mailbox);
+        }
+
+        assert!(replicas[1].status == Status::Normal);
+        assert_eq!(replicas[1].log, replicas[0].log);
+        assert_eq!(replicas[1].committed, replicas[0].committed);
+        assert_eq!(replicas[1].checkpoint().state, 15);
+    }
+
+    #[test]
+    fn recovery_generation() {
+        let configuration = Configuration::from(3);
+        let mut replicas =
+            Vec::from_iter((0..3).map(|index| Replica::new(configuration, index, 0)));
+        let mut mailbox = BufferedMailbox::default();
+
+        // The replica restarts twice, each time from the checkpoint of the previous incarnation.
+        for generation in 1..=2 {
+            let checkpoint = replicas[2].checkpoint();
+
+            replicas[2] = Replica::recovering(configuration, 2, checkpoint, &mut mailbox);
+
+            assert_eq!(replicas[2].generation(), generation);
+        }
+
+        let mut recoveries = Vec::from_iter(mailbox.drain_broadcast().map(|message| {
+            let ProtocolPayload::Recovery(recovery) = message else {
+                panic!("expected a recovery");
+            };
+
+            recovery
+        }));
+        let latest = recoveries.pop().unwrap();
+        let earlier = recoveries.pop().unwrap();
+
+        // A response to the earlier incarnation does not count toward the recovery of the latest one.
+        replicas[1].handle_recovery(earlier.clone(), &mut mailbox);
+        replicas[0].handle_recovery(latest.clone(), &mut mailbox);
+        replicas[0].handle_recovery(earlier, &mut mailbox);
+
+        let responses = Vec::from_iter(mailbox.drain_send());
+
+        assert_eq!(responses.len(), 2);
+
+        for envelope in responses {
+            replicas[2].handle_payload(envelope.payload, &mut mailbox);
+        }
+
+        assert!(replicas[2].status == Status::Recovering);
+
+        replicas[1].handle_recovery(latest, &mut mailbox);
+
+        for envelope in Vec::from_iter(mailbox.drain_send()) {
+            replicas[2].handle_payload(envelope.payload, &mut mailbox);
+        }
+
+        assert!(replicas[2].status == Status::Normal);
+        assert_eq!(replicas[2].checkpoint().generation, 2);
+    }
+
+    #[test]
+    fn recovered_reply_cache() {
+        let configuration = Configuration::from(3);
+        let mut replicas =
+            Vec::from_iter((0..3).map(|index| Replica::new(configuration, index, 0)));
+        let mut mailbox = BufferedMailbox::default();
+        let mut client = crate::Client::new(configuration);
+        let request = client.new_request(1);
+
+        for replica in replicas.iter_mut() {
+            replica.view.increment();
+        }
+
+        replicas[1].handle_request(request.clone(), &mut mailbox);
+
+        let prepare = mailbox.drain_broadcast().next().unwrap();
+
+        replicas[0].handle_payload(prepare, &mut mailbox);
+
+        let prepare_ok = mailbox.drain_send().next().unwrap().payload;
+
+        replicas[1].handle_payload(prepare_ok, &mut mailbox);
+        let commit = Commit {
+            view: replicas[1].view,
+            epoch: 0,
+            committed: replicas[1].committed,
+            timestamp: Default::default(),
+        };
+
+        replicas[0].handle_commit(commit, &mut mailbox);
+        mailbox.drain_replies().for_each(drop);
+
+        let checkpoint = replicas[0].checkpoint();
+
+        // The log no longer includes the request, so recovery cannot rebuild the reply cache from the log.
+        replicas[1].log.constrain(0);
+        replicas[0] = Replica::recovering(configuration, 0, checkpoint, &mut mailbox);
+
+        let recovery = mailbox.drain_broadcast().next().unwrap();
+
+        for replica in replicas.iter_mut().skip(1) {
+            replica.handle_payload(recovery.clone(), &mut mailbox);
+        }
+
+        for envelope in Vec::from_iter(mailbox.drain_send()) {
+            replicas[envelope.destination].handle_payload(envelope.payload, &mut mailbox);
+        }
+
+        assert!(replicas[0].status == Status::Normal);
+
+        // A later view change makes the recovered replica the primary.
+        replicas[0].view.increment();
+        replicas[0].view.increment();
+        replicas[0].handle_request(request.clone(), &mut mailbox);
+
+        let reply = mailbox
+            .drain_replies()
+            .next()
+            .unwrap()
+            .payload
+            .unwrap_reply();
+
+        assert_eq!(reply.id, request.id);
+        assert_eq!(reply.payload, 1);
+        assert_eq!(replicas[0].service, 1);
+        assert!(mailbox.is_empty());
+    }
+
+    #[test]
+    fn idle_after_prepare() {
+        let configuration = Configuration::from(3);
+        let mut replica = Replica::new(configuration, 0, 0);
+        let mut mailbox = BufferedMailbox::default();
+        let mut client = crate::Client::new(configuration);
+
+        replica.handle_request(client.new_request(1), &mut mailbox);
+        replica.handle_prepare_ok(
+            PrepareOk {
+                view: replica.view,
+                epoch: 0,
+                op_number: replica.log.last_op_number(),
+                index: 1,
+                committed: OpNumber::default(),
+                promised: replica.view,
+            },
+            &mut mailbox,
+        );
+        mailbox.drain_replies().for_each(drop);
+        mailbox.drain_broadcast().for_each(drop);
+
+        replica.idle(&mut mailbox);
+
+        assert!(mailbox.is_empty());
+
+        replica.idle_at(Duration::from_secs(1), &mut mailbox);
+
+        let mut messages = Vec::from_iter(mailbox.drain_broadcast());
+
+        assert_eq!(
+            messages.pop().map(ProtocolPayload::unwrap_commit),
+            Some(Commit {
+                view: replica.view,
+                epoch: 0,
+                committed: replica.committed,
+                timestamp: Duration::from_secs(1),
+            })
+        );
+        assert!(messages.is_empty());
+        assert!(mailbox.is_empty());
+    }
+
+    #[test]
+    fn adaptive_heartbeat() {
+        let configuration = Configuration::from(3)
+            .with_heartbeat(Duration::from_millis(100), Duration::from_millis(400));
+        let mut replica = Replica::new(configuration, 0, 0);
+        let mut mailbox = BufferedMailbox::default();
+        let mut client = crate::Client::new(configuration);
+        let mut heartbeat = |replica: &mut Replica<i32>, millis: u64| {
+            replica.idle_at(Duration::from_millis(millis), &mut mailbox);
+
+            let messages = Vec::from_iter(mailbox.drain_broadcast());
+
+            assert!(mailbox.is_empty());
+            messages.len() == 1
+        };
+        let prepare_ok = |replica: &Replica<i32>, index: usize| PrepareOk {
+            view: replica.view,
+            epoch: 0,
+            op_number: replica.log.last_op_number(),
+            index,
+            committed: OpNumber::default(),
+            promised: replica.view,
+        };
+
+        let mut outbox = BufferedMailbox::default();
+
+        replica.handle_request(client.new_request(1), &mut outbox);
+        replica.handle_prepare_ok(prepare_ok(&replica, 1), &mut outbox);
+
+        // The prepare counts as a heartbeat, and a backup that has not acknowledged it keeps the interval at its minimum.
+        assert!(!heartbeat(&mut replica, 0));
+        assert!(heartbeat(&mut replica, 100));
+
+        replica.handle_prepare_ok(prepare_ok(&replica, 2), &mut outbox);
+
+        // Once every backup caught up, the interval doubles up to its maximum.
+        assert!(heartbeat(&mut replica, 200));
+        assert!(!heartbeat(&mut replica, 300));
+        assert!(heartbeat(&mut replica, 400));
+        assert!(!heartbeat(&mut replica, 700));
+        assert!(heartbeat(&mut replica, 800));
+        assert!(!heartbeat(&mut replica, 1100));
+        assert!(heartbeat(&mut replica, 1200));
+
+        replica.suspect(2);
+
+        assert!(heartbeat(&mut replica, 1300));
+        assert!(heartbeat(&mut replica, 1400));
+    }
+
+    #[test]
+    fn backup_lag() {
+        let configuration = Configuration::from(3);
+        let mut primary = Replica::new(configuration, 0, 0);
+        let mut backup = Replica::new(configuration, 1, 0);
+        let mut mailbox = BufferedMailbox::default();
+        let mut client = crate::Client::new(configuration);
+
+        assert_eq!(primary.lag(1), None);
+
+        for payload in 1..=3 {
+            primary.handle_request(client.new_request(payload), &mut mailbox);
+
+            for message in Vec::from_iter(mailbox.drain_broadcast()) {
+                backup.handle_payload(message, &mut mailbox);
+            }
+
+            for envelope in Vec::from_iter(mailbox.drain_send()) {
+                primary.handle_payload(envelope.payload, &mut mailbox);
+            }
+
+            mailbox.drain_replies().for_each(drop);
+        }
+
+        assert_eq!(primary.lag(1), Some(1));
+        assert_eq!(primary.lag(2), None);
+        assert_eq!(backup.lag(0), None);
+    }
+
+    #[test]
+    fn chunked_state_transfer() {
+        let configuration = Configuration::from(3).with_state_transfer_limit(2);
+        let mut primary = Replica::new(configuration, 0, 0);
+        let mut backup = Replica::new(configuration, 1, 0);
+        let mut mailbox = BufferedMailbox::default();
+        let mut client = crate::Client::new(configuration);
+
+        for payload in 1..=5 {
+            let request = client.new_request(payload);
+
+            if payload == 1 {
+                backup.log.push(backup.view, request.clone(), ());
+            }
+
+            primary.log.push(primary.view, request, ());
+        }
+
+        primary.commit_operations(primary.log.last_op_number(), &mut mailbox);
+        mailbox.drain_replies().for_each(drop);
+
+        let mut transfers = 0;
+
+        backup.state_transfer(backup.view, &mut mailbox);
+
+        loop {
+            let Some(envelope) = mailbox.drain_send().next() else {
+                break;
+            };
+
+            primary.handle_get_state(envelope.payload.unwrap_get_state(), &mut mailbox);
+
+            let new_state = mailbox.drain_send().next().unwrap().payload;
+
+            assert!(new_state.clone().unwrap_new_state().log.len() <= 2);
+
+            backup.handle_payload(new_state, &mut mailbox);
+            transfers += 1;
+        }
+
+        assert_eq!(transfers, 2);
+        assert_eq!(backup.log, primary.log);
+        assert_eq!(backup.committed, primary.committed);
+        assert!(mailbox.is_empty());
+    }
+
+    #[test]
+    fn state_transfer_target() {
+        let configuration =
+            Configuration::from(5).with_state_transfer_policy(TransferPolicy::Primary);
+        let mut backup = Replica::new(configuration, 2, 0);
+        let mut mailbox = BufferedMailbox::default();
+        let view = backup.view.next().next().next();
+
+        backup.state_transfer(view, &mut mailbox);
+
+        let envelope = mailbox.take_send::<GetState>().unwrap();
+
+        assert_eq!(envelope.destination, 3);
+        assert!(mailbox.is_empty());
+
+        // A single replica has no one to transfer state from.
+        let mut replica = Replica::new(Configuration::from(1), 0, 0);
+
+        replica.state_transfer(view, &mut mailbox);
+
+        assert!(mailbox.is_empty());
+    }
+
+    #[derive(Clone, Default)]
+    struct Events(std::sync::Arc<std::sync::Mutex<Vec<String>>>);
+
+    impl EventListener for Events {
+        fn on_prepare(&mut self, view: View, op_number: OpNumber) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("prepare {view:?} {op_number:?}"));
+        }
+
+        fn on_commit(&mut self, op_number: OpNumber) {
+            self.0.lock().unwrap().push(format!("commit {op_number:?}"));
+        }
+
+        fn on_client_reply(&mut self, _: crate::ClientIdentifier, _: crate::RequestIdentifier) {
+            self.0.lock().unwrap().push("reply".to_string());
+        }
+
+        fn on_view_change_start(&mut self, view: View) {
+            self.0.lock().unwrap().push(format!("start {view:?}"));
+        }
+
+        fn on_recovery_stalled(&mut self, attempts: u32) {
+            self.0.lock().unwrap().push(format!("stalled {attempts}"));
+        }
+    }
+
+    #[test]
+    fn recovery_retries() {
+        let timeout = Duration::from_millis(100);
+        let configuration = Configuration::from(3)
+            .with_view_timeout(timeout, Duration::ZERO)
+            .with_recovery_backoff(timeout * 3)
+            .with_recovery_attempts(2);
+        let mut primary = Replica::new(configuration, 0, 0);
+        let mut backup = Replica::new(configuration, 2, 0);
+        let mut mailbox = BufferedMailbox::default();
+        let checkpoint = Replica::new(configuration, 1, 0).checkpoint();
+        let mut replica = Replica::recovering(configuration, 1, checkpoint, &mut mailbox);
+        let events = Events::default();
+
+        replica.add_listener(events.clone());
+
+        // The first recovery request is lost.
+        mailbox.take_broadcast::<Recovery>().unwrap();
+
+        assert_eq!(replica.idle_timeout(), timeout);
+
+        for expected in [timeout * 2, timeout * 3, timeout * 3] {
+            replica.idle(&mut mailbox);
+
+            assert_eq!(replica.idle_timeout(), expected);
+        }
+
+        assert_eq!(*events.0.lock().unwrap(), vec!["stalled 2".to_string()]);
+
+        let recovery = mailbox.select_broadcast::<Recovery>().pop().unwrap();
+
+        primary.handle_recovery(recovery.clone(), &mut mailbox);
+        backup.handle_recovery(recovery, &mut mailbox);
+
+        while let Some(envelope) = mailbox.take_send::<RecoveryResponse<_, _>>() {
+            replica.handle_recovery_response(envelope.payload, &mut mailbox);
+        }
+
+        assert!(replica.is_normal());
+        assert_eq!(replica.idle_timeout(), timeout);
+    }
+
+    #[test]
+    fn recovering_primary_idle_timeout() {
+        let configuration = Configuration::from(3)
+            .with_commit_interval(Duration::from_millis(10))
+            .with_view_timeout(Duration::from_millis(100), Duration::ZERO);
+        let mut primary = Replica::new(configuration, 0, 0);
+        let mut mailbox = BufferedMailbox::<i32>::default();
+
+        assert_eq!(primary.idle_timeout(), Duration::from_millis(10));
+
+        let recovering = Replica::recovering(configuration, 0, primary.checkpoint(), &mut mailbox);
+
+        assert_eq!(recovering.idle_timeout(), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn view_change_backoff() {
+        let timeout = Duration::from_millis(100);
+        let configuration = Configuration::from(3)
+            .with_commit_interval(timeout)
+            .with_view_timeout(timeout, Duration::ZERO)
+            .with_view_change_backoff(timeout * 3);
+        let mut replicas =
+            Vec::from_iter((0..3).map(|index| Replica::new(configuration, index, 0)));
+        let mut mailbox = BufferedMailbox::default();
+
+        assert_eq!(replicas[2].idle_timeout(), timeout);
+
+        replicas[2].idle(&mut mailbox);
+
+        assert_eq!(replicas[2].idle_timeout(), timeout * 2);
+
+        let start_view_change = mailbox.take_broadcast::<StartViewChange>().unwrap();
+
+        replicas[1].handle_start_view_change(start_view_change, &mut mailbox);
+
+        let start_view_change = mailbox.take_broadcast::<StartViewChange>().unwrap();
+
+        replicas[2].handle_start_view_change(start_view_change, &mut mailbox);
+        mailbox.drain_send().for_each(drop);
+
+        // The new primary never finishes the view change, so the backup starts another one.
+        replicas[2].idle(&mut mailbox);
+
+        assert_eq!(replicas[2].view, View::from(2));
+        assert_eq!(replicas[2].idle_timeout(), timeout * 3);
+
+        // Reaching a normal view resets the backoff.
+        replicas[2].set_status(Status::Normal);
+
+        assert_eq!(replicas[2].idle_timeout(), timeout);
+    }
+
+    #[test]
+    fn view_change_timeout() {
+        let timeout = Duration::from_millis(100);
+        let configuration = Configuration::from(3)
+            .with_commit_interval(timeout)
+            .with_view_timeout(timeout, Duration::ZERO)
+            .with_view_change_timeout(timeout * 2);
+        let mut replicas =
+            Vec::from_iter((0..3).map(|index| Replica::new(configuration, index, 0)));
+        let mut mailbox = BufferedMailbox::default();
+
+        replicas[1].idle(&mut mailbox);
+        mailbox.drain_broadcast().for_each(drop);
+
+        // The primary of the new view never hears from the other replicas, so it resends its vote at first.
+        replicas[1].idle(&mut mailbox);
+
+        assert_eq!(replicas[1].view, View::from(1));
+        assert_eq!(
+            mailbox.take_broadcast::<StartViewChange>().unwrap().view,
+            View::from(1)
+        );
+
+        // Then gives up on the view once the view change takes longer than the timeout.
+        replicas[1].idle(&mut mailbox);
+
+        assert_eq!(replicas[1].view, View::from(2));
+        assert_eq!(
+            mailbox.take_broadcast::<StartViewChange>().unwrap().view,
+            View::from(2)
+        );
+
+        // Without a timeout, the replica waits for the view change to complete.
+        let configuration = Configuration::from(3)
+            .with_commit_interval(timeout)
+            .with_view_timeout(timeout, Duration::ZERO);
+        let mut replica = Replica::new(configuration, 1, 0);
+
+        for _ in 0..5 {
+            replica.idle(&mut mailbox);
+        }
+
+        assert_eq!(replica.view, View::from(1));
+    }
+
+    #[derive(Clone, Default)]
+    struct Drops(std::sync::Arc<std::sync::Mutex<Vec<DropReason>>>);
+
+    impl EventListener for Drops {
+        fn on_drop(&mut self, reason: DropReason) {
+            self.0.lock().unwrap().push(reason);
+        }
+    }
+
+    #[test]
+    fn drop_reasons() {
+        let configuration = Configuration::from(3);
+        let mut primary = Replica::new(configuration, 0, 0);
+        let mut backup = Replica::new(configuration, 1, 0);
+        let mut mailbox = BufferedMailbox::default();
+        let mut client = crate::Client::new(configuration);
+        let drops = Drops::default();
+        let request = client.new_request(1);
+
+        primary.add_listener(drops.clone());
+        backup.add_listener(drops.clone());
+
+        backup.handle_request(request.clone(), &mut mailbox);
+        primary.handle_request(request.clone(), &mut mailbox);
+        primary.handle_request(request, &mut mailbox);
+
+        primary.view.increment();
+        primary.handle_commit(
+            Commit {
+                view: View::default(),
+                epoch: 0,
+                committed: OpNumber::default(),
+                timestamp: Default::default(),
+            },
+            &mut mailbox,
+        );
+
+        assert_eq!(
+            *drops.0.lock().unwrap(),
+            vec![
+                DropReason::NotPrimary,
+                DropReason::Duplicate,
+                DropReason::StaleView
+            ]
+        );
+    }
+
+    #[test]
+    fn stale_epoch() {
+        let configuration = Configuration::from(3).with_epoch(1);
+        let mut backup = Replica::new(configuration, 1, 0);
+        let mut mailbox = BufferedMailbox::default();
+        let drops = Drops::default();
+
+        backup.add_listener(drops.clone());
+
+        // A replica that operators have not replaced yet still runs the configuration of the previous epoch.
+        backup.handle_payload(
+            ProtocolPayload::StartViewChange(StartViewChange {
+                view: View::from(1),
+                epoch: 0,
+                index: 2,
+            }),
+            &mut mailbox,
+        );
+
+        assert_eq!(*drops.0.lock().unwrap(), vec![DropReason::StaleEpoch]);
+        assert_eq!(backup.view, View::default());
+        assert!(backup.status == Status::Normal);
+        assert_eq!(mailbox.drain_broadcast().count(), 0);
+
+        backup.handle_payload(
+            ProtocolPayload::StartViewChange(StartViewChange {
+                view: View::from(1),
+                epoch: 1,
+                index: 2,
+            }),
+            &mut mailbox,
+        );
+
+        assert_eq!(backup.view, View::from(1));
+        assert!(matches!(
+            mailbox.drain_broadcast().next(),
+            Some(ProtocolPayload::StartViewChange(StartViewChange {
+                epoch: 1,
+                ..
+            }))
+        ));
+    }
+
+    #[test]
+    fn implausible_view() {
+        let configuration = Configuration::from(3);
+        let mut backup = Replica::new(configuration, 1, 0);
+        let mut mailbox = BufferedMailbox::default();
+        let drops = Drops::default();
+
+        backup.add_listener(drops.clone());
+        backup.handle_payload(
+            ProtocolPayload::StartViewChange(StartViewChange {
+                view: View::from(u128::MAX),
+                epoch: 0,
+                index: 2,
+            }),
+            &mut mailbox,
+        );
+
+        assert_eq!(*drops.0.lock().unwrap(), vec![DropReason::Implausible]);
+        assert_eq!(backup.view, View::default());
+
+        backup.idle(&mut mailbox);
+
+        assert_eq!(backup.view, View::from(1));
+
+        // A replica already in the last view stays there instead of overflowing.
+        backup.view = View::from(u128::MAX);
+        backup.idle(&mut mailbox);
+
+        assert_eq!(backup.view, View::from(u128::MAX));
+    }
+
+    #[test]
+    fn implausible_op_number() {
+        let configuration = Configuration::from(3);
+        let mut primary = Replica::new(configuration, 0, 0);
+        let mut mailbox = BufferedMailbox::default();
+        let drops = Drops::default();
+        let message = GetState {
+            view: View::default(),
+            epoch: 0,
+            op_number: OpNumber::from(u128::MAX),
+            index: 1,
+        };
+
+        primary.add_listener(drops.clone());
+        primary.handle_payload(ProtocolPayload::GetState(message.clone()), &mut mailbox);
+        primary.handle_get_state(message, &mut mailbox);
+
+        assert_eq!(
+            *drops.0.lock().unwrap(),
+            vec![DropReason::Implausible, DropReason::Inconsistent]
+        );
+        assert!(mailbox.is_empty());
+    }
+
+    #[derive(Clone, Default)]
+    struct Traffic(std::sync::Arc<std::sync::Mutex<Vec<(View, usize)>>>);
+
+    impl EventListener for Traffic {
+        fn on_message(&mut self, view: View, replica: usize) {
+            self.0.lock().unwrap().push((view, replica));
+        }
+    }
+
+    #[test]
+    fn message_traffic() {
+        let configuration = Configuration::from(3);
+        let mut backup = Replica::new(configuration, 1, 0);
+        let mut mailbox = BufferedMailbox::default();
+        let traffic = Traffic::default();
+        let view = View::default();
+
+        backup.add_listener(traffic.clone());
+        backup.handle_payload(
+            ProtocolPayload::Commit(Commit {
+                view,
+                epoch: 0,
+                committed: OpNumber::default(),
+                timestamp: Default::default(),
+            }),
+            &mut mailbox,
+        );
+        backup.handle_payload(
+            ProtocolPayload::StartViewChange(StartViewChange {
+                view: view.next(),
+                epoch: 0,
+                index: 2,
+            }),
+            &mut mailbox,
+        );
+
+        assert_eq!(
+            *traffic.0.lock().unwrap(),
+            vec![(view, 0), (view.next(), 2)]
+        );
+    }
+
+    #[test]
+    fn listener() {
+        let configuration = Configuration::from(3);
+        let mut replica = Replica::new(configuration, 0, 0);
+        let mut mailbox = BufferedMailbox::default();
+        let mut client = crate::Client::new(configuration);
+        let events = Events::default();
+
+        replica.add_listener(events.clone());
+        replica.handle_request(client.new_request(1), &mut mailbox);
+        replica.handle_prepare_ok(
+            PrepareOk {
+                view: replica.view,
+                epoch: 0,
+                op_number: replica.log.last_op_number(),
+                index: 1,
+                committed: OpNumber::default(),
+                promised: replica.view,
+            },
+            &mut mailbox,
+        );
+        replica.handle_start_view_change(
+            StartViewChange {
+                view: replica.view.next(),
+                epoch: 0,
+                index: 1,
+            },
+            &mut mailbox,
+        );
+
+        let op_number = OpNumber::default().next();
+
+        assert_eq!(
+            *events.0.lock().unwrap(),
+            vec![
+                format!("prepare {:?} {op_number:?}", View::default()),
+                format!("commit {op_number:?}"),
+                "reply".to_string(),
+                format!("start {:?}", View::default().next()),
+            ]
+        );
+    }
+
+    #[test]
+    fn role_changes() {
+        /// Records the role changes it is notified of.
+        #[derive(Default)]
+        struct Roles(Vec<(Role, View)>);
+
+        impl From<()> for Roles {
+            fn from(_: ()) -> Self {
+                Self::default()
+            }
+        }
+
+        impl Protocol for Roles {
+            type Request = ();
+            type Prediction = ();
+            type Reply = ();
+            type Checkpoint = ();
+        }
+
+        impl Service for Roles {
+            fn predict(&self, _: &()) {}
+
+            fn checkpoint(&self) {}
+
+            fn invoke(&mut self, _: &(), _: &()) {}
+
+            fn on_role_change(&mut self, role: Role, view: View) {
+                self.0.push((role, view));
+            }
+        }
+
+        let configuration = Configuration::from(3);
+        let mut replica = Replica::new(configuration, 1, Roles::default());
+        let mut mailbox = BufferedMailbox::default();
+
+        replica.start_view_change(View::from(1), &mut mailbox);
+        replica.handle_start_view(
+            StartView {
+                view: View::from(2),
+                epoch: 0,
+                log: Log::default(),
+                committed: OpNumber::default(),
+            },
+            &mut mailbox,
+        );
+        replica.handle_start_view(
+            StartView {
+                view: View::from(4),
+                epoch: 0,
+                log: Log::default(),
+                committed: OpNumber::default(),
+            },
+            &mut mailbox,
+        );
+
+        assert_eq!(
+            replica.service.0,
+            vec![
+                (Role::Backup, View::from(0)),
+                (Role::Backup, View::from(2)),
+                (Role::Primary, View::from(4))
+            ]
+        );
+
+        let recovering = Replica::<Roles>::recovering(
+            configuration,
+            1,
+            Checkpoint {
+                committed: OpNumber::default(),
+                state: (),
+                client_table: Default::default(),
+                generation: 0,
+            },
+            &mut mailbox,
+        );
+
+        assert!(recovering.service.0.is_empty());
+    }
+
+    #[test]
+    fn invoke_context() {
+        /// Replies with the context of each operation.
+        struct Contexts;
+
+        impl From<()> for Contexts {
+            fn from(_: ()) -> Self {
+                Self
+            }
+        }
+
+        impl Protocol for Contexts {
+            type Request = ();
+            type Prediction = ();
+            type Reply = Option<Context>;
+            type Checkpoint = ();
         }
-    }
 
-    fn set_status(&mut self, status: Status) {
-        self.status = status;
-        self.prepared = Default::default();
+        impl Service for Contexts {
+            fn predict(&self, _: &()) {}
 
-        // We only need this on a new replica. Therefore, we can deallocate on any status change.
-        self.recovery_responses = Default::default();
+            fn checkpoint(&self) {}
 
-        // Avoid allocating unless we need it for the current protocol.
-        match self.status {
-            Status::ViewChange => {
-                self.start_view_changes = HashSet::with_capacity(self.configuration.sub_majority());
-                self.do_view_changes = HashMap::with_capacity(self.configuration.quorum());
+            fn invoke(&mut self, _: &(), _: &()) -> Option<Context> {
+                None
             }
-            _ => {
-                self.start_view_changes = Default::default();
-                self.do_view_changes = Default::default();
+
+            fn invoke_with_context(
+                &mut self,
+                _: &(),
+                _: &(),
+                context: &Context,
+            ) -> Option<Context> {
+                Some(*context)
             }
         }
-    }
 
-    pub fn is_primary(&self) -> bool {
-        (self.configuration % self.view) == self.index
-    }
+        let configuration = Configuration::from(3);
+        let mut primary = Replica::new(configuration, 0, Contexts);
+        let mut backup = Replica::new(configuration, 1, Contexts);
+        let mut mailbox = BufferedMailbox::default();
+        let mut client = crate::Client::new(configuration);
+        let now = Duration::from_secs(42);
 
-    pub fn is_backup(&self) -> bool {
-        !self.is_primary()
-    }
+        primary.handle_request_at(client.new_request(()), now, &mut mailbox);
 
-    fn should_ignore_normal(&self, view: View) -> bool {
-        self.view != view || self.status != Status::Normal
-    }
+        let prepare = mailbox.take_broadcast::<Prepare<_, _>>().unwrap();
 
-    fn need_state_transfer(&self, view: View) -> bool {
-        self.status == Status::Normal && view > self.view
-    }
+        assert_eq!(prepare.timestamp, now);
 
-    fn should_ignore_view_change(&self, view: View) -> bool {
-        self.view != view || self.status != Status::ViewChange
-    }
+        backup.handle_prepare(prepare, &mut mailbox);
 
-    fn need_view_change(&self, view: View) -> bool {
-        self.status != Status::Recovering && view > self.view
-    }
+        let prepare_ok = mailbox.take_send::<PrepareOk>().unwrap().payload;
 
-    fn should_do_view_change(&self) -> bool {
-        self.start_view_changes.len() >= self.configuration.sub_majority()
-    }
-}
+        assert_eq!(backup.log[prepare_ok.op_number].timestamp(), now);
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::buffer::{BufferedMailbox, ProtocolPayload};
+        primary.handle_prepare_ok(prepare_ok, &mut mailbox);
+
+        let context = Context::new(View::default(), OpNumber::from(1), now);
+
+        assert_ne!(
+            context.seed,
+            Context::new(View::default(), OpNumber::from(2), now).seed
+        );
+        assert_eq!(
+            mailbox
+                .drain_replies()
+                .last()
+                .unwrap()
+                .payload
+                .unwrap_reply()
+                .payload,
+            Some(context)
+        );
+    }
 
     #[test]
-    fn sender_behind_prepare() {
+    fn install_snapshot() {
         let configuration = Configuration::from(3);
-        let mut replica = Replica::new(configuration, 0, 0);
+        let mut primary = Replica::new(configuration, 0, 0);
+        let mut backup = Replica::new(configuration, 1, 0);
         let mut mailbox = BufferedMailbox::default();
+        let mut client = crate::Client::new(configuration);
 
-        replica.view.increment();
-        replica.view.increment();
+        for payload in 1..=5 {
+            primary
+                .log
+                .push(primary.view, client.new_request(payload), ());
+        }
 
-        let message = Prepare {
-            view: View::default().next(),
-            op_number: OpNumber::default().next(),
-            request: Request {
-                payload: 2,
-                client: Default::default(),
-                id: Default::default(),
-            },
-            prediction: (),
-            committed: OpNumber::default(),
-        };
+        primary.commit_operations(primary.log.last_op_number(), &mut mailbox);
+        primary.checkpoint_with_suffix(1).unwrap();
+        primary.log.push(primary.view, client.new_request(6), ());
+        mailbox.drain_replies().for_each(drop);
 
-        replica.handle_prepare(message, &mut mailbox);
+        backup.state_transfer(backup.view, &mut mailbox);
 
-        assert_eq!(Vec::from_iter(mailbox.drain_inbound()), vec![]);
+        let get_state = mailbox.take_send::<GetState>().unwrap().payload;
+
+        primary.handle_get_state(get_state, &mut mailbox);
+
+        let snapshot = mailbox.take_send::<InstallSnapshot<_, _, _, _>>().unwrap();
+
+        backup.handle_install_snapshot(snapshot.payload, &mut mailbox);
+
+        let envelope = mailbox.take_send::<PrepareOk>().unwrap();
+
+        assert_eq!(envelope.destination, 0);
+        assert_eq!(envelope.payload.op_number, primary.log.last_op_number());
         assert!(mailbox.is_empty());
+        assert_eq!(backup.committed, primary.committed);
+        assert_eq!(backup.checkpoint().state, 15);
+        assert_eq!(backup.log.last_op_number(), primary.log.last_op_number());
     }
 
     #[test]
-    fn sender_ahead_prepare() {
+    fn state_transfer_after_compaction() {
         let configuration = Configuration::from(3);
-        let mut replica = Replica::new(configuration, 1, 0);
+        let mut primary = Replica::new(configuration, 0, 0);
+        let mut backup = Replica::new(configuration, 1, 0);
         let mut mailbox = BufferedMailbox::default();
+        let mut client = crate::Client::new(configuration);
 
-        let message = Prepare {
-            view: View::default().next(),
-            op_number: OpNumber::default().next(),
-            request: Request {
-                payload: 2,
-                client: Default::default(),
-                id: Default::default(),
-            },
-            prediction: (),
-            committed: OpNumber::default(),
-        };
+        for payload in 1..=5 {
+            let request = client.new_request(payload);
 
-        replica.handle_prepare(message.clone(), &mut mailbox);
+            if payload < 5 {
+                backup.log.push(backup.view, request.clone(), ());
+            }
 
-        assert_eq!(
-            mailbox.pop_inbound().map(ProtocolPayload::unwrap_prepare),
-            Some(message)
-        );
+            primary.log.push(primary.view, request, ());
+        }
 
-        let mut messages = Vec::from_iter(mailbox.drain_send());
-        let outbound = GetState {
-            view: replica.view,
-            op_number: replica.log.last_op_number(),
-            index: replica.index,
-        };
-        let envelope = messages.pop().unwrap();
+        primary.commit_operations(primary.log.last_op_number(), &mut mailbox);
+        primary.checkpoint_with_suffix(1).unwrap();
+        primary.log.push(primary.view, client.new_request(6), ());
+        mailbox.drain_replies().for_each(drop);
 
-        assert_ne!(envelope.destination, replica.index);
-        assert_eq!(envelope.payload.unwrap_get_state(), outbound);
-        assert!(messages.is_empty());
-        assert!(mailbox.is_empty());
-    }
+        // The backup holds every entry before the first one left in the log of the primary.
+        assert_eq!(backup.log.next_op_number(), primary.log.first_op_number());
 
-    #[test]
-    fn sender_behind_prepare_ok() {
-        let configuration = Configuration::from(3);
-        let mut replica = Replica::new(configuration, 2, 0);
-        let mut mailbox = BufferedMailbox::default();
+        backup.state_transfer(backup.view, &mut mailbox);
 
-        replica.view.increment();
-        replica.view.increment();
+        let get_state = mailbox.take_send::<GetState>().unwrap().payload;
 
-        let message = PrepareOk {
-            view: View::default().next(),
-            op_number: OpNumber::default().next(),
-            index: 0,
-        };
+        primary.handle_get_state(get_state, &mut mailbox);
 
-        replica.handle_prepare_ok(message, &mut mailbox);
+        let new_state = mailbox.take_send::<NewState<_, _>>().unwrap().payload;
 
-        assert_eq!(Vec::from_iter(mailbox.drain_inbound()), vec![]);
+        assert_eq!(new_state.log.len(), 2);
+
+        backup.handle_new_state(new_state, &mut mailbox);
+
+        let envelope = mailbox.take_send::<PrepareOk>().unwrap();
+
+        assert_eq!(envelope.destination, 0);
+        assert_eq!(envelope.payload.op_number, primary.log.last_op_number());
         assert!(mailbox.is_empty());
+        assert_eq!(backup.committed, primary.committed);
+        assert_eq!(backup.checkpoint().state, 15);
+        assert_eq!(backup.log.last_op_number(), primary.log.last_op_number());
     }
 
     #[test]
-    fn sender_ahead_prepare_ok() {
+    fn anti_entropy_repair() {
         let configuration = Configuration::from(3);
-        let mut replica = Replica::new(configuration, 1, 0);
+        let mut primary = Replica::new(configuration, 0, 0);
+        let mut backup = Replica::new(configuration, 1, 0);
         let mut mailbox = BufferedMailbox::default();
+        let mut client = crate::Client::new(configuration);
 
-        let message = PrepareOk {
-            view: View::default().next(),
-            op_number: OpNumber::default().next(),
-            index: 0,
-        };
+        primary.enable_anti_entropy(1);
+        backup.enable_anti_entropy(1);
 
-        replica.handle_prepare_ok(message.clone(), &mut mailbox);
+        for payload in 1..=70 {
+            let request = client.new_request(payload);
 
-        assert_eq!(
-            mailbox
-                .pop_inbound()
-                .map(ProtocolPayload::unwrap_prepare_ok),
-            Some(message)
-        );
+            backup.log.push(
+                backup.view,
+                Request {
+                    payload: if payload == 10 { -1 } else { payload },
+                    ..request.clone()
+                },
+                (),
+            );
+            primary.log.push(primary.view, request, ());
+        }
 
-        let mut messages = Vec::from_iter(mailbox.drain_send());
-        let outbound = GetState {
-            view: replica.view,
-            op_number: replica.log.last_op_number(),
-            index: replica.index,
-        };
-        let envelope = messages.pop().unwrap();
+        primary.commit_operations(primary.log.last_op_number(), &mut mailbox);
+        backup.commit_operations(backup.log.last_op_number(), &mut mailbox);
+        mailbox.drain_replies().for_each(drop);
+
+        primary.idle(&mut mailbox);
+
+        let digest = mailbox.take_broadcast::<Digest>().unwrap();
+
+        assert_eq!(digest.ranges.len(), 1);
+        assert!(mailbox.take_broadcast::<Commit>().is_some());
+
+        backup.handle_digest(digest.clone(), &mut mailbox);
+
+        let get_state = mailbox.take_send::<GetState>().unwrap();
+
+        assert_eq!(get_state.destination, 0);
+        assert_eq!(get_state.payload.op_number, OpNumber::default());
+
+        primary.handle_get_state(get_state.payload, &mut mailbox);
+
+        let new_state = mailbox.take_send::<NewState<_, _>>().unwrap().payload;
+
+        backup.handle_new_state(new_state, &mut mailbox);
+
+        assert!(mailbox.is_empty());
+        assert_eq!(backup.log[OpNumber::from(10)].request().payload, 10);
+        assert_eq!(backup.log.last_op_number(), primary.log.last_op_number());
+
+        backup.handle_digest(digest, &mut mailbox);
 
-        assert_ne!(envelope.destination, replica.index);
-        assert_eq!(envelope.payload.unwrap_get_state(), outbound);
-        assert!(messages.is_empty());
         assert!(mailbox.is_empty());
     }
 
     #[test]
-    fn sender_behind_commit() {
+    fn view_change_missing_entries() {
         let configuration = Configuration::from(3);
-        let mut replica = Replica::new(configuration, 0, 0);
+        let mut replicas =
+            Vec::from_iter((0..3).map(|index| Replica::new(configuration, index, 0)));
         let mut mailbox = BufferedMailbox::default();
+        let mut client = crate::Client::new(configuration);
 
-        replica.view.increment();
-        replica.view.increment();
+        for payload in 1..=4 {
+            let request = client.new_request(payload);
 
-        let message = Commit {
-            view: View::default().next(),
-            committed: OpNumber::default().next(),
-        };
+            if payload <= 2 {
+                replicas[1].log.push(View::default(), request.clone(), ());
+            }
 
-        replica.handle_commit(message, &mut mailbox);
+            replicas[2].log.push(View::default(), request, ());
+        }
 
-        assert_eq!(Vec::from_iter(mailbox.drain_inbound()), vec![]);
-        assert!(mailbox.is_empty());
+        for replica in replicas.iter_mut().skip(1) {
+            replica.handle_start_view_change(
+                StartViewChange {
+                    view: View::default().next(),
+                    epoch: 0,
+                    index: 0,
+                },
+                &mut mailbox,
+            );
+        }
+
+        mailbox.drain_broadcast().for_each(drop);
+
+        let mut messages = Vec::new();
+
+        while !mailbox.is_empty() {
+            for envelope in Vec::from_iter(mailbox.drain_send()) {
+                messages.push(envelope.payload.clone());
+                replicas[envelope.destination].handle_payload(envelope.payload, &mut mailbox);
+            }
+
+            if let Some(ProtocolPayload::StartView(message)) = mailbox.drain_broadcast().next() {
+                assert_eq!(message.log.len(), 4);
+                break;
+            }
+        }
+
+        let get_log = messages
+            .iter()
+            .find_map(|message| match message {
+                ProtocolPayload::GetLog(message) => Some(message.clone()),
+                _ => None,
+            })
+            .unwrap();
+
+        assert!(messages.iter().all(|message| match message {
+            ProtocolPayload::DoViewChange(message) => message.log.is_empty(),
+            _ => true,
+        }));
+        let mut op_number = OpNumber::default();
+
+        op_number.increment_by(2);
+
+        assert_eq!(get_log.op_number, op_number);
+        assert!(replicas[1].status == Status::Normal);
+        assert_eq!(replicas[1].log, replicas[2].log);
+        assert_eq!(replicas[1].log.len(), 4);
     }
 
     #[test]
-    fn sender_ahead_commit() {
-        let configuration = Configuration::from(3);
-        let mut replica = Replica::new(configuration, 0, 0);
-        let mut mailbox = BufferedMailbox::default();
+    fn view_change_message_sizes() {
+        /// Runs a view change where the new primary holds the given number of entries and the backup half of them,
+        /// returning the serialized sizes of the do-view-change and start-view messages.
+        fn view_change(entries: u128) -> (usize, usize) {
+            let configuration = Configuration::from(3);
+            let mut replicas =
+                Vec::from_iter((0..3).map(|index| Replica::new(configuration, index, 0)));
+            let mut mailbox = BufferedMailbox::default();
+            let mut client = crate::Client::new(configuration);
+
+            for payload in 1..=entries {
+                let request = client.new_request(payload as i32);
+
+                if payload <= entries / 2 {
+                    replicas[2].log.push(View::default(), request.clone(), ());
+                }
 
-        let message = Commit {
-            view: View::default().next(),
-            committed: OpNumber::default().next(),
-        };
+                replicas[1].log.push(View::default(), request, ());
+            }
 
-        replica.handle_commit(message.clone(), &mut mailbox);
+            for replica in replicas.iter_mut().skip(1) {
+                replica.handle_start_view_change(
+                    StartViewChange {
+                        view: View::default().next(),
+                        epoch: 0,
+                        index: 0,
+                    },
+                    &mut mailbox,
+                );
+            }
 
-        assert_eq!(
-            mailbox.pop_inbound().map(ProtocolPayload::unwrap_commit),
-            Some(message)
-        );
+            mailbox.drain_broadcast().for_each(drop);
 
-        let mut messages = Vec::from_iter(mailbox.drain_send());
-        let outbound = GetState {
-            view: replica.view,
-            op_number: replica.log.last_op_number(),
-            index: replica.index,
-        };
-        let envelope = messages.pop().unwrap();
+            let mut do_view_change_size = 0;
 
-        assert_ne!(envelope.destination, replica.index);
-        assert_eq!(envelope.payload.unwrap_get_state(), outbound);
-        assert!(messages.is_empty());
-        assert!(mailbox.is_empty());
+            for envelope in mailbox.select_send::<DoViewChange<_, _>>() {
+                if envelope.payload.index == 2 {
+                    do_view_change_size = serde_json::to_vec(&envelope.payload).unwrap().len();
+                }
+
+                replicas[envelope.destination]
+                    .handle_do_view_change(envelope.payload, &mut mailbox);
+            }
+
+            let start_view = mailbox.take_broadcast::<StartView<_, _>>().unwrap();
+            let start_view_size = serde_json::to_vec(&start_view).unwrap().len();
+
+            replicas[2].handle_start_view(start_view, &mut mailbox);
+
+            // The backup adopts the log of the new view in place of its own.
+            assert!(replicas[2].status == Status::Normal);
+            assert_eq!(replicas[2].log, replicas[1].log);
+            assert_eq!(replicas[2].log.len(), entries as usize);
+
+            (do_view_change_size, start_view_size)
+        }
+
+        let (small_do_view_change, small_start_view) = view_change(10);
+        let (large_do_view_change, large_start_view) = view_change(1000);
+
+        // Votes only carry a summary of the log, so their size only grows with the digits of its op-numbers.
+        assert!(large_do_view_change <= small_do_view_change + 8);
+        // The new view's log is sent in full to the backups.
+        assert!(large_start_view > small_start_view * 50);
     }
 
     #[test]
-    fn sender_behind_get_state() {
+    fn duplicate_prepare() {
         let configuration = Configuration::from(3);
-        let mut replica = Replica::new(configuration, 0, 0);
+        let mut primary = Replica::new(configuration, 0, 0);
+        let mut backup = Replica::new(configuration, 1, 0);
         let mut mailbox = BufferedMailbox::default();
+        let mut client = crate::Client::new(configuration);
 
-        replica.view.increment();
-        replica.view.increment();
+        primary.handle_request(client.new_request(1), &mut mailbox);
 
-        let message = GetState {
-            view: View::default().next(),
-            op_number: OpNumber::default(),
-            index: 1,
-        };
+        let prepare = mailbox.drain_broadcast().next().unwrap();
 
-        replica.handle_get_state(message, &mut mailbox);
+        backup.handle_payload(prepare.clone(), &mut mailbox);
+        backup.handle_payload(prepare.clone(), &mut mailbox);
 
-        assert_eq!(Vec::from_iter(mailbox.drain_inbound()), vec![]);
+        assert_eq!(backup.log.len(), 1);
+        assert_eq!(mailbox.drain_send().count(), 1);
+
+        backup.commit_operations(backup.log.last_op_number(), &mut mailbox);
+        backup.log.constrain(0);
+        backup.handle_payload(prepare, &mut mailbox);
+
+        assert!(backup.log.is_empty());
+        assert_eq!(backup.log.last_op_number(), primary.log.last_op_number());
         assert!(mailbox.is_empty());
     }
 
     #[test]
-    fn sender_ahead_get_state() {
+    fn reordered_prepare() {
         let configuration = Configuration::from(3);
-        let mut replica = Replica::new(configuration, 0, 0);
+        let mut primary = Replica::new(configuration, 0, 0);
+        let mut backup = Replica::new(configuration, 1, 0);
         let mut mailbox = BufferedMailbox::default();
+        let mut clients = [
+            crate::Client::new(configuration),
+            crate::Client::new(configuration),
+        ];
 
-        let message = GetState {
-            view: View::default().next(),
-            op_number: OpNumber::default().next(),
-            index: 1,
-        };
+        primary.handle_request(clients[0].new_request(1), &mut mailbox);
+        primary.handle_request(clients[1].new_request(2), &mut mailbox);
 
-        replica.handle_get_state(message.clone(), &mut mailbox);
+        let mut prepares = Vec::from_iter(mailbox.drain_broadcast());
 
-        assert_eq!(
-            mailbox.pop_inbound().map(ProtocolPayload::unwrap_get_state),
-            Some(message)
-        );
+        backup.handle_payload(prepares[1].clone(), &mut mailbox);
 
-        let mut messages = Vec::from_iter(mailbox.drain_send());
-        let outbound = GetState {
-            view: replica.view,
-            op_number: replica.log.last_op_number(),
-            index: replica.index,
-        };
-        let envelope = messages.pop().unwrap();
+        assert!(backup.log.is_empty());
+        assert!(mailbox
+            .drain_send()
+            .next()
+            .map(|e| e.payload.unwrap_get_state())
+            .is_some());
 
-        assert_ne!(envelope.destination, replica.index);
-        assert_eq!(envelope.payload.unwrap_get_state(), outbound);
-        assert!(messages.is_empty());
-        assert!(mailbox.is_empty());
+        backup.handle_payload(prepares.remove(0), &mut mailbox);
+
+        while let Some(message) = mailbox.pop_inbound() {
+            backup.handle_payload(message, &mut mailbox);
+        }
+
+        backup.handle_payload(prepares.remove(0), &mut mailbox);
+
+        assert_eq!(backup.log, primary.log);
+        assert_eq!(
+            Vec::from_iter(
+                mailbox
+                    .drain_send()
+                    .map(|e| e.payload.unwrap_prepare_ok().op_number)
+            ),
+            vec![OpNumber::default().next(), primary.log.last_op_number()]
+        );
     }
 
     #[test]
@@ -901,14 +5145,20 @@ mod tests {
                 payload: 2,
                 client: Default::default(),
                 id: Default::default(),
+                deadline: None,
+                signature: None,
+                epoch: 0,
+                staleness: None,
             },
             (),
         );
 
         let message = NewState {
             view: View::default().next(),
+            epoch: 0,
             log: Log::default(),
             committed: OpNumber::default().next(),
+            op_number: OpNumber::default().next(),
         };
 
         replica.handle_new_state(message.clone(), &mut outbox);
@@ -917,4 +5167,24 @@ mod tests {
         assert_ne!(replica.committed, message.committed);
         assert!(outbox.is_empty());
     }
+
+    #[test]
+    fn idle_timeout() {
+        let timeout = Duration::from_millis(100);
+        let jitter = Duration::from_millis(50);
+        let configuration = Configuration::from(3)
+            .with_commit_interval(Duration::from_millis(10))
+            .with_view_timeout(timeout, jitter);
+        let primary = Replica::new(configuration, 0, 0);
+        let backup = Replica::new(configuration, 1, 0);
+
+        assert_eq!(primary.idle_timeout(), Duration::from_millis(10));
+
+        for _ in 0..100 {
+            let idle_timeout = backup.idle_timeout();
+
+            assert!(idle_timeout >= timeout);
+            assert!(idle_timeout <= timeout + jitter);
+        }
+    }
 }
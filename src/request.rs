@@ -1,15 +1,32 @@
-use crate::viewstamp::View;
+use crate::configuration::{Configuration, Group};
+use crate::viewstamp::{OpNumber, View};
+use alloc::vec::Vec;
+use core::net::SocketAddr;
+use core::time::Duration;
 use serde::{Deserialize, Serialize};
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub struct ClientIdentifier(u128);
 
+#[cfg(feature = "os")]
 impl Default for ClientIdentifier {
     fn default() -> Self {
         Self(uuid::Uuid::new_v4().as_u128())
     }
 }
 
+impl From<u128> for ClientIdentifier {
+    fn from(value: u128) -> Self {
+        Self(value)
+    }
+}
+
+impl From<ClientIdentifier> for u128 {
+    fn from(value: ClientIdentifier) -> Self {
+        value.0
+    }
+}
+
 #[derive(
     Copy, Clone, Debug, Default, Ord, PartialOrd, Eq, PartialEq, Hash, Serialize, Deserialize,
 )]
@@ -25,6 +42,12 @@ impl RequestIdentifier {
     }
 }
 
+impl From<RequestIdentifier> for u128 {
+    fn from(value: RequestIdentifier) -> Self {
+        value.0
+    }
+}
+
 #[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Request<R> {
     /// The operation (with its arguments) the client wants to run.
@@ -33,6 +56,57 @@ pub struct Request<R> {
     pub client: ClientIdentifier,
     /// Client-assigned number for the request.
     pub id: RequestIdentifier,
+    /// The time since the Unix epoch after which the client no longer waits for the reply.
+    #[serde(default)]
+    pub deadline: Option<Duration>,
+    /// Proof that the request came from the client, checked by a primary with a [`crate::Verifier`].
+    #[serde(default)]
+    pub signature: Option<Signature>,
+    /// The epoch of the configuration the client sent the request with.
+    /// Replicas only handle requests from their own epoch, and point clients from earlier epochs at the current one.
+    #[serde(default)]
+    pub epoch: u64,
+    /// Marks a read-only request that backups may answer from their committed state within the given bound.
+    #[serde(default)]
+    pub staleness: Option<Staleness>,
+}
+
+impl<R> Request<R> {
+    /// Sets the time since the Unix epoch after which the primary will not start the request.
+    pub fn with_deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Marks the request as read-only, so a backup with follower reads enabled answers it from its committed state
+    /// when the state is within the given bound of the primary, and points the client at the primary otherwise.
+    /// The primary executes the request like any other, so the operation must not change the state of the service.
+    pub fn with_staleness(mut self, staleness: Staleness) -> Self {
+        self.staleness = Some(staleness);
+        self
+    }
+
+    pub fn is_expired(&self, now: Duration) -> bool {
+        self.deadline.is_some_and(|deadline| deadline <= now)
+    }
+}
+
+/// How far behind the primary the state a backup answers a read-only request from may be.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum Staleness {
+    /// The backup committed all but at most the given number of the operations the primary told it were committed.
+    Operations(u64),
+    /// The backup committed every operation the primary told it were committed at most the given duration ago.
+    /// Measured against the clock of the primary, so the bound is only as precise as the clocks are synchronized.
+    Time(Duration),
+}
+
+/// A message authentication code over a request, computed with one of the keys of the client.
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct Signature {
+    /// The version of the client key used to sign the request, so keys can be rotated without downtime.
+    pub key: u64,
+    pub mac: Vec<u8>,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
@@ -44,3 +118,151 @@ pub struct Reply<R> {
     /// The response from the service after executing the operation.
     pub payload: R,
 }
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Busy {
+    /// The current view of the replica.
+    pub view: View,
+    /// Client-assigned number for the rejected request.
+    pub id: RequestIdentifier,
+    /// How long the client should wait before retrying, which grows with the load on the primary.
+    pub backoff: Duration,
+}
+
+/// Tells a client that sent a request to a backup which replica is the primary of the current view.
+/// Clients follow the redirect with [`crate::Client::redirect`].
+#[doc(alias = "NotPrimary")]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct OutdatedView {
+    /// The current view of the replica.
+    pub view: View,
+    /// The index of the primary of the view.
+    pub primary: usize,
+    /// The epoch of the configuration of the replica. A client holding an earlier epoch discovers the group again.
+    #[serde(default)]
+    pub epoch: u64,
+}
+
+/// Tells a client that sent a request with the configuration of an earlier epoch that the members of the group changed.
+/// Clients discover the group again (e.g. with [`crate::Client::discover`]) before re-sending the request.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct OutdatedEpoch {
+    /// The current view of the replica.
+    pub view: View,
+    /// The epoch of the configuration of the replica.
+    pub epoch: u64,
+}
+
+/// Asks any replica of a group (e.g. a seed node known ahead of time) for the configuration of the group,
+/// so clients do not need to hardcode the number of replicas or the identifier of the group.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Discover {
+    pub client: ClientIdentifier,
+}
+
+/// The configuration of the group as known to the replica that answered a [`Discover`] message.
+/// Clients adopt it with [`crate::Client::rediscover`].
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Discovered {
+    /// The current view of the replica.
+    pub view: View,
+    /// The number of times operators replaced the members of the group.
+    pub epoch: u64,
+    pub group: Group,
+    /// The number of replicas in the group.
+    pub replicas: usize,
+    /// The address clients reach each replica at, ordered by replica index,
+    /// or empty when the replica does not advertise the members of its group (see [`crate::Replica::advertise_members`]).
+    #[serde(default)]
+    pub members: Vec<SocketAddr>,
+}
+
+impl Discovered {
+    /// The configuration of the group with the default tunables, which clients do not use.
+    pub fn configuration(&self) -> Configuration {
+        Configuration::new(self.replicas, self.group).with_epoch(self.epoch)
+    }
+}
+
+/// Asks the primary for the last request number it knows of for a client with a durable identifier,
+/// so a restarted client can resume its sequence of request numbers without reusing one.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct OpenSession {
+    pub client: ClientIdentifier,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct SessionAccepted {
+    /// The current view of the replica.
+    pub view: View,
+    /// The number of the latest request the group started for the client, or the default for new clients.
+    pub last_request: RequestIdentifier,
+}
+
+/// Reopens the session of a client after its connection dropped, which the primary answers like an [`OpenSession`]
+/// before delivering again the replies to the client that it has not acknowledged yet.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Resume {
+    pub client: ClientIdentifier,
+    /// The number of the latest request whose reply the client received, or the default if it received none.
+    pub acknowledged: RequestIdentifier,
+}
+
+/// Tells the primary that the client received the replies to its requests up to the given one,
+/// so the primary stops buffering them for delivery on a [`Resume`].
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Acknowledge {
+    pub client: ClientIdentifier,
+    pub request: RequestIdentifier,
+}
+
+/// Asks the primary whether the group executed a request, so a client that gave up waiting for the reply
+/// can decide whether retrying a side-effecting operation as a new request could execute it twice.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct QueryRequestStatus {
+    pub client: ClientIdentifier,
+    /// Client-assigned number for the request in question.
+    pub request: RequestIdentifier,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct RequestStatus {
+    /// The current view of the replica.
+    pub view: View,
+    /// Client-assigned number for the request in question.
+    pub request: RequestIdentifier,
+    pub state: RequestState,
+}
+
+/// What the primary of a normal view knows about a request of a client.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum RequestState {
+    /// The group executed the request. Resending it with the same number returns the cached reply
+    /// when it is the latest request of the client.
+    Executed,
+    /// The primary started the request but has not executed it yet. Query again later.
+    Pending,
+    /// The group has not started the request. A request with an expired deadline can no longer start,
+    /// so the client may safely retry it as a new request.
+    NotStarted,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Expired {
+    /// The current view of the replica.
+    pub view: View,
+    /// Client-assigned number for the request that expired before it started.
+    pub id: RequestIdentifier,
+}
+
+/// A message the primary pushes to a client without a request, carrying output of a committed operation
+/// that the service addressed to the client (e.g. a change to a key the client watches).
+/// Notifications are not cached or resent, so clients that must not miss any re-read the state after reconnecting.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Notification<R> {
+    /// The current view of the replica.
+    pub view: View,
+    /// The op-number of the committed operation that produced the notification.
+    pub op_number: OpNumber,
+    pub payload: R,
+}
@@ -0,0 +1,285 @@
+use crate::buffer::{BufferedMailbox, ClientPayload, ProtocolPayload};
+use crate::request::ClientIdentifier;
+use crate::service::Protocol;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use hashbrown::HashMap;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "std")]
+use std::collections::VecDeque;
+#[cfg(feature = "std")]
+use std::sync::mpsc::Sender;
+#[cfg(feature = "std")]
+use std::sync::{Arc, Mutex};
+
+/// The destination of a message sent by a replica.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum Address {
+    /// The replica with the given index in the group.
+    Replica(usize),
+    Client(ClientIdentifier),
+}
+
+/// Hands messages for an [`Address`] to a transport (e.g. a socket, a channel or an in-process queue).
+pub trait Endpoint<M> {
+    /// Sends the message, returning `false` if the endpoint can no longer deliver messages (e.g. the connection closed).
+    fn send(&mut self, message: M) -> bool;
+}
+
+#[cfg(feature = "std")]
+impl<M> Endpoint<M> for Sender<M> {
+    fn send(&mut self, message: M) -> bool {
+        Sender::send(self, message).is_ok()
+    }
+}
+
+/// Queues messages in memory for a handle in the same process to take.
+#[cfg(feature = "std")]
+impl<M> Endpoint<M> for Arc<Mutex<VecDeque<M>>> {
+    fn send(&mut self, message: M) -> bool {
+        match self.lock() {
+            Ok(mut queue) => {
+                queue.push_back(message);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+impl<M, T> Endpoint<M> for Box<T>
+where
+    T: Endpoint<M> + ?Sized,
+{
+    fn send(&mut self, message: M) -> bool {
+        self.as_mut().send(message)
+    }
+}
+
+type PeerEndpoint<P> = Box<dyn Endpoint<ProtocolPayload<P>> + Send>;
+type ClientEndpoint<P> = Box<dyn Endpoint<ClientPayload<P>> + Send>;
+
+/// Moves the messages a replica queued in its mailbox to the endpoints registered for their addresses,
+/// so the same replica code runs against local and networked transports.
+///
+/// Clients come and go as their connections open and close, so a client whose endpoint stops delivering messages
+/// is deregistered and must register a new endpoint when it reconnects. Messages without a registered endpoint
+/// are discarded, which the protocol recovers from like lost messages.
+pub struct Router<P>
+where
+    P: Protocol,
+{
+    replicas: HashMap<usize, PeerEndpoint<P>>,
+    clients: HashMap<ClientIdentifier, ClientEndpoint<P>>,
+    unroutable: usize,
+}
+
+impl<P> Default for Router<P>
+where
+    P: Protocol,
+{
+    fn default() -> Self {
+        Self {
+            replicas: Default::default(),
+            clients: Default::default(),
+            unroutable: 0,
+        }
+    }
+}
+
+impl<P> Router<P>
+where
+    P: Protocol,
+{
+    /// Routes messages for the replica with the given index to the endpoint, returning the endpoint it replaces.
+    pub fn register_replica<E>(&mut self, index: usize, endpoint: E) -> Option<PeerEndpoint<P>>
+    where
+        E: Endpoint<ProtocolPayload<P>> + Send + 'static,
+    {
+        self.replicas.insert(index, Box::new(endpoint))
+    }
+
+    /// Routes messages for the client to the endpoint (e.g. the connection it opened), returning the endpoint it replaces.
+    pub fn register_client<E>(
+        &mut self,
+        client: ClientIdentifier,
+        endpoint: E,
+    ) -> Option<ClientEndpoint<P>>
+    where
+        E: Endpoint<ClientPayload<P>> + Send + 'static,
+    {
+        self.clients.insert(client, Box::new(endpoint))
+    }
+
+    /// Stops routing messages to the address. Returns whether an endpoint was registered for it.
+    pub fn deregister(&mut self, address: Address) -> bool {
+        match address {
+            Address::Replica(index) => self.replicas.remove(&index).is_some(),
+            Address::Client(client) => self.clients.remove(&client).is_some(),
+        }
+    }
+
+    pub fn contains(&self, address: Address) -> bool {
+        match address {
+            Address::Replica(index) => self.replicas.contains_key(&index),
+            Address::Client(client) => self.clients.contains_key(&client),
+        }
+    }
+
+    /// Sends a message to a replica. Returns whether its endpoint accepted the message.
+    pub fn send(&mut self, index: usize, message: ProtocolPayload<P>) -> bool {
+        let sent = self
+            .replicas
+            .get_mut(&index)
+            .is_some_and(|endpoint| endpoint.send(message));
+
+        if !sent {
+            self.unroutable += 1;
+        }
+
+        sent
+    }
+
+    /// Sends a message to a client. Returns whether its endpoint accepted the message.
+    pub fn reply(&mut self, client: ClientIdentifier, message: ClientPayload<P>) -> bool {
+        let sent = match self.clients.get_mut(&client) {
+            Some(endpoint) => {
+                let sent = endpoint.send(message);
+
+                if !sent {
+                    self.clients.remove(&client);
+                }
+
+                sent
+            }
+            None => false,
+        };
+
+        if !sent {
+            self.unroutable += 1;
+        }
+
+        sent
+    }
+
+    /// Moves every outbound message out of the mailbox of the replica with the given index,
+    /// sending broadcasts to every registered replica other than the source.
+    /// Returns the number of messages the endpoints accepted.
+    pub fn route(&mut self, source: usize, mailbox: &mut BufferedMailbox<P>) -> usize {
+        let mut sent = 0;
+
+        for envelope in mailbox.drain_replies() {
+            sent += usize::from(self.reply(envelope.destination, envelope.payload));
+        }
+
+        for envelope in mailbox.drain_send() {
+            sent += usize::from(self.send(envelope.destination, envelope.payload));
+        }
+
+        for message in mailbox.drain_broadcast() {
+            let mut destinations: Vec<usize> = self.replicas.keys().copied().collect();
+
+            destinations.sort_unstable();
+
+            for index in destinations.into_iter().filter(|&index| index != source) {
+                sent += usize::from(self.send(index, message.clone()));
+            }
+        }
+
+        sent
+    }
+
+    /// The number of messages discarded because their address had no endpoint or the endpoint failed to send them.
+    pub fn unroutable(&self) -> usize {
+        self.unroutable
+    }
+}
+
+#[cfg(all(test, feature = "os"))]
+mod tests {
+    use super::*;
+    use crate::client::Client;
+    use crate::configuration::Configuration;
+    use crate::protocol::Prepare;
+    use crate::replica::Replica;
+    use std::sync::mpsc;
+
+    #[test]
+    fn route() {
+        let configuration = Configuration::from(3);
+        let mut router = Router::<i32>::default();
+        let mut primary = Replica::new(configuration, 0, 0);
+        let mut mailbox = BufferedMailbox::default();
+        let mut client = Client::new(configuration);
+        let backup = Arc::new(Mutex::new(VecDeque::new()));
+        let (sender, receiver) = mpsc::channel();
+
+        router.register_replica(1, backup.clone());
+        router.register_replica(2, sender);
+
+        primary.handle_request(client.new_request(1), &mut mailbox);
+
+        assert_eq!(router.route(0, &mut mailbox), 2);
+        assert!(mailbox.is_empty());
+
+        let prepare = backup.lock().unwrap().pop_front().unwrap().unwrap_prepare();
+
+        assert_eq!(receiver.try_recv().unwrap().unwrap_prepare(), prepare);
+
+        // Broadcasts skip replicas without an endpoint, while sends to them are discarded.
+        assert!(router.deregister(Address::Replica(2)));
+        assert!(!router.contains(Address::Replica(2)));
+
+        let mut other = Client::new(configuration);
+
+        primary.handle_request(other.new_request(2), &mut mailbox);
+
+        assert_eq!(router.route(0, &mut mailbox), 1);
+        assert_eq!(backup.lock().unwrap().len(), 1);
+        assert!(!router.send(2, ProtocolPayload::Prepare(prepare)));
+        assert_eq!(router.unroutable(), 1);
+    }
+
+    #[test]
+    fn client_reconnect() {
+        let configuration = Configuration::from(3);
+        let mut router = Router::<i32>::default();
+        let mut primary = Replica::new(configuration, 0, 0);
+        let mut backup = Replica::new(configuration, 1, 0);
+        let mut mailbox = BufferedMailbox::default();
+        let mut client = Client::new(configuration);
+        let (sender, receiver) = mpsc::channel();
+
+        router.register_client(client.identifier(), sender);
+        primary.handle_request(client.new_request(1), &mut mailbox);
+
+        let prepare = mailbox.take_broadcast::<Prepare<i32, ()>>().unwrap();
+
+        backup.handle_payload(ProtocolPayload::Prepare(prepare), &mut mailbox);
+
+        let prepare_ok = mailbox.take_send::<crate::PrepareOk>().unwrap().payload;
+
+        primary.handle_payload(ProtocolPayload::PrepareOk(prepare_ok), &mut mailbox);
+
+        // The connection of the client closed, so its endpoint is deregistered until it reconnects.
+        drop(receiver);
+
+        assert_eq!(router.route(0, &mut mailbox), 0);
+        assert!(!router.contains(Address::Client(client.identifier())));
+
+        let connection = Arc::new(Mutex::new(VecDeque::new()));
+
+        router.register_client(client.identifier(), connection.clone());
+        router.reply(
+            client.identifier(),
+            ClientPayload::Reply(crate::Reply {
+                view: Default::default(),
+                id: client.last_request(),
+                payload: 1,
+            }),
+        );
+
+        assert_eq!(connection.lock().unwrap().len(), 1);
+        assert_eq!(router.unroutable(), 1);
+    }
+}
@@ -0,0 +1,293 @@
+use crate::request::ClientIdentifier;
+use crate::service::{Conflicts, Context, Protocol, Role, Rollback, Service};
+use crate::viewstamp::View;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::error::Error;
+use core::fmt::{Display, Formatter};
+use serde::{Deserialize, Serialize};
+
+/// Runs several instances of a service in one replica group, each registered under its own key
+/// (e.g. a string or an enum of tenants), so small state machines (e.g. locks, configuration and counters)
+/// share a group instead of each running their own. Each request names the instance it targets.
+///
+/// Every replica must register the same keys, since the instances are part of the replicated state.
+/// Services of different types can share a group by wrapping them in an enum that implements [`Service`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Routed<K, S> {
+    services: BTreeMap<K, S>,
+}
+
+impl<K, S> Default for Routed<K, S> {
+    fn default() -> Self {
+        Self {
+            services: Default::default(),
+        }
+    }
+}
+
+impl<K, S> Routed<K, S>
+where
+    K: Ord,
+{
+    /// Registers the service under the given key, replacing any service already registered under it.
+    pub fn with_service(mut self, key: K, service: S) -> Self {
+        self.services.insert(key, service);
+        self
+    }
+
+    pub fn service(&self, key: &K) -> Option<&S> {
+        self.services.get(key)
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.services.keys()
+    }
+
+    pub fn into_inner(self) -> BTreeMap<K, S> {
+        self.services
+    }
+}
+
+/// A request for the service registered under the key in a [`Routed`] service.
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct RoutedRequest<K, R> {
+    pub service: K,
+    pub operation: R,
+}
+
+impl<K, R> RoutedRequest<K, R> {
+    pub fn new(service: K, operation: R) -> Self {
+        Self { service, operation }
+    }
+}
+
+/// The reply to a request for a key without a registered service.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct UnknownService;
+
+impl Display for UnknownService {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "no service is registered under the requested key")
+    }
+}
+
+impl Error for UnknownService {}
+
+/// The checkpoint of a [`Routed`] service, holding the checkpoint of each registered service by its key.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(
+    transparent,
+    bound(deserialize = "K: Ord + Deserialize<'de>, C: Deserialize<'de>")
+)]
+pub struct RoutedCheckpoint<K, C>(pub BTreeMap<K, C>);
+
+impl<K, S> Protocol for Routed<K, S>
+where
+    K: Clone + Ord,
+    S: Protocol,
+{
+    type Request = RoutedRequest<K, S::Request>;
+    type Prediction = Option<S::Prediction>;
+    type Reply = Result<S::Reply, UnknownService>;
+    type Checkpoint = RoutedCheckpoint<K, S::Checkpoint>;
+}
+
+impl<K, S> From<RoutedCheckpoint<K, S::Checkpoint>> for Routed<K, S>
+where
+    K: Clone + Ord,
+    S: Service,
+{
+    fn from(checkpoint: RoutedCheckpoint<K, S::Checkpoint>) -> Self {
+        Self {
+            services: checkpoint
+                .0
+                .into_iter()
+                .map(|(key, checkpoint)| (key, S::from(checkpoint)))
+                .collect(),
+        }
+    }
+}
+
+impl<K, S> Service for Routed<K, S>
+where
+    K: Clone + Ord,
+    S: Service,
+{
+    fn predict(&self, request: &RoutedRequest<K, S::Request>) -> Option<S::Prediction> {
+        self.services
+            .get(&request.service)
+            .map(|service| service.predict(&request.operation))
+    }
+
+    fn checkpoint(&self) -> RoutedCheckpoint<K, S::Checkpoint> {
+        RoutedCheckpoint(
+            self.services
+                .iter()
+                .map(|(key, service)| (key.clone(), service.checkpoint()))
+                .collect(),
+        )
+    }
+
+    fn invoke(
+        &mut self,
+        request: &RoutedRequest<K, S::Request>,
+        prediction: &Option<S::Prediction>,
+    ) -> Result<S::Reply, UnknownService> {
+        match (self.services.get_mut(&request.service), prediction) {
+            (Some(service), Some(prediction)) => Ok(service.invoke(&request.operation, prediction)),
+            _ => Err(UnknownService),
+        }
+    }
+
+    fn invoke_with_context(
+        &mut self,
+        request: &RoutedRequest<K, S::Request>,
+        prediction: &Option<S::Prediction>,
+        context: &Context,
+    ) -> Result<S::Reply, UnknownService> {
+        match (self.services.get_mut(&request.service), prediction) {
+            (Some(service), Some(prediction)) => {
+                Ok(service.invoke_with_context(&request.operation, prediction, context))
+            }
+            _ => Err(UnknownService),
+        }
+    }
+
+    fn on_role_change(&mut self, role: Role, view: View) {
+        for service in self.services.values_mut() {
+            service.on_role_change(role, view);
+        }
+    }
+
+    fn take_notifications(&mut self) -> Vec<(ClientIdentifier, Result<S::Reply, UnknownService>)> {
+        self.services
+            .values_mut()
+            .flat_map(|service| service.take_notifications())
+            .map(|(client, payload)| (client, Ok(payload)))
+            .collect()
+    }
+}
+
+impl<K, S> Conflicts for Routed<K, S>
+where
+    K: Clone + Ord + Sync,
+    S: Conflicts,
+{
+    /// Requests for different services never conflict.
+    fn conflicts(
+        &self,
+        a: &RoutedRequest<K, S::Request>,
+        b: &RoutedRequest<K, S::Request>,
+    ) -> bool {
+        a.service == b.service
+            && self
+                .services
+                .get(&a.service)
+                .is_some_and(|service| service.conflicts(&a.operation, &b.operation))
+    }
+
+    fn invoke_concurrently(
+        &self,
+        request: &RoutedRequest<K, S::Request>,
+        prediction: &Option<S::Prediction>,
+    ) -> Result<S::Reply, UnknownService> {
+        match (self.services.get(&request.service), prediction) {
+            (Some(service), Some(prediction)) => {
+                Ok(service.invoke_concurrently(&request.operation, prediction))
+            }
+            _ => Err(UnknownService),
+        }
+    }
+}
+
+impl<K, S> Rollback for Routed<K, S>
+where
+    K: Clone + Ord,
+    S: Rollback,
+{
+    type Undo = Option<(K, S::Undo)>;
+
+    fn invoke_speculatively(
+        &mut self,
+        request: &RoutedRequest<K, S::Request>,
+        prediction: &Option<S::Prediction>,
+    ) -> (Result<S::Reply, UnknownService>, Option<(K, S::Undo)>) {
+        match (self.services.get_mut(&request.service), prediction) {
+            (Some(service), Some(prediction)) => {
+                let (reply, undo) = service.invoke_speculatively(&request.operation, prediction);
+
+                (Ok(reply), Some((request.service.clone(), undo)))
+            }
+            _ => (Err(UnknownService), None),
+        }
+    }
+
+    fn rollback(&mut self, undo: Option<(K, S::Undo)>) {
+        if let Some((key, undo)) = undo {
+            if let Some(service) = self.services.get_mut(&key) {
+                service.rollback(undo);
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "os"))]
+mod tests {
+    use super::*;
+    use crate::buffer::BufferedMailbox;
+    use crate::protocol::PrepareOk;
+    use crate::{Client, Configuration, Replica};
+
+    #[test]
+    fn routing() {
+        let configuration = Configuration::from(3);
+        let service = Routed::default()
+            .with_service("counter".to_string(), 0)
+            .with_service("offset".to_string(), 100);
+        let mut replica = Replica::new(configuration, 0, service);
+        let mut mailbox = BufferedMailbox::default();
+        let mut client = Client::new(configuration);
+        let mut replies = Vec::new();
+
+        for (key, operation) in [
+            ("counter", 2),
+            ("offset", 3),
+            ("counter", 4),
+            ("missing", 1),
+        ] {
+            let request = client.new_request(RoutedRequest::new(key.to_string(), operation));
+
+            replica.handle_request(request, &mut mailbox);
+
+            let prepare = mailbox.drain_broadcast().next().unwrap().unwrap_prepare();
+
+            replica.handle_prepare_ok(
+                PrepareOk {
+                    view: replica.view(),
+                    epoch: 0,
+                    op_number: prepare.op_number,
+                    index: 1,
+                    committed: Default::default(),
+                    promised: replica.view(),
+                },
+                &mut mailbox,
+            );
+
+            replies.extend(
+                mailbox
+                    .drain_replies()
+                    .map(|envelope| envelope.payload.unwrap_reply().payload),
+            );
+        }
+
+        assert_eq!(replies, vec![Ok(2), Ok(103), Ok(6), Err(UnknownService)]);
+
+        let checkpoint = replica.checkpoint().state;
+        let restored = Routed::<String, i32>::from(checkpoint.clone());
+
+        assert_eq!(checkpoint.0.get("counter"), Some(&6));
+        assert_eq!(restored.service(&"offset".to_string()), Some(&103));
+        assert_eq!(Vec::from_iter(restored.keys()), vec!["counter", "offset"]);
+    }
+}
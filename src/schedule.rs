@@ -0,0 +1,248 @@
+use crate::buffer::{BufferedMailbox, ProtocolPayload};
+use crate::service::Protocol;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+/// The state of the outbound queue of a single peer of a [`SendScheduler`].
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct PeerStats {
+    /// The number of messages waiting to be handed to the transport.
+    pub queued: usize,
+    /// The number of messages handed to the transport that it has not finished sending yet.
+    pub in_flight: usize,
+    /// The total number of messages handed to the transport.
+    pub sent: usize,
+    /// The total number of messages discarded because the queue of the peer was full.
+    pub dropped: usize,
+}
+
+struct Peer<M> {
+    queue: VecDeque<M>,
+    stats: PeerStats,
+}
+
+/// Schedules outbound messages across the peers of a replica, so a slow peer does not hold up the others
+/// (e.g. when a primary broadcasts prepares to many backups).
+///
+/// Every peer has its own queue, and the scheduler hands the transport one message at a time from each peer
+/// with queued messages in turn. With an in-flight limit, a peer whose transport has not finished sending earlier
+/// messages (see [`SendScheduler::complete`]) is skipped until it catches up, while the other peers keep going.
+pub struct SendScheduler<M> {
+    peers: Vec<Peer<M>>,
+    /// The peer to visit first on the next call to [`SendScheduler::next`].
+    cursor: usize,
+    in_flight_limit: Option<usize>,
+    queue_limit: Option<usize>,
+}
+
+impl<M> SendScheduler<M> {
+    /// Creates a scheduler for a group with the given number of replicas (observers included).
+    pub fn new(peers: usize) -> Self {
+        Self {
+            peers: (0..peers)
+                .map(|_| Peer {
+                    queue: VecDeque::new(),
+                    stats: PeerStats::default(),
+                })
+                .collect(),
+            cursor: 0,
+            in_flight_limit: None,
+            queue_limit: None,
+        }
+    }
+
+    /// Limits the number of messages the transport may be sending to a single peer at once.
+    pub fn with_in_flight_limit(mut self, limit: usize) -> Self {
+        self.in_flight_limit = Some(limit.max(1));
+        self
+    }
+
+    /// Limits the number of messages queued for a single peer, discarding the oldest message to make room.
+    /// The protocol recovers from the discarded messages like it does from lost ones (e.g. with a state transfer).
+    pub fn with_queue_limit(mut self, limit: usize) -> Self {
+        self.queue_limit = Some(limit.max(1));
+        self
+    }
+
+    /// Queues a message for the peer with the given index. Messages for unknown peers are discarded.
+    pub fn push(&mut self, peer: usize, message: M) {
+        let Some(peer) = self.peers.get_mut(peer) else {
+            return;
+        };
+
+        if self
+            .queue_limit
+            .is_some_and(|limit| peer.queue.len() >= limit)
+        {
+            peer.queue.pop_front();
+            peer.stats.dropped += 1;
+        }
+
+        peer.queue.push_back(message);
+        peer.stats.queued = peer.queue.len();
+    }
+
+    /// Queues a copy of the message for every peer except the sender.
+    pub fn broadcast(&mut self, source: usize, message: M)
+    where
+        M: Clone,
+    {
+        for peer in 0..self.peers.len() {
+            if peer != source {
+                self.push(peer, message.clone());
+            }
+        }
+    }
+
+    /// Removes the next message to hand to the transport along with the index of its destination,
+    /// visiting the peers in turn and skipping peers at their in-flight limit.
+    /// Returns `None` when no peer has a message that can be sent.
+    pub fn pop(&mut self) -> Option<(usize, M)> {
+        let peers = self.peers.len();
+
+        for offset in 0..peers {
+            let index = (self.cursor + offset) % peers;
+            let peer = &mut self.peers[index];
+
+            if self
+                .in_flight_limit
+                .is_some_and(|limit| peer.stats.in_flight >= limit)
+            {
+                continue;
+            }
+
+            if let Some(message) = peer.queue.pop_front() {
+                peer.stats.queued = peer.queue.len();
+                peer.stats.in_flight += 1;
+                peer.stats.sent += 1;
+                self.cursor = (index + 1) % peers;
+
+                return Some((index, message));
+            }
+        }
+
+        None
+    }
+
+    /// Records that the transport finished sending a message to the peer (successfully or not),
+    /// making room for another message within the in-flight limit.
+    pub fn complete(&mut self, peer: usize) {
+        if let Some(peer) = self.peers.get_mut(peer) {
+            peer.stats.in_flight = peer.stats.in_flight.saturating_sub(1);
+        }
+    }
+
+    /// The state of the queue of the peer with the given index, if the peer is part of the group.
+    pub fn stats(&self, peer: usize) -> Option<PeerStats> {
+        self.peers.get(peer).map(|peer| peer.stats)
+    }
+
+    /// The total number of messages waiting to be handed to the transport across all peers.
+    pub fn queued(&self) -> usize {
+        self.peers.iter().map(|peer| peer.stats.queued).sum()
+    }
+}
+
+impl<P> SendScheduler<ProtocolPayload<P>>
+where
+    P: Protocol,
+{
+    /// Moves the messages the replica with the given index queued for other replicas out of its mailbox
+    /// and into the queues of their destinations.
+    pub fn schedule(&mut self, source: usize, mailbox: &mut BufferedMailbox<P>) {
+        for envelope in mailbox.drain_send() {
+            self.push(envelope.destination, envelope.payload);
+        }
+
+        for message in mailbox.drain_broadcast() {
+            self.broadcast(source, message);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mail::Outbox;
+    use crate::protocol::Commit;
+
+    #[test]
+    fn round_robin() {
+        let mut scheduler = SendScheduler::new(3);
+
+        scheduler.push(1, 'a');
+        scheduler.push(1, 'b');
+        scheduler.push(1, 'c');
+        scheduler.broadcast(0, 'd');
+        scheduler.push(7, 'e');
+
+        let order: Vec<_> = std::iter::from_fn(|| scheduler.pop()).collect();
+
+        assert_eq!(order, [(1, 'a'), (2, 'd'), (1, 'b'), (1, 'c'), (1, 'd')]);
+        assert_eq!(
+            scheduler.stats(1),
+            Some(PeerStats {
+                queued: 0,
+                in_flight: 4,
+                sent: 4,
+                dropped: 0
+            })
+        );
+        assert_eq!(scheduler.stats(7), None);
+    }
+
+    #[test]
+    fn slow_peer() {
+        let mut scheduler = SendScheduler::new(3)
+            .with_in_flight_limit(1)
+            .with_queue_limit(2);
+
+        for message in 0..4 {
+            scheduler.broadcast(0, message);
+        }
+
+        assert_eq!(scheduler.pop(), Some((1, 2)));
+        assert_eq!(scheduler.pop(), Some((2, 2)));
+        assert_eq!(scheduler.pop(), None);
+
+        // Peer 1 finished sending while peer 2 is still stuck, so only peer 1 makes progress.
+        scheduler.complete(1);
+
+        assert_eq!(scheduler.pop(), Some((1, 3)));
+        assert_eq!(scheduler.pop(), None);
+        assert_eq!(
+            scheduler.stats(2),
+            Some(PeerStats {
+                queued: 1,
+                in_flight: 1,
+                sent: 1,
+                dropped: 2
+            })
+        );
+        assert_eq!(scheduler.queued(), 1);
+    }
+
+    #[test]
+    fn schedule() {
+        let mut scheduler = SendScheduler::new(3);
+        let mut mailbox = BufferedMailbox::<i32>::default();
+        let commit = Commit {
+            view: Default::default(),
+            epoch: 0,
+            committed: Default::default(),
+            timestamp: Default::default(),
+        };
+
+        mailbox.commit(commit.clone());
+        scheduler.schedule(0, &mut mailbox);
+
+        assert!(mailbox.is_empty());
+        assert_eq!(scheduler.queued(), 2);
+        assert_eq!(
+            scheduler
+                .pop()
+                .map(|(index, message)| (index, message.unwrap_commit())),
+            Some((1, commit))
+        );
+    }
+}
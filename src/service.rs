@@ -1,16 +1,76 @@
-use serde::{Deserialize, Serialize};
+use crate::request::ClientIdentifier;
+use crate::viewstamp::{OpNumber, View};
+use alloc::vec::Vec;
+use core::time::Duration;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 
-pub trait Payload: Clone + Serialize + Deserialize<'static> {}
+/// A value that can cross a transport boundary (e.g. the network or a trace file).
+pub trait Payload: Clone + Serialize + DeserializeOwned {}
 
-impl<P> Payload for P where P: Clone + Serialize + Deserialize<'static> {}
+impl<P> Payload for P where P: Clone + Serialize + DeserializeOwned {}
 
 /// A trait to associate all the necessary types together.
-/// All associated types must be serializable and not borrow data since replicas need to store these values.
+/// All associated types must not borrow data since replicas need to store these values.
+/// Replicas pass the values around as-is, so they only need to be serializable where messages leave the process.
 pub trait Protocol {
-    type Request: Payload;
-    type Prediction: Payload;
-    type Reply: Payload;
-    type Checkpoint: Payload;
+    type Request: Clone;
+    type Prediction: Clone;
+    type Reply: Clone;
+    type Checkpoint: Clone;
+}
+
+/// A protocol whose messages can be serialized, as required by transports, traces and the local driver.
+pub trait Serializable:
+    Protocol<Request: Payload, Prediction: Payload, Reply: Payload, Checkpoint: Payload>
+{
+}
+
+impl<P> Serializable for P where
+    P: Protocol<Request: Payload, Prediction: Payload, Reply: Payload, Checkpoint: Payload>
+{
+}
+
+/// The role a replica performs in a view.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Role {
+    Primary,
+    Backup,
+}
+
+/// Information about an operation that every replica agrees on, for services that need time or randomness
+/// without predicting it themselves.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Context {
+    /// The view in which the operation was added to the log.
+    pub view: View,
+    pub op_number: OpNumber,
+    /// The time since the Unix epoch at which the primary prepared the operation.
+    pub timestamp: Duration,
+    /// A seed for random number generators derived from the op-number.
+    pub seed: u64,
+}
+
+impl Context {
+    pub fn new(view: View, op_number: OpNumber, timestamp: Duration) -> Self {
+        let op_number_bits = u128::from(op_number);
+
+        Self {
+            view,
+            op_number,
+            timestamp,
+            seed: mix((op_number_bits as u64) ^ ((op_number_bits >> 64) as u64)),
+        }
+    }
+}
+
+/// The SplitMix64 finalizer, which spreads consecutive op-numbers across the range of seeds.
+fn mix(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9E37_79B9_7F4A_7C15);
+
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
 }
 
 pub trait Service: Protocol + From<<Self as Protocol>::Checkpoint> {
@@ -23,6 +83,76 @@ pub trait Service: Protocol + From<<Self as Protocol>::Checkpoint> {
         request: &<Self as Protocol>::Request,
         prediction: &<Self as Protocol>::Prediction,
     ) -> <Self as Protocol>::Reply;
+
+    /// Applies the request given information about the operation that is the same on every replica.
+    /// Defaults to [`Service::invoke`] for services that do not need the context.
+    /// Replicas only provide a context when applying committed operations one at a time,
+    /// so services that rely on it should not enable parallel or speculative execution.
+    fn invoke_with_context(
+        &mut self,
+        request: &<Self as Protocol>::Request,
+        prediction: &<Self as Protocol>::Prediction,
+        _context: &Context,
+    ) -> <Self as Protocol>::Reply {
+        self.invoke(request, prediction)
+    }
+
+    /// Called when the replica starts processing requests in a new view or role (e.g. after a view change or recovery),
+    /// so the service can start or stop work only the primary should do (e.g. background jobs).
+    /// The replica calls it before invoking any operation in the new view, including operations from earlier views
+    /// that commit as part of the transition. Replicas that start out recovering are only notified once they recover.
+    fn on_role_change(&mut self, _role: Role, _view: View) {}
+
+    /// Removes the notifications the service addressed to clients while applying the latest operation
+    /// (e.g. changes to watched keys), which the primary pushes through [`crate::Outbox::notify`].
+    /// Every replica drains its service after each committed operation, but only the primary sends the notifications.
+    /// Like the context, notifications are only attributed to the right operation when operations are applied
+    /// one at a time, so services that push them should not enable parallel or speculative execution.
+    fn take_notifications(&mut self) -> Vec<(ClientIdentifier, <Self as Protocol>::Reply)> {
+        Vec::new()
+    }
+}
+
+/// A service whose operations can be applied concurrently when they do not conflict with each other.
+/// Replicas with parallel execution enabled invoke consecutive non-conflicting operations on multiple threads,
+/// so the service must synchronize its own state and produce the same replies as executing the operations serially.
+pub trait Conflicts: Service + Sync {
+    /// Whether the result of either request depends on the order the two requests are applied in.
+    fn conflicts(&self, a: &<Self as Protocol>::Request, b: &<Self as Protocol>::Request) -> bool;
+
+    /// Applies the request through a shared reference.
+    /// Only called concurrently with requests that do not conflict with this one.
+    fn invoke_concurrently(
+        &self,
+        request: &<Self as Protocol>::Request,
+        prediction: &<Self as Protocol>::Prediction,
+    ) -> <Self as Protocol>::Reply;
+}
+
+/// A service that can answer read-only requests without applying them as operations,
+/// allowing backups to serve reads with bounded staleness (see [`crate::Request::with_staleness`]).
+pub trait Query: Service {
+    /// Answers the request from the current state of the service, which must be the same reply
+    /// invoking the request would produce, without changing the state.
+    fn query(&self, request: &<Self as Protocol>::Request) -> <Self as Protocol>::Reply;
+}
+
+/// A service that can undo operations, allowing the primary to apply operations speculatively before they commit.
+/// Speculative operations that do not commit (e.g. because a view change discards them) are rolled back
+/// in the reverse order they were applied in.
+pub trait Rollback: Service {
+    /// The information needed to undo a single operation.
+    type Undo;
+
+    /// Applies the request and returns the reply along with the information needed to undo it.
+    fn invoke_speculatively(
+        &mut self,
+        request: &<Self as Protocol>::Request,
+        prediction: &<Self as Protocol>::Prediction,
+    ) -> (<Self as Protocol>::Reply, Self::Undo);
+
+    /// Undoes the most recent speculative operation that has not been rolled back yet.
+    fn rollback(&mut self, undo: Self::Undo);
 }
 
 #[cfg(test)]
@@ -37,6 +167,7 @@ mod tests {
     }
 
     impl Service for i32 {
+        #[allow(clippy::unused_unit)]
         fn predict(&self, _: &<Self as Protocol>::Request) -> <Self as Protocol>::Prediction {
             ()
         }
@@ -55,6 +186,28 @@ mod tests {
         }
     }
 
+    impl Query for i32 {
+        fn query(&self, request: &<Self as Protocol>::Request) -> <Self as Protocol>::Reply {
+            *self + *request
+        }
+    }
+
+    impl Rollback for i32 {
+        type Undo = Self;
+
+        fn invoke_speculatively(
+            &mut self,
+            request: &<Self as Protocol>::Request,
+            prediction: &<Self as Protocol>::Prediction,
+        ) -> (<Self as Protocol>::Reply, Self::Undo) {
+            (self.invoke(request, prediction), *request)
+        }
+
+        fn rollback(&mut self, undo: Self::Undo) {
+            *self -= undo;
+        }
+    }
+
     #[test]
     fn adder() {
         let mut service = 0;
@@ -0,0 +1,17 @@
+//! Ready-made services that can be replicated as-is or used as references for implementing new services.
+
+mod config;
+mod kv;
+mod ledger;
+mod lock;
+
+#[cfg(feature = "threads")]
+pub use config::ConfigClient;
+pub use config::{
+    ConfigChange, ConfigCheckpoint, ConfigEntry, ConfigReply, ConfigRequest, ConfigStore,
+};
+pub use kv::{KvReply, KvRequest, KvStore};
+pub use ledger::{Ledger, LedgerError, LedgerRequest};
+pub use lock::{FencingToken, Lock, LockCheckpoint, LockError, LockManager, LockRequest};
+#[cfg(feature = "threads")]
+pub use lock::{LockClient, LockClientError};
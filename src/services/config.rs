@@ -0,0 +1,406 @@
+#[cfg(feature = "threads")]
+use crate::driver::{ClusterClient, Timeout};
+use crate::request::ClientIdentifier;
+use crate::service::{Protocol, Service};
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+#[cfg(feature = "threads")]
+use core::time::Duration;
+use serde::{Deserialize, Serialize};
+
+/// A replicated store of configuration and metadata where clients watch key prefixes for changes.
+/// Every change bumps the revision of the store, and the primary pushes a [`ConfigReply::Changed`] notification
+/// to each client watching a prefix of the changed key once the change commits.
+///
+/// Watches are part of the replicated state, so a new primary keeps notifying watchers after a view change.
+/// Notifications are not resent, so a watcher that reconnects compares revisions with [`ConfigRequest::Get`]
+/// to find changes it missed.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ConfigStore {
+    entries: BTreeMap<String, ConfigEntry>,
+    watches: BTreeMap<String, Vec<ClientIdentifier>>,
+    revision: u64,
+    notifications: Vec<(ClientIdentifier, ConfigReply)>,
+}
+
+impl ConfigStore {
+    pub fn get(&self, key: &str) -> Option<&ConfigEntry> {
+        self.entries.get(key)
+    }
+
+    /// The revision of the latest change to the store.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// The clients watching a prefix of the key.
+    pub fn watchers<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a ClientIdentifier> {
+        self.watches
+            .iter()
+            .filter(move |(prefix, _)| key.starts_with(prefix.as_str()))
+            .flat_map(|(_, watchers)| watchers)
+    }
+
+    fn change(&mut self, key: &str, value: Option<&str>) -> Option<ConfigEntry> {
+        self.revision += 1;
+
+        let previous = match value {
+            Some(value) => self.entries.insert(
+                key.to_string(),
+                ConfigEntry {
+                    value: value.to_string(),
+                    revision: self.revision,
+                },
+            ),
+            None => self.entries.remove(key),
+        };
+        let change = ConfigChange {
+            key: key.to_string(),
+            value: value.map(str::to_string),
+            revision: self.revision,
+        };
+        let notifications = Vec::from_iter(
+            self.watchers(key)
+                .map(|watcher| (*watcher, ConfigReply::Changed(change.clone()))),
+        );
+
+        self.notifications.extend(notifications);
+
+        previous
+    }
+
+    fn watch(&mut self, prefix: &str, watcher: ClientIdentifier) {
+        let watchers = self.watches.entry(prefix.to_string()).or_default();
+
+        if !watchers.contains(&watcher) {
+            watchers.push(watcher);
+        }
+    }
+
+    fn unwatch(&mut self, prefix: &str, watcher: ClientIdentifier) {
+        if let Some(watchers) = self.watches.get_mut(prefix) {
+            watchers.retain(|w| *w != watcher);
+
+            if watchers.is_empty() {
+                self.watches.remove(prefix);
+            }
+        }
+    }
+}
+
+/// The value of a key along with the revision of the change that last set it.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ConfigEntry {
+    pub value: String,
+    pub revision: u64,
+}
+
+/// A change to a key, where a `None` value means the key was deleted.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ConfigChange {
+    pub key: String,
+    pub value: Option<String>,
+    pub revision: u64,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum ConfigRequest {
+    Get {
+        key: String,
+    },
+    Set {
+        key: String,
+        value: String,
+    },
+    Delete {
+        key: String,
+    },
+    /// Notifies the watcher of every change to a key that starts with the prefix (an empty prefix watches every key).
+    Watch {
+        prefix: String,
+        watcher: ClientIdentifier,
+    },
+    Unwatch {
+        prefix: String,
+        watcher: ClientIdentifier,
+    },
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum ConfigReply {
+    /// The current entry of the key for a get, or its previous entry for a set or delete.
+    Entry(Option<ConfigEntry>),
+    /// The revision of the store when the watch was registered or removed.
+    /// Watchers are notified of every change after it.
+    Watching { revision: u64 },
+    /// A notification of a change to a watched key, pushed by the primary outside of the reply to any request.
+    Changed(ConfigChange),
+}
+
+/// The state of a [`ConfigStore`], including its watches so a new primary keeps notifying watchers.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ConfigCheckpoint {
+    pub entries: BTreeMap<String, ConfigEntry>,
+    pub watches: BTreeMap<String, Vec<ClientIdentifier>>,
+    pub revision: u64,
+}
+
+impl Protocol for ConfigStore {
+    type Request = ConfigRequest;
+    type Prediction = ();
+    type Reply = ConfigReply;
+    type Checkpoint = ConfigCheckpoint;
+}
+
+impl From<ConfigCheckpoint> for ConfigStore {
+    fn from(checkpoint: ConfigCheckpoint) -> Self {
+        Self {
+            entries: checkpoint.entries,
+            watches: checkpoint.watches,
+            revision: checkpoint.revision,
+            notifications: Vec::new(),
+        }
+    }
+}
+
+impl Service for ConfigStore {
+    fn predict(&self, _: &ConfigRequest) {}
+
+    fn checkpoint(&self) -> ConfigCheckpoint {
+        ConfigCheckpoint {
+            entries: self.entries.clone(),
+            watches: self.watches.clone(),
+            revision: self.revision,
+        }
+    }
+
+    fn invoke(&mut self, request: &ConfigRequest, _: &()) -> ConfigReply {
+        match request {
+            ConfigRequest::Get { key } => ConfigReply::Entry(self.entries.get(key).cloned()),
+            ConfigRequest::Set { key, value } => ConfigReply::Entry(self.change(key, Some(value))),
+            ConfigRequest::Delete { key } => match self.entries.contains_key(key) {
+                true => ConfigReply::Entry(self.change(key, None)),
+                false => ConfigReply::Entry(None),
+            },
+            ConfigRequest::Watch { prefix, watcher } => {
+                self.watch(prefix, *watcher);
+                ConfigReply::Watching {
+                    revision: self.revision,
+                }
+            }
+            ConfigRequest::Unwatch { prefix, watcher } => {
+                self.unwatch(prefix, *watcher);
+                ConfigReply::Watching {
+                    revision: self.revision,
+                }
+            }
+        }
+    }
+
+    fn take_notifications(&mut self) -> Vec<(ClientIdentifier, ConfigReply)> {
+        core::mem::take(&mut self.notifications)
+    }
+}
+
+/// A client of a [`ConfigStore`] replicated by a [`crate::driver::ClusterRunner`] that receives the changes
+/// to the prefixes it watches as notifications pushed by the primary.
+#[cfg(feature = "threads")]
+pub struct ConfigClient {
+    client: ClusterClient<ConfigStore>,
+    retry: Duration,
+    deadline: Duration,
+}
+
+#[cfg(feature = "threads")]
+impl ConfigClient {
+    pub fn new(client: ClusterClient<ConfigStore>) -> Self {
+        Self {
+            client,
+            retry: Duration::from_millis(100),
+            deadline: Duration::from_secs(5),
+        }
+    }
+
+    /// Sets how long to wait for a reply before resending an operation, and before giving up on it.
+    pub fn with_timeouts(mut self, retry: Duration, deadline: Duration) -> Self {
+        self.retry = retry;
+        self.deadline = deadline;
+        self
+    }
+
+    pub fn get(&mut self, key: &str) -> Result<Option<ConfigEntry>, Timeout> {
+        self.entry(ConfigRequest::Get {
+            key: key.to_string(),
+        })
+    }
+
+    /// Sets the key to the value and returns its previous entry.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<Option<ConfigEntry>, Timeout> {
+        self.entry(ConfigRequest::Set {
+            key: key.to_string(),
+            value: value.to_string(),
+        })
+    }
+
+    /// Deletes the key and returns its previous entry.
+    pub fn delete(&mut self, key: &str) -> Result<Option<ConfigEntry>, Timeout> {
+        self.entry(ConfigRequest::Delete {
+            key: key.to_string(),
+        })
+    }
+
+    /// Watches the keys that start with the prefix and returns the revision after which changes are notified.
+    pub fn watch(&mut self, prefix: &str) -> Result<u64, Timeout> {
+        self.revision(ConfigRequest::Watch {
+            prefix: prefix.to_string(),
+            watcher: self.client.identifier(),
+        })
+    }
+
+    pub fn unwatch(&mut self, prefix: &str) -> Result<u64, Timeout> {
+        self.revision(ConfigRequest::Unwatch {
+            prefix: prefix.to_string(),
+            watcher: self.client.identifier(),
+        })
+    }
+
+    /// Waits for the next change to a watched key.
+    /// Returns `None` if no change arrives within the timeout.
+    pub fn next_change(&mut self, timeout: Duration) -> Option<ConfigChange> {
+        match self.client.next_notification(timeout)?.payload {
+            ConfigReply::Changed(change) => Some(change),
+            _ => None,
+        }
+    }
+
+    fn entry(&mut self, request: ConfigRequest) -> Result<Option<ConfigEntry>, Timeout> {
+        match self.call(request)? {
+            ConfigReply::Entry(entry) => Ok(entry),
+            reply => unreachable!("unexpected reply to a key operation: {reply:?}"),
+        }
+    }
+
+    fn revision(&mut self, request: ConfigRequest) -> Result<u64, Timeout> {
+        match self.call(request)? {
+            ConfigReply::Watching { revision } => Ok(revision),
+            reply => unreachable!("unexpected reply to a watch operation: {reply:?}"),
+        }
+    }
+
+    fn call(&mut self, request: ConfigRequest) -> Result<ConfigReply, Timeout> {
+        self.client
+            .call_with_deadline(request, self.retry, self.deadline)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(key: &str, value: &str) -> ConfigRequest {
+        ConfigRequest::Set {
+            key: key.to_string(),
+            value: value.to_string(),
+        }
+    }
+
+    #[test]
+    fn watches() {
+        let mut store = ConfigStore::default();
+        let watcher = ClientIdentifier::from(1);
+
+        assert_eq!(
+            store.invoke(&set("db/host", "a"), &()),
+            ConfigReply::Entry(None)
+        );
+        assert_eq!(
+            store.invoke(
+                &ConfigRequest::Watch {
+                    prefix: "db/".to_string(),
+                    watcher,
+                },
+                &()
+            ),
+            ConfigReply::Watching { revision: 1 }
+        );
+        assert_eq!(store.take_notifications(), vec![]);
+        assert_eq!(
+            store.invoke(&set("db/host", "b"), &()),
+            ConfigReply::Entry(Some(ConfigEntry {
+                value: "a".to_string(),
+                revision: 1
+            }))
+        );
+        store.invoke(&set("web/host", "c"), &());
+
+        assert_eq!(
+            store.take_notifications(),
+            vec![(
+                watcher,
+                ConfigReply::Changed(ConfigChange {
+                    key: "db/host".to_string(),
+                    value: Some("b".to_string()),
+                    revision: 2
+                })
+            )]
+        );
+
+        let restored = ConfigStore::from(store.checkpoint());
+
+        assert_eq!(restored, store);
+        assert_eq!(Vec::from_iter(restored.watchers("db/port")), vec![&watcher]);
+
+        store.invoke(
+            &ConfigRequest::Unwatch {
+                prefix: "db/".to_string(),
+                watcher,
+            },
+            &(),
+        );
+        store.invoke(
+            &ConfigRequest::Delete {
+                key: "db/host".to_string(),
+            },
+            &(),
+        );
+
+        assert_eq!(store.take_notifications(), vec![]);
+        assert_eq!(store.revision(), 4);
+    }
+
+    #[cfg(feature = "threads")]
+    #[test]
+    fn client() {
+        use crate::driver::ClusterRunner;
+        use crate::Configuration;
+
+        let configuration = Configuration::from(3)
+            .with_commit_interval(Duration::from_millis(10))
+            .with_view_timeout(Duration::from_millis(50), Duration::from_millis(50));
+        let runner = ClusterRunner::start(configuration, |_| ConfigStore::default());
+        let mut watcher = ConfigClient::new(runner.client());
+        let mut writer = ConfigClient::new(runner.client());
+        let timeout = Duration::from_secs(5);
+
+        assert_eq!(watcher.watch("db/"), Ok(0));
+
+        writer.set("web/host", "a").unwrap();
+        writer.set("db/host", "b").unwrap();
+
+        assert_eq!(
+            watcher.next_change(timeout),
+            Some(ConfigChange {
+                key: "db/host".to_string(),
+                value: Some("b".to_string()),
+                revision: 2
+            })
+        );
+
+        writer.delete("db/host").unwrap();
+
+        assert_eq!(watcher.next_change(timeout).unwrap().value, None);
+        assert_eq!(watcher.get("db/host"), Ok(None));
+        assert_eq!(writer.next_change(Duration::from_millis(50)), None);
+    }
+}
@@ -0,0 +1,206 @@
+use crate::service::{Protocol, Rollback, Service};
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use serde::{Deserialize, Serialize};
+
+/// A replicated map from string keys to string values.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct KvStore {
+    entries: BTreeMap<String, String>,
+}
+
+impl KvStore {
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries.get(key).map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum KvRequest {
+    Get {
+        key: String,
+    },
+    Put {
+        key: String,
+        value: String,
+    },
+    Delete {
+        key: String,
+    },
+    /// Sets the key to the new value (or removes it when `None`) only if its current value is the expected one.
+    CompareAndSwap {
+        key: String,
+        expected: Option<String>,
+        value: Option<String>,
+    },
+}
+
+impl KvRequest {
+    fn key(&self) -> &str {
+        match self {
+            KvRequest::Get { key }
+            | KvRequest::Put { key, .. }
+            | KvRequest::Delete { key }
+            | KvRequest::CompareAndSwap { key, .. } => key,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum KvReply {
+    /// The current value of the key for a get, or its previous value for a put or delete.
+    Value(Option<String>),
+    /// Whether the compare-and-swap changed the key, along with the value the key had before the operation.
+    Swapped {
+        swapped: bool,
+        previous: Option<String>,
+    },
+}
+
+impl Protocol for KvStore {
+    type Request = KvRequest;
+    type Prediction = ();
+    type Reply = KvReply;
+    type Checkpoint = BTreeMap<String, String>;
+}
+
+impl From<BTreeMap<String, String>> for KvStore {
+    fn from(entries: BTreeMap<String, String>) -> Self {
+        Self { entries }
+    }
+}
+
+impl Service for KvStore {
+    fn predict(&self, _: &KvRequest) {}
+
+    fn checkpoint(&self) -> BTreeMap<String, String> {
+        self.entries.clone()
+    }
+
+    fn invoke(&mut self, request: &KvRequest, _: &()) -> KvReply {
+        match request {
+            KvRequest::Get { key } => KvReply::Value(self.entries.get(key).cloned()),
+            KvRequest::Put { key, value } => {
+                KvReply::Value(self.entries.insert(key.clone(), value.clone()))
+            }
+            KvRequest::Delete { key } => KvReply::Value(self.entries.remove(key)),
+            KvRequest::CompareAndSwap {
+                key,
+                expected,
+                value,
+            } => {
+                let previous = self.entries.get(key).cloned();
+                let swapped = previous == *expected;
+
+                if swapped {
+                    match value {
+                        Some(value) => self.entries.insert(key.clone(), value.clone()),
+                        None => self.entries.remove(key),
+                    };
+                }
+
+                KvReply::Swapped { swapped, previous }
+            }
+        }
+    }
+}
+
+impl Rollback for KvStore {
+    /// The key the operation touched and the value it had before the operation.
+    type Undo = (String, Option<String>);
+
+    fn invoke_speculatively(
+        &mut self,
+        request: &KvRequest,
+        prediction: &(),
+    ) -> (KvReply, Self::Undo) {
+        let key = request.key().to_string();
+        let previous = self.entries.get(&key).cloned();
+
+        (self.invoke(request, prediction), (key, previous))
+    }
+
+    fn rollback(&mut self, (key, previous): Self::Undo) {
+        match previous {
+            Some(value) => self.entries.insert(key, value),
+            None => self.entries.remove(&key),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn put(key: &str, value: &str) -> KvRequest {
+        KvRequest::Put {
+            key: key.to_string(),
+            value: value.to_string(),
+        }
+    }
+
+    #[test]
+    fn operations() {
+        let mut store = KvStore::default();
+
+        assert_eq!(store.invoke(&put("a", "1"), &()), KvReply::Value(None));
+        assert_eq!(
+            store.invoke(&put("a", "2"), &()),
+            KvReply::Value(Some("1".to_string()))
+        );
+        assert_eq!(
+            store.invoke(
+                &KvRequest::CompareAndSwap {
+                    key: "a".to_string(),
+                    expected: Some("1".to_string()),
+                    value: Some("3".to_string()),
+                },
+                &()
+            ),
+            KvReply::Swapped {
+                swapped: false,
+                previous: Some("2".to_string())
+            }
+        );
+        assert_eq!(
+            store.invoke(
+                &KvRequest::CompareAndSwap {
+                    key: "a".to_string(),
+                    expected: Some("2".to_string()),
+                    value: None,
+                },
+                &()
+            ),
+            KvReply::Swapped {
+                swapped: true,
+                previous: Some("2".to_string())
+            }
+        );
+        assert_eq!(
+            store.invoke(
+                &KvRequest::Get {
+                    key: "a".to_string()
+                },
+                &()
+            ),
+            KvReply::Value(None)
+        );
+
+        let checkpoint = store.checkpoint();
+        let (_, undo) = store.invoke_speculatively(&put("b", "1"), &());
+
+        assert_eq!(store.get("b"), Some("1"));
+
+        store.rollback(undo);
+
+        assert_eq!(store, KvStore::from(checkpoint));
+    }
+}
@@ -0,0 +1,219 @@
+use crate::service::{Protocol, Service};
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use core::error::Error;
+use core::fmt::{Display, Formatter};
+use serde::{Deserialize, Serialize};
+
+/// A replicated ledger of account balances whose operations only apply when the balances allow them.
+/// Accounts are created on their first credit and never go below zero.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Ledger {
+    balances: BTreeMap<String, u64>,
+}
+
+impl Ledger {
+    pub fn balance(&self, account: &str) -> u64 {
+        self.balances.get(account).copied().unwrap_or_default()
+    }
+
+    /// The sum of the balances of all accounts.
+    pub fn total(&self) -> u128 {
+        self.balances.values().map(|&balance| balance as u128).sum()
+    }
+
+    fn credit(&mut self, account: &str, amount: u64) -> Result<u64, LedgerError> {
+        let balance = self.balance(account);
+        let updated = balance
+            .checked_add(amount)
+            .ok_or(LedgerError::Overflow { balance })?;
+
+        self.balances.insert(account.to_string(), updated);
+
+        Ok(updated)
+    }
+
+    fn debit(&mut self, account: &str, amount: u64) -> Result<u64, LedgerError> {
+        let balance = self.balance(account);
+        let updated = balance
+            .checked_sub(amount)
+            .ok_or(LedgerError::InsufficientFunds { balance })?;
+
+        self.balances.insert(account.to_string(), updated);
+
+        Ok(updated)
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum LedgerRequest {
+    Balance {
+        account: String,
+    },
+    Credit {
+        account: String,
+        amount: u64,
+    },
+    /// Fails without changing the balance when the account holds less than the amount.
+    Debit {
+        account: String,
+        amount: u64,
+    },
+    /// Moves the amount between accounts, or neither account changes if the source holds less than the amount.
+    Transfer {
+        from: String,
+        to: String,
+        amount: u64,
+    },
+    /// Credits the account only if its balance is the expected one (e.g. to apply a read-modify-write exactly once).
+    CreditIf {
+        account: String,
+        expected: u64,
+        amount: u64,
+    },
+}
+
+/// The reason a ledger operation did not apply.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum LedgerError {
+    /// The account holds less than the amount to debit.
+    InsufficientFunds { balance: u64 },
+    /// The account does not hold the expected balance.
+    UnexpectedBalance { balance: u64 },
+    /// The credit would exceed the largest balance an account can hold.
+    Overflow { balance: u64 },
+}
+
+impl Display for LedgerError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            LedgerError::InsufficientFunds { balance } => {
+                write!(f, "insufficient funds with a balance of {balance}")
+            }
+            LedgerError::UnexpectedBalance { balance } => {
+                write!(f, "unexpected balance of {balance}")
+            }
+            LedgerError::Overflow { balance } => {
+                write!(f, "the credit would overflow the balance of {balance}")
+            }
+        }
+    }
+}
+
+impl Error for LedgerError {}
+
+impl Protocol for Ledger {
+    type Request = LedgerRequest;
+    type Prediction = ();
+    /// The balance of the account after the operation (the source account for transfers).
+    type Reply = Result<u64, LedgerError>;
+    type Checkpoint = BTreeMap<String, u64>;
+}
+
+impl From<BTreeMap<String, u64>> for Ledger {
+    fn from(balances: BTreeMap<String, u64>) -> Self {
+        Self { balances }
+    }
+}
+
+impl Service for Ledger {
+    fn predict(&self, _: &LedgerRequest) {}
+
+    fn checkpoint(&self) -> BTreeMap<String, u64> {
+        self.balances.clone()
+    }
+
+    fn invoke(&mut self, request: &LedgerRequest, _: &()) -> Result<u64, LedgerError> {
+        match request {
+            LedgerRequest::Balance { account } => Ok(self.balance(account)),
+            LedgerRequest::Credit { account, amount } => self.credit(account, *amount),
+            LedgerRequest::Debit { account, amount } => self.debit(account, *amount),
+            LedgerRequest::Transfer { from, to, amount } => {
+                if from != to && self.balance(to).checked_add(*amount).is_none() {
+                    return Err(LedgerError::Overflow {
+                        balance: self.balance(to),
+                    });
+                }
+
+                self.debit(from, *amount)?;
+                self.credit(to, *amount)?;
+
+                Ok(self.balance(from))
+            }
+            LedgerRequest::CreditIf {
+                account,
+                expected,
+                amount,
+            } => {
+                let balance = self.balance(account);
+
+                if balance != *expected {
+                    return Err(LedgerError::UnexpectedBalance { balance });
+                }
+
+                self.credit(account, *amount)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn credit(account: &str, amount: u64) -> LedgerRequest {
+        LedgerRequest::Credit {
+            account: account.to_string(),
+            amount,
+        }
+    }
+
+    fn transfer(from: &str, to: &str, amount: u64) -> LedgerRequest {
+        LedgerRequest::Transfer {
+            from: from.to_string(),
+            to: to.to_string(),
+            amount,
+        }
+    }
+
+    #[test]
+    fn conditional_operations() {
+        let mut ledger = Ledger::default();
+
+        assert_eq!(ledger.invoke(&credit("alice", 10), &()), Ok(10));
+        assert_eq!(
+            ledger.invoke(&transfer("alice", "bob", 15), &()),
+            Err(LedgerError::InsufficientFunds { balance: 10 })
+        );
+        assert_eq!(ledger.invoke(&transfer("alice", "bob", 4), &()), Ok(6));
+        assert_eq!(ledger.invoke(&transfer("bob", "bob", 4), &()), Ok(4));
+        assert_eq!(
+            ledger.invoke(
+                &LedgerRequest::CreditIf {
+                    account: "bob".to_string(),
+                    expected: 0,
+                    amount: 1,
+                },
+                &()
+            ),
+            Err(LedgerError::UnexpectedBalance { balance: 4 })
+        );
+        assert_eq!(
+            ledger.invoke(
+                &LedgerRequest::Debit {
+                    account: "carol".to_string(),
+                    amount: 1,
+                },
+                &()
+            ),
+            Err(LedgerError::InsufficientFunds { balance: 0 })
+        );
+        assert_eq!(
+            ledger.invoke(&credit("bob", u64::MAX), &()),
+            Err(LedgerError::Overflow { balance: 4 })
+        );
+        assert_eq!(ledger.invoke(&transfer("alice", "bob", 1), &()), Ok(5));
+        assert_eq!(ledger.total(), 10);
+        assert_eq!(Ledger::from(ledger.checkpoint()), ledger);
+    }
+}
@@ -0,0 +1,435 @@
+#[cfg(feature = "threads")]
+use crate::driver::{ClusterClient, Timeout};
+use crate::service::{Context, Protocol, Service};
+use crate::viewstamp::{OpNumber, View};
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use core::error::Error;
+use core::fmt::{Display, Formatter};
+use core::time::Duration;
+use serde::{Deserialize, Serialize};
+
+/// A replicated lock manager that grants named locks for a lease and fences out holders whose lease expired.
+/// Each grant carries a [`FencingToken`] derived from the viewstamp of the operation, so resources guarded by
+/// a lock can reject writes from a holder that lost the lock (e.g. after a long pause) by comparing tokens.
+///
+/// Leases are measured against the time the primary prepared each operation, which every replica agrees on.
+/// The manager never lets its clock go backwards, so a primary with a slower clock cannot revive an expired lease.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct LockManager {
+    locks: BTreeMap<String, Lock>,
+    now: Duration,
+    issued: FencingToken,
+}
+
+impl LockManager {
+    /// The lock with the given name if it is held and its lease has not expired.
+    pub fn holder(&self, name: &str) -> Option<&Lock> {
+        self.locks.get(name).filter(|lock| lock.expires > self.now)
+    }
+
+    fn acquire(&mut self, name: &str, owner: &str, lease: Duration) -> Result<Lock, LockError> {
+        if let Some(held) = self.holder(name) {
+            return match held.owner == owner {
+                true => Ok(held.clone()),
+                false => Err(LockError::Held {
+                    owner: held.owner.clone(),
+                    expires: held.expires,
+                }),
+            };
+        }
+
+        let lock = Lock {
+            name: name.to_string(),
+            owner: owner.to_string(),
+            token: self.issued,
+            expires: self.now.saturating_add(lease),
+        };
+
+        self.locks.insert(name.to_string(), lock.clone());
+
+        Ok(lock)
+    }
+
+    fn renew(
+        &mut self,
+        name: &str,
+        token: FencingToken,
+        lease: Duration,
+    ) -> Result<Lock, LockError> {
+        let now = self.now;
+
+        match self.locks.get_mut(name) {
+            Some(lock) if lock.token == token && lock.expires > now => {
+                lock.expires = now.saturating_add(lease);
+                Ok(lock.clone())
+            }
+            _ => Err(LockError::NotHeld),
+        }
+    }
+
+    fn release(&mut self, name: &str, token: FencingToken) -> Result<Lock, LockError> {
+        match self.locks.get(name) {
+            Some(lock) if lock.token == token => self.locks.remove(name).ok_or(LockError::NotHeld),
+            _ => Err(LockError::NotHeld),
+        }
+    }
+}
+
+/// A token that grows with every lock granted by a [`LockManager`], ordered by the view and then the op-number
+/// of the operation that granted the lock.
+#[derive(
+    Copy, Clone, Debug, Default, Ord, PartialOrd, Eq, PartialEq, Hash, Serialize, Deserialize,
+)]
+pub struct FencingToken {
+    pub view: View,
+    pub op_number: OpNumber,
+}
+
+/// A lock granted to an owner until its lease expires.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Lock {
+    pub name: String,
+    pub owner: String,
+    pub token: FencingToken,
+    /// The time since the Unix epoch at which the lease expires unless renewed.
+    pub expires: Duration,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum LockRequest {
+    /// Grants the lock to the owner if it is free or its lease expired.
+    /// Acquiring a lock the owner already holds returns the current grant without extending it.
+    Acquire {
+        name: String,
+        owner: String,
+        lease: Duration,
+    },
+    /// Extends the lease of the grant with the token, as long as the lease has not expired yet.
+    Renew {
+        name: String,
+        token: FencingToken,
+        lease: Duration,
+    },
+    /// Frees the lock if the grant with the token still holds it.
+    Release { name: String, token: FencingToken },
+    /// The current grant of the lock.
+    Holder { name: String },
+}
+
+/// The reason a lock operation did not apply.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum LockError {
+    /// Another owner holds the lock until its lease expires.
+    Held { owner: String, expires: Duration },
+    /// The grant no longer holds the lock (e.g. its lease expired or it was released).
+    NotHeld,
+}
+
+impl Display for LockError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            LockError::Held { owner, expires } => {
+                write!(f, "the lock is held by {owner} until {expires:?}")
+            }
+            LockError::NotHeld => write!(f, "the lock is not held by the grant"),
+        }
+    }
+}
+
+impl Error for LockError {}
+
+/// The state of a [`LockManager`], including its clock and the latest token it issued
+/// so a restored manager keeps both moving forward.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct LockCheckpoint {
+    pub locks: BTreeMap<String, Lock>,
+    pub now: Duration,
+    pub issued: FencingToken,
+}
+
+impl Protocol for LockManager {
+    type Request = LockRequest;
+    type Prediction = ();
+    type Reply = Result<Lock, LockError>;
+    type Checkpoint = LockCheckpoint;
+}
+
+impl From<LockCheckpoint> for LockManager {
+    fn from(checkpoint: LockCheckpoint) -> Self {
+        Self {
+            locks: checkpoint.locks,
+            now: checkpoint.now,
+            issued: checkpoint.issued,
+        }
+    }
+}
+
+impl Service for LockManager {
+    fn predict(&self, _: &LockRequest) {}
+
+    fn checkpoint(&self) -> LockCheckpoint {
+        LockCheckpoint {
+            locks: self.locks.clone(),
+            now: self.now,
+            issued: self.issued,
+        }
+    }
+
+    /// Applies the request at the time of the latest operation, with a token after the latest one issued.
+    /// Replicas apply operations with a context, so this is only used when invoking the service directly.
+    fn invoke(&mut self, request: &LockRequest, prediction: &()) -> Result<Lock, LockError> {
+        let context = Context::new(self.issued.view, self.issued.op_number.next(), self.now);
+
+        self.invoke_with_context(request, prediction, &context)
+    }
+
+    fn invoke_with_context(
+        &mut self,
+        request: &LockRequest,
+        _: &(),
+        context: &Context,
+    ) -> Result<Lock, LockError> {
+        self.now = self.now.max(context.timestamp);
+        self.issued = self.issued.max(FencingToken {
+            view: context.view,
+            op_number: context.op_number,
+        });
+
+        match request {
+            LockRequest::Acquire { name, owner, lease } => self.acquire(name, owner, *lease),
+            LockRequest::Renew { name, token, lease } => self.renew(name, *token, *lease),
+            LockRequest::Release { name, token } => self.release(name, *token),
+            LockRequest::Holder { name } => self.holder(name).cloned().ok_or(LockError::NotHeld),
+        }
+    }
+}
+
+/// A client of a [`LockManager`] replicated by a [`crate::driver::ClusterRunner`], acting as a single owner.
+/// Retries of an operation reuse its request number, so the group applies each operation exactly once.
+#[cfg(feature = "threads")]
+pub struct LockClient {
+    client: ClusterClient<LockManager>,
+    owner: String,
+    retry: Duration,
+    deadline: Duration,
+}
+
+#[cfg(feature = "threads")]
+impl LockClient {
+    pub fn new(client: ClusterClient<LockManager>, owner: impl Into<String>) -> Self {
+        Self {
+            client,
+            owner: owner.into(),
+            retry: Duration::from_millis(100),
+            deadline: Duration::from_secs(5),
+        }
+    }
+
+    /// Sets how long to wait for a reply before resending an operation, and before giving up on it.
+    pub fn with_timeouts(mut self, retry: Duration, deadline: Duration) -> Self {
+        self.retry = retry;
+        self.deadline = deadline;
+        self
+    }
+
+    pub fn owner(&self) -> &str {
+        &self.owner
+    }
+
+    pub fn acquire(&mut self, name: &str, lease: Duration) -> Result<Lock, LockClientError> {
+        self.call(LockRequest::Acquire {
+            name: name.to_string(),
+            owner: self.owner.clone(),
+            lease,
+        })
+    }
+
+    pub fn renew(&mut self, lock: &Lock, lease: Duration) -> Result<Lock, LockClientError> {
+        self.call(LockRequest::Renew {
+            name: lock.name.clone(),
+            token: lock.token,
+            lease,
+        })
+    }
+
+    pub fn release(&mut self, lock: &Lock) -> Result<Lock, LockClientError> {
+        self.call(LockRequest::Release {
+            name: lock.name.clone(),
+            token: lock.token,
+        })
+    }
+
+    pub fn holder(&mut self, name: &str) -> Result<Lock, LockClientError> {
+        self.call(LockRequest::Holder {
+            name: name.to_string(),
+        })
+    }
+
+    fn call(&mut self, request: LockRequest) -> Result<Lock, LockClientError> {
+        Ok(self
+            .client
+            .call_with_deadline(request, self.retry, self.deadline)??)
+    }
+}
+
+/// Why a [`LockClient`] operation failed.
+#[cfg(feature = "threads")]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum LockClientError {
+    /// The lock manager refused the operation.
+    Lock(LockError),
+    /// No reply arrived before the deadline.
+    Timeout(Timeout),
+}
+
+#[cfg(feature = "threads")]
+impl From<LockError> for LockClientError {
+    fn from(error: LockError) -> Self {
+        Self::Lock(error)
+    }
+}
+
+#[cfg(feature = "threads")]
+impl From<Timeout> for LockClientError {
+    fn from(error: Timeout) -> Self {
+        Self::Timeout(error)
+    }
+}
+
+#[cfg(feature = "threads")]
+impl Display for LockClientError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            LockClientError::Lock(error) => write!(f, "{error}"),
+            LockClientError::Timeout(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+#[cfg(feature = "threads")]
+impl Error for LockClientError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(view: u128, op_number: u128, millis: u64) -> Context {
+        Context::new(
+            View::from(view),
+            OpNumber::from(op_number),
+            Duration::from_millis(millis),
+        )
+    }
+
+    fn acquire(name: &str, owner: &str) -> LockRequest {
+        LockRequest::Acquire {
+            name: name.to_string(),
+            owner: owner.to_string(),
+            lease: Duration::from_millis(10),
+        }
+    }
+
+    #[test]
+    fn leases() {
+        let mut manager = LockManager::default();
+        let first = manager
+            .invoke_with_context(&acquire("a", "alice"), &(), &at(0, 1, 0))
+            .unwrap();
+
+        assert_eq!(first.expires, Duration::from_millis(10));
+        assert_eq!(
+            manager.invoke_with_context(&acquire("a", "bob"), &(), &at(0, 2, 5)),
+            Err(LockError::Held {
+                owner: "alice".to_string(),
+                expires: first.expires
+            })
+        );
+        assert_eq!(
+            manager.invoke_with_context(&acquire("a", "alice"), &(), &at(0, 3, 6)),
+            Ok(first.clone())
+        );
+
+        let renewed = manager
+            .invoke_with_context(
+                &LockRequest::Renew {
+                    name: "a".to_string(),
+                    token: first.token,
+                    lease: Duration::from_millis(10),
+                },
+                &(),
+                &at(0, 4, 8),
+            )
+            .unwrap();
+
+        assert_eq!(renewed.expires, Duration::from_millis(18));
+
+        // A primary with a slower clock in a later view does not move the clock backwards.
+        let second = manager
+            .invoke_with_context(&acquire("a", "bob"), &(), &at(1, 5, 1))
+            .unwrap_err();
+
+        assert!(matches!(second, LockError::Held { .. }));
+
+        let taken = manager
+            .invoke_with_context(&acquire("a", "bob"), &(), &at(1, 6, 20))
+            .unwrap();
+
+        assert!(taken.token > first.token);
+        assert_eq!(
+            manager.invoke_with_context(
+                &LockRequest::Release {
+                    name: "a".to_string(),
+                    token: first.token,
+                },
+                &(),
+                &at(1, 7, 21)
+            ),
+            Err(LockError::NotHeld)
+        );
+        assert_eq!(manager.holder("a"), Some(&taken));
+        assert_eq!(LockManager::from(manager.checkpoint()), manager);
+        assert_eq!(
+            manager.invoke(
+                &LockRequest::Release {
+                    name: "a".to_string(),
+                    token: taken.token,
+                },
+                &()
+            ),
+            Ok(taken)
+        );
+        assert_eq!(manager.holder("a"), None);
+    }
+
+    #[cfg(feature = "threads")]
+    #[test]
+    fn client() {
+        use crate::driver::ClusterRunner;
+        use crate::Configuration;
+
+        let configuration = Configuration::from(3)
+            .with_commit_interval(Duration::from_millis(10))
+            .with_view_timeout(Duration::from_millis(50), Duration::from_millis(50));
+        let runner = ClusterRunner::start(configuration, |_| LockManager::default());
+        let mut alice = LockClient::new(runner.client(), "alice");
+        let mut bob = LockClient::new(runner.client(), "bob");
+        let lease = Duration::from_secs(60);
+
+        let lock = alice.acquire("a", lease).unwrap();
+
+        assert!(matches!(
+            bob.acquire("a", lease),
+            Err(LockClientError::Lock(LockError::Held { .. }))
+        ));
+        assert_eq!(bob.holder("a").unwrap().owner, alice.owner());
+
+        let renewed = alice.renew(&lock, lease).unwrap();
+
+        assert_eq!(renewed.token, lock.token);
+
+        alice.release(&renewed).unwrap();
+
+        assert!(bob.acquire("a", lease).unwrap().token > lock.token);
+    }
+}
@@ -0,0 +1,192 @@
+use crate::request::Request;
+
+/// Decides whether a request really came from the client it claims to come from.
+/// A primary with a verifier drops requests that fail verification before they enter the log.
+///
+/// Replays of a validly signed request are already harmless, since the client table only starts requests
+/// with a higher request number than the last one from the same client, and the signature covers that number.
+pub trait Verifier<R> {
+    fn verify(&self, request: &Request<R>) -> bool;
+}
+
+impl<R, F> Verifier<R> for F
+where
+    F: Fn(&Request<R>) -> bool,
+{
+    fn verify(&self, request: &Request<R>) -> bool {
+        self(request)
+    }
+}
+
+#[cfg(feature = "signing")]
+pub use keys::{KeyRing, SigningKey};
+
+#[cfg(feature = "signing")]
+mod keys {
+    use super::Verifier;
+    use crate::request::{ClientIdentifier, Request, Signature};
+    use blake2::digest::Mac;
+    use blake2::Blake2bMac512;
+    use serde::Serialize;
+    use std::collections::HashMap;
+    use std::sync::{Arc, RwLock};
+
+    /// A versioned secret shared by a client and the replicas of its group.
+    #[derive(Clone, Eq, PartialEq)]
+    pub struct SigningKey {
+        version: u64,
+        secret: [u8; 32],
+    }
+
+    impl SigningKey {
+        pub fn new(version: u64, secret: [u8; 32]) -> Self {
+            Self { version, secret }
+        }
+
+        pub fn version(&self) -> u64 {
+            self.version
+        }
+
+        /// Computes the signature of the request, covering the client, request number, deadline and payload.
+        pub fn sign<R>(&self, request: &Request<R>) -> Signature
+        where
+            R: Serialize,
+        {
+            Signature {
+                key: self.version,
+                mac: self.mac(request).finalize().into_bytes().to_vec(),
+            }
+        }
+
+        fn verify<R>(&self, request: &Request<R>, signature: &Signature) -> bool
+        where
+            R: Serialize,
+        {
+            self.mac(request).verify_slice(&signature.mac).is_ok()
+        }
+
+        fn mac<R>(&self, request: &Request<R>) -> Blake2bMac512
+        where
+            R: Serialize,
+        {
+            let mut mac =
+                Blake2bMac512::new_from_slice(&self.secret).expect("32-byte keys are valid");
+            let message = (
+                request.client,
+                request.id,
+                request.deadline,
+                &request.payload,
+            );
+
+            mac.update(&serde_json::to_vec(&message).expect("unable to serialize the request"));
+            mac
+        }
+    }
+
+    impl core::fmt::Debug for SigningKey {
+        fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+            f.debug_struct("SigningKey")
+                .field("version", &self.version)
+                .finish_non_exhaustive()
+        }
+    }
+
+    /// The keys of each client, shared between the replica verifying requests and whoever rotates the keys.
+    /// Clients without keys may send unsigned requests; requests from clients with keys must be signed by one of them.
+    ///
+    /// To rotate a key without rejecting requests in flight, insert the new key, switch the client over to it
+    /// and then revoke the old one.
+    #[derive(Clone, Debug, Default)]
+    pub struct KeyRing {
+        keys: Arc<RwLock<HashMap<ClientIdentifier, Vec<SigningKey>>>>,
+    }
+
+    impl KeyRing {
+        /// Accepts requests from the client signed with the key, replacing any key with the same version.
+        pub fn insert(&self, client: ClientIdentifier, key: SigningKey) {
+            let mut keys = self.keys.write().expect("the key ring lock is poisoned");
+            let client_keys = keys.entry(client).or_default();
+
+            client_keys.retain(|existing| existing.version != key.version);
+            client_keys.push(key);
+        }
+
+        /// Stops accepting requests from the client signed with the given version of its key.
+        /// The client must still sign its requests while it has other keys.
+        pub fn revoke(&self, client: ClientIdentifier, version: u64) {
+            let mut keys = self.keys.write().expect("the key ring lock is poisoned");
+
+            if let Some(client_keys) = keys.get_mut(&client) {
+                client_keys.retain(|key| key.version != version);
+            }
+        }
+
+        /// Forgets every key of the client, allowing it to send unsigned requests again.
+        pub fn remove(&self, client: ClientIdentifier) {
+            let mut keys = self.keys.write().expect("the key ring lock is poisoned");
+
+            keys.remove(&client);
+        }
+    }
+
+    impl<R> Verifier<R> for KeyRing
+    where
+        R: Serialize,
+    {
+        fn verify(&self, request: &Request<R>) -> bool {
+            let keys = self.keys.read().expect("the key ring lock is poisoned");
+
+            match (keys.get(&request.client), &request.signature) {
+                (None, _) => true,
+                (Some(_), None) => false,
+                (Some(client_keys), Some(signature)) => client_keys
+                    .iter()
+                    .filter(|key| key.version == signature.key)
+                    .any(|key| key.verify(request, signature)),
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::{Client, Configuration};
+
+        #[test]
+        fn rotation() {
+            let keys = KeyRing::default();
+            let old = SigningKey::new(1, [1; 32]);
+            let new = SigningKey::new(2, [2; 32]);
+            let mut client = Client::new(Configuration::from(3)).with_signing_key(old.clone());
+
+            keys.insert(client.identifier(), old);
+
+            let unsigned = client.new_request(5);
+            let signed = client.new_signed_request(5);
+            let mut forged = signed.clone();
+
+            forged.payload = 6;
+
+            assert!(!keys.verify(&unsigned));
+            assert!(keys.verify(&signed));
+            assert!(!keys.verify(&forged));
+
+            keys.insert(client.identifier(), new.clone());
+            client.rotate_signing_key(new);
+
+            let rotated = client.new_signed_request(5);
+
+            assert!(keys.verify(&signed));
+            assert!(keys.verify(&rotated));
+
+            keys.revoke(client.identifier(), 1);
+
+            assert!(!keys.verify(&signed));
+            assert!(keys.verify(&rotated));
+
+            keys.remove(client.identifier());
+
+            assert!(keys.verify(&unsigned));
+        }
+    }
+}
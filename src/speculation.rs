@@ -0,0 +1,83 @@
+use crate::log::Entry;
+use crate::service::{Protocol, Rollback};
+use crate::viewstamp::OpNumber;
+use alloc::collections::VecDeque;
+
+/// The operations a primary applied speculatively that have not committed yet.
+/// Hides the undo type of the service so replicas do not need to know whether speculation is enabled.
+pub trait Speculation<S>
+where
+    S: Protocol,
+{
+    /// The op-number of the latest speculative operation.
+    fn last(&self) -> Option<OpNumber>;
+
+    /// Applies the operation to the service and keeps the reply until the operation commits.
+    fn invoke(
+        &mut self,
+        service: &mut S,
+        op_number: OpNumber,
+        entry: &Entry<S::Request, S::Prediction>,
+    );
+
+    /// Removes the reply to the oldest speculative operation when it has the given op-number.
+    fn take(&mut self, op_number: OpNumber) -> Option<S::Reply>;
+
+    /// Undoes all the speculative operations, starting from the latest.
+    fn rollback(&mut self, service: &mut S);
+}
+
+/// Undo records of speculative operations in log order.
+pub struct UndoLog<S>
+where
+    S: Rollback,
+{
+    operations: VecDeque<(OpNumber, S::Reply, S::Undo)>,
+}
+
+impl<S> Default for UndoLog<S>
+where
+    S: Rollback,
+{
+    fn default() -> Self {
+        Self {
+            operations: Default::default(),
+        }
+    }
+}
+
+impl<S> Speculation<S> for UndoLog<S>
+where
+    S: Rollback,
+{
+    fn last(&self) -> Option<OpNumber> {
+        self.operations.back().map(|(op_number, _, _)| *op_number)
+    }
+
+    fn invoke(
+        &mut self,
+        service: &mut S,
+        op_number: OpNumber,
+        entry: &Entry<S::Request, S::Prediction>,
+    ) {
+        let (reply, undo) =
+            service.invoke_speculatively(&entry.request().payload, entry.prediction());
+
+        self.operations.push_back((op_number, reply, undo));
+    }
+
+    fn take(&mut self, op_number: OpNumber) -> Option<S::Reply> {
+        match self.operations.front() {
+            Some((oldest, _, _)) if *oldest == op_number => {
+                self.operations.pop_front().map(|(_, reply, _)| reply)
+            }
+            _ => None,
+        }
+    }
+
+    fn rollback(&mut self, service: &mut S) {
+        while let Some((_, _, undo)) = self.operations.pop_back() {
+            service.rollback(undo);
+        }
+    }
+}
@@ -0,0 +1,570 @@
+use crate::client::Client;
+use crate::configuration::{Configuration, Group};
+use crate::request::{ClientIdentifier, Reply, Request, RequestIdentifier};
+use crate::viewstamp::View;
+use alloc::vec::Vec;
+use core::error::Error;
+use core::fmt::{Display, Formatter};
+use hashbrown::HashMap;
+use serde::{Deserialize, Serialize};
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct TransactionIdentifier(u128);
+
+#[cfg(feature = "os")]
+impl Default for TransactionIdentifier {
+    fn default() -> Self {
+        Self(uuid::Uuid::new_v4().as_u128())
+    }
+}
+
+impl From<u128> for TransactionIdentifier {
+    fn from(value: u128) -> Self {
+        Self(value)
+    }
+}
+
+impl From<TransactionIdentifier> for u128 {
+    fn from(value: TransactionIdentifier) -> Self {
+        value.0
+    }
+}
+
+/// The operations a participant group replicates for a distributed transaction.
+/// Services of participant groups use this as (part of) their request type.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum TransactionRequest<R> {
+    /// Validate the operation and hold any resources it needs until the transaction is committed or aborted.
+    Prepare {
+        transaction: TransactionIdentifier,
+        operation: R,
+    },
+    /// Apply the prepared operation.
+    Commit { transaction: TransactionIdentifier },
+    /// Release the resources held for the prepared operation.
+    Abort { transaction: TransactionIdentifier },
+}
+
+/// Whether a participant group is able to commit its part of a transaction.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum Vote {
+    Yes,
+    No,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum TransactionPhase {
+    /// Participants are being asked to prepare.
+    Preparing,
+    /// Participants are being told to commit.
+    Committing,
+    /// Participants are being told to abort.
+    Aborting,
+    /// Every participant acknowledged the commit.
+    Committed,
+    /// Every participant acknowledged the abort.
+    Aborted,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum TransactionError {
+    /// The coordinator has no record of the transaction.
+    UnknownTransaction,
+    /// The coordinator was not configured with the group.
+    UnknownGroup,
+    /// The group is already a participant in the transaction.
+    DuplicateParticipant,
+    /// The transaction has no participants to commit.
+    NoParticipants,
+    /// The operation is not allowed in the current phase of the transaction.
+    InvalidPhase(TransactionPhase),
+    /// The reply is not for the outstanding request of the participant.
+    UnexpectedReply,
+    /// The reply comes from an older view than the coordinator already knows of for the group.
+    StaleView { view: View, latest: View },
+    /// The group has not voted yet.
+    MissingVote(Group),
+    /// The group voted to abort the transaction.
+    VotedNo(Group),
+}
+
+impl Display for TransactionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            TransactionError::UnknownTransaction => write!(f, "the transaction is unknown"),
+            TransactionError::UnknownGroup => write!(f, "the group is unknown"),
+            TransactionError::DuplicateParticipant => {
+                write!(f, "the group is already a participant in the transaction")
+            }
+            TransactionError::NoParticipants => write!(f, "the transaction has no participants"),
+            TransactionError::InvalidPhase(phase) => {
+                write!(
+                    f,
+                    "the operation is not allowed while the transaction is {phase:?}"
+                )
+            }
+            TransactionError::UnexpectedReply => {
+                write!(f, "the reply does not match the outstanding request")
+            }
+            TransactionError::StaleView { view, latest } => write!(
+                f,
+                "the reply from view {view:?} is older than the latest known view {latest:?}"
+            ),
+            TransactionError::MissingVote(group) => write!(f, "group {group:?} has not voted"),
+            TransactionError::VotedNo(group) => write!(f, "group {group:?} voted to abort"),
+        }
+    }
+}
+
+impl Error for TransactionError {}
+
+/// A request the caller must send to the primary of a participant group.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Outbound<R> {
+    pub group: Group,
+    /// The index of the primary of the group in the latest view known to the coordinator.
+    pub primary: usize,
+    pub request: Request<TransactionRequest<R>>,
+}
+
+struct Participant {
+    client: Client,
+    pending: Option<RequestIdentifier>,
+    vote: Option<Vote>,
+}
+
+struct Transaction {
+    phase: TransactionPhase,
+    participants: HashMap<Group, Participant>,
+}
+
+/// Coordinates transactions across groups using two-phase commit,
+/// where each group replicates its part of the transaction through its primary.
+///
+/// The coordinator does not perform any I/O; callers send the returned requests and feed the replies back in.
+/// Replies are only accepted for the outstanding request of a participant and from a view no older than
+/// the latest view the coordinator knows of for the group.
+pub struct Coordinator {
+    configurations: HashMap<Group, Configuration>,
+    views: HashMap<Group, View>,
+    transactions: HashMap<TransactionIdentifier, Transaction>,
+}
+
+impl Coordinator {
+    pub fn new<I>(configurations: I) -> Self
+    where
+        I: IntoIterator<Item = Configuration>,
+    {
+        Self {
+            configurations: configurations
+                .into_iter()
+                .map(|configuration| (configuration.group(), configuration))
+                .collect(),
+            views: Default::default(),
+            transactions: Default::default(),
+        }
+    }
+
+    #[cfg(feature = "os")]
+    pub fn begin(&mut self) -> TransactionIdentifier {
+        self.begin_with(TransactionIdentifier::default())
+    }
+
+    /// Begins a transaction with the given identifier, which must be unique among the transactions of every coordinator
+    /// (e.g. drawn from a seeded random number generator in a deterministic simulation).
+    pub fn begin_with(&mut self, transaction: TransactionIdentifier) -> TransactionIdentifier {
+        self.transactions.insert(
+            transaction,
+            Transaction {
+                phase: TransactionPhase::Preparing,
+                participants: Default::default(),
+            },
+        );
+
+        transaction
+    }
+
+    pub fn phase(&self, transaction: TransactionIdentifier) -> Option<TransactionPhase> {
+        self.transactions.get(&transaction).map(|t| t.phase)
+    }
+
+    /// Forgets the transaction, returning its last phase.
+    pub fn remove(&mut self, transaction: TransactionIdentifier) -> Option<TransactionPhase> {
+        self.transactions.remove(&transaction).map(|t| t.phase)
+    }
+
+    /// Adds the group as a participant, asking it to prepare the given operation.
+    pub fn prepare<R>(
+        &mut self,
+        transaction: TransactionIdentifier,
+        group: Group,
+        operation: R,
+    ) -> Result<Outbound<R>, TransactionError> {
+        let configuration = *self
+            .configurations
+            .get(&group)
+            .ok_or(TransactionError::UnknownGroup)?;
+        let view = self.views.get(&group).copied().unwrap_or_default();
+        let state = self
+            .transactions
+            .get_mut(&transaction)
+            .ok_or(TransactionError::UnknownTransaction)?;
+
+        if state.phase != TransactionPhase::Preparing {
+            return Err(TransactionError::InvalidPhase(state.phase));
+        }
+
+        if state.participants.contains_key(&group) {
+            return Err(TransactionError::DuplicateParticipant);
+        }
+
+        let mut participant = Participant {
+            // A participant only has one client per transaction, so the transaction identifies it.
+            client: Client::new_with_identifier(
+                configuration,
+                ClientIdentifier::from(u128::from(transaction)),
+            ),
+            pending: None,
+            vote: None,
+        };
+        let request = participant.client.new_request(TransactionRequest::Prepare {
+            transaction,
+            operation,
+        });
+
+        participant.pending = Some(request.id);
+        state.participants.insert(group, participant);
+
+        Ok(Outbound {
+            group,
+            primary: configuration % view,
+            request,
+        })
+    }
+
+    /// Records the vote carried by the reply of the group to its prepare request.
+    pub fn vote<P>(
+        &mut self,
+        transaction: TransactionIdentifier,
+        group: Group,
+        reply: &Reply<P>,
+        vote: Vote,
+    ) -> Result<TransactionPhase, TransactionError> {
+        let latest = self.views.entry(group).or_default();
+        let state = self
+            .transactions
+            .get_mut(&transaction)
+            .ok_or(TransactionError::UnknownTransaction)?;
+
+        if state.phase != TransactionPhase::Preparing {
+            return Err(TransactionError::InvalidPhase(state.phase));
+        }
+
+        let participant = state
+            .participants
+            .get_mut(&group)
+            .ok_or(TransactionError::UnknownGroup)?;
+
+        accept(participant, latest, reply)?;
+        participant.vote = Some(vote);
+
+        Ok(state.phase)
+    }
+
+    /// Decides to commit the transaction once every participant voted yes.
+    pub fn commit<R>(
+        &mut self,
+        transaction: TransactionIdentifier,
+    ) -> Result<Vec<Outbound<R>>, TransactionError> {
+        let state = self
+            .transactions
+            .get(&transaction)
+            .ok_or(TransactionError::UnknownTransaction)?;
+
+        if state.phase != TransactionPhase::Preparing {
+            return Err(TransactionError::InvalidPhase(state.phase));
+        }
+
+        if state.participants.is_empty() {
+            return Err(TransactionError::NoParticipants);
+        }
+
+        for (&group, participant) in state.participants.iter() {
+            match participant.vote {
+                None => return Err(TransactionError::MissingVote(group)),
+                Some(Vote::No) => return Err(TransactionError::VotedNo(group)),
+                Some(Vote::Yes) => {}
+            }
+        }
+
+        Ok(self.decide(transaction, TransactionPhase::Committing))
+    }
+
+    /// Decides to abort the transaction. Allowed at any point before the transaction is committed.
+    pub fn abort<R>(
+        &mut self,
+        transaction: TransactionIdentifier,
+    ) -> Result<Vec<Outbound<R>>, TransactionError> {
+        let state = self
+            .transactions
+            .get(&transaction)
+            .ok_or(TransactionError::UnknownTransaction)?;
+
+        if state.phase != TransactionPhase::Preparing {
+            return Err(TransactionError::InvalidPhase(state.phase));
+        }
+
+        Ok(self.decide(transaction, TransactionPhase::Aborting))
+    }
+
+    /// Records the reply of the group to the decision, completing the transaction once every participant replied.
+    pub fn acknowledge<P>(
+        &mut self,
+        transaction: TransactionIdentifier,
+        group: Group,
+        reply: &Reply<P>,
+    ) -> Result<TransactionPhase, TransactionError> {
+        let latest = self.views.entry(group).or_default();
+        let state = self
+            .transactions
+            .get_mut(&transaction)
+            .ok_or(TransactionError::UnknownTransaction)?;
+
+        if !matches!(
+            state.phase,
+            TransactionPhase::Committing | TransactionPhase::Aborting
+        ) {
+            return Err(TransactionError::InvalidPhase(state.phase));
+        }
+
+        let participant = state
+            .participants
+            .get_mut(&group)
+            .ok_or(TransactionError::UnknownGroup)?;
+
+        accept(participant, latest, reply)?;
+
+        if state.participants.values().all(|p| p.pending.is_none()) {
+            state.phase = match state.phase {
+                TransactionPhase::Committing => TransactionPhase::Committed,
+                _ => TransactionPhase::Aborted,
+            };
+        }
+
+        Ok(state.phase)
+    }
+
+    fn decide<R>(
+        &mut self,
+        transaction: TransactionIdentifier,
+        phase: TransactionPhase,
+    ) -> Vec<Outbound<R>> {
+        let Some(state) = self.transactions.get_mut(&transaction) else {
+            return Vec::new();
+        };
+
+        state.phase = phase;
+        state
+            .participants
+            .iter_mut()
+            .map(|(&group, participant)| {
+                let payload = match phase {
+                    TransactionPhase::Committing => TransactionRequest::Commit { transaction },
+                    _ => TransactionRequest::Abort { transaction },
+                };
+                let request = participant.client.new_request(payload);
+
+                participant.pending = Some(request.id);
+
+                Outbound {
+                    group,
+                    primary: participant.client.primary(),
+                    request,
+                }
+            })
+            .collect()
+    }
+}
+
+fn accept<P>(
+    participant: &mut Participant,
+    latest: &mut View,
+    reply: &Reply<P>,
+) -> Result<(), TransactionError> {
+    if participant.pending != Some(reply.id) {
+        return Err(TransactionError::UnexpectedReply);
+    }
+
+    if reply.view < *latest {
+        return Err(TransactionError::StaleView {
+            view: reply.view,
+            latest: *latest,
+        });
+    }
+
+    *latest = reply.view;
+    participant.pending = None;
+    participant.client.update_view(reply);
+
+    Ok(())
+}
+
+#[cfg(all(test, feature = "threads"))]
+mod tests {
+    use super::*;
+    use crate::driver::LocalDriver;
+    use crate::service::{Protocol, Service};
+
+    #[derive(Default)]
+    struct Account {
+        balance: i32,
+        held: HashMap<TransactionIdentifier, i32>,
+    }
+
+    impl Protocol for Account {
+        type Request = TransactionRequest<i32>;
+        type Prediction = ();
+        type Reply = Vote;
+        type Checkpoint = (i32, Vec<(TransactionIdentifier, i32)>);
+    }
+
+    impl From<<Self as Protocol>::Checkpoint> for Account {
+        fn from((balance, held): <Self as Protocol>::Checkpoint) -> Self {
+            Self {
+                balance,
+                held: held.into_iter().collect(),
+            }
+        }
+    }
+
+    impl Service for Account {
+        fn predict(&self, _: &<Self as Protocol>::Request) -> <Self as Protocol>::Prediction {}
+
+        fn checkpoint(&self) -> <Self as Protocol>::Checkpoint {
+            (self.balance, self.held.clone().into_iter().collect())
+        }
+
+        fn invoke(
+            &mut self,
+            request: &<Self as Protocol>::Request,
+            _: &<Self as Protocol>::Prediction,
+        ) -> <Self as Protocol>::Reply {
+            match request {
+                TransactionRequest::Prepare {
+                    transaction,
+                    operation,
+                } => {
+                    if self.balance + operation < 0 {
+                        return Vote::No;
+                    }
+
+                    self.held.insert(*transaction, *operation);
+                }
+                TransactionRequest::Commit { transaction } => {
+                    self.balance += self.held.remove(transaction).unwrap_or_default();
+                }
+                TransactionRequest::Abort { transaction } => {
+                    self.held.remove(transaction);
+                }
+            }
+
+            Vote::Yes
+        }
+    }
+
+    fn send(
+        drivers: &mut HashMap<Group, LocalDriver<Account>>,
+        outbound: Outbound<i32>,
+    ) -> Reply<Vote> {
+        let driver = drivers.get_mut(&outbound.group).unwrap();
+        let client = outbound.request.client;
+
+        driver.request(outbound.primary, outbound.request);
+        driver.drive();
+        driver.take_replies(client).pop().unwrap().unwrap_reply()
+    }
+
+    #[test]
+    fn two_phase_commit() {
+        let source = Configuration::from(3);
+        let destination = Configuration::from(3);
+        let mut drivers = HashMap::from([
+            (
+                source.group(),
+                LocalDriver::new(source, |_| Account::default()),
+            ),
+            (
+                destination.group(),
+                LocalDriver::new(destination, |_| Account::default()),
+            ),
+        ]);
+        let mut coordinator = Coordinator::new([source, destination]);
+
+        // Without funds, the source votes to abort.
+        let transaction = coordinator.begin();
+
+        for (group, amount) in [(source.group(), -5), (destination.group(), 5)] {
+            let outbound = coordinator.prepare(transaction, group, amount).unwrap();
+            let reply = send(&mut drivers, outbound);
+            let vote = reply.payload;
+
+            coordinator.vote(transaction, group, &reply, vote).unwrap();
+        }
+
+        assert_eq!(
+            coordinator.commit::<i32>(transaction),
+            Err(TransactionError::VotedNo(source.group()))
+        );
+
+        for outbound in coordinator.abort::<i32>(transaction).unwrap() {
+            let group = outbound.group;
+            let reply = send(&mut drivers, outbound);
+
+            coordinator.acknowledge(transaction, group, &reply).unwrap();
+        }
+
+        assert_eq!(
+            coordinator.phase(transaction),
+            Some(TransactionPhase::Aborted)
+        );
+
+        // Both groups vote to commit.
+        let transaction = coordinator.begin();
+
+        for (group, amount) in [(source.group(), 5), (destination.group(), 7)] {
+            let outbound = coordinator.prepare(transaction, group, amount).unwrap();
+            let reply = send(&mut drivers, outbound);
+            let vote = reply.payload;
+
+            assert_eq!(
+                coordinator.acknowledge(transaction, group, &reply),
+                Err(TransactionError::InvalidPhase(TransactionPhase::Preparing))
+            );
+
+            coordinator.vote(transaction, group, &reply, vote).unwrap();
+
+            assert_eq!(
+                coordinator.vote(transaction, group, &reply, vote),
+                Err(TransactionError::UnexpectedReply)
+            );
+        }
+
+        for outbound in coordinator.commit::<i32>(transaction).unwrap() {
+            let group = outbound.group;
+            let reply = send(&mut drivers, outbound);
+
+            coordinator.acknowledge(transaction, group, &reply).unwrap();
+        }
+
+        assert_eq!(
+            coordinator.phase(transaction),
+            Some(TransactionPhase::Committed)
+        );
+
+        for (group, balance) in [(source.group(), 5), (destination.group(), 7)] {
+            let primary = drivers.get_mut(&group).unwrap().replica_mut(0);
+
+            assert_eq!(primary.checkpoint().state, (balance, Vec::new()));
+        }
+    }
+}
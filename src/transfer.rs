@@ -0,0 +1,173 @@
+use alloc::vec::Vec;
+use hashbrown::HashSet;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// How a replica that fell behind chooses the replica it requests the missing state from.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub enum TransferPolicy {
+    /// A random replica other than the lagging one.
+    #[default]
+    Random,
+    /// The primary of the view the replica is catching up to,
+    /// falling back to round-robin when the primary recently failed to answer.
+    Primary,
+    /// The replica after the previous target, spreading the load of repeated transfers across the group.
+    RoundRobin,
+}
+
+/// Chooses the targets of state transfer requests according to a policy,
+/// avoiding peers that did not answer a request before the replica was last idle.
+#[derive(Clone, Debug)]
+pub(crate) struct TransferTargets {
+    policy: TransferPolicy,
+    index: usize,
+    replicas: usize,
+    /// The last replica a request was sent to.
+    previous: usize,
+    /// The replica a request was sent to that has not answered yet.
+    pending: Option<usize>,
+    /// The replicas that did not answer a request before the replica was idle.
+    failed: HashSet<usize>,
+    /// Chooses random targets deterministically once seeded.
+    rng: Option<StdRng>,
+}
+
+impl TransferTargets {
+    pub fn new(policy: TransferPolicy, index: usize, replicas: usize) -> Self {
+        Self {
+            policy,
+            index,
+            replicas,
+            previous: index,
+            pending: None,
+            failed: HashSet::new(),
+            rng: None,
+        }
+    }
+
+    pub fn seed(&mut self, seed: u64) {
+        self.rng = Some(StdRng::seed_from_u64(seed));
+    }
+
+    /// Chooses the replica to request state from, never the replica itself.
+    /// Returns `None` when the group has no other replicas.
+    pub fn select(&mut self, primary: usize) -> Option<usize> {
+        let mut candidates = self.candidates();
+
+        if candidates.is_empty() {
+            // Every peer failed recently, so give them all another chance.
+            self.failed.clear();
+            candidates = self.candidates();
+        }
+
+        if candidates.is_empty() {
+            return None;
+        }
+
+        let target = match self.policy {
+            TransferPolicy::Random => {
+                let choice = match self.rng.as_mut() {
+                    Some(rng) => rng.gen_range(0..candidates.len()),
+                    #[cfg(feature = "os")]
+                    None => rand::thread_rng().gen_range(0..candidates.len()),
+                    // Without a source of randomness, an unseeded replica chooses as if it was seeded with its index.
+                    #[cfg(not(feature = "os"))]
+                    None => self
+                        .rng
+                        .insert(StdRng::seed_from_u64(self.index as u64))
+                        .gen_range(0..candidates.len()),
+                };
+
+                candidates[choice]
+            }
+            TransferPolicy::Primary if candidates.contains(&primary) => primary,
+            TransferPolicy::Primary | TransferPolicy::RoundRobin => candidates
+                .iter()
+                .copied()
+                .find(|&candidate| candidate > self.previous)
+                .unwrap_or(candidates[0]),
+        };
+
+        self.previous = target;
+        self.pending = Some(target);
+
+        Some(target)
+    }
+
+    /// Records that the pending request was answered, so the target is healthy again.
+    pub fn answered(&mut self) {
+        if let Some(target) = self.pending.take() {
+            self.failed.remove(&target);
+        }
+    }
+
+    /// Marks the target of an unanswered request as failed once the replica is idle.
+    pub fn expire(&mut self) {
+        if let Some(target) = self.pending.take() {
+            self.failed.insert(target);
+        }
+    }
+
+    fn candidates(&self) -> Vec<usize> {
+        (0..self.replicas)
+            .filter(|&replica| replica != self.index && !self.failed.contains(&replica))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn never_self() {
+        let mut targets = TransferTargets::new(TransferPolicy::Random, 1, 3);
+
+        for _ in 0..100 {
+            assert_ne!(targets.select(0), Some(1));
+        }
+
+        let mut targets = TransferTargets::new(TransferPolicy::Primary, 0, 1);
+
+        assert_eq!(targets.select(0), None);
+    }
+
+    #[test]
+    fn primary() {
+        let mut targets = TransferTargets::new(TransferPolicy::Primary, 0, 5);
+
+        assert_eq!(targets.select(3), Some(3));
+        assert_eq!(targets.select(3), Some(3));
+
+        targets.expire();
+
+        assert_eq!(targets.select(3), Some(4));
+        assert_eq!(targets.select(0), Some(1));
+    }
+
+    #[test]
+    fn round_robin() {
+        let mut targets = TransferTargets::new(TransferPolicy::RoundRobin, 1, 3);
+
+        assert_eq!(targets.select(0), Some(2));
+        assert_eq!(targets.select(0), Some(0));
+        assert_eq!(targets.select(0), Some(2));
+    }
+
+    #[test]
+    fn avoid_failed() {
+        let mut targets = TransferTargets::new(TransferPolicy::RoundRobin, 0, 3);
+
+        assert_eq!(targets.select(0), Some(1));
+        targets.expire();
+        assert_eq!(targets.select(0), Some(2));
+        assert_eq!(targets.select(0), Some(2));
+        targets.expire();
+
+        // All peers failed, so they are all candidates again.
+        assert_eq!(targets.select(0), Some(1));
+        targets.answered();
+        assert_eq!(targets.select(0), Some(2));
+    }
+}
@@ -0,0 +1,80 @@
+use core::error::Error;
+use core::fmt::{Display, Formatter};
+use serde::{Deserialize, Serialize};
+
+/// The version of the protocol messages exchanged between replicas.
+/// Incremented whenever a message changes in a way that replicas running an earlier version cannot decode.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// A message tagged with the protocol version of the sender.
+/// Receivers must check the version before decoding the message, since its format depends on the version.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Versioned<M> {
+    pub version: u32,
+    pub message: M,
+}
+
+impl<M> Versioned<M> {
+    /// Tags the message with the protocol version of this crate.
+    pub fn new(message: M) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            message,
+        }
+    }
+
+    /// Returns the message when the sender runs the same version of the protocol.
+    pub fn into_message(self) -> Result<M, IncompatibleVersion> {
+        if self.version == PROTOCOL_VERSION {
+            Ok(self.message)
+        } else {
+            Err(IncompatibleVersion {
+                expected: PROTOCOL_VERSION,
+                actual: self.version,
+            })
+        }
+    }
+}
+
+/// The message was sent by a replica running an incompatible version of the protocol.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct IncompatibleVersion {
+    /// The version of the protocol of the receiver.
+    pub expected: u32,
+    /// The version of the protocol of the sender.
+    pub actual: u32,
+}
+
+impl Display for IncompatibleVersion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "incompatible protocol version {} (expected version {})",
+            self.actual, self.expected
+        )
+    }
+}
+
+impl Error for IncompatibleVersion {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn incompatible() {
+        let mut message = Versioned::new(42);
+
+        assert_eq!(message.clone().into_message(), Ok(42));
+
+        message.version += 1;
+
+        assert_eq!(
+            message.into_message(),
+            Err(IncompatibleVersion {
+                expected: PROTOCOL_VERSION,
+                actual: PROTOCOL_VERSION + 1
+            })
+        );
+    }
+}
@@ -1,6 +1,6 @@
 use crate::configuration::Configuration;
+use core::ops::{Rem, Sub};
 use serde::{Deserialize, Serialize};
-use std::ops::{Rem, Sub};
 
 #[derive(
     Copy, Clone, Debug, Default, Ord, PartialOrd, Eq, PartialEq, Hash, Serialize, Deserialize,
@@ -11,22 +11,60 @@ pub struct OpNumber(u128);
 impl Sub for OpNumber {
     type Output = usize;
 
+    /// The number of operations between two op-numbers.
+    ///
+    /// Panics if the right-hand side is greater or the distance does not fit in a `usize`.
     fn sub(self, rhs: Self) -> Self::Output {
-        (self.0 - rhs.0) as usize
+        self.checked_sub(rhs)
+            .expect("op-number distance is negative or does not fit in a usize")
+    }
+}
+
+impl From<u128> for OpNumber {
+    fn from(value: u128) -> Self {
+        Self(value)
+    }
+}
+
+impl From<OpNumber> for u128 {
+    fn from(value: OpNumber) -> Self {
+        value.0
     }
 }
 
 impl OpNumber {
     pub fn increment(&mut self) {
-        self.0 += 1;
+        *self = self.next();
     }
 
     pub fn increment_by(&mut self, delta: usize) {
-        self.0 += delta as u128;
+        self.0 = self
+            .0
+            .checked_add(delta as u128)
+            .expect("op-number overflowed");
     }
 
+    /// The following op-number. Only for op-numbers the replica assigned itself;
+    /// use [`OpNumber::checked_next`] for ones taken from a message.
     pub fn next(&self) -> Self {
-        Self(self.0 + 1)
+        self.checked_next().expect("op-number overflowed")
+    }
+
+    /// The following op-number, or `None` if there is none (e.g. in a corrupt or malicious message).
+    pub fn checked_next(&self) -> Option<Self> {
+        self.0.checked_add(1).map(Self)
+    }
+
+    /// The number of operations between two op-numbers, or `None` if the other op-number is greater
+    /// or the distance does not fit in a `usize` on this target.
+    pub fn checked_sub(&self, other: Self) -> Option<usize> {
+        self.0
+            .checked_sub(other.0)
+            .and_then(|distance| usize::try_from(distance).ok())
+    }
+
+    pub fn previous(&self) -> Self {
+        Self(self.0.saturating_sub(1))
     }
 }
 
@@ -44,12 +82,68 @@ impl Rem<View> for Configuration {
     }
 }
 
+impl From<u128> for View {
+    fn from(value: u128) -> Self {
+        Self(value)
+    }
+}
+
+impl From<View> for u128 {
+    fn from(value: View) -> Self {
+        value.0
+    }
+}
+
 impl View {
     pub fn increment(&mut self) {
-        self.0 += 1;
+        *self = self.next();
     }
 
+    /// The following view. Only for views the replica started itself;
+    /// use [`View::checked_next`] for ones taken from a message.
     pub fn next(&self) -> Self {
-        Self(1 + self.0)
+        self.checked_next().expect("view overflowed")
+    }
+
+    /// The following view, or `None` if there is none (e.g. in a corrupt or malicious message).
+    pub fn checked_next(&self) -> Option<Self> {
+        self.0.checked_add(1).map(Self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn op_number_distance() {
+        let first = OpNumber::from(3);
+        let last = OpNumber::from(10);
+
+        assert_eq!(last - first, 7);
+        assert_eq!(last.checked_sub(first), Some(7));
+        assert_eq!(first.checked_sub(last), None);
+        assert_eq!(OpNumber::from(u128::MAX).checked_sub(first), None);
+        assert_eq!(u128::from(last.previous()), 9);
+    }
+
+    #[test]
+    fn checked_next() {
+        assert_eq!(OpNumber::from(1).checked_next(), Some(OpNumber::from(2)));
+        assert_eq!(OpNumber::from(u128::MAX).checked_next(), None);
+        assert_eq!(View::from(1).checked_next(), Some(View::from(2)));
+        assert_eq!(View::from(u128::MAX).checked_next(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "op-number overflowed")]
+    fn op_number_overflow() {
+        OpNumber::from(u128::MAX).increment();
+    }
+
+    #[test]
+    #[should_panic(expected = "view overflowed")]
+    fn view_overflow() {
+        View::from(u128::MAX).increment();
     }
 }
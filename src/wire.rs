@@ -0,0 +1,575 @@
+//! Golden vectors for the encoding of every message exchanged between replicas and with clients.
+//!
+//! Replicas running different builds of the same protocol version must decode each other's messages,
+//! so a change to any of these vectors needs a new [`crate::PROTOCOL_VERSION`] (or a field with a default).
+
+use crate::buffer::{ClientPayload, ProtocolPayload};
+use crate::client_table::ClientTable;
+use crate::configuration::Group;
+use crate::log::Log;
+use crate::protocol::{
+    Checkpoint, Commit, Digest, DoViewChange, GetLog, GetState, InstallSnapshot, NewLog, NewState,
+    Prepare, PrepareOk, Recovery, RecoveryResponse, StartView, StartViewChange,
+};
+use crate::request::{
+    Acknowledge, Busy, ClientIdentifier, Discover, Discovered, Expired, Notification, OpenSession,
+    OutdatedEpoch, OutdatedView, QueryRequestStatus, Reply, Request, RequestIdentifier,
+    RequestState, RequestStatus, Resume, SessionAccepted, Signature, Staleness,
+};
+use crate::service::Protocol;
+use crate::version::Versioned;
+use crate::viewstamp::{OpNumber, View};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt::Debug;
+use std::time::Duration;
+
+#[derive(Debug, Eq, PartialEq)]
+struct Wire;
+
+impl Protocol for Wire {
+    type Request = String;
+    type Prediction = u64;
+    type Reply = String;
+    type Checkpoint = Vec<String>;
+}
+
+fn assert_golden<M>(message: M, expected: &str)
+where
+    M: Debug + PartialEq + Serialize + DeserializeOwned,
+{
+    assert_eq!(serde_json::to_string(&message).unwrap(), expected);
+    assert_eq!(serde_json::from_str::<M>(expected).unwrap(), message);
+}
+
+fn request(client: u128, id: u128, payload: &str) -> Request<String> {
+    let mut identifier = RequestIdentifier::default();
+
+    for _ in 0..id {
+        identifier.increment();
+    }
+
+    Request {
+        payload: payload.to_string(),
+        client: ClientIdentifier::from(client),
+        id: identifier,
+        deadline: None,
+        signature: None,
+        epoch: 0,
+        staleness: None,
+    }
+}
+
+fn log(view: u128, payloads: &[&str]) -> Log<String, u64> {
+    let mut log = Log::default();
+
+    for (index, payload) in payloads.iter().enumerate() {
+        log.push_at(
+            View::from(view),
+            request(7, index as u128 + 1, payload),
+            index as u64,
+            Duration::from_millis(1_000 + index as u64),
+        );
+    }
+
+    log
+}
+
+fn protocol_messages() -> Vec<(ProtocolPayload<Wire>, &'static str)> {
+    let mut client_table = ClientTable::default();
+    let started = request(7, 1, "a");
+
+    client_table.start(&started);
+    client_table.finish(
+        &started,
+        Reply {
+            view: View::from(1),
+            id: started.id,
+            payload: "A".to_string(),
+        },
+    );
+
+    vec![
+        (
+            ProtocolPayload::Prepare(Prepare {
+                view: View::from(1),
+                epoch: 0,
+                op_number: OpNumber::from(2),
+                request: request(7, 2, "b").with_deadline(Duration::from_secs(5)),
+                prediction: 42,
+                committed: OpNumber::from(1),
+                previous: Some(View::from(1)),
+                timestamp: Duration::from_millis(1_500),
+            }),
+            r#"{"Prepare":{"view":1,"epoch":0,"op_number":2,"request":{"payload":"b","client":7,"id":2,"deadline":{"secs":5,"nanos":0},"signature":null,"epoch":0,"staleness":null},"prediction":42,"committed":1,"previous":1,"timestamp":{"secs":1,"nanos":500000000}}}"#,
+        ),
+        (
+            ProtocolPayload::PrepareOk(PrepareOk {
+                view: View::from(1),
+                epoch: 0,
+                op_number: OpNumber::from(2),
+                index: 1,
+                committed: OpNumber::from(1),
+                promised: View::from(1),
+            }),
+            r#"{"PrepareOk":{"view":1,"epoch":0,"op_number":2,"index":1,"committed":1,"promised":1}}"#,
+        ),
+        (
+            ProtocolPayload::Commit(Commit {
+                view: View::from(1),
+                epoch: 0,
+                committed: OpNumber::from(2),
+                timestamp: Duration::from_secs(3),
+            }),
+            r#"{"Commit":{"view":1,"epoch":0,"committed":2,"timestamp":{"secs":3,"nanos":0}}}"#,
+        ),
+        (
+            ProtocolPayload::GetState(GetState {
+                view: View::from(1),
+                epoch: 0,
+                op_number: OpNumber::from(1),
+                index: 2,
+            }),
+            r#"{"GetState":{"view":1,"epoch":0,"op_number":1,"index":2}}"#,
+        ),
+        (
+            ProtocolPayload::NewState(NewState {
+                view: View::from(1),
+                epoch: 0,
+                log: log(1, &["a"]),
+                committed: OpNumber::from(1),
+                op_number: OpNumber::from(1),
+            }),
+            r#"{"NewState":{"view":1,"epoch":0,"log":{"view":1,"range":[1,1],"views":[[1,1]],"entries":[{"request":{"payload":"a","client":7,"id":1,"deadline":null,"signature":null,"epoch":0,"staleness":null},"prediction":0,"timestamp":{"secs":1,"nanos":0}}]},"committed":1,"op_number":1}}"#,
+        ),
+        (
+            ProtocolPayload::InstallSnapshot(InstallSnapshot {
+                view: View::from(1),
+                epoch: 0,
+                checkpoint: Checkpoint {
+                    committed: OpNumber::from(1),
+                    state: vec!["a".to_string()],
+                    client_table,
+                    generation: 2,
+                },
+                log: Log::empty(View::from(1), OpNumber::from(1)),
+                op_number: OpNumber::from(1),
+            }),
+            r#"{"InstallSnapshot":{"view":1,"epoch":0,"checkpoint":{"committed":1,"state":["a"],"client_table":{"7":{"request":1,"reply":{"view":1,"id":1,"payload":"A"}}},"generation":2},"log":{"view":1,"range":[1,1],"views":[],"entries":[]},"op_number":1}}"#,
+        ),
+        (
+            ProtocolPayload::StartViewChange(StartViewChange {
+                view: View::from(2),
+                epoch: 0,
+                index: 1,
+            }),
+            r#"{"StartViewChange":{"view":2,"epoch":0,"index":1}}"#,
+        ),
+        (
+            ProtocolPayload::DoViewChange(DoViewChange {
+                view: View::from(2),
+                epoch: 0,
+                log: log(1, &["a", "b"]).summary(),
+                committed: OpNumber::from(1),
+                index: 1,
+            }),
+            r#"{"DoViewChange":{"view":2,"epoch":0,"log":{"view":1,"range":[1,2],"views":[[1,2]],"entries":[]},"committed":1,"index":1}}"#,
+        ),
+        (
+            ProtocolPayload::GetLog(GetLog {
+                view: View::from(2),
+                epoch: 0,
+                op_number: OpNumber::from(1),
+                index: 2,
+            }),
+            r#"{"GetLog":{"view":2,"epoch":0,"op_number":1,"index":2}}"#,
+        ),
+        (
+            ProtocolPayload::NewLog(NewLog {
+                view: View::from(2),
+                epoch: 0,
+                log: log(1, &["a"]),
+                index: 2,
+            }),
+            r#"{"NewLog":{"view":2,"epoch":0,"log":{"view":1,"range":[1,1],"views":[[1,1]],"entries":[{"request":{"payload":"a","client":7,"id":1,"deadline":null,"signature":null,"epoch":0,"staleness":null},"prediction":0,"timestamp":{"secs":1,"nanos":0}}]},"index":2}}"#,
+        ),
+        (
+            ProtocolPayload::StartView(StartView {
+                view: View::from(2),
+                epoch: 0,
+                log: log(1, &["a"]),
+                committed: OpNumber::from(1),
+            }),
+            r#"{"StartView":{"view":2,"epoch":0,"log":{"view":1,"range":[1,1],"views":[[1,1]],"entries":[{"request":{"payload":"a","client":7,"id":1,"deadline":null,"signature":null,"epoch":0,"staleness":null},"prediction":0,"timestamp":{"secs":1,"nanos":0}}]},"committed":1}}"#,
+        ),
+        (
+            ProtocolPayload::Recovery(Recovery {
+                index: 2,
+                epoch: 0,
+                committed: OpNumber::from(1),
+                nonce: 9.into(),
+                digest: Some(log(1, &["a"]).digest()),
+                generation: 3,
+            }),
+            r#"{"Recovery":{"index":2,"epoch":0,"committed":1,"nonce":9,"digest":{"views":[[1,1]],"last":1},"generation":3}}"#,
+        ),
+        (
+            ProtocolPayload::RecoveryResponse(RecoveryResponse {
+                view: View::from(2),
+                epoch: 0,
+                nonce: 9.into(),
+                log: None,
+                committed: None,
+                index: 1,
+                generation: 3,
+            }),
+            r#"{"RecoveryResponse":{"view":2,"epoch":0,"nonce":9,"log":null,"committed":null,"index":1,"generation":3}}"#,
+        ),
+        (
+            ProtocolPayload::Digest(Digest {
+                view: View::from(2),
+                epoch: 0,
+                ranges: vec![(OpNumber::from(1), OpNumber::from(2))],
+                hashes: vec![3],
+                index: 0,
+            }),
+            r#"{"Digest":{"view":2,"epoch":0,"ranges":[[1,2]],"hashes":[3],"index":0}}"#,
+        ),
+    ]
+}
+
+fn client_messages() -> Vec<(ClientPayload<Wire>, &'static str)> {
+    let id = request(7, 2, "b").id;
+
+    vec![
+        (
+            ClientPayload::Reply(Reply {
+                view: View::from(1),
+                id,
+                payload: "B".to_string(),
+            }),
+            r#"{"Reply":{"view":1,"id":2,"payload":"B"}}"#,
+        ),
+        (
+            ClientPayload::Busy(Busy {
+                view: View::from(1),
+                id,
+                backoff: Duration::from_millis(250),
+            }),
+            r#"{"Busy":{"view":1,"id":2,"backoff":{"secs":0,"nanos":250000000}}}"#,
+        ),
+        (
+            ClientPayload::Expired(Expired {
+                view: View::from(1),
+                id,
+            }),
+            r#"{"Expired":{"view":1,"id":2}}"#,
+        ),
+        (
+            ClientPayload::SessionAccepted(SessionAccepted {
+                view: View::from(1),
+                last_request: id,
+            }),
+            r#"{"SessionAccepted":{"view":1,"last_request":2}}"#,
+        ),
+        (
+            ClientPayload::RequestStatus(RequestStatus {
+                view: View::from(1),
+                request: id,
+                state: RequestState::Executed,
+            }),
+            r#"{"RequestStatus":{"view":1,"request":2,"state":"Executed"}}"#,
+        ),
+        (
+            ClientPayload::OutdatedView(OutdatedView {
+                view: View::from(2),
+                primary: 2,
+                epoch: 1,
+            }),
+            r#"{"OutdatedView":{"view":2,"primary":2,"epoch":1}}"#,
+        ),
+        (
+            ClientPayload::Notification(Notification {
+                view: View::from(1),
+                op_number: OpNumber::from(3),
+                payload: "changed".to_string(),
+            }),
+            r#"{"Notification":{"view":1,"op_number":3,"payload":"changed"}}"#,
+        ),
+        (
+            ClientPayload::OutdatedEpoch(OutdatedEpoch {
+                view: View::from(2),
+                epoch: 1,
+            }),
+            r#"{"OutdatedEpoch":{"view":2,"epoch":1}}"#,
+        ),
+        (
+            ClientPayload::Discovered(Discovered {
+                view: View::from(2),
+                epoch: 1,
+                group: Group::from(5),
+                replicas: 3,
+                members: vec!["203.0.113.7:3000".parse().unwrap()],
+            }),
+            r#"{"Discovered":{"view":2,"epoch":1,"group":5,"replicas":3,"members":["203.0.113.7:3000"]}}"#,
+        ),
+    ]
+}
+
+/// Generates a message of a random kind with random numbers, payloads and logs.
+fn arbitrary(rng: &mut StdRng) -> ProtocolPayload<Wire> {
+    let view = View::from(rng.gen_range(0..u128::MAX));
+    let op_number = OpNumber::from(rng.gen_range(0..u128::MAX));
+    let index = rng.gen_range(0..usize::MAX);
+    let payloads: Vec<String> = (0..rng.gen_range(0..4))
+        .map(|_| rng.gen::<u32>().to_string())
+        .collect();
+    let payloads: Vec<&str> = payloads.iter().map(String::as_str).collect();
+    let log = log(rng.gen_range(0..u128::MAX), &payloads);
+
+    match rng.gen_range(0..14) {
+        0 => ProtocolPayload::Prepare(Prepare {
+            view,
+            epoch: 0,
+            op_number,
+            request: Request {
+                payload: rng.gen::<u64>().to_string(),
+                client: rng.gen::<u128>().into(),
+                id: Default::default(),
+                deadline: rng
+                    .gen_bool(0.5)
+                    .then(|| Duration::new(rng.gen(), rng.gen_range(0..1_000_000_000))),
+                signature: rng.gen_bool(0.5).then(|| Signature {
+                    key: rng.gen(),
+                    mac: (0..rng.gen_range(0..32)).map(|_| rng.gen()).collect(),
+                }),
+                epoch: rng.gen(),
+                staleness: match rng.gen_range(0..3) {
+                    0 => None,
+                    1 => Some(Staleness::Operations(rng.gen())),
+                    _ => Some(Staleness::Time(Duration::new(
+                        rng.gen(),
+                        rng.gen_range(0..1_000_000_000),
+                    ))),
+                },
+            },
+            prediction: rng.gen(),
+            committed: OpNumber::from(rng.gen::<u128>()),
+            previous: rng.gen_bool(0.5).then_some(view),
+            timestamp: Duration::new(rng.gen(), rng.gen_range(0..1_000_000_000)),
+        }),
+        1 => ProtocolPayload::PrepareOk(PrepareOk {
+            view,
+            epoch: 0,
+            op_number,
+            index,
+            committed: OpNumber::from(rng.gen::<u128>()),
+            promised: View::from(rng.gen::<u128>()),
+        }),
+        2 => ProtocolPayload::Commit(Commit {
+            view,
+            epoch: 0,
+            committed: op_number,
+            timestamp: Duration::new(rng.gen(), rng.gen_range(0..1_000_000_000)),
+        }),
+        3 => ProtocolPayload::GetState(GetState {
+            view,
+            epoch: 0,
+            op_number,
+            index,
+        }),
+        4 => ProtocolPayload::NewState(NewState {
+            view,
+            epoch: 0,
+            log,
+            committed: op_number,
+            op_number,
+        }),
+        5 => ProtocolPayload::InstallSnapshot(InstallSnapshot {
+            view,
+            epoch: 0,
+            checkpoint: Checkpoint {
+                committed: op_number,
+                state: payloads.iter().map(|payload| payload.to_string()).collect(),
+                client_table: ClientTable::default(),
+                generation: rng.gen(),
+            },
+            log,
+            op_number,
+        }),
+        6 => ProtocolPayload::StartViewChange(StartViewChange {
+            view,
+            epoch: 0,
+            index,
+        }),
+        7 => ProtocolPayload::DoViewChange(DoViewChange {
+            view,
+            epoch: 0,
+            log: log.summary(),
+            committed: op_number,
+            index,
+        }),
+        8 => ProtocolPayload::GetLog(GetLog {
+            view,
+            epoch: 0,
+            op_number,
+            index,
+        }),
+        9 => ProtocolPayload::NewLog(NewLog {
+            view,
+            epoch: 0,
+            log,
+            index,
+        }),
+        10 => ProtocolPayload::StartView(StartView {
+            view,
+            epoch: 0,
+            log,
+            committed: op_number,
+        }),
+        11 => ProtocolPayload::Recovery(Recovery {
+            index,
+            epoch: 0,
+            committed: op_number,
+            nonce: rng.gen::<u128>().into(),
+            digest: rng.gen_bool(0.5).then(|| log.digest()),
+            generation: rng.gen(),
+        }),
+        12 => ProtocolPayload::RecoveryResponse(RecoveryResponse {
+            view,
+            epoch: 0,
+            nonce: rng.gen::<u128>().into(),
+            committed: rng.gen_bool(0.5).then_some(op_number),
+            log: rng.gen_bool(0.5).then_some(log),
+            index,
+            generation: rng.gen(),
+        }),
+        _ => ProtocolPayload::Digest(Digest {
+            view,
+            epoch: 0,
+            ranges: vec![(op_number, op_number); payloads.len()],
+            hashes: (0..payloads.len()).map(|_| rng.gen()).collect(),
+            index,
+        }),
+    }
+}
+
+#[test]
+fn protocol_vectors() {
+    for (message, expected) in protocol_messages() {
+        assert_golden(message, expected);
+    }
+}
+
+#[test]
+fn client_vectors() {
+    for (message, expected) in client_messages() {
+        assert_golden(message, expected);
+    }
+}
+
+#[test]
+fn client_requests() {
+    let mut signed = request(7, 3, "c");
+
+    signed.signature = Some(Signature {
+        key: 1,
+        mac: vec![0xAB, 0xCD],
+    });
+
+    assert_golden(
+        signed,
+        r#"{"payload":"c","client":7,"id":3,"deadline":null,"signature":{"key":1,"mac":[171,205]},"epoch":0,"staleness":null}"#,
+    );
+    assert_golden(
+        request(7, 4, "d").with_staleness(Staleness::Operations(2)),
+        r#"{"payload":"d","client":7,"id":4,"deadline":null,"signature":null,"epoch":0,"staleness":{"Operations":2}}"#,
+    );
+    assert_golden(
+        request(7, 5, "e").with_staleness(Staleness::Time(Duration::from_millis(250))),
+        r#"{"payload":"e","client":7,"id":5,"deadline":null,"signature":null,"epoch":0,"staleness":{"Time":{"secs":0,"nanos":250000000}}}"#,
+    );
+    assert_golden(
+        OpenSession {
+            client: ClientIdentifier::from(7),
+        },
+        r#"{"client":7}"#,
+    );
+    assert_golden(
+        QueryRequestStatus {
+            client: ClientIdentifier::from(7),
+            request: request(7, 3, "c").id,
+        },
+        r#"{"client":7,"request":3}"#,
+    );
+    assert_golden(
+        Resume {
+            client: ClientIdentifier::from(7),
+            acknowledged: request(7, 2, "b").id,
+        },
+        r#"{"client":7,"acknowledged":2}"#,
+    );
+    assert_golden(
+        Acknowledge {
+            client: ClientIdentifier::from(7),
+            request: request(7, 3, "c").id,
+        },
+        r#"{"client":7,"request":3}"#,
+    );
+    assert_golden(
+        Discover {
+            client: ClientIdentifier::from(7),
+        },
+        r#"{"client":7}"#,
+    );
+}
+
+#[test]
+fn versioned() {
+    let (message, _) = protocol_messages().swap_remove(2);
+
+    assert_golden(
+        Versioned::new(message),
+        r#"{"version":1,"message":{"Commit":{"view":1,"epoch":0,"committed":2,"timestamp":{"secs":3,"nanos":0}}}}"#,
+    );
+}
+
+/// Fields added after the first release of a protocol version decode with their defaults when missing.
+#[test]
+fn optional_fields() {
+    let prepare_ok: PrepareOk =
+        serde_json::from_str(r#"{"view":1,"op_number":2,"index":1}"#).unwrap();
+    let request: Request<String> =
+        serde_json::from_str(r#"{"payload":"a","client":7,"id":1}"#).unwrap();
+    let recovery: Recovery =
+        serde_json::from_str(r#"{"index":2,"committed":1,"nonce":9}"#).unwrap();
+    let outdated: OutdatedView = serde_json::from_str(r#"{"view":2,"primary":2}"#).unwrap();
+    let commit: Commit = serde_json::from_str(r#"{"view":1,"committed":2}"#).unwrap();
+
+    assert_eq!(prepare_ok.committed, OpNumber::default());
+    assert_eq!(prepare_ok.promised, View::default());
+    assert_eq!(request.deadline, None);
+    assert_eq!(request.signature, None);
+    assert_eq!(request.epoch, 0);
+    assert_eq!(request.staleness, None);
+    assert_eq!(recovery.digest, None);
+    assert_eq!(recovery.generation, 0);
+    assert_eq!(outdated.epoch, 0);
+    assert_eq!(commit.timestamp, Duration::ZERO);
+}
+
+#[test]
+fn round_trip() {
+    let mut rng = StdRng::seed_from_u64(0x5EED);
+
+    for _ in 0..1_000 {
+        let message = arbitrary(&mut rng);
+        let encoded = serde_json::to_vec(&message).unwrap();
+        let decoded: ProtocolPayload<Wire> = serde_json::from_slice(&encoded).unwrap();
+
+        assert_eq!(decoded, message);
+        assert_eq!(serde_json::to_vec(&decoded).unwrap(), encoded);
+    }
+}
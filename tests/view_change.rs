@@ -0,0 +1,397 @@
+#![cfg(feature = "threads")]
+
+//! Regression scenarios for view changes, each checking that the operations replicas committed
+//! never change and are never lost as the group moves through views.
+
+use viewstamped_replication::buffer::{BufferedMailbox, ProtocolPayload};
+use viewstamped_replication::driver::LocalDriver;
+use viewstamped_replication::{
+    Client, Configuration, DoViewChange, GetLog, Log, NewLog, OpNumber, Prepare, Protocol, Replica,
+    Request, Service, StartView, View,
+};
+
+/// Adds the payload of each request to a running total.
+#[derive(Default)]
+struct Counter(i32);
+
+impl From<i32> for Counter {
+    fn from(total: i32) -> Self {
+        Self(total)
+    }
+}
+
+impl Protocol for Counter {
+    type Request = i32;
+    type Prediction = ();
+    type Reply = i32;
+    type Checkpoint = i32;
+}
+
+impl Service for Counter {
+    fn predict(&self, _: &i32) {}
+
+    fn checkpoint(&self) -> i32 {
+        self.0
+    }
+
+    fn invoke(&mut self, request: &i32, _: &()) -> i32 {
+        self.0 += request;
+        self.0
+    }
+}
+
+/// The operations committed by any replica so far, in op-number order.
+#[derive(Default)]
+struct Prefix(Vec<Request<i32>>);
+
+impl Prefix {
+    /// Checks that the committed entries of the replica agree with every replica seen before it, extending the prefix.
+    fn observe(&mut self, index: usize, replica: &Replica<Counter>) {
+        let log = replica.log();
+
+        for op_number in 1..=u128::from(replica.committed()) {
+            // Entries before the start of the log were compacted into a checkpoint.
+            let Some(entry) = log.get(OpNumber::from(op_number)) else {
+                continue;
+            };
+            let position = (op_number - 1) as usize;
+
+            match self.0.get(position) {
+                Some(request) => assert_eq!(
+                    request,
+                    entry.request(),
+                    "replica {index} committed a different operation at op-number {op_number}"
+                ),
+                None if position == self.0.len() => self.0.push(entry.request().clone()),
+                None => {}
+            }
+        }
+    }
+
+    /// Delivers every pending message, checking the committed operations of the running replicas after each one.
+    fn settle(&mut self, driver: &mut LocalDriver<Counter>) {
+        loop {
+            for index in 0..driver.configuration().members() {
+                if !driver.is_crashed(index) {
+                    self.observe(index, driver.replica(index));
+                }
+            }
+
+            if !driver.step() {
+                break;
+            }
+        }
+    }
+
+    /// Checks that the replica committed every operation in the prefix, so none were lost.
+    fn assert_kept_by(&self, replica: &Replica<Counter>) {
+        assert!(u128::from(replica.committed()) >= self.0.len() as u128);
+    }
+}
+
+/// Sends the request to the primary and lets the primary tell the backups it committed.
+fn commit(
+    driver: &mut LocalDriver<Counter>,
+    prefix: &mut Prefix,
+    primary: usize,
+    request: Request<i32>,
+) {
+    driver.request(primary, request);
+    prefix.settle(driver);
+
+    // The primary skips the commit message on the first idle period after a prepare.
+    driver.idle(primary);
+    driver.idle(primary);
+    prefix.settle(driver);
+}
+
+fn idle_all(driver: &mut LocalDriver<Counter>, prefix: &mut Prefix) {
+    for index in 0..driver.configuration().members() {
+        driver.idle(index);
+    }
+
+    prefix.settle(driver);
+}
+
+#[test]
+fn primary_crash_with_partially_prepared_operations() {
+    let configuration = Configuration::from(5);
+    let mut driver = LocalDriver::new(configuration, |_| Counter::default());
+    let mut client = Client::new(configuration);
+    let mut prefix = Prefix::default();
+
+    commit(&mut driver, &mut prefix, 0, client.new_request(1));
+
+    // Only one backup receives the next prepare, which is short of a quorum.
+    for backup in 2..5 {
+        driver.set_loss_rate(0, backup, 1.0);
+    }
+
+    let request = client.new_request(2);
+
+    driver.request(0, request.clone());
+    prefix.settle(&mut driver);
+
+    assert_eq!(driver.replica(0).committed(), OpNumber::from(1));
+
+    driver.crash(0);
+    idle_all(&mut driver, &mut prefix);
+
+    assert_eq!(driver.replica(1).view(), View::from(1));
+    assert!(driver.replica(1).is_primary());
+
+    // The client retries the request with the new primary, which commits it exactly once.
+    commit(&mut driver, &mut prefix, 1, request.clone());
+
+    assert_eq!(prefix.0.len(), 2);
+    assert_eq!(prefix.0[1], request);
+
+    for index in 1..5 {
+        prefix.assert_kept_by(driver.replica(index));
+    }
+}
+
+#[test]
+fn double_view_change() {
+    let configuration = Configuration::from(5);
+    let mut driver = LocalDriver::new(configuration, |_| Counter::default());
+    let mut client = Client::new(configuration);
+    let mut prefix = Prefix::default();
+
+    commit(&mut driver, &mut prefix, 0, client.new_request(1));
+    commit(&mut driver, &mut prefix, 0, client.new_request(2));
+
+    // The primary of the next view crashes along with the current one, so the first view change never completes.
+    driver.crash(0);
+    driver.crash(1);
+    idle_all(&mut driver, &mut prefix);
+
+    assert!((2..5).all(|index| !driver.replica(index).is_normal()));
+
+    idle_all(&mut driver, &mut prefix);
+
+    assert_eq!(driver.replica(2).view(), View::from(2));
+    assert!(driver.replica(2).is_primary());
+    assert!(driver.replica(2).is_normal());
+
+    driver.recover(0);
+    driver.recover(1);
+    prefix.settle(&mut driver);
+
+    commit(&mut driver, &mut prefix, 2, client.new_request(3));
+
+    assert_eq!(prefix.0.len(), 3);
+
+    for index in 0..5 {
+        prefix.assert_kept_by(driver.replica(index));
+    }
+}
+
+#[test]
+fn equal_logs_with_different_last_normal_views() {
+    let configuration = Configuration::from(5);
+    let mut client = Client::new(configuration);
+    let view = View::from(5);
+    let mut primary = Replica::new(configuration, configuration % view, Counter::default());
+    let mut mailbox = BufferedMailbox::default();
+    let first = client.new_request(1);
+    let earlier = client.new_request(2);
+    let later = client.new_request(3);
+
+    // Both logs hold two operations, but the second one was prepared in different views.
+    let mut outdated = Log::default();
+    let mut latest = Log::default();
+
+    outdated.push(View::default(), first.clone(), ());
+    outdated.push(View::from(1), earlier, ());
+    latest.push(View::default(), first.clone(), ());
+    latest.push(View::from(3), later.clone(), ());
+
+    for (index, log) in [(1, &outdated), (2, &latest)] {
+        primary.handle_payload(
+            ProtocolPayload::DoViewChange(DoViewChange {
+                view,
+                epoch: 0,
+                log: log.summary(),
+                committed: OpNumber::from(1),
+                index,
+            }),
+            &mut mailbox,
+        );
+    }
+
+    // The new primary sends its own do-view-change to itself once it has heard from enough replicas.
+    let own = mailbox.take_send::<DoViewChange<i32, ()>>().unwrap();
+
+    assert_eq!(own.destination, 0);
+
+    primary.handle_payload(ProtocolPayload::DoViewChange(own.payload), &mut mailbox);
+
+    // The primary asks the replica with the latest last-normal view for its log.
+    let envelope = mailbox.take_send::<GetLog>().unwrap();
+
+    assert_eq!(envelope.destination, 2);
+
+    primary.handle_payload(
+        ProtocolPayload::NewLog(NewLog {
+            view,
+            epoch: 0,
+            log: latest.after(envelope.payload.op_number),
+            index: 2,
+        }),
+        &mut mailbox,
+    );
+
+    let start_view = mailbox.take_broadcast::<StartView<i32, ()>>().unwrap();
+    let mut prefix = Prefix::default();
+
+    assert!(primary.is_normal());
+    assert_eq!(
+        start_view.log.view_at(OpNumber::from(2)),
+        Some(View::from(3))
+    );
+    assert_eq!(
+        start_view
+            .log
+            .get(OpNumber::from(2))
+            .map(|entry| entry.request()),
+        Some(&later)
+    );
+
+    prefix.observe(0, &primary);
+
+    assert_eq!(prefix.0, [first]);
+}
+
+#[test]
+fn stale_start_view() {
+    let configuration = Configuration::from(3);
+    let mut client = Client::new(configuration);
+    let mut backup = Replica::new(configuration, 1, Counter::default());
+    let mut mailbox = BufferedMailbox::default();
+    let mut prefix = Prefix::default();
+    let mut old = Log::default();
+
+    old.push(View::default(), client.new_request(1), ());
+
+    let mut new = old.clone();
+
+    new.push(View::from(2), client.new_request(2), ());
+
+    backup.handle_payload(
+        ProtocolPayload::StartView(StartView {
+            view: View::from(2),
+            epoch: 0,
+            log: new.clone(),
+            committed: OpNumber::from(2),
+        }),
+        &mut mailbox,
+    );
+    prefix.observe(1, &backup);
+
+    assert_eq!(backup.committed(), OpNumber::from(2));
+
+    // The start of an earlier view arrives late (e.g. delayed in the network) with a shorter log.
+    backup.handle_payload(
+        ProtocolPayload::StartView(StartView {
+            view: View::from(1),
+            epoch: 0,
+            log: old,
+            committed: OpNumber::from(1),
+        }),
+        &mut mailbox,
+    );
+    prefix.observe(1, &backup);
+
+    assert_eq!(backup.view(), View::from(2));
+    assert_eq!(backup.log().last_op_number(), OpNumber::from(2));
+    assert_eq!(prefix.0.len(), 2);
+    prefix.assert_kept_by(&backup);
+}
+
+#[test]
+fn empty_log_does_not_replace_the_primary_log() {
+    let configuration = Configuration::from(3);
+    let mut client = Client::new(configuration);
+    let view = View::from(4);
+    let mut primary = Replica::new(configuration, configuration % view, Counter::default());
+    let mut mailbox = BufferedMailbox::default();
+    let first = client.new_request(1);
+    let later = client.new_request(2);
+    let mut latest = Log::default();
+
+    latest.push(View::default(), first.clone(), ());
+    latest.push(View::from(3), later.clone(), ());
+
+    primary.handle_payload(
+        ProtocolPayload::Prepare(Prepare {
+            view: View::default(),
+            epoch: 0,
+            op_number: OpNumber::from(1),
+            request: first.clone(),
+            prediction: (),
+            committed: OpNumber::default(),
+            previous: None,
+            timestamp: std::time::Duration::ZERO,
+        }),
+        &mut mailbox,
+    );
+    primary.handle_payload(
+        ProtocolPayload::DoViewChange(DoViewChange {
+            view,
+            epoch: 0,
+            log: latest.summary(),
+            committed: OpNumber::default(),
+            index: 2,
+        }),
+        &mut mailbox,
+    );
+
+    let own = mailbox.take_send::<DoViewChange<i32, ()>>().unwrap();
+
+    primary.handle_payload(ProtocolPayload::DoViewChange(own.payload), &mut mailbox);
+
+    let envelope = mailbox.take_send::<GetLog>().unwrap();
+
+    assert_eq!(envelope.destination, 2);
+
+    // A log without entries (e.g. one whose range was corrupted in transit) holds none of the operations to adopt.
+    primary.handle_payload(
+        ProtocolPayload::NewLog(NewLog {
+            view,
+            epoch: 0,
+            log: Log::default(),
+            index: 2,
+        }),
+        &mut mailbox,
+    );
+
+    assert!(!primary.is_normal());
+    assert!(mailbox.take_broadcast::<StartView<i32, ()>>().is_none());
+    assert_eq!(
+        primary
+            .log()
+            .get(OpNumber::from(1))
+            .map(|entry| entry.request()),
+        Some(&first)
+    );
+
+    primary.handle_payload(
+        ProtocolPayload::NewLog(NewLog {
+            view,
+            epoch: 0,
+            log: latest.after(envelope.payload.op_number),
+            index: 2,
+        }),
+        &mut mailbox,
+    );
+
+    assert!(primary.is_normal());
+    assert_eq!(
+        primary
+            .log()
+            .get(OpNumber::from(2))
+            .map(|entry| entry.request()),
+        Some(&later)
+    );
+}